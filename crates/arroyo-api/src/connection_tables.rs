@@ -1,4 +1,5 @@
 use anyhow::anyhow;
+use arrow_schema::DataType;
 use axum::extract::{Path, Query, State};
 use axum::response::sse::Event;
 use axum::response::Sse;
@@ -15,14 +16,14 @@ use tracing::debug;
 use arroyo_connectors::confluent::ConfluentProfile;
 use arroyo_connectors::connector_for_type;
 use arroyo_connectors::kafka::{KafkaConfig, KafkaTable, SchemaRegistry};
-use arroyo_formats::{avro, json};
+use arroyo_formats::{avro, json, proto};
 use arroyo_operator::connector::ErasedConnector;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionTable, ConnectionTablePost, ConnectionType,
     SchemaDefinition,
 };
 use arroyo_rpc::api_types::{ConnectionTableCollection, PaginationQueryParams};
-use arroyo_rpc::formats::{AvroFormat, Format, JsonFormat};
+use arroyo_rpc::formats::{AvroFormat, Format, JsonFormat, ProtobufFormat};
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_rpc::schema_resolver::{
     ConfluentSchemaRegistry, ConfluentSchemaSubjectResponse, ConfluentSchemaType,
@@ -463,6 +464,17 @@ pub(crate) async fn expand_schema(
             )
             .await
         }
+        Format::Protobuf(_) => {
+            expand_protobuf_schema(
+                connector,
+                connection_type,
+                schema,
+                profile_config,
+                table_config,
+            )
+            .await
+        }
+        Format::Csv(_) => Ok(schema),
         Format::Parquet(_) => Ok(schema),
         Format::RawString(_) => Ok(schema),
         Format::RawBytes(_) => Ok(schema),
@@ -483,7 +495,7 @@ async fn expand_avro_schema(
     {
         let schema_response = get_schema(connector, table_config, profile_config).await?;
         match connection_type {
-            ConnectionType::Source => {
+            ConnectionType::Source | ConnectionType::Lookup => {
                 let schema_response = schema_response.ok_or_else(|| bad_request(
                         "No schema was found; ensure that the topic exists and has a value schema configured in the schema registry".to_string()))?;
 
@@ -504,7 +516,7 @@ async fn expand_avro_schema(
 
     let Some(SchemaDefinition::AvroSchema(definition)) = schema.definition.as_ref() else {
         return match connection_type {
-            ConnectionType::Source => Err(bad_request(
+            ConnectionType::Source | ConnectionType::Lookup => Err(bad_request(
                 "avro format requires an avro schema be set for sources",
             )),
             ConnectionType::Sink => {
@@ -521,8 +533,113 @@ async fn expand_avro_schema(
         );
     }
 
-    let fields: Result<_, String> = avro::schema::to_arrow(definition)
+    let mut arrow_fields = avro::schema::to_arrow(definition)
         .map_err(|e| bad_request(format!("Invalid avro schema: {}", e)))?
+        .fields;
+
+    if let Some(Format::Avro(AvroFormat { debezium: true, .. })) = &schema.format {
+        // the schema describes the debezium envelope; the table is defined by the row type
+        arrow_fields = match arrow_fields.find("after").map(|(_, f)| f.data_type()) {
+            Some(DataType::Struct(fields)) => fields.clone(),
+            _ => {
+                return Err(bad_request(
+                    "debezium avro schema must have an 'after' field containing a record",
+                ))
+            }
+        };
+    }
+
+    let fields: Result<_, String> = arrow_fields
+        .into_iter()
+        .map(|f| (**f).clone().try_into())
+        .collect();
+
+    schema.fields = fields.map_err(|e| bad_request(format!("Failed to convert schema: {}", e)))?;
+
+    Ok(schema)
+}
+
+async fn expand_protobuf_schema(
+    connector: &str,
+    connection_type: ConnectionType,
+    mut schema: ConnectionSchema,
+    profile_config: &Value,
+    table_config: &Value,
+) -> Result<ConnectionSchema, ErrorResp> {
+    let mut dependencies = vec![];
+
+    if let Some(Format::Protobuf(ProtobufFormat {
+        confluent_schema_registry: true,
+        ..
+    })) = &schema.format
+    {
+        match connection_type {
+            ConnectionType::Source | ConnectionType::Lookup => {
+                let registry = get_schema_registry(connector, table_config, profile_config)?;
+                let schema_response = registry
+                    .get_schema_for_version(None)
+                    .await
+                    .map_err(schema_registry_error)?
+                    .ok_or_else(|| bad_request(
+                        "No schema was found; ensure that the topic exists and has a value schema configured in the schema registry".to_string()))?;
+
+                if schema_response.schema_type != ConfluentSchemaType::Protobuf {
+                    return Err(bad_request(format!(
+                        "Format configured is protobuf, but confluent schema repository returned a {:?} schema",
+                        schema_response.schema_type
+                    )));
+                }
+
+                dependencies = registry
+                    .resolve_references(&schema_response.references)
+                    .await
+                    .map_err(schema_registry_error)?;
+
+                schema.definition = Some(SchemaDefinition::ProtobufSchema(schema_response.schema));
+            }
+            ConnectionType::Sink => {
+                // sinks with a schema definition write with that schema (which must already be
+                // registered for the subject); otherwise we register a schema generated from
+                // the output when the pipeline is created
+            }
+        }
+    }
+
+    let Some(SchemaDefinition::ProtobufSchema(definition)) = schema.definition.as_ref() else {
+        return match connection_type {
+            ConnectionType::Source | ConnectionType::Lookup => Err(bad_request(
+                "protobuf format requires a protobuf schema be set for sources",
+            )),
+            ConnectionType::Sink => {
+                schema.inferred = Some(true);
+                Ok(schema)
+            }
+        };
+    };
+
+    let compiled =
+        proto::schema::schema_file_to_descriptor_with_dependencies(definition, &dependencies)
+            .map_err(|e| bad_request(e.to_string()))?;
+
+    let Some(Format::Protobuf(format)) = &mut schema.format else {
+        unreachable!("expand_protobuf_schema called with non-protobuf format");
+    };
+
+    let descriptor =
+        proto::schema::get_message_descriptor(&compiled, format.message_name.as_deref())
+            .map_err(|e| bad_request(e.to_string()))?;
+
+    format.message_name = Some(descriptor.full_name().to_string());
+    format.compiled_schema = Some(compiled);
+    format.definition = Some(definition.clone());
+
+    if format.into_unstructured_json {
+        schema.fields = vec![raw_schema().field(0).clone().try_into().unwrap()];
+        return Ok(schema);
+    }
+
+    let fields: Result<_, String> = proto::schema::protobuf_to_arrow(&descriptor)
+        .map_err(|e| bad_request(format!("Invalid protobuf schema: {}", e)))?
         .fields
         .into_iter()
         .map(|f| (**f).clone().try_into())
@@ -550,7 +667,7 @@ async fn expand_json_schema(
         let schema_response = get_schema(connector, table_config, profile_config).await?;
 
         match connection_type {
-            ConnectionType::Source => {
+            ConnectionType::Source | ConnectionType::Lookup => {
                 let schema_response = schema_response.ok_or_else(|| bad_request(
                     "No schema was found; ensure that the topic exists and has a value schema configured in the schema registry".to_string()))?;
 
@@ -560,7 +677,7 @@ async fn expand_json_schema(
                         schema_response.schema_type
                     )));
                 }
-                confluent_schema_id.replace(schema_response.version);
+                confluent_schema_id.replace(schema_response.id);
 
                 schema.definition = Some(SchemaDefinition::JsonSchema(schema_response.schema));
             }
@@ -597,6 +714,27 @@ async fn get_schema(
     table_config: &Value,
     profile_config: &Value,
 ) -> Result<Option<ConfluentSchemaSubjectResponse>, ErrorResp> {
+    get_schema_registry(connector, table_config, profile_config)?
+        .get_schema_for_version(None)
+        .await
+        .map_err(schema_registry_error)
+}
+
+fn schema_registry_error(e: anyhow::Error) -> ErrorResp {
+    bad_request(format!(
+        "failed to fetch schemas from schema repository: {}",
+        e.chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ")
+    ))
+}
+
+fn get_schema_registry(
+    connector: &str,
+    table_config: &Value,
+    profile_config: &Value,
+) -> Result<ConfluentSchemaRegistry, ErrorResp> {
     let profile: KafkaConfig = match connector {
         "kafka" => {
             // we unwrap here because this should already have been validated
@@ -628,7 +766,7 @@ async fn get_schema(
         ));
     };
 
-    let resolver = ConfluentSchemaRegistry::new(
+    ConfluentSchemaRegistry::new(
         &endpoint,
         &table.subject(),
         api_key.clone(),
@@ -639,16 +777,6 @@ async fn get_schema(
             "failed to fetch schemas from schema repository: {}",
             e
         ))
-    })?;
-
-    resolver.get_schema_for_version(None).await.map_err(|e| {
-        bad_request(format!(
-            "failed to fetch schemas from schema repository: {}",
-            e.chain()
-                .map(|e| e.to_string())
-                .collect::<Vec<_>>()
-                .join(": ")
-        ))
     })
 }
 
@@ -677,6 +805,18 @@ pub(crate) async fn test_schema(
                 Ok(())
             }
         }
+        SchemaDefinition::ProtobufSchema(schema) => {
+            let message_name = match &req.format {
+                Some(Format::Protobuf(format)) => format.message_name.as_deref(),
+                _ => None,
+            };
+
+            proto::schema::schema_file_to_descriptor(&schema)
+                .and_then(|compiled| proto::schema::get_message_descriptor(&compiled, message_name))
+                .and_then(|descriptor| proto::schema::protobuf_to_arrow(&descriptor))
+                .map(|_| ())
+                .map_err(|e| bad_request(e.to_string()))
+        }
         _ => {
             // TODO: add testing for other schema types
             Ok(())
@@ -155,6 +155,7 @@ async fn compile_sql<'a>(
         schema_provider,
         SqlConfig {
             default_parallelism: parallelism,
+            ..Default::default()
         },
     )
     .await
@@ -170,6 +171,18 @@ fn set_parallelism(program: &mut LogicalProgram, parallelism: usize) {
     }
 }
 
+/// Finds the id that the user-provided protobuf schema `definition` is registered under for the
+/// subject; the registry does the comparison, so formatting differences don't matter
+async fn find_protobuf_schema_id(
+    schema_registry: &ConfluentSchemaRegistry,
+    definition: &str,
+) -> anyhow::Result<Option<u32>> {
+    Ok(schema_registry
+        .lookup_schema(definition, ConfluentSchemaType::Protobuf)
+        .await?
+        .map(|registered| registered.id))
+}
+
 #[allow(unused)]
 async fn try_register_confluent_schema(
     sink: &mut ConnectorOp,
@@ -222,6 +235,32 @@ async fn try_register_confluent_schema(
                 config.format = Some(Format::Json(json))
             }
         }
+        Some(Format::Protobuf(mut proto)) => {
+            if proto.confluent_schema_registry && proto.schema_id.is_none() {
+                let id = if let Some(definition) = &proto.definition {
+                    // the user provided a schema, so we write with the id it's registered under
+                    find_protobuf_schema_id(&schema_registry, definition)
+                        .await?
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "the protobuf schema for this sink is not registered for subject \
+                                '{}'; register the schema or remove the schema definition to \
+                                have one generated",
+                                table.subject()
+                            )
+                        })?
+                } else {
+                    let proto_schema = ArrowSerializer::protobuf_schema(schema)?;
+
+                    schema_registry
+                        .write_schema(proto_schema, ConfluentSchemaType::Protobuf)
+                        .await? as u32
+                };
+
+                proto.schema_id = Some(id);
+                config.format = Some(Format::Protobuf(proto))
+            }
+        }
         _ => {
             // unsupported for schema registry
         }
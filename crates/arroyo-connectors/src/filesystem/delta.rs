@@ -6,12 +6,15 @@ use std::collections::HashMap;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
+use arroyo_rpc::formats::Format;
 use arroyo_rpc::OperatorConfig;
 
+use crate::filesystem::source::FileSystemSourceFunc;
 use crate::filesystem::{
-    file_system_sink_from_options, CommitStyle, FileSystemTable, FormatSettings, TableType,
+    file_system_sink_from_options, get_storage_url_and_options, CommitStyle, FileSystemTable,
+    FormatSettings, TableType,
 };
-use crate::EmptyConfig;
+use crate::{pull_option_to_u64, EmptyConfig};
 
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
@@ -36,9 +39,9 @@ impl Connector for DeltaLakeConnector {
             id: "delta".to_string(),
             name: "Delta Lake".to_string(),
             icon: "".to_string(),
-            description: "Write to a Delta Lake table".to_string(),
+            description: "Read from or write to a Delta Lake table".to_string(),
             enabled: true,
-            source: false,
+            source: true,
             sink: true,
             testing: false,
             hidden: true,
@@ -48,6 +51,10 @@ impl Connector for DeltaLakeConnector {
         }
     }
 
+    fn supports_filter_pushdown(&self) -> bool {
+        true
+    }
+
     fn test(
         &self,
         _: &str,
@@ -66,8 +73,11 @@ impl Connector for DeltaLakeConnector {
         });
     }
 
-    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
-        ConnectionType::Source
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.table_type {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
     }
 
     fn from_config(
@@ -78,37 +88,52 @@ impl Connector for DeltaLakeConnector {
         table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<arroyo_operator::connector::Connection> {
-        let TableType::Sink {
-            write_path,
-            file_settings,
-            format_settings,
-            ..
-        } = &table.table_type
-        else {
-            bail!("Delta Lake connector only supports sink tables");
-        };
-        // confirm commit style is DeltaLake
-        if let Some(CommitStyle::DeltaLake) = file_settings
-            .as_ref()
-            .ok_or_else(|| anyhow!("no file_settings"))?
-            .commit_style
-        {
-            // ok
-        } else {
-            bail!("commit_style must be DeltaLake");
-        }
+        let (description, connection_type) = match &table.table_type {
+            TableType::Source { .. } => {
+                // the data files of a Delta table are always parquet
+                if !matches!(
+                    schema.and_then(|s| s.format.as_ref()),
+                    Some(Format::Parquet(_))
+                ) {
+                    bail!("Delta Lake source only supports Parquet format");
+                }
+                ("DeltaLake<Parquet>".to_string(), ConnectionType::Source)
+            }
+            TableType::Sink {
+                write_path,
+                file_settings,
+                format_settings,
+                ..
+            } => {
+                // confirm commit style is DeltaLake
+                if let Some(CommitStyle::DeltaLake) = file_settings
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no file_settings"))?
+                    .commit_style
+                {
+                    // ok
+                } else {
+                    bail!("commit_style must be DeltaLake");
+                }
 
-        let backend_config = BackendConfig::parse_url(write_path, true)?;
-        let is_local = backend_config.is_local();
-        let description = match (&format_settings, is_local) {
-            (Some(FormatSettings::Parquet { .. }), true) => "LocalDeltaLake<Parquet>".to_string(),
-            (Some(FormatSettings::Parquet { .. }), false) => "DeltaLake<Parquet>".to_string(),
-            _ => bail!("Delta Lake sink only supports Parquet format"),
+                let backend_config = BackendConfig::parse_url(write_path, true)?;
+                let is_local = backend_config.is_local();
+                let description = match (&format_settings, is_local) {
+                    (Some(FormatSettings::Parquet { .. }), true) => {
+                        "LocalDeltaLake<Parquet>".to_string()
+                    }
+                    (Some(FormatSettings::Parquet { .. }), false) => {
+                        "DeltaLake<Parquet>".to_string()
+                    }
+                    _ => bail!("Delta Lake sink only supports Parquet format"),
+                };
+                (description, ConnectionType::Sink)
+            }
         };
 
         let schema = schema
             .map(|s| s.to_owned())
-            .ok_or_else(|| anyhow!("no schema defined for Delta Lake sink"))?;
+            .ok_or_else(|| anyhow!("no schema defined for Delta Lake connection"))?;
 
         let format = schema
             .format
@@ -123,13 +148,14 @@ impl Connector for DeltaLakeConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
             id,
             connector: self.name(),
             name: name.to_string(),
-            connection_type: ConnectionType::Sink,
+            connection_type,
             schema,
             config: serde_json::to_string(&config).unwrap(),
             description,
@@ -143,7 +169,47 @@ impl Connector for DeltaLakeConnector {
         schema: Option<&ConnectionSchema>,
         _profile: Option<&ConnectionProfile>,
     ) -> anyhow::Result<Connection> {
-        let table = file_system_sink_from_options(options, schema, CommitStyle::DeltaLake)?;
+        let table = match options.remove("type").as_deref() {
+            Some("source") => {
+                let (path, storage_options) = get_storage_url_and_options(options)?;
+                let monitor_interval_secs =
+                    pull_option_to_u64("source.monitor_interval_secs", options)?
+                        .map(|t| t.try_into())
+                        .transpose()
+                        .map_err(|_| {
+                            anyhow!("source.monitor_interval_secs must be greater than 0")
+                        })?;
+                let read_snapshot = options
+                    .remove("source.read_snapshot")
+                    .map(|t| t.parse::<bool>())
+                    .transpose()
+                    .map_err(|_| anyhow!("source.read_snapshot must be 'true' or 'false'"))?;
+                let ignore_changes = options
+                    .remove("source.ignore_changes")
+                    .map(|t| t.parse::<bool>())
+                    .transpose()
+                    .map_err(|_| anyhow!("source.ignore_changes must be 'true' or 'false'"))?;
+
+                FileSystemTable {
+                    table_type: TableType::Source {
+                        path,
+                        storage_options,
+                        compression_format: None,
+                        regex_pattern: None,
+                        // partition columns are read from the table metadata
+                        partition_fields: vec![],
+                        monitor_interval_secs,
+                        file_retention_secs: None,
+                        read_snapshot,
+                        ignore_changes,
+                    },
+                }
+            }
+            Some("sink") | None => {
+                file_system_sink_from_options(options, schema, CommitStyle::DeltaLake)?
+            }
+            Some(t) => bail!("unknown type: {}", t),
+        };
 
         self.from_config(None, name, EmptyConfig {}, table, schema)
     }
@@ -161,7 +227,18 @@ impl Connector for DeltaLakeConnector {
             ..
         } = &table.table_type
         else {
-            bail!("Delta Lake connector only supports sink tables");
+            return Ok(OperatorNode::from_source(Box::new(FileSystemSourceFunc {
+                table: table.table_type.clone(),
+                format: config
+                    .format
+                    .ok_or_else(|| anyhow!("format required for Delta Lake source"))?,
+                framing: config.framing.clone(),
+                bad_data: config.bad_data.clone(),
+                filters: config.filters.clone(),
+                file_states: HashMap::new(),
+                delta: true,
+                delta_version: None,
+            })));
         };
         // confirm commit style is DeltaLake
         if let Some(CommitStyle::DeltaLake) = file_settings
@@ -0,0 +1,205 @@
+use anyhow::{anyhow, bail};
+use arroyo_operator::connector::Connection;
+use arroyo_storage::BackendConfig;
+use std::collections::HashMap;
+
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::OperatorConfig;
+
+use crate::filesystem::{
+    file_system_sink_from_options, CatalogType, CommitStyle, FileSettings, FileSystemTable,
+    FormatSettings, TableType,
+};
+use crate::EmptyConfig;
+
+use arroyo_operator::connector::Connector;
+use arroyo_operator::operator::OperatorNode;
+
+use super::sink::{iceberg::IcebergSchema, LocalParquetFileSystemSink, ParquetFileSystemSink};
+
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+
+fn validate_file_settings(file_settings: Option<&FileSettings>) -> anyhow::Result<()> {
+    let file_settings = file_settings.ok_or_else(|| anyhow!("no file_settings"))?;
+    let Some(CommitStyle::Iceberg) = file_settings.commit_style else {
+        bail!("commit_style must be Iceberg");
+    };
+
+    // data files are added to the table's unpartitioned spec, so they can't be split up by
+    // partition
+    if file_settings.partitioning.is_some() {
+        bail!("Iceberg sink does not support partitioning");
+    }
+
+    let settings = file_settings
+        .iceberg
+        .as_ref()
+        .ok_or_else(|| anyhow!("Iceberg sink requires iceberg settings"))?;
+    if settings.catalog_type == CatalogType::Rest
+        && (settings.rest_uri.is_none()
+            || settings.namespace.is_none()
+            || settings.table_name.is_none())
+    {
+        bail!("a REST catalog requires a URI, namespace, and table name");
+    }
+
+    Ok(())
+}
+
+pub struct IcebergConnector {}
+
+impl Connector for IcebergConnector {
+    type ProfileT = EmptyConfig;
+
+    type TableT = FileSystemTable;
+
+    fn name(&self) -> &'static str {
+        "iceberg"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "iceberg".to_string(),
+            name: "Apache Iceberg".to_string(),
+            icon: "".to_string(),
+            description: "Write to an Apache Iceberg table".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: false,
+            hidden: true,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_owned(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(message).await.unwrap();
+        });
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<arroyo_operator::connector::Connection> {
+        let TableType::Sink {
+            write_path,
+            file_settings,
+            format_settings,
+            ..
+        } = &table.table_type
+        else {
+            bail!("Iceberg connector only supports sink tables");
+        };
+        validate_file_settings(file_settings.as_ref())?;
+
+        let backend_config = BackendConfig::parse_url(write_path, true)?;
+        let is_local = backend_config.is_local();
+        let description = match (&format_settings, is_local) {
+            (Some(FormatSettings::Parquet { .. }), true) => "LocalIceberg<Parquet>".to_string(),
+            (Some(FormatSettings::Parquet { .. }), false) => "Iceberg<Parquet>".to_string(),
+            _ => bail!("Iceberg sink only supports Parquet format"),
+        };
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Iceberg sink"))?;
+
+        // make sure that all of the columns can be written to Iceberg
+        IcebergSchema::new(&schema.arroyo_schema().schema_without_timestamp())?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for Iceberg connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            format: Some(format),
+            bad_data: schema.bad_data.clone(),
+            framing: schema.framing.clone(),
+            ..Default::default()
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        _profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let table = file_system_sink_from_options(options, schema, CommitStyle::Iceberg)?;
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+
+    fn make_operator(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        let TableType::Sink {
+            write_path,
+            file_settings,
+            format_settings,
+            ..
+        } = &table.table_type
+        else {
+            bail!("Iceberg connector only supports sink tables");
+        };
+        validate_file_settings(file_settings.as_ref())?;
+
+        let backend_config = BackendConfig::parse_url(write_path, true)?;
+        let is_local = backend_config.is_local();
+        match (&format_settings, is_local) {
+            (Some(FormatSettings::Parquet { .. }), true) => {
+                Ok(OperatorNode::from_operator(Box::new(
+                    LocalParquetFileSystemSink::new(write_path.to_string(), table, config),
+                )))
+            }
+            (Some(FormatSettings::Parquet { .. }), false) => Ok(OperatorNode::from_operator(
+                Box::new(ParquetFileSystemSink::new(table, config)),
+            )),
+            _ => bail!("Iceberg sink only supports Parquet format"),
+        }
+    }
+}
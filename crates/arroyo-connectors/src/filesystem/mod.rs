@@ -1,4 +1,6 @@
 pub mod delta;
+pub mod iceberg;
+mod pruning;
 mod sink;
 mod source;
 
@@ -10,13 +12,14 @@ use typify::import_types;
 
 use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{
-    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+    ConnectionProfile, ConnectionSchema, ConnectionType, FieldType, PrimitiveType,
+    TestSourceMessage,
 };
 use arroyo_rpc::formats::Format;
 use arroyo_rpc::OperatorConfig;
 use serde::{Deserialize, Serialize};
 
-use crate::{pull_opt, pull_option_to_i64, EmptyConfig};
+use crate::{pull_opt, pull_option_to_i64, pull_option_to_u64, EmptyConfig};
 
 use crate::filesystem::source::FileSystemSourceFunc;
 use arroyo_operator::connector::Connector;
@@ -59,6 +62,10 @@ impl Connector for FileSystemConnector {
         }
     }
 
+    fn supports_filter_pushdown(&self) -> bool {
+        true
+    }
+
     fn test(
         &self,
         _: &str,
@@ -138,6 +145,13 @@ impl Connector for FileSystemConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for FileSystem connection"))?;
 
+        if matches!(
+            (&connection_type, &format),
+            (ConnectionType::Source, Format::Protobuf(_))
+        ) {
+            bail!("protobuf is not supported for FileSystem sources");
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -145,6 +159,7 @@ impl Connector for FileSystemConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -174,6 +189,21 @@ impl Connector for FileSystemConnector {
                     .transpose()?
                     .unwrap_or(CompressionFormat::None);
                 let matching_pattern = options.remove("source.regex-pattern");
+                let partition_fields = source_partition_fields_from_options(options, schema)?;
+                let monitor_interval_secs =
+                    pull_option_to_u64("source.monitor_interval_secs", options)?
+                        .map(|t| t.try_into())
+                        .transpose()
+                        .map_err(|_| {
+                            anyhow!("source.monitor_interval_secs must be greater than 0")
+                        })?;
+                let file_retention_secs =
+                    pull_option_to_u64("source.file_retention_secs", options)?
+                        .map(|t| t.try_into())
+                        .transpose()
+                        .map_err(|_| {
+                            anyhow!("source.file_retention_secs must be greater than 0")
+                        })?;
                 self.from_config(
                     None,
                     name,
@@ -184,6 +214,11 @@ impl Connector for FileSystemConnector {
                             storage_options,
                             compression_format: Some(compression_format),
                             regex_pattern: matching_pattern,
+                            partition_fields,
+                            monitor_interval_secs,
+                            file_retention_secs,
+                            read_snapshot: None,
+                            ignore_changes: None,
                         },
                     },
                     schema,
@@ -214,7 +249,10 @@ impl Connector for FileSystemConnector {
                         .ok_or_else(|| anyhow!("format required for FileSystem source"))?,
                     framing: config.framing.clone(),
                     bad_data: config.bad_data.clone(),
+                    filters: config.filters.clone(),
                     file_states: HashMap::new(),
+                    delta: false,
+                    delta_version: None,
                 })))
             }
             TableType::Sink {
@@ -264,6 +302,62 @@ fn get_storage_url_and_options(
     Ok((storage_url, storage_options))
 }
 
+/// Parses the partition fields of a source, which must be top-level columns with types that can
+/// be parsed from the text of a path
+fn source_partition_fields_from_options(
+    opts: &mut HashMap<String, String>,
+    schema: Option<&ConnectionSchema>,
+) -> Result<Vec<String>> {
+    let Some(fields) = opts.remove("partition_fields") else {
+        return Ok(vec![]);
+    };
+
+    fields
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .map(|name| {
+            let field = schema
+                .and_then(|s| s.fields.iter().find(|f| f.field_name == name))
+                .ok_or_else(|| anyhow!("partition field '{}' is not a column of the table", name))?;
+
+            match &field.field_type.r#type {
+                FieldType::Primitive(
+                    PrimitiveType::String
+                    | PrimitiveType::Int32
+                    | PrimitiveType::Int64
+                    | PrimitiveType::F64
+                    | PrimitiveType::Bool,
+                ) => Ok(name),
+                t => bail!(
+                    "partition field '{}' has type {:?}, but partition fields must be TEXT, INT, BIGINT, DOUBLE or BOOLEAN",
+                    name,
+                    t
+                ),
+            }
+        })
+        .collect()
+}
+
+fn iceberg_settings_from_options(opts: &mut HashMap<String, String>) -> Result<IcebergSettings> {
+    let catalog_type = match opts.remove("catalog.type").as_deref() {
+        Some("hadoop") | None => CatalogType::Hadoop,
+        Some("rest") => CatalogType::Rest,
+        Some(t) => bail!(
+            "unknown catalog.type '{}'; expected one of 'hadoop' or 'rest'",
+            t
+        ),
+    };
+
+    Ok(IcebergSettings {
+        catalog_type,
+        rest_uri: opts.remove("catalog.uri"),
+        warehouse: opts.remove("catalog.warehouse"),
+        token: opts.remove("catalog.token"),
+        namespace: opts.remove("namespace"),
+        table_name: opts.remove("table_name"),
+    })
+}
+
 pub fn file_system_sink_from_options(
     opts: &mut std::collections::HashMap<String, String>,
     schema: Option<&ConnectionSchema>,
@@ -312,6 +406,11 @@ pub fn file_system_sink_from_options(
         None
     };
 
+    let iceberg = match commit_style {
+        CommitStyle::Iceberg => Some(iceberg_settings_from_options(opts)?),
+        CommitStyle::Direct | CommitStyle::DeltaLake => None,
+    };
+
     let file_settings = Some(FileSettings {
         inactivity_rollover_seconds,
         max_parts,
@@ -320,6 +419,7 @@ pub fn file_system_sink_from_options(
         target_part_size,
         partitioning,
         commit_style: Some(commit_style),
+        iceberg,
         file_naming,
     });
 
@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow::array::{
+    new_null_array, Array, ArrayRef, BooleanArray, RecordBatch, StringArray, UInt64Array,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arroyo_operator::operator::Registry;
+use datafusion::common::{Column, DataFusionError, Result as DFResult, ScalarValue};
+use datafusion::physical_expr::utils::collect_columns;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_optimizer::pruning::{PruningPredicate, PruningStatistics};
+use datafusion_proto::physical_plan::from_proto::parse_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+use datafusion_proto::protobuf::PhysicalExprNode;
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::statistics::Statistics;
+use parquet::schema::types::SchemaDescriptor;
+use prost::Message;
+use tracing::warn;
+
+/// The directory name that hive uses for null partition values
+const HIVE_DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Parses the hive-style `key=value` segments of a path (like `dt=2024-01-01/hour=03/data.json`)
+/// into the values of the partition fields they contain
+pub fn partition_values<'a>(
+    path: &'a str,
+    partition_fields: &[String],
+) -> HashMap<&'a str, Option<&'a str>> {
+    path.split('/')
+        .filter_map(|segment| segment.split_once('='))
+        .filter(|(key, _)| partition_fields.iter().any(|f| f == key))
+        .map(|(key, value)| (key, (value != HIVE_DEFAULT_PARTITION).then_some(value)))
+        .collect()
+}
+
+/// Builds a column of `num_rows` copies of a partition value, cast to the type of its field.
+/// Values that can't be cast are null.
+pub fn partition_column(
+    value: Option<&str>,
+    data_type: &DataType,
+    num_rows: usize,
+) -> DFResult<ArrayRef> {
+    let value = cast(&StringArray::from(vec![value]), data_type)?;
+    ScalarValue::try_from_array(&value, 0)?.to_array_of_size(num_rows)
+}
+
+/// The filters that were pushed down to the source by the planner, which are used to skip
+/// partitions that can't match when listing and parquet row groups that can't match when reading
+pub struct SourceFilters {
+    // the schema of the table, with all fields nullable so that rows of partition values (where
+    // everything else is null) can be evaluated against it
+    schema: SchemaRef,
+    partition_fields: Vec<String>,
+    // filters that only refer to partition fields, along with the names of those fields
+    partition_filters: Vec<(Arc<dyn PhysicalExpr>, Vec<String>)>,
+    row_group_predicates: Vec<PruningPredicate>,
+}
+
+impl SourceFilters {
+    pub fn new(filters: &[Vec<u8>], schema: &Schema, partition_fields: &[String]) -> Self {
+        let schema = Arc::new(Schema::new(
+            schema
+                .fields()
+                .iter()
+                .map(|f| f.as_ref().clone().with_nullable(true))
+                .collect::<Vec<Field>>(),
+        ));
+
+        let registry = Registry::default();
+        let mut partition_filters = vec![];
+        let mut row_group_predicates = vec![];
+
+        for filter in filters {
+            let expr = match PhysicalExprNode::decode(&mut filter.as_slice())
+                .map_err(|e| DataFusionError::External(Box::new(e)))
+                .and_then(|node| {
+                    parse_physical_expr(
+                        &node,
+                        &registry,
+                        &schema,
+                        &DefaultPhysicalExtensionCodec {},
+                    )
+                }) {
+                Ok(expr) => expr,
+                Err(e) => {
+                    warn!("ignoring filter that could not be parsed: {:?}", e);
+                    continue;
+                }
+            };
+
+            let columns: Vec<String> = collect_columns(&expr)
+                .into_iter()
+                .map(|c| c.name().to_string())
+                .collect();
+
+            if columns.iter().all(|c| partition_fields.contains(c)) {
+                partition_filters.push((expr.clone(), columns));
+            }
+
+            if let Ok(predicate) = PruningPredicate::try_new(expr, schema.clone()) {
+                if !predicate.always_true() {
+                    row_group_predicates.push(predicate);
+                }
+            }
+        }
+
+        Self {
+            schema,
+            partition_fields: partition_fields.to_vec(),
+            partition_filters,
+            row_group_predicates,
+        }
+    }
+
+    pub fn has_partition_filters(&self) -> bool {
+        !self.partition_filters.is_empty()
+    }
+
+    /// Returns false if no row with these partition values can match the filters. If `complete`
+    /// is false, only some of the partition values are known (as for a directory part-way down
+    /// the hierarchy), so only filters over those are checked; otherwise partitions that are
+    /// missing are null.
+    pub fn may_match(&self, values: &HashMap<&str, Option<&str>>, complete: bool) -> bool {
+        let filters: Vec<_> = self
+            .partition_filters
+            .iter()
+            .filter(|(_, columns)| {
+                complete || columns.iter().all(|c| values.contains_key(c.as_str()))
+            })
+            .collect();
+
+        if filters.is_empty() {
+            return true;
+        }
+
+        let columns: DFResult<Vec<ArrayRef>> = self
+            .schema
+            .fields()
+            .iter()
+            .map(|f| match values.get(f.name().as_str()) {
+                Some(value) if self.partition_fields.contains(f.name()) => {
+                    partition_column(*value, f.data_type(), 1)
+                }
+                _ => Ok(new_null_array(f.data_type(), 1)),
+            })
+            .collect();
+
+        let Ok(batch) = columns
+            .map_err(|e| e.to_string())
+            .and_then(|c| RecordBatch::try_new(self.schema.clone(), c).map_err(|e| e.to_string()))
+        else {
+            return true;
+        };
+
+        filters.iter().all(|(filter, _)| {
+            match filter
+                .evaluate(&batch)
+                .and_then(|v| v.into_array(1))
+                .map(|v| v.as_any().downcast_ref::<BooleanArray>().cloned())
+            {
+                // a null result filters out the row just as false does
+                Ok(Some(result)) => result.is_valid(0) && result.value(0),
+                _ => true,
+            }
+        })
+    }
+
+    /// Returns the row groups of a parquet file that may contain rows matching the filters, or
+    /// None if all of them do
+    pub fn row_groups(
+        &self,
+        row_groups: &[RowGroupMetaData],
+        parquet_schema: &SchemaDescriptor,
+    ) -> Option<Vec<usize>> {
+        if self.row_group_predicates.is_empty() {
+            return None;
+        }
+
+        let statistics = RowGroupStatistics {
+            row_groups,
+            parquet_schema,
+            schema: &self.schema,
+        };
+
+        let mut matches = vec![true; row_groups.len()];
+        for predicate in &self.row_group_predicates {
+            match predicate.prune(&statistics) {
+                Ok(result) => {
+                    for (m, r) in matches.iter_mut().zip(result) {
+                        *m &= r;
+                    }
+                }
+                Err(e) => {
+                    warn!("failed to prune row groups: {:?}", e);
+                }
+            }
+        }
+
+        if matches.iter().all(|m| *m) {
+            return None;
+        }
+
+        Some(
+            matches
+                .into_iter()
+                .enumerate()
+                .filter(|(_, m)| *m)
+                .map(|(i, _)| i)
+                .collect(),
+        )
+    }
+}
+
+/// Exposes the min/max statistics of the row groups of a parquet file for the top-level columns
+/// of the table with simple types
+struct RowGroupStatistics<'a> {
+    row_groups: &'a [RowGroupMetaData],
+    parquet_schema: &'a SchemaDescriptor,
+    schema: &'a Schema,
+}
+
+impl<'a> RowGroupStatistics<'a> {
+    fn statistics(&self, column: &Column) -> Option<(&Field, Vec<Option<&Statistics>>)> {
+        let field = self.schema.field_with_name(&column.name).ok()?;
+        let index = self
+            .parquet_schema
+            .columns()
+            .iter()
+            .position(|c| c.path().parts().len() == 1 && c.name() == column.name)?;
+
+        Some((
+            field,
+            self.row_groups
+                .iter()
+                .map(|rg| rg.column(index).statistics())
+                .collect(),
+        ))
+    }
+
+    fn values(&self, column: &Column, min: bool) -> Option<ArrayRef> {
+        let (field, statistics) = self.statistics(column)?;
+        let null = ScalarValue::try_from(field.data_type()).ok()?;
+        let values = statistics.into_iter().map(|s| {
+            s.and_then(|s| statistic_value(s, field.data_type(), min))
+                .unwrap_or_else(|| null.clone())
+        });
+        ScalarValue::iter_to_array(values).ok()
+    }
+}
+
+/// Converts the min or max of a parquet statistic into a value of the given type, if the
+/// statistic has a representation for that type
+fn statistic_value(
+    statistics: &Statistics,
+    data_type: &DataType,
+    min: bool,
+) -> Option<ScalarValue> {
+    if !statistics.has_min_max_set() {
+        return None;
+    }
+
+    macro_rules! pick {
+        ($s:expr) => {
+            if min {
+                $s.min()
+            } else {
+                $s.max()
+            }
+        };
+    }
+
+    Some(match (statistics, data_type) {
+        (Statistics::Boolean(s), DataType::Boolean) => ScalarValue::Boolean(Some(*pick!(s))),
+        (Statistics::Int32(s), DataType::Int32) => ScalarValue::Int32(Some(*pick!(s))),
+        (Statistics::Int64(s), DataType::Int64) => ScalarValue::Int64(Some(*pick!(s))),
+        (Statistics::Float(s), DataType::Float32) => ScalarValue::Float32(Some(*pick!(s))),
+        (Statistics::Double(s), DataType::Float64) => ScalarValue::Float64(Some(*pick!(s))),
+        (Statistics::ByteArray(s), DataType::Utf8) => {
+            ScalarValue::Utf8(Some(std::str::from_utf8(pick!(s).data()).ok()?.to_string()))
+        }
+        _ => return None,
+    })
+}
+
+impl<'a> PruningStatistics for RowGroupStatistics<'a> {
+    fn min_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.values(column, true)
+    }
+
+    fn max_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.values(column, false)
+    }
+
+    fn num_containers(&self) -> usize {
+        self.row_groups.len()
+    }
+
+    fn null_counts(&self, column: &Column) -> Option<ArrayRef> {
+        let (_, statistics) = self.statistics(column)?;
+        Some(Arc::new(
+            statistics
+                .into_iter()
+                .map(|s| s.map(|s| s.null_count()))
+                .collect::<UInt64Array>(),
+        ))
+    }
+
+    fn row_counts(&self, _column: &Column) -> Option<ArrayRef> {
+        Some(Arc::new(
+            self.row_groups
+                .iter()
+                .map(|rg| Some(rg.num_rows() as u64))
+                .collect::<UInt64Array>(),
+        ))
+    }
+
+    fn contained(&self, _column: &Column, _values: &HashSet<ScalarValue>) -> Option<BooleanArray> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{partition_values, SourceFilters};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::logical_expr::Operator;
+    use datafusion::physical_expr::expressions::{col, lit, BinaryExpr};
+    use datafusion::scalar::ScalarValue;
+    use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
+    use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+    use prost::Message;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_partition_values() {
+        let fields = vec!["dt".to_string(), "hour".to_string()];
+        assert_eq!(
+            partition_values("logs/dt=2024-01-01/hour=03/part-1.json", &fields),
+            HashMap::from([("dt", Some("2024-01-01")), ("hour", Some("03"))])
+        );
+        assert_eq!(
+            partition_values(
+                "logs/dt=__HIVE_DEFAULT_PARTITION__/x=1/part-1.json",
+                &fields
+            ),
+            HashMap::from([("dt", None)])
+        );
+    }
+
+    #[test]
+    fn test_partition_pruning() {
+        let schema = Schema::new(vec![
+            Field::new("dt", DataType::Utf8, true),
+            Field::new("hour", DataType::Int32, true),
+            Field::new("value", DataType::Int64, false),
+        ]);
+
+        let filter = |name: &str, value: ScalarValue| {
+            let expr = Arc::new(BinaryExpr::new(
+                col(name, &schema).unwrap(),
+                Operator::Eq,
+                lit(value),
+            ));
+            serialize_physical_expr(expr, &DefaultPhysicalExtensionCodec {})
+                .unwrap()
+                .encode_to_vec()
+        };
+
+        let filters = SourceFilters::new(
+            &[
+                filter("dt", ScalarValue::Utf8(Some("2024-01-01".to_string()))),
+                filter("hour", ScalarValue::Int32(Some(3))),
+                filter("value", ScalarValue::Int64(Some(5))),
+            ],
+            &schema,
+            &["dt".to_string(), "hour".to_string()],
+        );
+
+        assert!(filters.has_partition_filters());
+
+        let fields = ["dt".to_string(), "hour".to_string()];
+        let check = |path: &str, complete: bool| {
+            filters.may_match(&partition_values(path, &fields), complete)
+        };
+
+        assert!(check("logs/dt=2024-01-01", false));
+        assert!(!check("logs/dt=2024-01-02", false));
+        assert!(check("logs/dt=2024-01-01/hour=03", false));
+        assert!(!check("logs/dt=2024-01-01/hour=04", false));
+        // the hour isn't known yet, so only the date can be checked
+        assert!(check("logs/dt=2024-01-01/data.json", false));
+        // but files must have every partition value
+        assert!(!check("logs/dt=2024-01-01/data.json", true));
+        assert!(check("logs/dt=2024-01-01/hour=3/data.json", true));
+    }
+}
@@ -91,7 +91,7 @@ async fn create_new_table(
         .map_err(Into::into)
 }
 
-async fn configure_storage_options(
+pub(crate) async fn configure_storage_options(
     table_path: &str,
     storage_provider: Arc<StorageProvider>,
 ) -> Result<HashMap<String, String>> {
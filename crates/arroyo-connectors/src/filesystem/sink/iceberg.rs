@@ -0,0 +1,1531 @@
+use super::FinishedFile;
+use crate::filesystem::{CatalogType, CommitStyle, FileSystemTable, IcebergSettings, TableType};
+use anyhow::{anyhow, bail, Context, Result};
+use apache_avro::types::Value as AvroValue;
+use arrow::array::{Array, ArrayRef, AsArray, ListArray, RecordBatch, StructArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use arroyo_storage::StorageProvider;
+use arroyo_types::to_millis;
+use bytes::Bytes;
+use object_store::{path::Path, PutMode};
+use parquet::arrow::async_reader::{AsyncFileReader, ParquetObjectReader};
+use parquet::arrow::PARQUET_FIELD_ID_META_KEY;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const COMMIT_ATTEMPTS: usize = 5;
+const MAIN_BRANCH: &str = "main";
+
+/// Summary property recording the first data file added by a snapshot, used to find out whether
+/// a commit that is being retried after a failure already made it into the table
+const COMMIT_FILE_PROPERTY: &str = "arroyo.commit-file";
+
+const MANIFEST_ENTRY_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "manifest_entry",
+  "fields": [
+    {"name": "status", "type": "int", "field-id": 0},
+    {"name": "snapshot_id", "type": ["null", "long"], "default": null, "field-id": 1},
+    {"name": "sequence_number", "type": ["null", "long"], "default": null, "field-id": 3},
+    {"name": "file_sequence_number", "type": ["null", "long"], "default": null, "field-id": 4},
+    {"name": "data_file", "field-id": 2, "type": {
+      "type": "record",
+      "name": "r2",
+      "fields": [
+        {"name": "content", "type": "int", "field-id": 134},
+        {"name": "file_path", "type": "string", "field-id": 100},
+        {"name": "file_format", "type": "string", "field-id": 101},
+        {"name": "partition", "field-id": 102, "type": {"type": "record", "name": "r102", "fields": []}},
+        {"name": "record_count", "type": "long", "field-id": 103},
+        {"name": "file_size_in_bytes", "type": "long", "field-id": 104}
+      ]
+    }}
+  ]
+}"#;
+
+const MANIFEST_FILE_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "manifest_file",
+  "fields": [
+    {"name": "manifest_path", "type": "string", "field-id": 500},
+    {"name": "manifest_length", "type": "long", "field-id": 501},
+    {"name": "partition_spec_id", "type": "int", "field-id": 502},
+    {"name": "content", "type": "int", "field-id": 517},
+    {"name": "sequence_number", "type": "long", "field-id": 515},
+    {"name": "min_sequence_number", "type": "long", "field-id": 516},
+    {"name": "added_snapshot_id", "type": "long", "field-id": 503},
+    {"name": "added_files_count", "type": "int", "field-id": 504},
+    {"name": "existing_files_count", "type": "int", "field-id": 505},
+    {"name": "deleted_files_count", "type": "int", "field-id": 506},
+    {"name": "added_rows_count", "type": "long", "field-id": 512},
+    {"name": "existing_rows_count", "type": "long", "field-id": 513},
+    {"name": "deleted_rows_count", "type": "long", "field-id": 514}
+  ]
+}"#;
+
+/// The Iceberg schema for the data written by the sink, along with the Arrow schema that data is
+/// converted to before being written so that the Parquet files carry Iceberg field ids
+#[derive(Debug, Clone)]
+pub(crate) struct IcebergSchema {
+    pub(crate) arrow: SchemaRef,
+    fields: Vec<Value>,
+    last_column_id: i32,
+}
+
+impl IcebergSchema {
+    /// Assigns field ids the same way Iceberg does for new tables: all of the fields of a struct
+    /// are numbered before the fields nested within them
+    pub(crate) fn new(schema: &Schema) -> Result<Self> {
+        let mut last_column_id = 0;
+        let (fields, iceberg_fields) = convert_fields(schema.fields(), &mut last_column_id)?;
+        Ok(Self {
+            arrow: Arc::new(Schema::new(fields)),
+            fields: iceberg_fields,
+            last_column_id,
+        })
+    }
+
+    fn to_json(&self, schema_id: i32) -> Value {
+        json!({
+            "type": "struct",
+            "schema-id": schema_id,
+            "fields": self.fields,
+        })
+    }
+}
+
+/// Returns the schema that the Parquet files for this table should be written with, if the table
+/// is committed to Iceberg. The schema is checked when the Iceberg connection is planned, so this
+/// only fails if the table was created some other way.
+pub(crate) fn iceberg_schema_for_table(
+    table: &FileSystemTable,
+    schema: &Schema,
+) -> Result<Option<SchemaRef>> {
+    let TableType::Sink {
+        file_settings: Some(file_settings),
+        ..
+    } = &table.table_type
+    else {
+        return Ok(None);
+    };
+
+    if file_settings.commit_style != Some(CommitStyle::Iceberg) {
+        return Ok(None);
+    }
+
+    Ok(Some(IcebergSchema::new(schema)?.arrow))
+}
+
+fn with_field_id(field: Field, id: i32) -> Field {
+    field.with_metadata(HashMap::from([(
+        PARQUET_FIELD_ID_META_KEY.to_string(),
+        id.to_string(),
+    )]))
+}
+
+fn convert_fields(fields: &Fields, last_id: &mut i32) -> Result<(Vec<Field>, Vec<Value>)> {
+    let ids: Vec<i32> = fields
+        .iter()
+        .map(|_| {
+            *last_id += 1;
+            *last_id
+        })
+        .collect();
+
+    let mut arrow_fields = vec![];
+    let mut iceberg_fields = vec![];
+    for (field, id) in fields.iter().zip(ids) {
+        let (data_type, iceberg_type) = convert_type(field.name(), field.data_type(), last_id)?;
+        arrow_fields.push(with_field_id(
+            Field::new(field.name(), data_type, field.is_nullable()),
+            id,
+        ));
+        iceberg_fields.push(json!({
+            "id": id,
+            "name": field.name(),
+            "required": !field.is_nullable(),
+            "type": iceberg_type,
+        }));
+    }
+
+    Ok((arrow_fields, iceberg_fields))
+}
+
+/// Maps an Arrow type to the Iceberg type it's stored as, and the Arrow type that Iceberg readers
+/// expect for it in Parquet (for example, Iceberg timestamps have microsecond precision)
+fn convert_type(name: &str, data_type: &DataType, last_id: &mut i32) -> Result<(DataType, Value)> {
+    let (data_type, iceberg_type) = match data_type {
+        DataType::Boolean => (DataType::Boolean, json!("boolean")),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            (DataType::Int32, json!("int"))
+        }
+        DataType::Int64 | DataType::UInt32 => (DataType::Int64, json!("long")),
+        DataType::Float16 | DataType::Float32 => (DataType::Float32, json!("float")),
+        DataType::Float64 => (DataType::Float64, json!("double")),
+        DataType::Decimal128(precision, scale) => (
+            DataType::Decimal128(*precision, *scale),
+            json!(format!("decimal({}, {})", precision, scale)),
+        ),
+        DataType::Utf8 | DataType::LargeUtf8 => (DataType::Utf8, json!("string")),
+        DataType::Binary | DataType::LargeBinary => (DataType::Binary, json!("binary")),
+        DataType::Date32 | DataType::Date64 => (DataType::Date32, json!("date")),
+        DataType::Timestamp(_, None) => (
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            json!("timestamp"),
+        ),
+        DataType::Timestamp(_, Some(tz)) => (
+            DataType::Timestamp(TimeUnit::Microsecond, Some(tz.clone())),
+            json!("timestamptz"),
+        ),
+        DataType::Struct(fields) => {
+            let (fields, iceberg_fields) = convert_fields(fields, last_id)?;
+            (
+                DataType::Struct(fields.into()),
+                json!({
+                    "type": "struct",
+                    "fields": iceberg_fields,
+                }),
+            )
+        }
+        DataType::List(element) | DataType::LargeList(element) => {
+            *last_id += 1;
+            let id = *last_id;
+            let (data_type, element_type) = convert_type(name, element.data_type(), last_id)?;
+            let element = with_field_id(
+                Field::new(element.name(), data_type, element.is_nullable()),
+                id,
+            );
+            (
+                DataType::List(Arc::new(element.clone())),
+                json!({
+                    "type": "list",
+                    "element-id": id,
+                    "element": element_type,
+                    "element-required": !element.is_nullable(),
+                }),
+            )
+        }
+        t => bail!(
+            "field '{}' has type {}, which can't be written to Iceberg",
+            name,
+            t
+        ),
+    };
+
+    Ok((data_type, iceberg_type))
+}
+
+/// Converts a batch to the schema produced by [IcebergSchema::new]
+pub(crate) fn to_iceberg_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| conform(column, field.data_type()))
+        .collect::<Result<_>>()?;
+
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+fn conform(array: &ArrayRef, data_type: &DataType) -> Result<ArrayRef> {
+    Ok(match (array.data_type(), data_type) {
+        (DataType::Struct(_), DataType::Struct(fields)) => {
+            let array = array.as_struct();
+            let columns = array
+                .columns()
+                .iter()
+                .zip(fields)
+                .map(|(column, field)| conform(column, field.data_type()))
+                .collect::<Result<_>>()?;
+            Arc::new(StructArray::try_new(
+                fields.clone(),
+                columns,
+                array.nulls().cloned(),
+            )?)
+        }
+        (DataType::List(_), DataType::List(field)) => {
+            let array = array.as_list::<i32>();
+            Arc::new(ListArray::try_new(
+                field.clone(),
+                array.offsets().clone(),
+                conform(array.values(), field.data_type())?,
+                array.nulls().cloned(),
+            )?)
+        }
+        (DataType::LargeList(element), DataType::List(_)) => {
+            let array = cast(array, &DataType::List(element.clone()))?;
+            conform(&array, data_type)?
+        }
+        (from, to) if from == to => array.clone(),
+        (_, to) => cast(array, to)?,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PartitionSpec {
+    spec_id: i32,
+    fields: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Snapshot {
+    snapshot_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_snapshot_id: Option<i64>,
+    #[serde(default)]
+    sequence_number: i64,
+    timestamp_ms: i64,
+    manifest_list: String,
+    summary: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_id: Option<i32>,
+}
+
+/// The parts of the table metadata that we read or update on commit; everything else is carried
+/// over unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TableMetadata {
+    format_version: i32,
+    location: String,
+    #[serde(default)]
+    last_sequence_number: i64,
+    last_updated_ms: i64,
+    last_column_id: i32,
+    current_schema_id: i32,
+    schemas: Vec<Value>,
+    default_spec_id: i32,
+    partition_specs: Vec<PartitionSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_snapshot_id: Option<i64>,
+    #[serde(default)]
+    snapshots: Vec<Snapshot>,
+    #[serde(default)]
+    snapshot_log: Vec<Value>,
+    #[serde(default)]
+    metadata_log: Vec<Value>,
+    #[serde(default)]
+    refs: Map<String, Value>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+impl TableMetadata {
+    fn new(location: String, schema: &IcebergSchema) -> Self {
+        let Value::Object(other) = json!({
+            "table-uuid": Uuid::new_v4().to_string(),
+            "last-partition-id": 999,
+            "default-sort-order-id": 0,
+            "sort-orders": [{"order-id": 0, "fields": []}],
+            "properties": {},
+        }) else {
+            unreachable!()
+        };
+
+        Self {
+            format_version: 2,
+            location,
+            last_sequence_number: 0,
+            last_updated_ms: to_millis(SystemTime::now()) as i64,
+            last_column_id: schema.last_column_id,
+            current_schema_id: 0,
+            schemas: vec![schema.to_json(0)],
+            default_spec_id: 0,
+            partition_specs: vec![PartitionSpec {
+                spec_id: 0,
+                fields: vec![],
+            }],
+            current_snapshot_id: None,
+            snapshots: vec![],
+            snapshot_log: vec![],
+            metadata_log: vec![],
+            refs: Map::new(),
+            other,
+        }
+    }
+
+    fn current_snapshot(&self) -> Option<&Snapshot> {
+        // older writers use -1 for a table with no snapshots
+        let id = self.current_snapshot_id.filter(|id| *id != -1)?;
+        self.snapshots.iter().find(|s| s.snapshot_id == id)
+    }
+
+    /// Checks that data written with our schema can be added to this table
+    fn validate(&self, schema: &IcebergSchema) -> Result<()> {
+        if self.format_version != 2 {
+            bail!(
+                "only version 2 Iceberg tables are supported, but the table has format version {}",
+                self.format_version
+            );
+        }
+
+        let spec = self
+            .partition_specs
+            .iter()
+            .find(|s| s.spec_id == self.default_spec_id)
+            .ok_or_else(|| anyhow!("table metadata is missing its default partition spec"))?;
+        if !spec.fields.is_empty() {
+            bail!("the Iceberg sink does not support writing to partitioned tables");
+        }
+
+        let current = self
+            .schemas
+            .iter()
+            .find(|s| {
+                s.get("schema-id").and_then(Value::as_i64) == Some(self.current_schema_id as i64)
+            })
+            .ok_or_else(|| anyhow!("table metadata is missing its current schema"))?;
+
+        let mut table_ids = HashMap::new();
+        field_ids("", current, &mut table_ids);
+        let mut our_ids = HashMap::new();
+        field_ids("", &schema.to_json(0), &mut our_ids);
+
+        for (name, id) in our_ids {
+            match table_ids.get(&name) {
+                Some(table_id) if *table_id == id => {}
+                Some(table_id) => bail!(
+                    "field '{}' has id {} in the Iceberg table, but {} in the sink schema",
+                    name,
+                    table_id,
+                    id
+                ),
+                None => bail!("field '{}' is not in the Iceberg table's schema", name),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds a snapshot that was created by an earlier attempt at this commit
+    fn find_commit(&self, commit_file: &str) -> Option<i64> {
+        self.snapshots
+            .iter()
+            .find(|s| s.summary.get(COMMIT_FILE_PROPERTY).map(|f| f.as_str()) == Some(commit_file))
+            .map(|s| s.snapshot_id)
+    }
+
+    fn with_snapshot(&self, snapshot: &Snapshot, metadata_location: Option<&str>) -> Self {
+        let mut metadata = self.clone();
+        if let Some(location) = metadata_location {
+            metadata.metadata_log.push(json!({
+                "metadata-file": location,
+                "timestamp-ms": self.last_updated_ms,
+            }));
+        }
+        metadata.last_sequence_number = snapshot.sequence_number;
+        metadata.last_updated_ms = snapshot.timestamp_ms;
+        metadata.current_snapshot_id = Some(snapshot.snapshot_id);
+        metadata.snapshots.push(snapshot.clone());
+        metadata.snapshot_log.push(json!({
+            "snapshot-id": snapshot.snapshot_id,
+            "timestamp-ms": snapshot.timestamp_ms,
+        }));
+        metadata.refs.insert(
+            MAIN_BRANCH.to_string(),
+            json!({
+                "snapshot-id": snapshot.snapshot_id,
+                "type": "branch",
+            }),
+        );
+        metadata
+    }
+}
+
+/// Collects the ids of the fields in an Iceberg type, keyed by their dotted path
+fn field_ids(prefix: &str, iceberg_type: &Value, ids: &mut HashMap<String, i64>) {
+    match iceberg_type.get("type").and_then(Value::as_str) {
+        Some("struct") => {
+            for field in iceberg_type
+                .get("fields")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let (Some(name), Some(id)) = (
+                    field.get("name").and_then(Value::as_str),
+                    field.get("id").and_then(Value::as_i64),
+                ) else {
+                    continue;
+                };
+                let path = format!("{}{}", prefix, name);
+                ids.insert(path.clone(), id);
+                if let Some(t) = field.get("type") {
+                    field_ids(&format!("{}.", path), t, ids);
+                }
+            }
+        }
+        Some("list") => {
+            let path = format!("{}element", prefix);
+            if let Some(id) = iceberg_type.get("element-id").and_then(Value::as_i64) {
+                ids.insert(path.clone(), id);
+            }
+            if let Some(t) = iceberg_type.get("element") {
+                field_ids(&format!("{}.", path), t, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// An entry in a manifest list. Fields that were renamed between format versions accept both names.
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestFile {
+    manifest_path: String,
+    manifest_length: i64,
+    partition_spec_id: i32,
+    #[serde(default)]
+    content: i32,
+    #[serde(default)]
+    sequence_number: i64,
+    #[serde(default)]
+    min_sequence_number: i64,
+    added_snapshot_id: i64,
+    #[serde(default, alias = "added_data_files_count")]
+    added_files_count: i32,
+    #[serde(default, alias = "existing_data_files_count")]
+    existing_files_count: i32,
+    #[serde(default, alias = "deleted_data_files_count")]
+    deleted_files_count: i32,
+    #[serde(default)]
+    added_rows_count: i64,
+    #[serde(default)]
+    existing_rows_count: i64,
+    #[serde(default)]
+    deleted_rows_count: i64,
+}
+
+impl ManifestFile {
+    fn to_avro(&self) -> AvroValue {
+        AvroValue::Record(vec![
+            (
+                "manifest_path".to_string(),
+                AvroValue::String(self.manifest_path.clone()),
+            ),
+            (
+                "manifest_length".to_string(),
+                AvroValue::Long(self.manifest_length),
+            ),
+            (
+                "partition_spec_id".to_string(),
+                AvroValue::Int(self.partition_spec_id),
+            ),
+            ("content".to_string(), AvroValue::Int(self.content)),
+            (
+                "sequence_number".to_string(),
+                AvroValue::Long(self.sequence_number),
+            ),
+            (
+                "min_sequence_number".to_string(),
+                AvroValue::Long(self.min_sequence_number),
+            ),
+            (
+                "added_snapshot_id".to_string(),
+                AvroValue::Long(self.added_snapshot_id),
+            ),
+            (
+                "added_files_count".to_string(),
+                AvroValue::Int(self.added_files_count),
+            ),
+            (
+                "existing_files_count".to_string(),
+                AvroValue::Int(self.existing_files_count),
+            ),
+            (
+                "deleted_files_count".to_string(),
+                AvroValue::Int(self.deleted_files_count),
+            ),
+            (
+                "added_rows_count".to_string(),
+                AvroValue::Long(self.added_rows_count),
+            ),
+            (
+                "existing_rows_count".to_string(),
+                AvroValue::Long(self.existing_rows_count),
+            ),
+            (
+                "deleted_rows_count".to_string(),
+                AvroValue::Long(self.deleted_rows_count),
+            ),
+        ])
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DataFile {
+    file_path: String,
+    record_count: i64,
+    file_size_in_bytes: i64,
+}
+
+impl DataFile {
+    fn to_manifest_entry(&self, snapshot_id: i64) -> AvroValue {
+        AvroValue::Record(vec![
+            // ADDED
+            ("status".to_string(), AvroValue::Int(1)),
+            (
+                "snapshot_id".to_string(),
+                AvroValue::Union(1, Box::new(AvroValue::Long(snapshot_id))),
+            ),
+            // the sequence numbers are inherited from the manifest list
+            (
+                "sequence_number".to_string(),
+                AvroValue::Union(0, Box::new(AvroValue::Null)),
+            ),
+            (
+                "file_sequence_number".to_string(),
+                AvroValue::Union(0, Box::new(AvroValue::Null)),
+            ),
+            (
+                "data_file".to_string(),
+                AvroValue::Record(vec![
+                    ("content".to_string(), AvroValue::Int(0)),
+                    (
+                        "file_path".to_string(),
+                        AvroValue::String(self.file_path.clone()),
+                    ),
+                    (
+                        "file_format".to_string(),
+                        AvroValue::String("PARQUET".to_string()),
+                    ),
+                    ("partition".to_string(), AvroValue::Record(vec![])),
+                    (
+                        "record_count".to_string(),
+                        AvroValue::Long(self.record_count),
+                    ),
+                    (
+                        "file_size_in_bytes".to_string(),
+                        AvroValue::Long(self.file_size_in_bytes),
+                    ),
+                ]),
+            ),
+        ])
+    }
+}
+
+fn file_uri(storage_provider: &StorageProvider, path: &Path) -> String {
+    format!(
+        "{}/{}",
+        storage_provider
+            .object_store_base_url()
+            .trim_end_matches('/'),
+        path
+    )
+}
+
+/// Reads a file referenced by the table metadata, which will usually be in the same store as the
+/// table but may have been written elsewhere by another engine
+async fn read_uri(storage_provider: &StorageProvider, uri: &str) -> Result<Bytes> {
+    let base = storage_provider
+        .object_store_base_url()
+        .trim_end_matches('/');
+    match uri.strip_prefix(base) {
+        Some(path) => Ok(storage_provider
+            .get_backing_store()
+            .get(&Path::parse(path.trim_start_matches('/'))?)
+            .await?
+            .bytes()
+            .await?),
+        None => Ok(StorageProvider::get_url(uri).await?),
+    }
+}
+
+/// Writes a file only if it doesn't already exist, returning whether it was written. This is what
+/// makes commits to a hadoop catalog atomic, so stores that can't do it are rejected rather than
+/// risking a concurrent writer silently replacing a commit.
+async fn put_if_absent(
+    storage_provider: &StorageProvider,
+    path: &Path,
+    bytes: Vec<u8>,
+) -> Result<bool> {
+    match storage_provider
+        .get_backing_store()
+        .put_opts(path, bytes.into(), PutMode::Create.into())
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(object_store::Error::AlreadyExists { .. }) => Ok(false),
+        Err(object_store::Error::NotImplemented) => bail!(
+            "the storage for {} doesn't support conditional writes, which are required to commit to \
+            an Iceberg hadoop catalog; use a REST catalog instead",
+            path
+        ),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn data_file(storage_provider: &StorageProvider, file: &FinishedFile) -> Result<DataFile> {
+    let path = Path::parse(&file.filename)?;
+    let store = storage_provider.get_backing_store();
+    let meta = store.head(&path).await?;
+    let mut reader = ParquetObjectReader::new(store, meta);
+    let metadata = reader
+        .get_metadata()
+        .await
+        .with_context(|| format!("failed to read Parquet metadata for {}", file.filename))?;
+
+    Ok(DataFile {
+        file_path: file_uri(storage_provider, &path),
+        record_count: metadata.file_metadata().num_rows(),
+        file_size_in_bytes: file.size as i64,
+    })
+}
+
+fn write_manifest(schema_json: &Value, snapshot_id: i64, files: &[DataFile]) -> Result<Vec<u8>> {
+    let avro_schema = apache_avro::Schema::parse_str(MANIFEST_ENTRY_SCHEMA)?;
+    let mut writer = apache_avro::Writer::new(&avro_schema, vec![]);
+    writer.add_user_metadata("schema".to_string(), schema_json.to_string())?;
+    writer.add_user_metadata(
+        "schema-id".to_string(),
+        schema_json
+            .get("schema-id")
+            .and_then(Value::as_i64)
+            .unwrap_or_default()
+            .to_string(),
+    )?;
+    writer.add_user_metadata("partition-spec".to_string(), "[]")?;
+    writer.add_user_metadata("partition-spec-id".to_string(), "0")?;
+    writer.add_user_metadata("format-version".to_string(), "2")?;
+    writer.add_user_metadata("content".to_string(), "data")?;
+
+    for file in files {
+        writer.append(file.to_manifest_entry(snapshot_id))?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+fn write_manifest_list(
+    snapshot: &Snapshot,
+    manifests: impl Iterator<Item = ManifestFile>,
+) -> Result<Vec<u8>> {
+    let avro_schema = apache_avro::Schema::parse_str(MANIFEST_FILE_SCHEMA)?;
+    let mut writer = apache_avro::Writer::new(&avro_schema, vec![]);
+    writer.add_user_metadata("snapshot-id".to_string(), snapshot.snapshot_id.to_string())?;
+    writer.add_user_metadata(
+        "parent-snapshot-id".to_string(),
+        snapshot
+            .parent_snapshot_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "null".to_string()),
+    )?;
+    writer.add_user_metadata(
+        "sequence-number".to_string(),
+        snapshot.sequence_number.to_string(),
+    )?;
+    writer.add_user_metadata("format-version".to_string(), "2")?;
+
+    for manifest in manifests {
+        writer.append(manifest.to_avro())?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Writes the manifest and manifest list for a new snapshot that appends `files` to the table
+async fn write_snapshot(
+    storage_provider: &StorageProvider,
+    table_path: &Path,
+    metadata: &TableMetadata,
+    files: &[DataFile],
+    commit_file: &str,
+) -> Result<Snapshot> {
+    let parent = metadata.current_snapshot();
+    let snapshot_id = (rand::random::<u64>() >> 1) as i64;
+    let sequence_number = metadata.last_sequence_number + 1;
+    let commit_id = Uuid::new_v4();
+
+    let schema_json = metadata
+        .schemas
+        .iter()
+        .find(|s| {
+            s.get("schema-id").and_then(Value::as_i64) == Some(metadata.current_schema_id as i64)
+        })
+        .ok_or_else(|| anyhow!("table metadata is missing its current schema"))?;
+
+    let manifest = write_manifest(schema_json, snapshot_id, files)?;
+    let manifest_path = table_path
+        .child("metadata")
+        .child(format!("{}-m0.avro", commit_id));
+    let manifest_length = manifest.len() as i64;
+    storage_provider
+        .get_backing_store()
+        .put(&manifest_path, manifest.into())
+        .await?;
+
+    let mut manifests = vec![];
+    if let Some(parent) = parent {
+        let bytes = read_uri(storage_provider, &parent.manifest_list).await?;
+        for value in apache_avro::Reader::new(&bytes[..])? {
+            manifests.push(apache_avro::from_value::<ManifestFile>(&value?)?);
+        }
+    }
+
+    let added_records: i64 = files.iter().map(|f| f.record_count).sum();
+    let added_size: i64 = files.iter().map(|f| f.file_size_in_bytes).sum();
+    manifests.push(ManifestFile {
+        manifest_path: file_uri(storage_provider, &manifest_path),
+        manifest_length,
+        partition_spec_id: metadata.default_spec_id,
+        content: 0,
+        sequence_number,
+        min_sequence_number: sequence_number,
+        added_snapshot_id: snapshot_id,
+        added_files_count: files.len() as i32,
+        existing_files_count: 0,
+        deleted_files_count: 0,
+        added_rows_count: added_records,
+        existing_rows_count: 0,
+        deleted_rows_count: 0,
+    });
+
+    let manifest_list_path = table_path
+        .child("metadata")
+        .child(format!("snap-{}-1-{}.avro", snapshot_id, commit_id));
+
+    let snapshot = Snapshot {
+        snapshot_id,
+        parent_snapshot_id: parent.map(|p| p.snapshot_id),
+        sequence_number,
+        timestamp_ms: to_millis(SystemTime::now()) as i64,
+        manifest_list: file_uri(storage_provider, &manifest_list_path),
+        summary: HashMap::from([
+            ("operation".to_string(), "append".to_string()),
+            ("added-data-files".to_string(), files.len().to_string()),
+            ("added-records".to_string(), added_records.to_string()),
+            ("added-files-size".to_string(), added_size.to_string()),
+            (COMMIT_FILE_PROPERTY.to_string(), commit_file.to_string()),
+        ]),
+        schema_id: Some(metadata.current_schema_id),
+    };
+
+    let manifest_list = write_manifest_list(&snapshot, manifests.into_iter())?;
+    storage_provider
+        .get_backing_store()
+        .put(&manifest_list_path, manifest_list.into())
+        .await?;
+
+    Ok(snapshot)
+}
+
+struct LoadedTable {
+    metadata: TableMetadata,
+    metadata_location: Option<String>,
+    // the version of the metadata file, for hadoop catalogs
+    version: i64,
+}
+
+/// A catalog that stores the table metadata in the table directory, compatible with Iceberg's
+/// HadoopCatalog: `metadata/v<N>.metadata.json` holds each version of the metadata, with the
+/// latest version recorded in `metadata/version-hint.text`
+struct HadoopCatalog {
+    storage_provider: Arc<StorageProvider>,
+    metadata_path: Path,
+}
+
+impl HadoopCatalog {
+    fn metadata_file(&self, version: i64) -> Path {
+        self.metadata_path
+            .child(format!("v{}.metadata.json", version))
+    }
+
+    fn version_hint(&self) -> Path {
+        self.metadata_path.child("version-hint.text")
+    }
+
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.storage_provider.get_backing_store().head(path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn load(&self) -> Result<Option<LoadedTable>> {
+        let store = self.storage_provider.get_backing_store();
+
+        let hint = match store.get(&self.version_hint()).await {
+            Ok(result) => Some(
+                String::from_utf8(result.bytes().await?.to_vec())?
+                    .trim()
+                    .parse::<i64>()
+                    .context("invalid Iceberg version hint")?,
+            ),
+            Err(object_store::Error::NotFound { .. }) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = match hint {
+            Some(version) => version,
+            None => {
+                // without a hint we look for the highest version in the metadata directory
+                let listing = self
+                    .storage_provider
+                    .list_directory(Some(&self.metadata_path))
+                    .await?;
+                let latest = listing
+                    .objects
+                    .iter()
+                    .filter_map(|o| {
+                        o.location
+                            .filename()?
+                            .strip_prefix('v')?
+                            .strip_suffix(".metadata.json")?
+                            .parse::<i64>()
+                            .ok()
+                    })
+                    .max();
+                let Some(latest) = latest else {
+                    return Ok(None);
+                };
+                latest
+            }
+        };
+
+        // the hint is written after the metadata file, so it may be behind
+        let mut version = version;
+        while self.exists(&self.metadata_file(version + 1)).await? {
+            version += 1;
+        }
+
+        let path = self.metadata_file(version);
+        let bytes = store.get(&path).await?.bytes().await?;
+        let metadata = serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid Iceberg table metadata in {}", path))?;
+
+        Ok(Some(LoadedTable {
+            metadata,
+            metadata_location: Some(file_uri(&self.storage_provider, &path)),
+            version,
+        }))
+    }
+
+    /// Writes a new version of the table metadata, returning false if another writer already has
+    async fn write_version(&self, version: i64, metadata: &TableMetadata) -> Result<bool> {
+        if !put_if_absent(
+            &self.storage_provider,
+            &self.metadata_file(version),
+            serde_json::to_vec_pretty(metadata)?,
+        )
+        .await?
+        {
+            return Ok(false);
+        }
+
+        self.storage_provider
+            .get_backing_store()
+            .put(&self.version_hint(), version.to_string().into())
+            .await?;
+        Ok(true)
+    }
+
+    async fn create(&self, location: &str, schema: &IcebergSchema) -> Result<Option<LoadedTable>> {
+        let metadata = TableMetadata::new(location.to_string(), schema);
+        if !self.write_version(1, &metadata).await? {
+            return Ok(None);
+        }
+
+        info!("created Iceberg table at {}", location);
+        Ok(Some(LoadedTable {
+            metadata,
+            metadata_location: Some(file_uri(&self.storage_provider, &self.metadata_file(1))),
+            version: 1,
+        }))
+    }
+
+    async fn commit(&self, table: &LoadedTable, snapshot: &Snapshot) -> Result<bool> {
+        let metadata = table
+            .metadata
+            .with_snapshot(snapshot, table.metadata_location.as_deref());
+        self.write_version(table.version + 1, &metadata).await
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct LoadTableResult {
+    metadata_location: Option<String>,
+    metadata: TableMetadata,
+}
+
+#[derive(Deserialize)]
+struct CatalogConfig {
+    #[serde(default)]
+    defaults: HashMap<String, String>,
+    #[serde(default)]
+    overrides: HashMap<String, String>,
+}
+
+/// A catalog that implements the Iceberg REST catalog API
+struct RestCatalog {
+    client: reqwest::Client,
+    token: Option<String>,
+    tables_url: String,
+    table_url: String,
+    table_name: String,
+}
+
+impl RestCatalog {
+    async fn new(settings: &IcebergSettings) -> Result<Self> {
+        let (Some(uri), Some(namespace), Some(table_name)) = (
+            &settings.rest_uri,
+            &settings.namespace,
+            &settings.table_name,
+        ) else {
+            bail!("a REST catalog requires a URI, namespace, and table name");
+        };
+
+        let uri = uri.trim_end_matches('/');
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        let mut catalog = Self {
+            client,
+            token: settings.token.clone(),
+            tables_url: String::new(),
+            table_url: String::new(),
+            table_name: table_name.clone(),
+        };
+
+        // the catalog may tell us to use a prefix for the warehouse
+        let mut config_request =
+            catalog.request(reqwest::Method::GET, &format!("{}/v1/config", uri));
+        if let Some(warehouse) = &settings.warehouse {
+            config_request = config_request.query(&[("warehouse", warehouse)]);
+        }
+        let config: CatalogConfig = Self::parse(config_request.send().await?).await?;
+        let prefix = config
+            .overrides
+            .get("prefix")
+            .or_else(|| config.defaults.get("prefix"))
+            .map(|p| format!("{}/", p.trim_matches('/')))
+            .unwrap_or_default();
+
+        // multi-level namespaces are separated by the unit separator character
+        let namespace = namespace.split('.').collect::<Vec<_>>().join("%1F");
+        catalog.tables_url = format!("{}/v1/{}namespaces/{}/tables", uri, prefix, namespace);
+        catalog.table_url = format!("{}/{}", catalog.tables_url, table_name);
+        Ok(catalog)
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn parse<T: for<'de> Deserialize<'de>>(response: reqwest::Response) -> Result<T> {
+        let status = response.status();
+        let url = response.url().to_string();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            bail!(
+                "request to Iceberg catalog {} failed with {}: {}",
+                url,
+                status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        serde_json::from_slice(&body)
+            .with_context(|| format!("invalid response from Iceberg catalog {}", url))
+    }
+
+    fn loaded(result: LoadTableResult) -> LoadedTable {
+        LoadedTable {
+            metadata: result.metadata,
+            metadata_location: result.metadata_location,
+            version: 0,
+        }
+    }
+
+    async fn load(&self) -> Result<Option<LoadedTable>> {
+        let response = self
+            .request(reqwest::Method::GET, &self.table_url)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(Self::loaded(Self::parse(response).await?)))
+    }
+
+    async fn create(&self, location: &str, schema: &IcebergSchema) -> Result<Option<LoadedTable>> {
+        let body = json!({
+            "name": self.table_name,
+            "location": location,
+            "schema": schema.to_json(0),
+            "partition-spec": {"spec-id": 0, "fields": []},
+            "write-order": {"order-id": 0, "fields": []},
+            "stage-create": false,
+            "properties": {"format-version": "2"},
+        });
+
+        let response = self
+            .request(reqwest::Method::POST, &self.tables_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        if response.status() == StatusCode::CONFLICT {
+            return Ok(None);
+        }
+
+        info!("created Iceberg table {}", self.table_url);
+        Ok(Some(Self::loaded(Self::parse(response).await?)))
+    }
+
+    async fn commit(&self, table: &LoadedTable, snapshot: &Snapshot) -> Result<bool> {
+        let body = json!({
+            "requirements": [{
+                "type": "assert-ref-snapshot-id",
+                "ref": MAIN_BRANCH,
+                "snapshot-id": table.metadata.current_snapshot().map(|s| s.snapshot_id),
+            }],
+            "updates": [
+                {
+                    "action": "add-snapshot",
+                    "snapshot": snapshot,
+                },
+                {
+                    "action": "set-snapshot-ref",
+                    "ref-name": MAIN_BRANCH,
+                    "type": "branch",
+                    "snapshot-id": snapshot.snapshot_id,
+                },
+            ],
+        });
+
+        let response = self
+            .request(reqwest::Method::POST, &self.table_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        if response.status() == StatusCode::CONFLICT {
+            return Ok(false);
+        }
+        Self::parse::<Value>(response).await?;
+        Ok(true)
+    }
+}
+
+enum Catalog {
+    Hadoop(HadoopCatalog),
+    Rest(RestCatalog),
+}
+
+impl Catalog {
+    async fn new(
+        settings: &IcebergSettings,
+        storage_provider: Arc<StorageProvider>,
+        table_path: &Path,
+    ) -> Result<Self> {
+        Ok(match settings.catalog_type {
+            CatalogType::Hadoop => Catalog::Hadoop(HadoopCatalog {
+                storage_provider,
+                metadata_path: table_path.child("metadata"),
+            }),
+            CatalogType::Rest => Catalog::Rest(RestCatalog::new(settings).await?),
+        })
+    }
+
+    async fn load(&self) -> Result<Option<LoadedTable>> {
+        match self {
+            Catalog::Hadoop(c) => c.load().await,
+            Catalog::Rest(c) => c.load().await,
+        }
+    }
+
+    /// Creates the table, returning None if it was concurrently created by someone else
+    async fn create(&self, location: &str, schema: &IcebergSchema) -> Result<Option<LoadedTable>> {
+        match self {
+            Catalog::Hadoop(c) => c.create(location, schema).await,
+            Catalog::Rest(c) => c.create(location, schema).await,
+        }
+    }
+
+    /// Makes the snapshot the current snapshot of the table, returning false if the table has
+    /// changed since it was loaded
+    async fn commit(&self, table: &LoadedTable, snapshot: &Snapshot) -> Result<bool> {
+        match self {
+            Catalog::Hadoop(c) => c.commit(table, snapshot).await,
+            Catalog::Rest(c) => c.commit(table, snapshot).await,
+        }
+    }
+}
+
+/// Appends the finished files to the Iceberg table as a new snapshot, creating the table if it
+/// doesn't exist yet. Returns the id of the snapshot that contains the files.
+pub(crate) async fn commit_files_to_iceberg(
+    finished_files: Vec<FinishedFile>,
+    relative_table_path: Path,
+    storage_provider: Arc<StorageProvider>,
+    settings: &IcebergSettings,
+    schema: &Schema,
+) -> Result<Option<i64>> {
+    if finished_files.is_empty() {
+        return Ok(None);
+    }
+
+    let schema = IcebergSchema::new(schema)?;
+    let location = file_uri(&storage_provider, &relative_table_path);
+
+    let mut files = vec![];
+    for file in &finished_files {
+        files.push(data_file(&storage_provider, file).await?);
+    }
+    files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    let commit_file = files[0].file_path.clone();
+
+    let catalog = Catalog::new(settings, storage_provider.clone(), &relative_table_path).await?;
+
+    for attempt in 0..COMMIT_ATTEMPTS {
+        let table = match catalog.load().await? {
+            Some(table) => table,
+            None => match catalog.create(&location, &schema).await? {
+                Some(table) => table,
+                None => continue,
+            },
+        };
+
+        table.metadata.validate(&schema)?;
+
+        if let Some(snapshot_id) = table.metadata.find_commit(&commit_file) {
+            info!(
+                "files were already committed to Iceberg in snapshot {}",
+                snapshot_id
+            );
+            return Ok(Some(snapshot_id));
+        }
+
+        let snapshot = write_snapshot(
+            &storage_provider,
+            &relative_table_path,
+            &table.metadata,
+            &files,
+            &commit_file,
+        )
+        .await?;
+
+        if catalog.commit(&table, &snapshot).await? {
+            info!(
+                "committed {} files to Iceberg in snapshot {}",
+                files.len(),
+                snapshot.snapshot_id
+            );
+            return Ok(Some(snapshot.snapshot_id));
+        }
+
+        warn!(
+            "Iceberg table changed while committing (attempt {}), retrying",
+            attempt + 1
+        );
+    }
+
+    bail!(
+        "failed to commit to Iceberg table after {} attempts due to concurrent changes",
+        COMMIT_ATTEMPTS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_ids() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new(
+                "location",
+                DataType::Struct(
+                    vec![
+                        Field::new("lat", DataType::Float64, true),
+                        Field::new("lon", DataType::Float64, true),
+                    ]
+                    .into(),
+                ),
+                true,
+            ),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+            Field::new(
+                "created",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+        ]);
+
+        let schema = IcebergSchema::new(&schema).unwrap();
+        let mut ids = HashMap::new();
+        field_ids("", &schema.to_json(0), &mut ids);
+
+        // top-level fields are numbered before nested fields
+        assert_eq!(ids["id"], 1);
+        assert_eq!(ids["location"], 2);
+        assert_eq!(ids["tags"], 3);
+        assert_eq!(ids["created"], 4);
+        assert_eq!(ids["location.lat"], 5);
+        assert_eq!(ids["location.lon"], 6);
+        assert_eq!(ids["tags.element"], 7);
+        assert_eq!(schema.last_column_id, 7);
+
+        let field = schema.arrow.field_with_name("created").unwrap();
+        assert_eq!(
+            field.data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, None)
+        );
+        assert_eq!(field.metadata()[PARQUET_FIELD_ID_META_KEY], "4");
+    }
+
+    #[test]
+    fn test_table_metadata_round_trip() {
+        let schema = IcebergSchema::new(&Schema::new(vec![Field::new(
+            "value",
+            DataType::Utf8,
+            true,
+        )]))
+        .unwrap();
+
+        let metadata = TableMetadata::new("file:/tmp/table".to_string(), &schema);
+        metadata.validate(&schema).unwrap();
+        assert!(metadata.current_snapshot().is_none());
+
+        let snapshot = Snapshot {
+            snapshot_id: 10,
+            parent_snapshot_id: None,
+            sequence_number: 1,
+            timestamp_ms: 1000,
+            manifest_list: "file:/tmp/table/metadata/snap-10.avro".to_string(),
+            summary: HashMap::from([(
+                COMMIT_FILE_PROPERTY.to_string(),
+                "file:/tmp/table/00000-000.parquet".to_string(),
+            )]),
+            schema_id: Some(0),
+        };
+
+        let updated =
+            metadata.with_snapshot(&snapshot, Some("file:/tmp/table/metadata/v1.metadata.json"));
+        let json = serde_json::to_string(&updated).unwrap();
+        let parsed: TableMetadata = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.current_snapshot().unwrap().snapshot_id, 10);
+        assert_eq!(parsed.last_sequence_number, 1);
+        assert_eq!(parsed.metadata_log.len(), 1);
+        assert_eq!(
+            parsed.find_commit("file:/tmp/table/00000-000.parquet"),
+            Some(10)
+        );
+        assert_eq!(
+            parsed.find_commit("file:/tmp/table/00001-000.parquet"),
+            None
+        );
+        assert_eq!(
+            parsed.other.get("table-uuid"),
+            metadata.other.get("table-uuid")
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_mismatched_schema() {
+        let ours = IcebergSchema::new(&Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Utf8, true),
+        ]))
+        .unwrap();
+        let theirs = IcebergSchema::new(&Schema::new(vec![
+            Field::new("b", DataType::Utf8, true),
+            Field::new("a", DataType::Int64, true),
+        ]))
+        .unwrap();
+
+        let metadata = TableMetadata::new("file:/tmp/table".to_string(), &theirs);
+        assert!(metadata.validate(&ours).is_err());
+    }
+
+    /// Writes a Parquet file with the given values under the table directory
+    fn write_data_file(
+        dir: &std::path::Path,
+        name: &str,
+        schema: &SchemaRef,
+        values: Vec<i64>,
+    ) -> FinishedFile {
+        let path = dir.join(name);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(arrow::array::Int64Array::from(values))],
+        )
+        .unwrap();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path).unwrap(),
+            schema.clone(),
+            None,
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        FinishedFile {
+            filename: Path::parse(path.to_string_lossy()).unwrap().to_string(),
+            partition: None,
+            size: std::fs::metadata(&path).unwrap().len() as usize,
+        }
+    }
+
+    async fn read_manifest_files(
+        storage_provider: &StorageProvider,
+        snapshot: &Snapshot,
+    ) -> Vec<(ManifestFile, Vec<(String, i64)>)> {
+        let bytes = read_uri(storage_provider, &snapshot.manifest_list)
+            .await
+            .unwrap();
+        let mut manifests = vec![];
+        for value in apache_avro::Reader::new(&bytes[..]).unwrap() {
+            let manifest: ManifestFile = apache_avro::from_value(&value.unwrap()).unwrap();
+            let bytes = read_uri(storage_provider, &manifest.manifest_path)
+                .await
+                .unwrap();
+            let mut files = vec![];
+            for entry in apache_avro::Reader::new(&bytes[..]).unwrap() {
+                let AvroValue::Record(fields) = entry.unwrap() else {
+                    panic!("manifest entry should be a record");
+                };
+                let Some((_, AvroValue::Record(data_file))) =
+                    fields.into_iter().find(|(name, _)| name == "data_file")
+                else {
+                    panic!("manifest entry should have a data file");
+                };
+                let data_file: HashMap<_, _> = data_file.into_iter().collect();
+                let (AvroValue::String(path), AvroValue::Long(records)) =
+                    (&data_file["file_path"], &data_file["record_count"])
+                else {
+                    panic!("invalid data file {:?}", data_file);
+                };
+                files.push((path.clone(), *records));
+            }
+            manifests.push((manifest, files));
+        }
+        manifests
+    }
+
+    #[tokio::test]
+    async fn test_commit_to_hadoop_catalog() {
+        let dir = std::env::temp_dir().join(format!("arroyo-iceberg-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let schema = Schema::new(vec![Field::new("value", DataType::Int64, false)]);
+        let iceberg_schema = IcebergSchema::new(&schema).unwrap();
+        let settings = IcebergSettings {
+            catalog_type: CatalogType::Hadoop,
+            rest_uri: None,
+            warehouse: None,
+            token: None,
+            namespace: None,
+            table_name: None,
+        };
+        let storage_provider = Arc::new(StorageProvider::for_url("/").await.unwrap());
+        let table_path = Path::parse(dir.to_string_lossy()).unwrap();
+
+        let first = write_data_file(&dir, "00000.parquet", &iceberg_schema.arrow, vec![1, 2, 3]);
+        let first_snapshot = commit_files_to_iceberg(
+            vec![first.clone()],
+            table_path.clone(),
+            storage_provider.clone(),
+            &settings,
+            &schema,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // retrying the same commit finds the existing snapshot rather than adding the files again
+        assert_eq!(
+            commit_files_to_iceberg(
+                vec![first],
+                table_path.clone(),
+                storage_provider.clone(),
+                &settings,
+                &schema,
+            )
+            .await
+            .unwrap(),
+            Some(first_snapshot)
+        );
+
+        let second = write_data_file(&dir, "00001.parquet", &iceberg_schema.arrow, vec![4, 5]);
+        let second_snapshot = commit_files_to_iceberg(
+            vec![second],
+            table_path.clone(),
+            storage_provider.clone(),
+            &settings,
+            &schema,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // read the table back the way an Iceberg reader would
+        let catalog = HadoopCatalog {
+            storage_provider: storage_provider.clone(),
+            metadata_path: table_path.child("metadata"),
+        };
+        let table = catalog.load().await.unwrap().unwrap();
+        assert_eq!(table.version, 3);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("metadata/version-hint.text")).unwrap(),
+            "3"
+        );
+        table.metadata.validate(&iceberg_schema).unwrap();
+
+        let snapshot = table.metadata.current_snapshot().unwrap();
+        assert_eq!(snapshot.snapshot_id, second_snapshot);
+        assert_eq!(snapshot.parent_snapshot_id, Some(first_snapshot));
+        assert_eq!(snapshot.sequence_number, 2);
+        assert_eq!(table.metadata.last_sequence_number, 2);
+        assert_eq!(table.metadata.snapshots.len(), 2);
+        assert_eq!(table.metadata.metadata_log.len(), 2);
+
+        let manifests = read_manifest_files(&storage_provider, snapshot).await;
+        let files: Vec<_> = manifests
+            .iter()
+            .map(|(manifest, files)| (manifest.added_snapshot_id, files.clone()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                (
+                    first_snapshot,
+                    vec![(format!("file:{}/00000.parquet", dir.display()), 3)]
+                ),
+                (
+                    second_snapshot,
+                    vec![(format!("file:{}/00001.parquet", dir.display()), 2)]
+                ),
+            ]
+        );
+        assert_eq!(manifests[1].0.sequence_number, 2);
+        assert_eq!(manifests[1].0.added_rows_count, 2);
+
+        // a metadata version that already exists is never overwritten
+        assert!(!catalog.write_version(3, &table.metadata).await.unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -45,15 +45,15 @@ impl BatchBufferingWriter for JsonWriter {
         "json".to_string()
     }
 
-    fn add_batch_data(&mut self, batch: RecordBatch) -> Option<Vec<u8>> {
+    fn add_batch_data(&mut self, batch: RecordBatch) -> anyhow::Result<Option<Vec<u8>>> {
         for k in self.serializer.serialize(&batch) {
             self.current_buffer.extend(k);
             self.current_buffer.extend(b"\n");
         }
         if self.buffer_length() > self.target_part_size {
-            Some(self.evict_current_buffer())
+            Ok(Some(self.evict_current_buffer()))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -75,7 +75,7 @@ impl BatchBufferingWriter for JsonWriter {
 
     fn close(&mut self, final_batch: Option<RecordBatch>) -> Option<Vec<u8>> {
         if let Some(final_batch) = final_batch {
-            if let Some(final_batch) = self.add_batch_data(final_batch) {
+            if let Some(final_batch) = self.add_batch_data(final_batch).unwrap() {
                 return Some(final_batch);
             }
         }
@@ -17,13 +17,13 @@ use tracing::info;
 use uuid::Uuid;
 
 use crate::filesystem::{sink::two_phase_committer::TwoPhaseCommitter, FileSettings};
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 
 use super::{
-    add_suffix_prefix, delta, get_partitioner_from_file_settings, parquet::batches_by_partition,
-    two_phase_committer::TwoPhaseCommitterOperator, CommitState, CommitStyle, FileNaming,
-    FileSystemTable, FilenameStrategy, FinishedFile, MultiPartWriterStats, RollingPolicy,
-    TableType,
+    add_suffix_prefix, delta, get_partitioner_from_file_settings, iceberg,
+    parquet::batches_by_partition, two_phase_committer::TwoPhaseCommitterOperator, CommitState,
+    CommitStyle, FileNaming, FileSystemTable, FilenameStrategy, FinishedFile, MultiPartWriterStats,
+    RollingPolicy, TableType,
 };
 
 pub struct LocalFileSystemWriter<V: LocalWriter> {
@@ -67,6 +67,7 @@ impl<V: LocalWriter> LocalFileSystemWriter<V> {
         let commit_state = match file_settings.as_ref().unwrap().commit_style.unwrap() {
             CommitStyle::DeltaLake => CommitState::DeltaLake { last_version: -1 },
             CommitStyle::Direct => CommitState::VanillaParquet,
+            CommitStyle::Iceberg => CommitState::Iceberg,
         };
 
         let mut filenaming = file_settings
@@ -311,21 +312,40 @@ impl<V: LocalWriter + Send + 'static> TwoPhaseCommitter for LocalFileSystemWrite
                 size: destination.metadata()?.len() as usize,
             });
         }
-        if let CommitState::DeltaLake { last_version } = self.commit_state {
-            let storage_provider = Arc::new(StorageProvider::for_url("/").await?);
-            if let Some(version) = delta::commit_files_to_delta(
-                finished_files,
-                object_store::path::Path::parse(&self.final_dir)?,
-                storage_provider,
-                last_version,
-                Arc::new(self.schema.as_ref().unwrap().schema_without_timestamp()),
-            )
-            .await?
-            {
-                self.commit_state = CommitState::DeltaLake {
-                    last_version: version,
-                };
+        match self.commit_state {
+            CommitState::DeltaLake { last_version } => {
+                let storage_provider = Arc::new(StorageProvider::for_url("/").await?);
+                if let Some(version) = delta::commit_files_to_delta(
+                    finished_files,
+                    object_store::path::Path::parse(&self.final_dir)?,
+                    storage_provider,
+                    last_version,
+                    Arc::new(self.schema.as_ref().unwrap().schema_without_timestamp()),
+                )
+                .await?
+                {
+                    self.commit_state = CommitState::DeltaLake {
+                        last_version: version,
+                    };
+                }
+            }
+            CommitState::Iceberg => {
+                let settings = self
+                    .file_settings
+                    .iceberg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Iceberg sink requires iceberg settings"))?;
+                let storage_provider = Arc::new(StorageProvider::for_url("/").await?);
+                iceberg::commit_files_to_iceberg(
+                    finished_files,
+                    object_store::path::Path::parse(&self.final_dir)?,
+                    storage_provider,
+                    settings,
+                    &self.schema.as_ref().unwrap().schema_without_timestamp(),
+                )
+                .await?;
             }
+            CommitState::VanillaParquet => {}
         }
         Ok(())
     }
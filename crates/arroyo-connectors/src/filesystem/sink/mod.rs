@@ -44,7 +44,8 @@ use uuid::Uuid;
 
 use arroyo_types::*;
 pub mod arrow;
-mod delta;
+pub(crate) mod delta;
+pub(crate) mod iceberg;
 pub mod json;
 pub mod local;
 pub mod parquet;
@@ -92,7 +93,7 @@ impl<R: MultiPartWriter + Send + 'static> FileSystemSink<R> {
         };
         let commit_strategy = match file_settings.as_ref().unwrap().commit_style.unwrap() {
             CommitStyle::Direct => CommitStrategy::PerSubtask,
-            CommitStyle::DeltaLake => CommitStrategy::PerOperator,
+            CommitStyle::DeltaLake | CommitStyle::Iceberg => CommitStrategy::PerOperator,
         };
 
         TwoPhaseCommitterOperator::new(Self {
@@ -448,6 +449,7 @@ struct AsyncMultipartFileSystemWriter<R: MultiPartWriter> {
 pub enum CommitState {
     DeltaLake { last_version: i64 },
     VanillaParquet,
+    Iceberg,
 }
 
 #[async_trait]
@@ -729,6 +731,7 @@ where
         let commit_state = match file_settings.commit_style.unwrap() {
             CommitStyle::DeltaLake => CommitState::DeltaLake { last_version: -1 },
             CommitStyle::Direct => CommitState::VanillaParquet,
+            CommitStyle::Iceberg => CommitState::Iceberg,
         };
         let mut file_naming = file_settings.file_naming.clone().unwrap_or(FileNaming {
             strategy: Some(FilenameStrategy::Serial),
@@ -933,20 +936,44 @@ where
                 finished_files.push(file);
             }
         }
-        if let CommitState::DeltaLake { last_version } = self.commit_state {
-            if let Some(new_version) = delta::commit_files_to_delta(
-                finished_files,
-                self.path.clone(),
-                self.object_store.clone(),
-                last_version,
-                Arc::new(self.schema.schema_without_timestamp()),
-            )
-            .await?
-            {
-                self.commit_state = CommitState::DeltaLake {
-                    last_version: new_version,
+        match self.commit_state {
+            CommitState::DeltaLake { last_version } => {
+                if let Some(new_version) = delta::commit_files_to_delta(
+                    finished_files,
+                    self.path.clone(),
+                    self.object_store.clone(),
+                    last_version,
+                    Arc::new(self.schema.schema_without_timestamp()),
+                )
+                .await?
+                {
+                    self.commit_state = CommitState::DeltaLake {
+                        last_version: new_version,
+                    };
+                }
+            }
+            CommitState::Iceberg => {
+                let TableType::Sink {
+                    file_settings:
+                        Some(FileSettings {
+                            iceberg: Some(settings),
+                            ..
+                        }),
+                    ..
+                } = &self.properties.table_type
+                else {
+                    bail!("Iceberg sink requires iceberg settings");
                 };
+                iceberg::commit_files_to_iceberg(
+                    finished_files,
+                    self.path.clone(),
+                    self.object_store.clone(),
+                    settings,
+                    &self.schema.schema_without_timestamp(),
+                )
+                .await?;
             }
+            CommitState::VanillaParquet => {}
         }
         let finished_message = CheckpointData::Finished {
             max_file_index: self.max_file_index,
@@ -959,7 +986,7 @@ where
     fn delta_version(&mut self) -> i64 {
         match self.commit_state {
             CommitState::DeltaLake { last_version } => last_version,
-            CommitState::VanillaParquet => 0,
+            CommitState::VanillaParquet | CommitState::Iceberg => 0,
         }
     }
 
@@ -1357,7 +1384,7 @@ impl MultipartManager {
 pub trait BatchBufferingWriter: Send {
     fn new(config: &FileSystemTable, format: Option<Format>, schema: ArroyoSchemaRef) -> Self;
     fn suffix() -> String;
-    fn add_batch_data(&mut self, data: RecordBatch) -> Option<Vec<u8>>;
+    fn add_batch_data(&mut self, data: RecordBatch) -> Result<Option<Vec<u8>>>;
     fn buffer_length(&self) -> usize;
     fn evict_current_buffer(&mut self) -> Vec<u8>;
     fn get_trailing_bytes_for_checkpoint(&mut self) -> Option<Vec<u8>>;
@@ -1420,7 +1447,7 @@ impl<BBW: BatchBufferingWriter> MultiPartWriter for BatchMultipartWriter<BBW> {
         stats.last_write_at = Instant::now();
 
         let prev_size = self.batch_buffering_writer.buffer_length();
-        if let Some(bytes) = self.batch_buffering_writer.add_batch_data(batch) {
+        if let Some(bytes) = self.batch_buffering_writer.add_batch_data(batch)? {
             stats.bytes_written += bytes.len() - prev_size;
             stats.parts_written += 1;
             self.multipart_manager.write_next_part(bytes)
@@ -10,6 +10,7 @@ use anyhow::Result;
 use arrow::{
     array::{Array, RecordBatch, StringArray, TimestampNanosecondArray},
     compute::{sort_to_indices, take},
+    datatypes::SchemaRef,
 };
 use arroyo_rpc::{df::ArroyoSchemaRef, formats::Format};
 use arroyo_types::from_nanos;
@@ -21,6 +22,7 @@ use parquet::{
 };
 
 use super::{
+    iceberg::{iceberg_schema_for_table, to_iceberg_batch},
     local::{CurrentFileRecovery, FilePreCommit, LocalWriter},
     BatchBufferingWriter, FileSettings, FileSystemTable, MultiPartWriterStats, TableType,
 };
@@ -91,6 +93,8 @@ pub struct RecordBatchBufferingWriter {
     shared_buffer: SharedBuffer,
     target_part_size: usize,
     schema: ArroyoSchemaRef,
+    // the schema that data is converted to before writing, for tables committed to Iceberg
+    iceberg_schema: Option<SchemaRef>,
 }
 
 impl BatchBufferingWriter for RecordBatchBufferingWriter {
@@ -110,9 +114,13 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
         };
         let shared_buffer = SharedBuffer::new(target_part_size);
         let writer_properties = writer_properties_from_table(config);
+        let iceberg_schema =
+            iceberg_schema_for_table(config, &schema.schema_without_timestamp()).unwrap();
         let writer = ArrowWriter::try_new(
             shared_buffer.clone(),
-            Arc::new(schema.schema_without_timestamp()),
+            iceberg_schema
+                .clone()
+                .unwrap_or_else(|| Arc::new(schema.schema_without_timestamp())),
             Some(writer_properties),
         )
         .unwrap();
@@ -122,6 +130,7 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
             shared_buffer,
             target_part_size,
             schema,
+            iceberg_schema,
         }
     }
 
@@ -129,15 +138,18 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
         "parquet".to_string()
     }
 
-    fn add_batch_data(&mut self, mut data: RecordBatch) -> Option<Vec<u8>> {
+    fn add_batch_data(&mut self, mut data: RecordBatch) -> Result<Option<Vec<u8>>> {
         let writer = self.writer.as_mut().unwrap();
         // remove timestamp column
         self.schema.remove_timestamp_column(&mut data);
-        writer.write(&data).unwrap();
+        if let Some(iceberg_schema) = &self.iceberg_schema {
+            data = to_iceberg_batch(&data, iceberg_schema)?;
+        }
+        writer.write(&data)?;
         if self.buffer_length() > self.target_part_size {
-            Some(self.evict_current_buffer())
+            Ok(Some(self.evict_current_buffer()))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -187,6 +199,7 @@ pub struct ParquetLocalWriter {
     shared_buffer: SharedBuffer,
     stats: Option<MultiPartWriterStats>,
     schema: ArroyoSchemaRef,
+    iceberg_schema: Option<SchemaRef>,
 }
 
 impl LocalWriter for ParquetLocalWriter {
@@ -199,9 +212,13 @@ impl LocalWriter for ParquetLocalWriter {
     ) -> Self {
         let shared_buffer = SharedBuffer::new(0);
         let writer_properties = writer_properties_from_table(table_properties);
+        let iceberg_schema =
+            iceberg_schema_for_table(table_properties, &schema.schema_without_timestamp()).unwrap();
         let writer = ArrowWriter::try_new(
             shared_buffer.clone(),
-            Arc::new(schema.schema_without_timestamp()),
+            iceberg_schema
+                .clone()
+                .unwrap_or_else(|| Arc::new(schema.schema_without_timestamp())),
             Some(writer_properties),
         )
         .unwrap();
@@ -214,6 +231,7 @@ impl LocalWriter for ParquetLocalWriter {
             shared_buffer,
             stats: None,
             schema,
+            iceberg_schema,
         }
     }
 
@@ -236,6 +254,9 @@ impl LocalWriter for ParquetLocalWriter {
             self.stats.as_mut().unwrap().last_write_at = Instant::now();
         }
         self.schema.remove_timestamp_column(&mut batch);
+        if let Some(iceberg_schema) = &self.iceberg_schema {
+            batch = to_iceberg_batch(&batch, iceberg_schema)?;
+        }
         self.writer.as_mut().unwrap().write(&batch)?;
         Ok(())
     }
@@ -1,39 +1,47 @@
+mod delta;
+
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::future::ready;
+use std::collections::{HashMap, VecDeque};
 use std::hash::{Hash, Hasher};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use arrow::array::RecordBatch;
 
-use arrow::datatypes::SchemaRef;
+use arroyo_formats::csv::de::RecordSplitter;
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::schema_resolver::FailingSchemaResolver;
+use arroyo_rpc::MetadataField;
 use arroyo_state::global_table_config;
 use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
 use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use datafusion::common::ScalarValue;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::ObjectMeta;
 use parquet::arrow::async_reader::ParquetObjectReader;
 use parquet::arrow::ParquetRecordBatchStreamBuilder;
 
 use arroyo_operator::context::ArrowContext;
 use regex::Regex;
-use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 use tokio::select;
 use tokio_stream::wrappers::LinesStream;
 use tokio_stream::Stream;
-use tracing::info;
+use tracing::{debug, info};
 
+use crate::filesystem::pruning::{partition_column, partition_values, SourceFilters};
 use crate::filesystem::{CompressionFormat, TableType};
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
-use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::formats::{BadData, CsvFormat, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{grpc::StopMode, ControlMessage};
 use arroyo_storage::StorageProvider;
-use arroyo_types::{to_nanos, UserError};
+use arroyo_types::{to_nanos, MetadataValue, UserError};
 
 #[allow(unused)]
 pub struct FileSystemSourceFunc {
@@ -41,19 +49,67 @@ pub struct FileSystemSourceFunc {
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
+    // serialized filters from the query, which are used to skip partitions and row groups
+    pub filters: Vec<Vec<u8>>,
     pub file_states: HashMap<String, FileReadState>,
+    // whether the files are discovered from the transaction log of a Delta Lake table, rather
+    // than by listing the path
+    pub delta: bool,
+    // the Delta table version through which this subtask has read all of its files
+    pub delta_version: Option<i64>,
 }
 
 #[derive(Encode, Decode, Debug, Clone, PartialEq, PartialOrd)]
 pub enum FileReadState {
+    // finished files without a known modification time, which are never expired
     Finished,
     RecordsRead(usize),
+    // the modification time of the file in microseconds, used to expire it from the state
+    FinishedModifiedAt(u64),
+}
+
+/// The subtask responsible for reading the file at `location`
+fn task_for_file(location: &Path, parallelism: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    location.hash(&mut hasher);
+    (hasher.finish() as usize) % parallelism
+}
+
+impl FileReadState {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            FileReadState::Finished | FileReadState::FinishedModifiedAt(_)
+        )
+    }
+}
+
+/// Finished files that were last modified before this time (in microseconds) are dropped from
+/// state and are ignored when listing, so that a monitoring source doesn't track every file it
+/// has ever read
+fn retention_cutoff(
+    file_states: &HashMap<String, FileReadState>,
+    retention: Option<Duration>,
+) -> Option<u64> {
+    let retention = retention?;
+    let newest = file_states
+        .values()
+        .filter_map(|state| match state {
+            FileReadState::FinishedModifiedAt(modified_at) => Some(*modified_at),
+            _ => None,
+        })
+        .max()?;
+    Some(newest.saturating_sub(retention.as_micros() as u64))
 }
 
 #[async_trait]
 impl SourceOperator for FileSystemSourceFunc {
     fn tables(&self) -> HashMap<String, TableConfig> {
-        global_table_config("a", "fs")
+        let mut tables = global_table_config("a", "fs");
+        if self.delta {
+            tables.extend(global_table_config("v", "delta versions"));
+        }
+        tables
     }
 
     fn name(&self) -> String {
@@ -61,7 +117,12 @@ impl SourceOperator for FileSystemSourceFunc {
     }
 
     async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
-        match self.run_int(ctx).await {
+        let result = if self.delta {
+            self.run_delta(ctx).await
+        } else {
+            self.run_int(ctx).await
+        };
+        match result {
             Ok(s) => s,
             Err(e) => {
                 ctx.report_error(e.name.clone(), e.details.clone()).await;
@@ -83,13 +144,71 @@ impl FileSystemSourceFunc {
         }
     }
 
+    fn partition_fields(&self) -> &[String] {
+        match &self.table {
+            TableType::Source {
+                partition_fields, ..
+            } => partition_fields,
+            TableType::Sink { .. } => unreachable!(),
+        }
+    }
+
+    /// Lists the files under the path. If there are filters on the partition fields, this walks
+    /// the partition directories so that those that can't match are skipped without listing
+    /// their contents.
+    async fn list_files(
+        &self,
+        storage_provider: &StorageProvider,
+        include_subdirectories: bool,
+        filters: &SourceFilters,
+    ) -> Result<Vec<ObjectMeta>, UserError> {
+        if !filters.has_partition_filters() {
+            return storage_provider
+                .list_with_metadata(include_subdirectories)
+                .await
+                .map_err(|err| UserError::new("could not list files", err.to_string()))?
+                .try_collect()
+                .await
+                .map_err(|err| UserError::new("could not get next path", err.to_string()));
+        }
+
+        let mut files = vec![];
+        let mut directories = vec![None];
+        while let Some(directory) = directories.pop() {
+            let listing = storage_provider
+                .list_directory(directory.as_ref())
+                .await
+                .map_err(|err| UserError::new("could not list files", err.to_string()))?;
+
+            for prefix in listing.common_prefixes {
+                if filters.may_match(
+                    &partition_values(prefix.as_ref(), self.partition_fields()),
+                    false,
+                ) {
+                    directories.push(Some(prefix));
+                } else {
+                    debug!("skipping partition {}", prefix);
+                }
+            }
+
+            files.extend(listing.objects);
+        }
+
+        Ok(files)
+    }
+
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        let (storage_provider, regex_pattern) = match &self.table {
+        let (storage_provider, regex_pattern, monitor_interval, retention) = match &self.table {
             TableType::Source {
                 path,
                 storage_options,
                 compression_format: _,
                 regex_pattern,
+                partition_fields: _,
+                monitor_interval_secs,
+                file_retention_secs,
+                read_snapshot: _,
+                ignore_changes: _,
             } => {
                 let storage_provider =
                     StorageProvider::for_url_with_options(path, storage_options.clone())
@@ -107,7 +226,12 @@ impl FileSystemSourceFunc {
                             err.to_string(),
                         )
                     })?;
-                (storage_provider, matcher)
+                (
+                    storage_provider,
+                    matcher,
+                    monitor_interval_secs.map(|t| Duration::from_secs(t.get())),
+                    file_retention_secs.map(|t| Duration::from_secs(t.get())),
+                )
             }
             TableType::Sink { .. } => {
                 return Err(UserError::new(
@@ -116,37 +240,40 @@ impl FileSystemSourceFunc {
                 ))
             }
         };
-        ctx.initialize_deserializer(
-            self.format.clone(),
-            self.framing.clone(),
-            self.bad_data.clone(),
+        let partition_fields = self.partition_fields().to_vec();
+        if partition_fields.is_empty() {
+            ctx.initialize_deserializer(
+                self.format.clone(),
+                self.framing.clone(),
+                self.bad_data.clone(),
+            );
+        } else {
+            // the partition values are passed to the deserializer as metadata for each record
+            let metadata_fields: Vec<_> = partition_fields
+                .iter()
+                .map(|f| MetadataField {
+                    field_name: f.clone(),
+                    key: f.clone(),
+                })
+                .collect();
+            ctx.initialize_deserializer_with_resolver(
+                self.format.clone(),
+                self.framing.clone(),
+                self.bad_data.clone(),
+                &metadata_fields,
+                Arc::new(FailingSchemaResolver::new()),
+            );
+        }
+
+        let filters = SourceFilters::new(
+            &self.filters,
+            &ctx.out_schema.as_ref().unwrap().schema,
+            &partition_fields,
         );
+
         let parallelism = ctx.task_info.parallelism;
         let task_index = ctx.task_info.task_index;
 
-        // TODO: sort by creation time
-        let mut file_paths = storage_provider
-            .list(regex_pattern.is_some())
-            .await
-            .map_err(|err| UserError::new("could not list files", err.to_string()))?
-            .filter(|path| {
-                let Ok(path) = path else {
-                    return ready(true);
-                };
-                // hash the path and modulo by the number of tasks
-                let mut hasher = DefaultHasher::new();
-                path.hash(&mut hasher);
-                if (hasher.finish() as usize) % parallelism != task_index {
-                    return ready(false);
-                }
-
-                if let Some(matcher) = &regex_pattern {
-                    ready(matcher.is_match(path.as_ref()))
-                } else {
-                    ready(true)
-                }
-            });
-
         let state: &mut GlobalKeyedView<String, (String, FileReadState)> = ctx
             .table_manager
             .get_global_keyed_state("a")
@@ -154,22 +281,109 @@ impl FileSystemSourceFunc {
             .expect("should have table");
         self.file_states = state.get_all().clone().into_values().collect();
 
-        while let Some(path) = file_paths.next().await {
-            let obj_key = path
-                .map_err(|err| UserError::new("could not get next path", err.to_string()))?
-                .to_string();
+        loop {
+            let cutoff = retention_cutoff(&self.file_states, retention);
+
+            let files = self
+                .list_files(
+                    &storage_provider,
+                    regex_pattern.is_some() || !partition_fields.is_empty(),
+                    &filters,
+                )
+                .await?;
+
+            let mut new_files = vec![];
+            for meta in files {
+                if task_for_file(&meta.location, parallelism) != task_index {
+                    continue;
+                }
+
+                if let Some(matcher) = &regex_pattern {
+                    if !matcher.is_match(meta.location.as_ref()) {
+                        continue;
+                    }
+                }
+
+                let obj_key = meta.location.to_string();
+                let modified_at = meta.last_modified.timestamp_micros().max(0) as u64;
+
+                match self.file_states.get(&obj_key) {
+                    // already finished
+                    Some(state) if state.is_finished() => continue,
+                    // files we've started reading are always finished, even if they've since aged
+                    // out of the retention window
+                    Some(FileReadState::RecordsRead(_)) => {}
+                    _ if cutoff.is_some_and(|cutoff| modified_at < cutoff) => continue,
+                    _ => {}
+                }
+
+                if !filters.may_match(&partition_values(&obj_key, &partition_fields), true) {
+                    continue;
+                }
+
+                new_files.push((modified_at, obj_key));
+            }
+
+            // read files in the order they were written, so that new data is picked up in order
+            new_files.sort();
+
+            for (modified_at, obj_key) in new_files {
+                if let Some(finish_type) = self
+                    .read_file(
+                        ctx,
+                        &storage_provider,
+                        &filters,
+                        &obj_key,
+                        partition_values(&obj_key, &partition_fields),
+                        modified_at,
+                    )
+                    .await?
+                {
+                    return Ok(finish_type);
+                }
+            }
+
+            let Some(monitor_interval) = monitor_interval else {
+                info!("FileSystem source finished");
+                return Ok(SourceFinishType::Final);
+            };
 
-            if let Some(FileReadState::Finished) = self.file_states.get(&obj_key) {
-                // already finished
-                continue;
+            if let Some(cutoff) = retention_cutoff(&self.file_states, retention) {
+                self.file_states.retain(|_, state| match state {
+                    FileReadState::FinishedModifiedAt(modified_at) => *modified_at >= cutoff,
+                    _ => true,
+                });
             }
 
-            if let Some(finish_type) = self.read_file(ctx, &storage_provider, &obj_key).await? {
-                return Ok(finish_type);
+            let sleep = tokio::time::sleep(monitor_interval);
+            tokio::pin!(sleep);
+            loop {
+                select! {
+                    _ = &mut sleep => break,
+                    msg_res = ctx.control_rx.recv() => {
+                        if let Some(control_message) = msg_res {
+                            if let Some(finish_type) = self.process_control_message(ctx, control_message).await {
+                                return Ok(finish_type);
+                            }
+                        }
+                    }
+                }
             }
         }
-        info!("FileSystem source finished");
-        Ok(SourceFinishType::Final)
+    }
+
+    async fn get_decompressed_reader(
+        &self,
+        storage_provider: &StorageProvider,
+        path: String,
+    ) -> Box<dyn AsyncRead + Unpin + Send> {
+        let stream_reader = storage_provider.get_as_stream(path).await.unwrap();
+
+        match self.get_compression_format() {
+            CompressionFormat::Zstd => Box::new(ZstdDecoder::new(BufReader::new(stream_reader))),
+            CompressionFormat::Gzip => Box::new(GzipDecoder::new(BufReader::new(stream_reader))),
+            CompressionFormat::None => Box::new(BufReader::new(stream_reader)),
+        }
     }
 
     async fn get_newline_separated_stream(
@@ -179,18 +393,7 @@ impl FileSystemSourceFunc {
     ) -> Result<Box<dyn Stream<Item = Result<String, UserError>> + Unpin + Send>, UserError> {
         match &self.format {
             Format::Json(_) => {
-                let stream_reader = storage_provider.get_as_stream(path).await.unwrap();
-
-                let compression_reader: Box<dyn AsyncRead + Unpin + Send> =
-                    match self.get_compression_format() {
-                        CompressionFormat::Zstd => {
-                            Box::new(ZstdDecoder::new(BufReader::new(stream_reader)))
-                        }
-                        CompressionFormat::Gzip => {
-                            Box::new(GzipDecoder::new(BufReader::new(stream_reader)))
-                        }
-                        CompressionFormat::None => Box::new(BufReader::new(stream_reader)),
-                    };
+                let compression_reader = self.get_decompressed_reader(storage_provider, path).await;
                 // use line iterators
                 let lines = LinesStream::new(BufReader::new(compression_reader).lines());
                 Ok(Box::new(lines.map(|string_result| {
@@ -206,13 +409,64 @@ impl FileSystemSourceFunc {
         }
     }
 
+    /// Reads a CSV file as a stream of records; unlike lines, records may contain newlines
+    /// within quoted fields
+    async fn get_csv_record_stream(
+        &self,
+        storage_provider: &StorageProvider,
+        path: String,
+        format: &CsvFormat,
+    ) -> Box<dyn Stream<Item = Result<String, UserError>> + Unpin + Send> {
+        let reader = self.get_decompressed_reader(storage_provider, path).await;
+        let splitter = RecordSplitter::new(format);
+
+        let records = futures::stream::unfold(
+            (reader, splitter, VecDeque::new(), false),
+            |(mut reader, mut splitter, mut records, mut done)| async move {
+                loop {
+                    if let Some(record) = records.pop_front() {
+                        let record = String::from_utf8(record).map_err(|err| {
+                            UserError::new("could not read record from stream", err.to_string())
+                        });
+                        return Some((record, (reader, splitter, records, done)));
+                    }
+
+                    if done {
+                        return None;
+                    }
+
+                    let mut buf = vec![0; 8192];
+                    match reader.read(&mut buf).await {
+                        Ok(read) => {
+                            // an empty read is the end of the file, which flushes the last record
+                            done = read == 0;
+                            records.extend(splitter.split(&buf[..read]));
+                        }
+                        Err(err) => {
+                            let err = UserError::new(
+                                "could not read record from stream",
+                                err.to_string(),
+                            );
+                            return Some((Err(err), (reader, splitter, records, true)));
+                        }
+                    }
+                }
+            },
+        );
+
+        Box::new(Box::pin(records))
+    }
+
     async fn get_record_batch_stream(
         &mut self,
         storage_provider: &StorageProvider,
         path: String,
-        out_schema: SchemaRef,
+        out_schema: ArroyoSchema,
+        partitions: HashMap<String, Option<String>>,
+        filters: &SourceFilters,
     ) -> Result<Box<dyn Stream<Item = Result<RecordBatch, UserError>> + Unpin + Send>, UserError>
     {
+        let partition_fields = self.partition_fields().to_vec();
         match &self.format {
             Format::Parquet(_) => {
                 let object_meta = storage_provider
@@ -224,7 +478,7 @@ impl FileSystemSourceFunc {
                     })?;
                 let object_reader =
                     ParquetObjectReader::new(storage_provider.get_backing_store(), object_meta);
-                let reader_builder = ParquetRecordBatchStreamBuilder::new(object_reader)
+                let mut reader_builder = ParquetRecordBatchStreamBuilder::new(object_reader)
                     .await
                     .map_err(|err| {
                         UserError::new(
@@ -233,6 +487,22 @@ impl FileSystemSourceFunc {
                         )
                     })?
                     .with_batch_size(8192);
+
+                // skip the row groups whose statistics show they can't match the filters
+                let row_groups = filters.row_groups(
+                    reader_builder.metadata().row_groups(),
+                    reader_builder.parquet_schema(),
+                );
+                if let Some(row_groups) = row_groups {
+                    debug!(
+                        "reading {} of {} row groups from {}",
+                        row_groups.len(),
+                        reader_builder.metadata().num_row_groups(),
+                        path
+                    );
+                    reader_builder = reader_builder.with_row_groups(row_groups);
+                }
+
                 let stream = reader_builder.build().map_err(|err| {
                     UserError::new(
                         "could not build parquet record batch stream",
@@ -241,25 +511,49 @@ impl FileSystemSourceFunc {
                 })?;
                 let result = Box::new(stream.map(move |res| match res {
                     Ok(record_batch) => {
+                        let num_rows = record_batch.num_rows();
+
+                        // partition values come from the path, so any columns for them in the
+                        // file are ignored
+                        let schema = record_batch.schema();
+                        let mut file_columns = record_batch
+                            .columns()
+                            .iter()
+                            .zip(schema.fields())
+                            .filter(|(_, f)| !partition_fields.contains(f.name()))
+                            .map(|(c, _)| c.clone());
+
                         // add timestamp
-                        let mut columns = record_batch.columns().to_vec();
-                            let current_time = to_nanos(SystemTime::now());
-                            let current_time_scalar =
-                                ScalarValue::TimestampNanosecond(Some(current_time as i64), None);
-
-                            let time_column = current_time_scalar
-                                .to_array_of_size(record_batch.num_rows())
-                                .unwrap();
-
-                            columns.push(time_column);
-
-                            let out_batch = RecordBatch::try_new(
-                                out_schema.clone(),
-                                columns
-                            ).map_err(|e| UserError::new("data does not match schema",
-                                format!("The parquet file has a schema that does not match the table schema: {:?}", e)))?;
-                                Ok(out_batch)
-                    },
+                        let current_time = to_nanos(SystemTime::now());
+                        let current_time_scalar =
+                            ScalarValue::TimestampNanosecond(Some(current_time as i64), None);
+                        let time_column = current_time_scalar.to_array_of_size(num_rows).unwrap();
+
+                        let mut columns = vec![];
+                        for (i, field) in out_schema.schema.fields().iter().enumerate() {
+                            if i == out_schema.timestamp_index {
+                                columns.push(time_column.clone());
+                            } else if partition_fields.contains(field.name()) {
+                                let value = partitions.get(field.name()).cloned().flatten();
+                                let column =
+                                    partition_column(value.as_deref(), field.data_type(), num_rows)
+                                        .map_err(|e| {
+                                            UserError::new(
+                                                "invalid partition value",
+                                                format!("{} in {}: {:?}", field.name(), path, e),
+                                            )
+                                        })?;
+                                columns.push(column);
+                            } else if let Some(column) = file_columns.next() {
+                                columns.push(column);
+                            }
+                        }
+
+                        RecordBatch::try_new(out_schema.schema.clone(), columns).map_err(|e| {
+                            UserError::new("data does not match schema",
+                                format!("The parquet file has a schema that does not match the table schema: {:?}", e))
+                        })
+                    }
                     Err(err) => Err(UserError::new(
                         "could not read record batch from stream",
                         err.to_string(),
@@ -276,7 +570,10 @@ impl FileSystemSourceFunc {
         &mut self,
         ctx: &mut ArrowContext,
         storage_provider: &StorageProvider,
+        filters: &SourceFilters,
         obj_key: &String,
+        partitions: HashMap<&str, Option<&str>>,
+        modified_at: u64,
     ) -> Result<Option<SourceFinishType>, UserError> {
         let read_state = self
             .file_states
@@ -284,7 +581,7 @@ impl FileSystemSourceFunc {
             .or_insert(FileReadState::RecordsRead(0));
         let records_read = match read_state {
             FileReadState::RecordsRead(records_read) => *records_read,
-            FileReadState::Finished => {
+            FileReadState::Finished | FileReadState::FinishedModifiedAt(_) => {
                 return Err(UserError::new(
                     "reading finished file",
                     format!("{} has already been read", obj_key),
@@ -292,27 +589,70 @@ impl FileSystemSourceFunc {
             }
         };
 
+        let metadata: HashMap<&str, MetadataValue> = partitions
+            .iter()
+            .filter_map(|(k, v)| Some((*k, MetadataValue::String((*v)?))))
+            .collect();
+
         match self.format {
             Format::Json(_) => {
                 let line_reader = self
                     .get_newline_separated_stream(storage_provider, obj_key.to_string())
                     .await?
                     .skip(records_read);
-                self.read_line_file(ctx, line_reader, obj_key, records_read)
-                    .await
+                self.read_line_file(
+                    ctx,
+                    line_reader,
+                    obj_key,
+                    &metadata,
+                    records_read,
+                    modified_at,
+                )
+                .await
+            }
+            Format::Csv(ref csv) => {
+                let csv = csv.clone();
+                let mut record_reader = self
+                    .get_csv_record_stream(storage_provider, obj_key.to_string(), &csv)
+                    .await;
+
+                // the header is read even when resuming so that we can map its columns
+                if csv.has_header {
+                    if let Some(header) = record_reader.next().await.transpose()? {
+                        ctx.set_csv_header(header.as_bytes()).await?;
+                    }
+                }
+
+                self.read_line_file(
+                    ctx,
+                    record_reader.skip(records_read),
+                    obj_key,
+                    &metadata,
+                    records_read,
+                    modified_at,
+                )
+                .await
             }
             Format::Avro(_) => todo!(),
+            Format::Protobuf(_) => {
+                unreachable!("protobuf is rejected when creating file system sources")
+            }
             Format::Parquet(_) => {
                 let record_batch_stream = self
                     .get_record_batch_stream(
                         storage_provider,
                         obj_key.to_string(),
-                        ctx.out_schema.as_ref().unwrap().schema.clone(),
+                        ctx.out_schema.as_ref().unwrap().clone(),
+                        partitions
+                            .iter()
+                            .map(|(k, v)| (k.to_string(), v.map(|v| v.to_string())))
+                            .collect(),
+                        filters,
                     )
                     .await?
                     .skip(records_read);
 
-                self.read_parquet_file(ctx, record_batch_stream, obj_key, records_read)
+                self.read_parquet_file(ctx, record_batch_stream, obj_key, records_read, modified_at)
                     .await
             }
             Format::RawString(_) => todo!(),
@@ -326,6 +666,7 @@ impl FileSystemSourceFunc {
         mut record_batch_stream: impl Stream<Item = Result<RecordBatch, UserError>> + Unpin + Send,
         obj_key: &String,
         mut records_read: usize,
+        modified_at: u64,
     ) -> Result<Option<SourceFinishType>, UserError> {
         loop {
             select! {
@@ -337,7 +678,7 @@ impl FileSystemSourceFunc {
                         }
                         None => {
                             info!("finished reading file {}", obj_key);
-                            self.file_states.insert(obj_key.to_string(), FileReadState::Finished);
+                            self.file_states.insert(obj_key.to_string(), FileReadState::FinishedModifiedAt(modified_at));
                             return Ok(None);
                         }
                     }
@@ -359,14 +700,16 @@ impl FileSystemSourceFunc {
         ctx: &mut ArrowContext,
         mut line_reader: impl Stream<Item = Result<String, UserError>> + Unpin + Send,
         obj_key: &String,
+        metadata: &HashMap<&str, MetadataValue<'_>>,
         mut records_read: usize,
+        modified_at: u64,
     ) -> Result<Option<SourceFinishType>, UserError> {
         loop {
             select! {
                 line = line_reader.next() => {
                     match line.transpose()? {
                         Some(line) => {
-                            ctx.deserialize_slice(line.as_bytes(), SystemTime::now()).await?;
+                            ctx.deserialize_slice(line.as_bytes(), SystemTime::now(), Some(metadata)).await?;
                             records_read += 1;
                             if ctx.should_flush() {
                                 ctx.flush_buffer().await?;
@@ -375,7 +718,7 @@ impl FileSystemSourceFunc {
                         None => {
                             info!("finished reading file {}", obj_key);
                             ctx.flush_buffer().await?;
-                            self.file_states.insert(obj_key.to_string(), FileReadState::Finished);
+                            self.file_states.insert(obj_key.to_string(), FileReadState::FinishedModifiedAt(modified_at));
                             return Ok(None);
                         }
                     }
@@ -407,6 +750,20 @@ impl FileSystemSourceFunc {
                         .insert(file.clone(), (file.clone(), read_state.clone()))
                         .await;
                 }
+                if let Some(version) = self.delta_version {
+                    ctx.table_manager
+                        .get_global_keyed_state("v")
+                        .await
+                        .unwrap()
+                        .insert(
+                            ctx.task_info.task_index,
+                            delta::DeltaVersionState {
+                                parallelism: ctx.task_info.parallelism,
+                                version,
+                            },
+                        )
+                        .await;
+                }
                 // checkpoint our state
                 if self.start_checkpoint(c, ctx).await {
                     Some(SourceFinishType::Immediate)
@@ -428,3 +785,42 @@ impl FileSystemSourceFunc {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{retention_cutoff, FileReadState};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retention_cutoff() {
+        let mut states = HashMap::from([
+            (
+                "a".to_string(),
+                FileReadState::FinishedModifiedAt(5_000_000),
+            ),
+            (
+                "b".to_string(),
+                FileReadState::FinishedModifiedAt(12_000_000),
+            ),
+            ("c".to_string(), FileReadState::RecordsRead(10)),
+            ("d".to_string(), FileReadState::Finished),
+        ]);
+
+        assert_eq!(retention_cutoff(&states, None), None);
+        assert_eq!(
+            retention_cutoff(&states, Some(Duration::from_secs(5))),
+            Some(7_000_000)
+        );
+        assert_eq!(
+            retention_cutoff(&states, Some(Duration::from_secs(60))),
+            Some(0)
+        );
+
+        states.retain(|_, s| !matches!(s, FileReadState::FinishedModifiedAt(_)));
+        assert_eq!(
+            retention_cutoff(&states, Some(Duration::from_secs(5))),
+            None
+        );
+    }
+}
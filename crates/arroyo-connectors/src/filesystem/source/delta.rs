@@ -0,0 +1,540 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::SourceFinishType;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_storage::StorageProvider;
+use arroyo_types::UserError;
+use bincode::{Decode, Encode};
+use deltalake::kernel::{Action, Add, Protocol, ReaderFeatures};
+use deltalake::table::PeekCommit;
+use deltalake::{DeltaTable, DeltaTableBuilder};
+use object_store::path::Path;
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::{task_for_file, FileReadState, FileSystemSourceFunc};
+use crate::filesystem::pruning::SourceFilters;
+use crate::filesystem::sink::delta::configure_storage_options;
+use crate::filesystem::TableType;
+
+// how often the transaction log is checked for new commits, if no monitor interval is set
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The table version through which a subtask has read all of the files it's responsible for,
+/// along with the parallelism at the time, which determines which files those were
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct DeltaVersionState {
+    pub parallelism: usize,
+    pub version: i64,
+}
+
+/// The versions that each subtask had read through when the state was checkpointed, which
+/// determine where reading resumes after a restore, possibly with a different parallelism
+#[derive(Debug, Default)]
+struct RestoredVersions {
+    parallelism: usize,
+    versions: HashMap<usize, i64>,
+}
+
+impl RestoredVersions {
+    fn new(state: &HashMap<usize, DeltaVersionState>) -> Self {
+        Self {
+            parallelism: state
+                .values()
+                .map(|s| s.parallelism)
+                .max()
+                .unwrap_or_default(),
+            versions: state.iter().map(|(task, s)| (*task, s.version)).collect(),
+        }
+    }
+
+    /// The version that every subtask had read through, or None if this is a new source or some
+    /// of the subtasks were still reading the snapshot
+    fn resume_version(&self) -> Option<i64> {
+        if (0..self.parallelism).any(|task| !self.versions.contains_key(&task)) {
+            return None;
+        }
+        self.versions.values().min().copied()
+    }
+
+    /// The version through which the subtask that was responsible for the file had read
+    fn read_through(&self, location: &Path) -> Option<i64> {
+        if self.parallelism == 0 {
+            return None;
+        }
+        self.versions
+            .get(&task_for_file(location, self.parallelism))
+            .copied()
+    }
+}
+
+/// A data file in the table, from an add action in the log
+struct DeltaFile {
+    location: Path,
+    partitions: HashMap<String, Option<String>>,
+    // in microseconds
+    modified_at: u64,
+}
+
+impl DeltaFile {
+    fn new(storage_provider: &StorageProvider, add: &Add) -> Result<Self, UserError> {
+        if add.path.contains("://") {
+            return Err(UserError::new(
+                "unsupported Delta table",
+                format!(
+                    "data file {} is outside of the table directory, which is not supported",
+                    add.path
+                ),
+            ));
+        }
+        let path = Path::from_url_path(&add.path).map_err(|e| {
+            UserError::new(
+                "invalid path in Delta table",
+                format!("{}: {}", add.path, e),
+            )
+        })?;
+
+        Ok(Self {
+            location: storage_provider.qualify_path(&path),
+            partitions: add.partition_values.clone(),
+            modified_at: add.modification_time.max(0) as u64 * 1000,
+        })
+    }
+
+    fn partitions(&self) -> HashMap<&str, Option<&str>> {
+        self.partitions
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_deref()))
+            .collect()
+    }
+}
+
+fn delta_error(err: impl ToString) -> UserError {
+    UserError::new("failed to read Delta table", err.to_string())
+}
+
+/// The URI of the table, in the form expected by delta-rs
+fn table_uri(storage_provider: &StorageProvider) -> String {
+    let key = storage_provider.qualify_path(&Path::default());
+    if key.as_ref().is_empty() {
+        storage_provider.object_store_base_url().to_string()
+    } else {
+        format!("{}/{}", storage_provider.object_store_base_url(), key)
+    }
+}
+
+async fn load_table(
+    table_uri: &str,
+    storage_options: &HashMap<String, String>,
+    version: Option<i64>,
+) -> Result<DeltaTable, UserError> {
+    deltalake::aws::register_handlers(None);
+    let mut builder =
+        DeltaTableBuilder::from_uri(table_uri).with_storage_options(storage_options.clone());
+    if let Some(version) = version {
+        builder = builder.with_version(version);
+    }
+    builder.load().await.map_err(delta_error)
+}
+
+fn snapshot_files(
+    storage_provider: &StorageProvider,
+    table: &DeltaTable,
+) -> Result<Vec<DeltaFile>, UserError> {
+    table
+        .snapshot()
+        .map_err(delta_error)?
+        .file_actions()
+        .map_err(delta_error)?
+        .iter()
+        .map(|add| DeltaFile::new(storage_provider, add))
+        .collect()
+}
+
+/// Checks that the table doesn't use features that change which rows are in a data file, as the
+/// source reads the files in full
+fn check_protocol(protocol: &Protocol) -> Result<(), UserError> {
+    if protocol
+        .reader_features
+        .as_ref()
+        .is_some_and(|f| f.contains(&ReaderFeatures::DeletionVectors))
+    {
+        return Err(UserError::new(
+            "unsupported Delta table",
+            "tables with deletion vectors are not supported by the Delta Lake source".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The files added by a commit. Files that are only rewritten, like by compaction, don't change
+/// the data and are skipped. A streaming source can't retract data, so commits that remove data
+/// (like updates and deletes) fail the source unless `ignore_changes` is set, in which case the
+/// removes are skipped and the files they add are read.
+fn commit_files(
+    storage_provider: &StorageProvider,
+    version: i64,
+    actions: &[Action],
+    ignore_changes: bool,
+) -> Result<Vec<DeltaFile>, UserError> {
+    let mut files = vec![];
+    for action in actions {
+        match action {
+            Action::Protocol(protocol) => check_protocol(protocol)?,
+            Action::Add(add) if add.deletion_vector.is_some() => {
+                return Err(UserError::new(
+                    "unsupported Delta table",
+                    format!(
+                        "data file {} has a deletion vector, which is not supported by the Delta Lake source",
+                        add.path
+                    ),
+                ));
+            }
+            Action::Add(add) if add.data_change => {
+                files.push(DeltaFile::new(storage_provider, add)?)
+            }
+            Action::Remove(remove) if remove.data_change => {
+                if !ignore_changes {
+                    return Err(UserError::new(
+                        "unsupported change to Delta table",
+                        format!(
+                            "version {} of the table removes data from {}, which can't be retracted by \
+                            the Delta Lake source; set 'source.ignore_changes' to skip removals",
+                            version, remove.path
+                        ),
+                    ));
+                }
+                warn!(
+                    "ignoring removal of {} from Delta table in version {}",
+                    remove.path, version
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(files)
+}
+
+impl FileSystemSourceFunc {
+    /// Reads a Delta Lake table, optionally starting with the files in its current snapshot, and
+    /// then tails the transaction log, reading the files added by each new commit
+    pub(super) async fn run_delta(
+        &mut self,
+        ctx: &mut ArrowContext,
+    ) -> Result<SourceFinishType, UserError> {
+        let TableType::Source {
+            path,
+            storage_options,
+            monitor_interval_secs,
+            read_snapshot,
+            ignore_changes,
+            ..
+        } = &self.table
+        else {
+            return Err(UserError::new(
+                "invalid table config",
+                "Delta Lake source cannot be used as a sink".to_string(),
+            ));
+        };
+        let poll_interval = monitor_interval_secs
+            .map(|t| Duration::from_secs(t.get()))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        let read_snapshot = read_snapshot.unwrap_or(true);
+        let ignore_changes = ignore_changes.unwrap_or(false);
+
+        let storage_provider = Arc::new(
+            StorageProvider::for_url_with_options(path, storage_options.clone())
+                .await
+                .map_err(|err| {
+                    UserError::new("failed to create storage provider", err.to_string())
+                })?,
+        );
+        let table_uri = table_uri(&storage_provider);
+        let delta_options = configure_storage_options(&table_uri, storage_provider.clone())
+            .await
+            .map_err(delta_error)?;
+        let table = load_table(&table_uri, &delta_options, None).await?;
+        check_protocol(table.protocol().map_err(delta_error)?)?;
+
+        // partition values aren't stored in the data files, so they're taken from the log
+        let schema = ctx.out_schema.as_ref().unwrap().schema.clone();
+        let partition_fields: Vec<String> = table
+            .metadata()
+            .map_err(delta_error)?
+            .partition_columns
+            .iter()
+            .filter(|c| schema.index_of(c).is_ok())
+            .cloned()
+            .collect();
+        if let TableType::Source {
+            partition_fields: fields,
+            ..
+        } = &mut self.table
+        {
+            fields.clone_from(&partition_fields);
+        }
+
+        ctx.initialize_deserializer(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+        );
+        let filters = SourceFilters::new(&self.filters, &schema, &partition_fields);
+
+        let parallelism = ctx.task_info.parallelism;
+        let task_index = ctx.task_info.task_index;
+
+        let state: &mut GlobalKeyedView<String, (String, FileReadState)> = ctx
+            .table_manager
+            .get_global_keyed_state("a")
+            .await
+            .expect("should have table");
+        self.file_states = state.get_all().clone().into_values().collect();
+
+        let state: &mut GlobalKeyedView<usize, DeltaVersionState> = ctx
+            .table_manager
+            .get_global_keyed_state("v")
+            .await
+            .expect("should have table");
+        let restored = RestoredVersions::new(state.get_all());
+
+        let mut version = match restored.resume_version() {
+            Some(version) => {
+                info!(
+                    "resuming Delta table {} after version {}",
+                    table_uri, version
+                );
+                version
+            }
+            None if !read_snapshot && restored.versions.is_empty() => table.version(),
+            None => {
+                // the subtasks that finished the snapshot before the restore read the files
+                // that were in the table at the version they recorded
+                let mut read_snapshots: HashMap<i64, HashSet<Path>> = HashMap::new();
+                for version in restored.versions.values() {
+                    if !read_snapshots.contains_key(version) {
+                        let table = load_table(&table_uri, &delta_options, Some(*version)).await?;
+                        let files = snapshot_files(&storage_provider, &table)?;
+                        read_snapshots
+                            .insert(*version, files.into_iter().map(|f| f.location).collect());
+                    }
+                }
+
+                let files = snapshot_files(&storage_provider, &table)?;
+                let mut to_read: Vec<_> = files
+                    .iter()
+                    .filter(|file| {
+                        task_for_file(&file.location, parallelism) == task_index
+                            && !self
+                                .file_states
+                                .get(file.location.as_ref())
+                                .is_some_and(|s| s.is_finished())
+                            && !restored
+                                .read_through(&file.location)
+                                .is_some_and(|v| read_snapshots[&v].contains(&file.location))
+                            && filters.may_match(&file.partitions(), true)
+                    })
+                    .collect();
+                // read files in the order they were written
+                to_read.sort_by(|a, b| {
+                    (a.modified_at, &a.location).cmp(&(b.modified_at, &b.location))
+                });
+
+                info!(
+                    "reading {} files from the snapshot of Delta table {} at version {}",
+                    to_read.len(),
+                    table_uri,
+                    table.version()
+                );
+                for file in to_read {
+                    if let Some(finish_type) = self
+                        .read_file(
+                            ctx,
+                            &storage_provider,
+                            &filters,
+                            &file.location.to_string(),
+                            file.partitions(),
+                            file.modified_at,
+                        )
+                        .await?
+                    {
+                        return Ok(finish_type);
+                    }
+                }
+
+                // the version now covers these files, so they no longer need to be tracked
+                for file in &files {
+                    self.file_states.remove(file.location.as_ref());
+                }
+                table.version()
+            }
+        };
+        self.delta_version = Some(version);
+
+        loop {
+            let PeekCommit::New(next, actions) =
+                table.peek_next_commit(version).await.map_err(delta_error)?
+            else {
+                let sleep = tokio::time::sleep(poll_interval);
+                tokio::pin!(sleep);
+                loop {
+                    select! {
+                        _ = &mut sleep => break,
+                        msg_res = ctx.control_rx.recv() => {
+                            if let Some(control_message) = msg_res {
+                                if let Some(finish_type) = self.process_control_message(ctx, control_message).await {
+                                    return Ok(finish_type);
+                                }
+                            }
+                        }
+                    }
+                }
+                continue;
+            };
+
+            let files = commit_files(&storage_provider, next, &actions, ignore_changes)?;
+            for file in &files {
+                if task_for_file(&file.location, parallelism) != task_index
+                    || self
+                        .file_states
+                        .get(file.location.as_ref())
+                        .is_some_and(|s| s.is_finished())
+                    || restored
+                        .read_through(&file.location)
+                        .is_some_and(|v| next <= v)
+                    || !filters.may_match(&file.partitions(), true)
+                {
+                    continue;
+                }
+
+                if let Some(finish_type) = self
+                    .read_file(
+                        ctx,
+                        &storage_provider,
+                        &filters,
+                        &file.location.to_string(),
+                        file.partitions(),
+                        file.modified_at,
+                    )
+                    .await?
+                {
+                    return Ok(finish_type);
+                }
+            }
+
+            debug!(
+                "finished reading version {} of Delta table {}",
+                next, table_uri
+            );
+            for file in &files {
+                self.file_states.remove(file.location.as_ref());
+            }
+            version = next;
+            self.delta_version = Some(version);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{commit_files, DeltaVersionState, RestoredVersions};
+    use crate::filesystem::source::task_for_file;
+    use arroyo_storage::StorageProvider;
+    use deltalake::kernel::{Action, Add, Remove};
+    use object_store::path::Path;
+    use std::collections::HashMap;
+
+    fn state(parallelism: usize, versions: &[(usize, i64)]) -> HashMap<usize, DeltaVersionState> {
+        versions
+            .iter()
+            .map(|(task, version)| {
+                (
+                    *task,
+                    DeltaVersionState {
+                        parallelism,
+                        version: *version,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_version() {
+        assert_eq!(
+            RestoredVersions::new(&HashMap::new()).resume_version(),
+            None
+        );
+
+        let restored = RestoredVersions::new(&state(3, &[(0, 7), (1, 5), (2, 9)]));
+        assert_eq!(restored.resume_version(), Some(5));
+
+        // subtask 1 hadn't finished reading the snapshot
+        let restored = RestoredVersions::new(&state(3, &[(0, 7), (2, 9)]));
+        assert_eq!(restored.resume_version(), None);
+    }
+
+    #[test]
+    fn test_read_through() {
+        let restored = RestoredVersions::new(&state(2, &[(0, 7), (1, 5)]));
+
+        for name in ["a.parquet", "b.parquet", "dt=2024-01-01/c.parquet"] {
+            let path = Path::from(name);
+            let expected = if task_for_file(&path, 2) == 0 { 7 } else { 5 };
+            assert_eq!(restored.read_through(&path), Some(expected));
+        }
+
+        assert_eq!(
+            RestoredVersions::default().read_through(&Path::from("a.parquet")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_files() {
+        let storage_provider = StorageProvider::for_url("file:///tmp/arroyo/delta/commit-files")
+            .await
+            .unwrap();
+
+        let add = |path: &str, data_change| {
+            Action::Add(Add {
+                path: path.to_string(),
+                data_change,
+                ..Default::default()
+            })
+        };
+        let remove = |path: &str, data_change| {
+            Action::Remove(Remove {
+                path: path.to_string(),
+                data_change,
+                ..Default::default()
+            })
+        };
+
+        // files that are only rewritten by compaction are skipped
+        let actions = vec![
+            add("a.parquet", true),
+            add("b.parquet", false),
+            remove("c.parquet", false),
+        ];
+        let files = commit_files(&storage_provider, 1, &actions, false).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].location,
+            storage_provider.qualify_path(&Path::from("a.parquet"))
+        );
+
+        // removing data fails unless changes are ignored
+        let actions = vec![remove("a.parquet", true), add("d.parquet", true)];
+        assert!(commit_files(&storage_provider, 2, &actions, false).is_err());
+        let files = commit_files(&storage_provider, 2, &actions, true).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].location,
+            storage_provider.qualify_path(&Path::from("d.parquet"))
+        );
+    }
+}
@@ -154,6 +154,7 @@ impl Connector for FluvioConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -190,10 +191,11 @@ impl Connector for FluvioConnector {
                 topic: table.topic,
                 endpoint: table.endpoint,
                 producer: None,
-                serializer: ArrowSerializer::new(
+                serializer: ArrowSerializer::with_framing(
                     config
                         .format
                         .ok_or_else(|| anyhow!("format required for fluvio sink"))?,
+                    config.framing,
                 ),
             }))),
         }
@@ -1,7 +1,8 @@
 use anyhow::{anyhow, bail};
+use arrow::datatypes::{DataType, Schema, TimeUnit};
 use arroyo_formats::de::ArrowDeserializer;
 use arroyo_formats::ser::ArrowSerializer;
-use arroyo_operator::connector::Connection;
+use arroyo_operator::connector::{Connection, MetadataDef};
 use arroyo_rpc::api_types::connections::{ConnectionProfile, ConnectionSchema, TestSourceMessage};
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{BadData, Format, JsonFormat};
@@ -31,7 +32,9 @@ use typify::import_types;
 
 use crate::{pull_opt, send, ConnectionType};
 
-use crate::kafka::sink::KafkaSinkFunc;
+use crate::kafka::sink::{
+    validate_exactly_once, validate_message_fields, KafkaSinkFunc, KeySerializer,
+};
 use crate::kafka::source::KafkaSourceFunc;
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
@@ -60,6 +63,35 @@ impl KafkaTable {
     }
 }
 
+static METADATA_DEFS: [MetadataDef; 6] = [
+    // keys are arbitrary bytes, but may also be read into a TEXT field
+    MetadataDef {
+        name: "key",
+        data_type: DataType::Binary,
+    },
+    MetadataDef {
+        name: "topic",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "partition",
+        data_type: DataType::Int32,
+    },
+    MetadataDef {
+        name: "offset",
+        data_type: DataType::Int64,
+    },
+    MetadataDef {
+        name: "timestamp",
+        data_type: DataType::Timestamp(TimeUnit::Nanosecond, None),
+    },
+    // headers are exposed as a JSON object of header names to values
+    MetadataDef {
+        name: "headers",
+        data_type: DataType::Utf8,
+    },
+];
+
 pub struct KafkaConnector {}
 
 impl KafkaConnector {
@@ -125,6 +157,16 @@ impl KafkaConnector {
                         Some("exactly_once") => SinkCommitMode::ExactlyOnce,
                         Some(other) => bail!("invalid value for commit_mode '{}'", other),
                     },
+                    key_field: options.remove("sink.key_field"),
+                    key_format: match options.remove("sink.key_format").as_deref() {
+                        Some("raw_string") => Some(SinkKeyFormat::RawString),
+                        Some("raw_bytes") => Some(SinkKeyFormat::RawBytes),
+                        Some("json") => Some(SinkKeyFormat::Json),
+                        None => None,
+                        Some(other) => bail!("invalid value for sink.key_format '{}'", other),
+                    },
+                    headers_field: options.remove("sink.headers_field"),
+                    partition_field: options.remove("sink.partition_field"),
                 }
             }
             _ => {
@@ -174,6 +216,10 @@ impl Connector for KafkaConnector {
         }
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        &METADATA_DEFS
+    }
+
     fn config_description(&self, config: Self::ProfileT) -> String {
         (*config.bootstrap_servers).clone()
     }
@@ -204,6 +250,9 @@ impl Connector for KafkaConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for Kafka connection"))?;
 
+        validate_sink_fields(&table, &schema)?;
+        validate_exactly_once(&config, &table)?;
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -211,6 +260,8 @@ impl Connector for KafkaConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: schema.metadata_fields(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -323,6 +374,33 @@ impl Connector for KafkaConnector {
         Self::from_config(self, None, name, connection, table, schema)
     }
 
+    fn validate_sink_schema(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        schema: &Schema,
+    ) -> anyhow::Result<()> {
+        let TableType::Sink {
+            key_field,
+            key_format,
+            headers_field,
+            partition_field,
+            ..
+        } = &table.type_
+        else {
+            return Ok(());
+        };
+
+        validate_message_fields(
+            key_field
+                .as_deref()
+                .map(|f| (f, key_format.unwrap_or(SinkKeyFormat::RawString))),
+            headers_field.as_deref(),
+            partition_field.as_deref(),
+            schema,
+        )
+    }
+
     fn make_operator(
         &self,
         profile: Self::ProfileT,
@@ -372,6 +450,7 @@ impl Connector for KafkaConnector {
                     framing: config.framing,
                     schema_resolver,
                     bad_data: config.bad_data,
+                    metadata_fields: config.metadata_fields,
                     client_configs,
                     messages_per_second: NonZeroU32::new(
                         config
@@ -382,23 +461,70 @@ impl Connector for KafkaConnector {
                     .unwrap(),
                 })))
             }
-            TableType::Sink { commit_mode } => {
-                Ok(OperatorNode::from_operator(Box::new(KafkaSinkFunc {
-                    bootstrap_servers: profile.bootstrap_servers.to_string(),
-                    producer: None,
-                    consistency_mode: (*commit_mode).into(),
-                    write_futures: vec![],
-                    client_config: client_configs(&profile, &table),
-                    topic: table.topic,
-                    serializer: ArrowSerializer::new(
-                        config.format.expect("Format must be defined for KafkaSink"),
-                    ),
-                })))
-            }
+            TableType::Sink {
+                commit_mode,
+                key_field,
+                key_format,
+                headers_field,
+                partition_field,
+            } => Ok(OperatorNode::from_operator(Box::new(KafkaSinkFunc {
+                bootstrap_servers: profile.bootstrap_servers.to_string(),
+                producer: None,
+                consistency_mode: (*commit_mode).into(),
+                write_futures: vec![],
+                client_config: client_configs(&profile, &table),
+                topic: table.topic,
+                serializer: ArrowSerializer::with_framing(
+                    config.format.expect("Format must be defined for KafkaSink"),
+                    config.framing,
+                ),
+                key: key_field.clone().map(|field| {
+                    KeySerializer::new(field, key_format.unwrap_or(SinkKeyFormat::RawString))
+                }),
+                headers_field: headers_field.clone(),
+                partition_field: partition_field.clone(),
+            }))),
         }
     }
 }
 
+/// Checks that the columns used for the key, headers and partition of messages written by a
+/// sink exist
+fn validate_sink_fields(table: &KafkaTable, schema: &ConnectionSchema) -> anyhow::Result<()> {
+    let TableType::Sink {
+        key_field,
+        headers_field,
+        partition_field,
+        ..
+    } = &table.type_
+    else {
+        return Ok(());
+    };
+
+    let fields = [key_field, headers_field, partition_field];
+    if fields.iter().all(|f| f.is_none()) {
+        return Ok(());
+    }
+
+    if schema.framing.is_some() {
+        bail!("key, headers and partition fields can't be used with framing, as multiple rows are written to each message");
+    }
+
+    // the schema may be inferred from the query, in which case it's checked when the query
+    // is planned
+    if schema.fields.is_empty() {
+        return Ok(());
+    }
+
+    for field in fields.into_iter().flatten() {
+        if !schema.fields.iter().any(|f| &f.field_name == field) {
+            bail!("field '{}' does not exist in the sink table", field);
+        }
+    }
+
+    Ok(())
+}
+
 pub struct KafkaTester {
     pub connection: KafkaConfig,
 }
@@ -607,13 +733,14 @@ impl KafkaTester {
                         format.clone(),
                         None,
                         aschema.clone(),
+                        &schema.metadata_fields(),
                         BadData::Fail {},
                         Arc::new(schema_resolver),
                     );
                     let mut builders = aschema.builders();
 
                     let mut error = deserializer
-                        .deserialize_slice(&mut builders, &msg, SystemTime::now())
+                        .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
                         .await
                         .into_iter()
                         .next();
@@ -635,7 +762,7 @@ impl KafkaTester {
                     let mut builders = aschema.builders();
 
                     let mut error = deserializer
-                        .deserialize_slice(&mut builders, &msg, SystemTime::now())
+                        .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
                         .await
                         .into_iter()
                         .next();
@@ -652,6 +779,54 @@ impl KafkaTester {
                     }
                 }
             }
+            Format::Protobuf(proto) => {
+                if proto.confluent_schema_registry && msg.first() != Some(&0) {
+                    bail!("Message appears to be encoded as normal Protobuf, rather than SR-Protobuf, but the schema registry is enabled. Ensure that the format and schema type are correct.");
+                }
+
+                let aschema: ArroyoSchema = schema.clone().into();
+                let mut deserializer =
+                    ArrowDeserializer::new(format.clone(), aschema.clone(), None, BadData::Fail {});
+                let mut builders = aschema.builders();
+
+                let mut error = deserializer
+                    .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
+                    .await
+                    .into_iter()
+                    .next();
+                if let Some(Err(e)) = deserializer.flush_buffer() {
+                    error.replace(e);
+                }
+
+                if let Some(error) = error {
+                    if proto.confluent_schema_registry {
+                        bail!("Failed to parse message as schema-registry Protobuf (SR-Protobuf): {:?}. Ensure that the format and schema type are correct.", error.details());
+                    } else if msg.first() == Some(&0) {
+                        bail!("Failed to parse message as regular Protobuf. It may be encoded as SR-Protobuf, but the schema registry is not enabled. Ensure that the format and schema type are correct.");
+                    } else {
+                        bail!("Failed to parse message as Protobuf: {:?}. Ensure that the format and schema type are correct.", error.details());
+                    }
+                }
+            }
+            Format::Csv(_) => {
+                let aschema: ArroyoSchema = schema.clone().into();
+                let mut deserializer =
+                    ArrowDeserializer::new(format.clone(), aschema.clone(), None, BadData::Fail {});
+                let mut builders = aschema.builders();
+
+                let mut error = deserializer
+                    .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
+                    .await
+                    .into_iter()
+                    .next();
+                if let Some(Err(e)) = deserializer.flush_buffer() {
+                    error.replace(e);
+                }
+
+                if let Some(error) = error {
+                    bail!("Failed to parse message as CSV: {:?}. Ensure that the format and schema type are correct.", error.details());
+                }
+            }
             Format::Parquet(_) => {
                 unreachable!()
             }
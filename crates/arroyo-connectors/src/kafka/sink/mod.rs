@@ -1,56 +1,374 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
 use arroyo_types::*;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 
-use rdkafka::ClientConfig;
+use rdkafka::{ClientConfig, ClientContext};
 
-use arrow::array::RecordBatch;
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch};
+use arrow::compute::{can_cast_types, cast};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::formats::{Format, JsonFormat, RawBytesFormat, RawStringFormat};
+use arroyo_state::global_table_config;
 use arroyo_types::CheckpointBarrier;
 use async_trait::async_trait;
+use bincode::{Decode, Encode};
 use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use serde::Deserialize;
 use std::time::{Duration, SystemTime};
 
-use super::SinkCommitMode;
+use super::{
+    KafkaConfig, KafkaConfigAuthentication, KafkaTable, SinkCommitMode, SinkKeyFormat, TableType,
+};
 
 #[cfg(test)]
 mod test;
+mod transactions;
+
+use transactions::RecoveryOutcome;
+
+const MAX_COMMIT_ATTEMPTS: u32 = 10;
 
 pub struct KafkaSinkFunc {
     pub topic: String,
     pub bootstrap_servers: String,
     pub consistency_mode: ConsistencyMode,
-    pub producer: Option<FutureProducer>,
+    pub producer: Option<FutureProducer<SinkProducerContext>>,
     pub write_futures: Vec<DeliveryFuture>,
     pub client_config: HashMap<String, String>,
     pub serializer: ArrowSerializer,
+    pub key: Option<KeySerializer>,
+    pub headers_field: Option<String>,
+    pub partition_field: Option<String>,
+}
+
+/// Serializes a column of the input into the keys of the messages
+pub struct KeySerializer {
+    field: String,
+    format: SinkKeyFormat,
+    serializer: ArrowSerializer,
+}
+
+impl KeySerializer {
+    pub fn new(field: String, format: SinkKeyFormat) -> Self {
+        let serializer = ArrowSerializer::new(match format {
+            SinkKeyFormat::RawString => Format::RawString(RawStringFormat {}),
+            SinkKeyFormat::RawBytes => Format::RawBytes(RawBytesFormat {}),
+            SinkKeyFormat::Json => Format::Json(JsonFormat::default()),
+        });
+
+        Self {
+            field,
+            format,
+            serializer,
+        }
+    }
+
+    /// Checks that the key field is in the schema and can be written in the key format
+    fn validate(field: &str, format: SinkKeyFormat, schema: &Schema) -> Result<()> {
+        let data_type = message_field_type(schema, "key", field)?;
+
+        let target = match format {
+            SinkKeyFormat::RawString => DataType::Utf8,
+            SinkKeyFormat::RawBytes => DataType::Binary,
+            SinkKeyFormat::Json => return Ok(()),
+        };
+
+        if !can_cast_types(data_type, &target) {
+            bail!(
+                "key field '{}' has type {}, which can't be converted to {}",
+                field,
+                data_type,
+                target
+            );
+        }
+
+        Ok(())
+    }
+
+    fn serialize(&mut self, batch: &RecordBatch) -> Result<Vec<Option<Vec<u8>>>> {
+        let column = message_field(batch, "key", &self.field)?;
+
+        let key_batch = match (self.format, column.as_struct_opt()) {
+            (SinkKeyFormat::Json, Some(fields)) => RecordBatch::from(fields.clone()),
+            (SinkKeyFormat::RawString, _) => {
+                single_column_batch("value", cast(column, &DataType::Utf8)?)
+            }
+            (SinkKeyFormat::RawBytes, _) => {
+                single_column_batch("value", cast(column, &DataType::Binary)?)
+            }
+            (SinkKeyFormat::Json, None) => single_column_batch(&self.field, column.clone()),
+        };
+
+        Ok(self
+            .serializer
+            .serialize(&key_batch)
+            .enumerate()
+            .map(|(i, key)| column.is_valid(i).then_some(key))
+            .collect())
+    }
+}
+
+/// Checks that an exactly-once sink's pending transactions can be committed after a restart,
+/// which is done with our own client that doesn't support every security setting of the producer
+pub fn validate_exactly_once(connection: &KafkaConfig, table: &KafkaTable) -> Result<()> {
+    let TableType::Sink {
+        commit_mode: SinkCommitMode::ExactlyOnce,
+        ..
+    } = &table.type_
+    else {
+        return Ok(());
+    };
+
+    let mut config = HashMap::new();
+    if let KafkaConfigAuthentication::Sasl {
+        mechanism,
+        protocol,
+        ..
+    } = &connection.authentication
+    {
+        config.insert("sasl.mechanism".to_string(), mechanism.to_string());
+        config.insert("security.protocol".to_string(), protocol.to_string());
+    }
+    config.extend(table.client_configs.clone());
+
+    transactions::check_security(&config).map_err(|e| {
+        anyhow!(
+            "exactly-once Kafka sinks can't be used with this connection: {}",
+            e
+        )
+    })
+}
+
+/// Checks that the columns used for the key, headers and partition of messages are in the
+/// sink's input and can be converted into those parts of the messages
+pub fn validate_message_fields(
+    key: Option<(&str, SinkKeyFormat)>,
+    headers_field: Option<&str>,
+    partition_field: Option<&str>,
+    schema: &Schema,
+) -> Result<()> {
+    if let Some((field, format)) = key {
+        KeySerializer::validate(field, format, schema)?;
+    }
+
+    if let Some(field) = headers_field {
+        let data_type = message_field_type(schema, "headers", field)?;
+        let valid = match data_type {
+            DataType::Struct(fields) => fields.iter().all(|f| is_header_value(f.data_type())),
+            DataType::Map(entries, _) => match entries.data_type() {
+                DataType::Struct(entry) if entry.len() == 2 => {
+                    can_cast_types(entry[0].data_type(), &DataType::Utf8)
+                        && is_header_value(entry[1].data_type())
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+
+        if !valid {
+            bail!(
+                "headers field '{}' must be a struct or map with values that can be converted to TEXT, not {}",
+                field,
+                data_type
+            );
+        }
+    }
+
+    if let Some(field) = partition_field {
+        let data_type = message_field_type(schema, "partition", field)?;
+        if !data_type.is_integer() {
+            bail!(
+                "partition field '{}' must be an integer, not {}",
+                field,
+                data_type
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn message_field_type<'a>(schema: &'a Schema, kind: &str, field: &str) -> Result<&'a DataType> {
+    schema
+        .field_with_name(field)
+        .map(|f| f.data_type())
+        .map_err(|_| anyhow!("{} field '{}' is not in the sink's input", kind, field))
+}
+
+fn message_field<'a>(batch: &'a RecordBatch, kind: &str, field: &str) -> Result<&'a ArrayRef> {
+    batch
+        .column_by_name(field)
+        .ok_or_else(|| anyhow!("{} field '{}' is not in the sink's input", kind, field))
+}
+
+fn is_header_value(data_type: &DataType) -> bool {
+    *data_type == DataType::Binary || can_cast_types(data_type, &DataType::Utf8)
+}
+
+fn single_column_batch(name: &str, column: ArrayRef) -> RecordBatch {
+    let schema = Schema::new(vec![Field::new(name, column.data_type().clone(), true)]);
+    RecordBatch::try_new(Arc::new(schema), vec![column]).unwrap()
+}
+
+/// Converts a struct or map column into the headers for each row; each entry of the struct
+/// or map becomes a header, with values that aren't BYTEA written as text
+fn headers_for_column(column: &ArrayRef) -> Result<Vec<Option<OwnedHeaders>>> {
+    fn header_values(values: &ArrayRef) -> Result<ArrayRef> {
+        Ok(match values.data_type() {
+            DataType::Binary => values.clone(),
+            _ => cast(values, &DataType::Utf8)?,
+        })
+    }
+
+    fn header_value(values: &ArrayRef, i: usize) -> Option<&[u8]> {
+        if values.is_null(i) {
+            return None;
+        }
+
+        Some(match values.data_type() {
+            DataType::Binary => values.as_binary::<i32>().value(i),
+            _ => values.as_string::<i32>().value(i).as_bytes(),
+        })
+    }
+
+    if let Some(fields) = column.as_struct_opt() {
+        let values = fields
+            .columns()
+            .iter()
+            .map(header_values)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((0..column.len())
+            .map(|row| {
+                column.is_valid(row).then(|| {
+                    fields.column_names().into_iter().zip(&values).fold(
+                        OwnedHeaders::new_with_capacity(values.len()),
+                        |headers, (key, values)| {
+                            headers.insert(Header {
+                                key,
+                                value: header_value(values, row),
+                            })
+                        },
+                    )
+                })
+            })
+            .collect())
+    } else if let Some(map) = column.as_map_opt() {
+        let keys = cast(map.keys(), &DataType::Utf8)?;
+        let keys = keys.as_string::<i32>();
+        let values = header_values(map.values())?;
+        let offsets = map.value_offsets();
+
+        Ok((0..column.len())
+            .map(|row| {
+                column.is_valid(row).then(|| {
+                    let (start, end) = (offsets[row] as usize, offsets[row + 1] as usize);
+                    (start..end).fold(
+                        OwnedHeaders::new_with_capacity(end - start),
+                        |headers, i| {
+                            headers.insert(Header {
+                                key: keys.value(i),
+                                value: header_value(&values, i),
+                            })
+                        },
+                    )
+                })
+            })
+            .collect())
+    } else {
+        bail!(
+            "headers field must be a struct or map, not {}",
+            column.data_type()
+        );
+    }
+}
+
+/// The context of our producers, which records the producer id and epoch that librdkafka
+/// reports in its statistics so that they can be stored with pre-committed transactions
+#[derive(Clone, Default)]
+pub struct SinkProducerContext {
+    producer_id: Arc<Mutex<Option<(i64, i16)>>>,
+}
+
+impl SinkProducerContext {
+    fn producer_id(&self) -> Option<(i64, i16)> {
+        *self.producer_id.lock().unwrap()
+    }
+}
+
+impl ClientContext for SinkProducerContext {
+    fn stats_raw(&self, statistics: &[u8]) {
+        #[derive(Deserialize)]
+        struct Statistics {
+            eos: Option<ExactlyOnceStatistics>,
+        }
+
+        #[derive(Deserialize)]
+        struct ExactlyOnceStatistics {
+            producer_id: i64,
+            producer_epoch: i16,
+        }
+
+        if let Ok(Statistics { eos: Some(eos) }) = serde_json::from_slice(statistics) {
+            // the id is -1 until the producer has been initialized
+            if eos.producer_id >= 0 {
+                *self.producer_id.lock().unwrap() = Some((eos.producer_id, eos.producer_epoch));
+            }
+        }
+    }
 }
 
 pub enum ConsistencyMode {
     AtLeastOnce,
     ExactlyOnce {
         next_transaction_index: usize,
-        producer_to_complete: Option<FutureProducer>,
+        transactional_id: Option<String>,
+        producer_to_complete: Option<FutureProducer<SinkProducerContext>>,
+        /// the context of the current transactional producer
+        producer_context: SinkProducerContext,
     },
 }
 
+/// The state stored for each subtask of an exactly-once sink
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct KafkaSinkState {
+    next_transaction_index: usize,
+    pending_transaction: Option<PendingTransaction>,
+}
+
+/// A transaction that was flushed as part of a checkpoint, and will be committed once that
+/// checkpoint is complete. The Kafka producer id and epoch allow the transaction to be committed
+/// by a later run if this one stops first.
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct PendingTransaction {
+    transactional_id: String,
+    checkpoint_epoch: u32,
+    producer_id: i64,
+    producer_epoch: i16,
+}
+
 impl From<SinkCommitMode> for ConsistencyMode {
     fn from(commit_mode: SinkCommitMode) -> Self {
         match commit_mode {
             SinkCommitMode::AtLeastOnce => ConsistencyMode::AtLeastOnce,
             SinkCommitMode::ExactlyOnce => ConsistencyMode::ExactlyOnce {
                 next_transaction_index: 0,
+                transactional_id: None,
                 producer_to_complete: None,
+                producer_context: SinkProducerContext::default(),
             },
         }
     }
@@ -61,39 +379,226 @@ impl KafkaSinkFunc {
         matches!(self.consistency_mode, ConsistencyMode::ExactlyOnce { .. })
     }
 
-    fn init_producer(&mut self, task_info: &TaskInfo) -> Result<()> {
+    fn client_config(&self) -> ClientConfig {
         let mut client_config = ClientConfig::new();
         client_config.set("bootstrap.servers", &self.bootstrap_servers);
         for (key, value) in &self.client_config {
             client_config.set(key, value);
         }
+        client_config
+    }
+
+    fn transactional_producer(
+        &self,
+        transactional_id: &str,
+        context: SinkProducerContext,
+    ) -> Result<FutureProducer<SinkProducerContext>> {
+        let mut client_config = self.client_config();
+        client_config.set("enable.idempotence", "true");
+        client_config.set("transactional.id", transactional_id);
+        // the producer id and epoch are only exposed through the statistics, so they must be
+        // reported often enough for every checkpoint, whatever the user configured
+        client_config.set("statistics.interval.ms", "500");
+        let producer: FutureProducer<SinkProducerContext> =
+            client_config.create_with_context(context)?;
+        producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
+        Ok(producer)
+    }
+
+    fn init_producer(&mut self, task_info: &TaskInfo) -> Result<()> {
+        let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            ..
+        } = &self.consistency_mode
+        else {
+            self.producer = Some(
+                self.client_config()
+                    .create_with_context(SinkProducerContext::default())?,
+            );
+            return Ok(());
+        };
+
+        let id = format!(
+            "arroyo-id-{}-{}-{}-{}-{}",
+            task_info.job_id,
+            task_info.operator_id,
+            self.topic,
+            task_info.task_index,
+            next_transaction_index
+        );
+        let context = SinkProducerContext::default();
+        let producer = self.transactional_producer(&id, context.clone())?;
+        producer.begin_transaction()?;
+        self.producer = Some(producer);
+
+        if let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            transactional_id,
+            producer_context,
+            ..
+        } = &mut self.consistency_mode
+        {
+            *next_transaction_index += 1;
+            *transactional_id = Some(id);
+            *producer_context = context;
+        }
+        Ok(())
+    }
 
-        match &mut self.consistency_mode {
-            ConsistencyMode::AtLeastOnce => {
-                self.producer = Some(client_config.create()?);
+    /// Restores the transaction index for this subtask and returns the transactions that it
+    /// pre-committed before it stopped. If the parallelism has been reduced, pending
+    /// transactions of subtasks that no longer exist are taken over by the subtask with the same
+    /// index modulo the parallelism.
+    async fn restore_transactions(&mut self, ctx: &mut ArrowContext) -> Vec<PendingTransaction> {
+        let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            ..
+        } = &mut self.consistency_mode
+        else {
+            return vec![];
+        };
+
+        let state = ctx
+            .table_manager
+            .get_global_keyed_state::<usize, KafkaSinkState>("i")
+            .await
+            .expect("should be able to get kafka sink state");
+
+        let mut pending = vec![];
+        for (task_index, state) in state.get_all() {
+            if *task_index == ctx.task_info.task_index {
+                *next_transaction_index = state.next_transaction_index;
             }
-            ConsistencyMode::ExactlyOnce {
-                next_transaction_index,
-                ..
-            } => {
-                client_config.set("enable.idempotence", "true");
-                let transactional_id = format!(
-                    "arroyo-id-{}-{}-{}-{}-{}",
-                    task_info.job_id,
-                    task_info.operator_id,
-                    self.topic,
-                    task_info.task_index,
-                    next_transaction_index
-                );
-                client_config.set("transactional.id", transactional_id);
-                let producer: FutureProducer = client_config.create()?;
-                producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
-                producer.begin_transaction()?;
-                *next_transaction_index += 1;
-                self.producer = Some(producer);
+
+            if task_index % ctx.task_info.parallelism == ctx.task_info.task_index {
+                pending.extend(state.pending_transaction.clone());
             }
         }
-        Ok(())
+        pending
+    }
+
+    /// Commits a transaction that was pre-committed before this subtask was restarted. The
+    /// checkpoint that we restored from is complete, so its transactions must be committed,
+    /// even if the previous run stopped before it could do so.
+    async fn recover_transaction(&self, transaction: &PendingTransaction, ctx: &mut ArrowContext) {
+        info!(
+            "recovering transaction {} for checkpoint {}",
+            transaction.transactional_id, transaction.checkpoint_epoch
+        );
+
+        let mut attempts = 0;
+        loop {
+            let result = transactions::commit_transaction(
+                &self.bootstrap_servers,
+                &self.client_config,
+                &transaction.transactional_id,
+                transaction.producer_id,
+                transaction.producer_epoch,
+            )
+            .await;
+
+            match result {
+                Ok(RecoveryOutcome::Committed) => {
+                    info!(
+                        "committed recovered transaction {}",
+                        transaction.transactional_id
+                    );
+                    return;
+                }
+                Ok(RecoveryOutcome::NotCommittable(reason)) => {
+                    // retrying can't help, and failing would prevent the pipeline from ever
+                    // restarting, so we report the lost data and move on
+                    ctx.error_reporter
+                        .report_error(
+                            "Failed to recover Kafka transaction",
+                            format!(
+                                "transaction {} for checkpoint {} could not be committed: {}",
+                                transaction.transactional_id, transaction.checkpoint_epoch, reason
+                            ),
+                        )
+                        .await;
+                    return;
+                }
+                Err(e) => {
+                    attempts += 1;
+                    if attempts == MAX_COMMIT_ATTEMPTS {
+                        ctx.error_reporter
+                            .report_error(
+                                "Failed to recover Kafka transaction",
+                                format!(
+                                    "could not commit transaction {} after {} attempts: {:?}",
+                                    transaction.transactional_id, attempts, e
+                                ),
+                            )
+                            .await;
+                        panic!(
+                            "failed to recover transaction {}: {:?}",
+                            transaction.transactional_id, e
+                        );
+                    }
+                    error!(
+                        "failed to recover transaction {} ({} attempts), retrying: {:?}",
+                        transaction.transactional_id, attempts, e
+                    );
+                    tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts))).await;
+                }
+            }
+        }
+    }
+
+    /// Commits the transaction, retrying with backoff while the failure is retriable. If it
+    /// can't be committed, the task fails and the transaction is recovered from the checkpoint
+    /// when it restarts.
+    async fn commit_transaction(
+        producer: &FutureProducer<SinkProducerContext>,
+        ctx: &mut ArrowContext,
+    ) {
+        let mut attempts: u32 = 0;
+        loop {
+            let e = match producer.commit_transaction(Timeout::After(Duration::from_secs(10))) {
+                Ok(()) => return,
+                Err(KafkaError::Transaction(e))
+                    if e.is_retriable() && attempts + 1 < MAX_COMMIT_ATTEMPTS =>
+                {
+                    e
+                }
+                Err(e) => {
+                    ctx.error_reporter
+                        .report_error("Failed to commit Kafka transaction", format!("{:?}", e))
+                        .await;
+                    panic!("failed to commit Kafka transaction: {:?}", e);
+                }
+            };
+
+            attempts += 1;
+            error!("failed to commit {} times, retrying: {:?}", attempts, e);
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts))).await;
+        }
+    }
+
+    /// Waits for librdkafka to report the id and epoch of the current transactional producer
+    async fn producer_id(&self, ctx: &mut ArrowContext) -> (i64, i16) {
+        let ConsistencyMode::ExactlyOnce {
+            producer_context, ..
+        } = &self.consistency_mode
+        else {
+            unreachable!("only transactional producers have a producer id");
+        };
+
+        for _ in 0..100 {
+            if let Some(id) = producer_context.producer_id() {
+                return id;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        ctx.error_reporter
+            .report_error(
+                "Failed to pre-commit Kafka transaction",
+                "the Kafka producer did not report its producer id",
+            )
+            .await;
+        panic!("Kafka producer did not report its producer id");
     }
 
     async fn flush(&mut self, ctx: &mut ArrowContext) {
@@ -116,14 +621,61 @@ impl KafkaSinkFunc {
         }
     }
 
-    async fn publish(&mut self, k: Option<Vec<u8>>, v: Vec<u8>, ctx: &mut ArrowContext) {
-        let mut rec = {
-            if let Some(k) = k.as_ref() {
-                FutureRecord::to(&self.topic).key(k).payload(&v)
-            } else {
-                FutureRecord::to(&self.topic).payload(&v)
-            }
-        };
+    /// Builds the keys, headers and partitions of the messages for a batch from the columns
+    /// that are configured for them
+    #[allow(clippy::type_complexity)]
+    fn message_parts(
+        &mut self,
+        batch: &RecordBatch,
+    ) -> Result<(
+        Option<Vec<Option<Vec<u8>>>>,
+        Option<Vec<Option<OwnedHeaders>>>,
+        Option<ArrayRef>,
+    )> {
+        let keys = self
+            .key
+            .as_mut()
+            .map(|key| key.serialize(batch))
+            .transpose()?;
+
+        let headers = self
+            .headers_field
+            .as_ref()
+            .map(|field| headers_for_column(message_field(batch, "headers", field)?))
+            .transpose()?;
+
+        let partitions = self
+            .partition_field
+            .as_ref()
+            .map(|field| -> Result<ArrayRef> {
+                Ok(cast(
+                    message_field(batch, "partition", field)?,
+                    &DataType::Int32,
+                )?)
+            })
+            .transpose()?;
+
+        Ok((keys, headers, partitions))
+    }
+
+    async fn publish(
+        &mut self,
+        k: Option<Vec<u8>>,
+        v: Vec<u8>,
+        headers: Option<OwnedHeaders>,
+        partition: Option<i32>,
+        ctx: &mut ArrowContext,
+    ) {
+        let mut rec = FutureRecord::to(&self.topic).payload(&v);
+        if let Some(k) = k.as_ref() {
+            rec = rec.key(k);
+        }
+        if let Some(headers) = headers {
+            rec = rec.headers(headers);
+        }
+        if let Some(partition) = partition {
+            rec = rec.partition(partition);
+        }
 
         loop {
             match self.producer.as_mut().unwrap().send_result(rec) {
@@ -157,39 +709,91 @@ impl ArrowOperator for KafkaSinkFunc {
 
     fn tables(&self) -> HashMap<String, TableConfig> {
         if self.is_committing() {
-            todo!("implement committing state")
+            global_table_config("i", "kafka sink state")
         } else {
             HashMap::new()
         }
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        for transaction in self.restore_transactions(ctx).await {
+            self.recover_transaction(&transaction, ctx).await;
+        }
         self.init_producer(&ctx.task_info)
             .expect("Producer creation failed");
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        // the fields are validated when the sink is planned, so this is only reachable if the
+        // input doesn't match its plan
+        let (keys, headers, partitions) = match self.message_parts(&batch) {
+            Ok(parts) => parts,
+            Err(e) => {
+                ctx.error_reporter
+                    .report_error("Could not build Kafka messages", e.to_string())
+                    .await;
+
+                panic!("Could not build Kafka messages: {:?}", e);
+            }
+        };
+
+        let mut keys = keys.map(|k| k.into_iter());
+        let mut headers = headers.map(|h| h.into_iter());
+        let partitions = partitions.as_ref().map(|p| p.as_primitive::<Int32Type>());
+
+        // the headers column is written to the headers rather than the value
+        let batch = match &self.headers_field {
+            Some(field) => {
+                let mut batch = batch.clone();
+                batch.remove_column(batch.schema().index_of(field).unwrap());
+                batch
+            }
+            None => batch,
+        };
+
         let values = self.serializer.serialize(&batch);
 
-        for v in values {
-            self.publish(None, v, ctx).await;
+        for (i, v) in values.enumerate() {
+            let k = keys.as_mut().and_then(|k| k.next().flatten());
+            let h = headers.as_mut().and_then(|h| h.next().flatten());
+            let p = partitions.and_then(|p| p.is_valid(i).then(|| p.value(i)));
+            self.publish(k, v, h, p, ctx).await;
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
         self.flush(ctx).await;
+        if !self.is_committing() {
+            return;
+        }
+
+        let (producer_id, producer_epoch) = self.producer_id(ctx).await;
+
         if let ConsistencyMode::ExactlyOnce {
             next_transaction_index,
+            transactional_id,
             producer_to_complete,
+            ..
         } = &mut self.consistency_mode
         {
             *producer_to_complete = self.producer.take();
+            let state = KafkaSinkState {
+                next_transaction_index: *next_transaction_index,
+                pending_transaction: transactional_id.clone().map(|transactional_id| {
+                    PendingTransaction {
+                        transactional_id,
+                        checkpoint_epoch: barrier.epoch,
+                        producer_id,
+                        producer_epoch,
+                    }
+                }),
+            };
             ctx.table_manager
                 .get_global_keyed_state("i")
                 .await
                 .as_mut()
                 .unwrap()
-                .insert(ctx.task_info.task_index, *next_transaction_index)
+                .insert(ctx.task_info.task_index, state)
                 .await;
             self.init_producer(&ctx.task_info)
                 .expect("creating new producer during checkpointing");
@@ -203,30 +807,21 @@ impl ArrowOperator for KafkaSinkFunc {
         ctx: &mut ArrowContext,
     ) {
         let ConsistencyMode::ExactlyOnce {
-            next_transaction_index: _,
             producer_to_complete,
+            ..
         } = &mut self.consistency_mode
         else {
             warn!("received commit but consistency mode is not exactly once");
             return;
         };
 
-        let Some(committing_producer) = producer_to_complete.take() else {
-            unimplemented!("received a commit message without a producer ready to commit. Restoring from commit phase not yet implemented");
-        };
-        let mut commits_attempted = 0;
-        loop {
-            if committing_producer
-                .commit_transaction(Timeout::After(Duration::from_secs(10)))
-                .is_ok()
-            {
-                break;
-            } else if commits_attempted == 5 {
-                panic!("failed to commit 5 times, giving up");
-            } else {
-                error!("failed to commit {} times, retrying", commits_attempted);
-                commits_attempted += 1;
-            }
+        if let Some(committing_producer) = producer_to_complete.take() {
+            Self::commit_transaction(&committing_producer, ctx).await;
+        } else {
+            warn!(
+                "received commit for epoch {} without a transaction to commit",
+                epoch
+            );
         }
         let checkpoint_event = ControlResp::CheckpointEvent(CheckpointEvent {
             checkpoint_epoch: epoch,
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use arrow::array::{RecordBatch, UInt32Array};
+use arrow::array::{Int64Array, RecordBatch, StringArray, StructArray, UInt32Array};
 use arrow::datatypes::Field;
 use arrow::datatypes::{DataType, Schema, SchemaRef};
 use arroyo_formats::ser::ArrowSerializer;
@@ -17,12 +17,16 @@ use arroyo_types::*;
 use itertools::Itertools;
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
 use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Headers;
 use rdkafka::producer::Producer;
 use rdkafka::{ClientConfig, Message};
 use serde::Deserialize;
 use tokio::sync::mpsc::channel;
 
-use super::{ConsistencyMode, KafkaSinkFunc};
+use super::{
+    headers_for_column, validate_message_fields, ConsistencyMode, KafkaSinkFunc, KeySerializer,
+};
+use crate::kafka::SinkKeyFormat;
 
 pub struct KafkaTopicTester {
     topic: String,
@@ -78,6 +82,9 @@ impl KafkaTopicTester {
             write_futures: vec![],
             client_config: HashMap::new(),
             serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+            key: None,
+            headers_field: None,
+            partition_field: None,
         };
 
         let (_, control_rx) = channel(128);
@@ -204,3 +211,85 @@ async fn test_kafka() {
         assert_eq!(message, result.value);
     }
 }
+
+#[test]
+fn test_keys_and_headers() {
+    let headers = StructArray::from(vec![
+        (
+            Arc::new(Field::new("source", DataType::Utf8, true)),
+            Arc::new(StringArray::from(vec![Some("a"), None])) as _,
+        ),
+        (
+            Arc::new(Field::new("version", DataType::Int64, true)),
+            Arc::new(Int64Array::from(vec![1, 2])) as _,
+        ),
+    ]);
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("headers", headers.data_type().clone(), true),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![Some(5), None])),
+            Arc::new(headers),
+        ],
+    )
+    .unwrap();
+
+    let mut keys = KeySerializer::new("id".to_string(), SinkKeyFormat::RawString);
+    assert_eq!(
+        keys.serialize(&batch).unwrap(),
+        vec![Some(b"5".to_vec()), None]
+    );
+
+    let headers = headers_for_column(batch.column(1)).unwrap();
+    let first = headers[0].as_ref().unwrap();
+    assert_eq!(first.count(), 2);
+    assert_eq!(first.get(0).key, "source");
+    assert_eq!(first.get(0).value, Some(b"a".as_slice()));
+    assert_eq!(first.get(1).value, Some(b"1".as_slice()));
+
+    let second = headers[1].as_ref().unwrap();
+    assert_eq!(second.get(0).value, None);
+    assert_eq!(second.get(1).value, Some(b"2".as_slice()));
+}
+
+#[test]
+fn test_validate_message_fields() {
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, true),
+        Field::new(
+            "headers",
+            DataType::Struct(vec![Field::new("source", DataType::Utf8, true)].into()),
+            true,
+        ),
+        Field::new("name", DataType::Utf8, true),
+    ]);
+
+    validate_message_fields(
+        Some(("id", SinkKeyFormat::RawString)),
+        Some("headers"),
+        Some("id"),
+        &schema,
+    )
+    .unwrap();
+
+    let err = validate_message_fields(Some(("missing", SinkKeyFormat::Json)), None, None, &schema)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "key field 'missing' is not in the sink's input"
+    );
+
+    let err = validate_message_fields(None, Some("name"), None, &schema).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("headers field 'name' must be a struct or map"));
+
+    let err = validate_message_fields(None, None, Some("name"), &schema).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "partition field 'name' must be an integer, not Utf8"
+    );
+}
@@ -0,0 +1,674 @@
+//! A minimal client for the Kafka transaction coordinator, used to commit transactions that were
+//! pre-committed by a producer that no longer exists.
+//!
+//! librdkafka can't resume a transaction that was started by another producer, and
+//! re-initializing a producer with the same transactional id aborts it. So, like Flink's
+//! `FlinkKafkaInternalProducer`, we persist the producer id and epoch of each pre-committed
+//! transaction and end it by sending `EndTxn` to its coordinator ourselves.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rustls_native_certs::load_native_certs;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+const FIND_COORDINATOR: i16 = 10;
+const SASL_HANDSHAKE: i16 = 17;
+const END_TXN: i16 = 26;
+const SASL_AUTHENTICATE: i16 = 36;
+
+const COORDINATOR_TYPE_TRANSACTION: i8 = 1;
+
+const CLIENT_ID: &str = "arroyo-transaction-recovery";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The result of committing a transaction that was pre-committed by another producer
+#[derive(Debug, PartialEq)]
+pub enum RecoveryOutcome {
+    Committed,
+    /// The coordinator no longer has an open transaction for the producer, because the
+    /// transaction timed out and was aborted or the transactional id has expired, or we can't
+    /// connect with the producer's security settings
+    NotCommittable(String),
+}
+
+/// Commits the open transaction of the producer with the given id and epoch. Errors are
+/// returned for failures that may succeed if retried, like an unavailable coordinator.
+pub async fn commit_transaction(
+    bootstrap_servers: &str,
+    client_config: &HashMap<String, String>,
+    transactional_id: &str,
+    producer_id: i64,
+    producer_epoch: i16,
+) -> Result<RecoveryOutcome> {
+    // the producer's settings are the same on every attempt, so retrying can't help
+    let security = match Security::from_config(client_config) {
+        Ok(security) => security,
+        Err(e) => return Ok(RecoveryOutcome::NotCommittable(e.to_string())),
+    };
+    let (host, port) = find_coordinator(bootstrap_servers, &security, transactional_id).await?;
+    let mut connection = Connection::connect(&host, port, &security).await?;
+
+    let mut body = BytesMut::new();
+    put_string(&mut body, transactional_id);
+    body.put_i64(producer_id);
+    body.put_i16(producer_epoch);
+    // committed
+    body.put_u8(1);
+
+    let mut response = connection.request(END_TXN, 1, &body).await?;
+    let _throttle_time_ms = response.i32()?;
+
+    match response.i16()? {
+        0 => Ok(RecoveryOutcome::Committed),
+        // COORDINATOR_LOAD_IN_PROGRESS, COORDINATOR_NOT_AVAILABLE, NOT_COORDINATOR and
+        // CONCURRENT_TRANSACTIONS
+        code @ (14 | 15 | 16 | 51) => bail!(
+            "transaction coordinator {}:{} is not ready (error code {})",
+            host,
+            port,
+            code
+        ),
+        // INVALID_PRODUCER_EPOCH, INVALID_TXN_STATE and PRODUCER_FENCED
+        code @ (47 | 48 | 90) => Ok(RecoveryOutcome::NotCommittable(format!(
+            "the transaction is no longer open for producer {} with epoch {} (error code {}); \
+            it was likely aborted after exceeding transaction.timeout.ms",
+            producer_id, producer_epoch, code
+        ))),
+        // INVALID_PRODUCER_ID_MAPPING
+        49 => Ok(RecoveryOutcome::NotCommittable(format!(
+            "the coordinator has no producer for transactional id {}, which has likely expired",
+            transactional_id
+        ))),
+        code => bail!("failed to commit transaction (error code {})", code),
+    }
+}
+
+async fn find_coordinator(
+    bootstrap_servers: &str,
+    security: &Security,
+    transactional_id: &str,
+) -> Result<(String, u16)> {
+    let mut last_error = anyhow!("no bootstrap servers are configured");
+
+    for server in bootstrap_servers.split(',').map(str::trim) {
+        if server.is_empty() {
+            continue;
+        }
+
+        let result: Result<(String, u16)> = async {
+            let (host, port) = parse_server(server)?;
+            let mut connection = Connection::connect(&host, port, security).await?;
+
+            let mut body = BytesMut::new();
+            put_string(&mut body, transactional_id);
+            body.put_i8(COORDINATOR_TYPE_TRANSACTION);
+
+            let mut response = connection.request(FIND_COORDINATOR, 1, &body).await?;
+            let _throttle_time_ms = response.i32()?;
+            let error_code = response.i16()?;
+            let error_message = response.nullable_string()?;
+            if error_code != 0 {
+                bail!(
+                    "failed to find transaction coordinator (error code {}): {}",
+                    error_code,
+                    error_message.unwrap_or_default()
+                );
+            }
+
+            let _node_id = response.i32()?;
+            let host = response.string()?;
+            let port = u16::try_from(response.i32()?)?;
+            Ok((host, port))
+        }
+        .await;
+
+        match result {
+            Ok(coordinator) => return Ok(coordinator),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn parse_server(server: &str) -> Result<(String, u16)> {
+    // servers may be prefixed with their protocol, like PLAINTEXT://host:port
+    let server = server.rsplit("://").next().unwrap_or(server);
+    let (host, port) = server
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("bootstrap server '{}' has no port", server))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host.to_string(), port.parse()?))
+}
+
+/// The TLS and SASL settings of the producer, which we need to match to connect to the brokers
+struct Security {
+    tls: Option<Arc<ClientConfig>>,
+    sasl: Option<Sasl>,
+}
+
+struct Sasl {
+    mechanism: SaslMechanism,
+    username: String,
+    password: String,
+}
+
+/// Checks that transactions can be recovered with the producer's security.protocol and
+/// sasl.mechanism, which is needed for exactly-once sinks
+pub fn check_security(config: &HashMap<String, String>) -> Result<()> {
+    if Security::protocol(config)?.1 {
+        Security::sasl_mechanism(config)?;
+    }
+    Ok(())
+}
+
+impl Security {
+    /// Whether the producer's security.protocol uses TLS and SASL
+    fn protocol(config: &HashMap<String, String>) -> Result<(bool, bool)> {
+        let protocol = config
+            .get("security.protocol")
+            .map(|p| p.to_lowercase())
+            .unwrap_or_else(|| "plaintext".to_string());
+
+        Ok(match protocol.as_str() {
+            "plaintext" => (false, false),
+            "ssl" => (true, false),
+            "sasl_plaintext" => (false, true),
+            "sasl_ssl" => (true, true),
+            p => bail!("unsupported security.protocol '{}'", p),
+        })
+    }
+
+    fn sasl_mechanism(config: &HashMap<String, String>) -> Result<SaslMechanism> {
+        let mechanism = config
+            .get("sasl.mechanism")
+            .or_else(|| config.get("sasl.mechanisms"))
+            .map(|m| m.as_str())
+            .unwrap_or("GSSAPI");
+
+        Ok(match mechanism.to_uppercase().as_str() {
+            "PLAIN" => SaslMechanism::Plain,
+            "SCRAM-SHA-256" => SaslMechanism::ScramSha256,
+            "SCRAM-SHA-512" => SaslMechanism::ScramSha512,
+            m => bail!(
+                "SASL mechanism {} is not supported when recovering transactions",
+                m
+            ),
+        })
+    }
+
+    fn from_config(config: &HashMap<String, String>) -> Result<Self> {
+        let (tls, sasl) = Self::protocol(config)?;
+
+        let tls = tls.then(|| tls_config(config)).transpose()?;
+
+        let sasl = sasl
+            .then(|| {
+                let mechanism = Self::sasl_mechanism(config)?;
+
+                let get = |key: &str| {
+                    config
+                        .get(key)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("'{}' must be set for SASL", key))
+                };
+
+                Ok(Sasl {
+                    mechanism,
+                    username: get("sasl.username")?,
+                    password: get("sasl.password")?,
+                })
+            })
+            .transpose()?;
+
+        Ok(Self { tls, sasl })
+    }
+}
+
+fn tls_config(config: &HashMap<String, String>) -> Result<Arc<ClientConfig>> {
+    let mut root_cert_store = RootCertStore::empty();
+    if let Some(ca) = config.get("ssl.ca.location") {
+        for cert in load_certs(ca)? {
+            root_cert_store.add(&cert)?;
+        }
+    } else {
+        for cert in load_native_certs()? {
+            root_cert_store.add(&Certificate(cert.0))?;
+        }
+    }
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_cert_store);
+
+    let config = match (
+        config.get("ssl.certificate.location"),
+        config.get("ssl.key.location"),
+    ) {
+        (Some(cert), Some(key)) => {
+            builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey> {
+    let pem = std::fs::read(path)?;
+    rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())?
+        .into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| anyhow!("no private key found in {}", path))
+}
+
+trait KafkaStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> KafkaStream for T {}
+
+struct Connection {
+    stream: Box<dyn KafkaStream>,
+    correlation_id: i32,
+}
+
+impl Connection {
+    async fn connect(host: &str, port: u16, security: &Security) -> Result<Self> {
+        let tcp = tokio::time::timeout(REQUEST_TIMEOUT, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| anyhow!("timed out connecting to {}:{}", host, port))??;
+
+        let stream: Box<dyn KafkaStream> = match &security.tls {
+            Some(tls) => {
+                let name = ServerName::try_from(host)?;
+                Box::new(TlsConnector::from(tls.clone()).connect(name, tcp).await?)
+            }
+            None => Box::new(tcp),
+        };
+
+        let mut connection = Self {
+            stream,
+            correlation_id: 0,
+        };
+
+        if let Some(sasl) = &security.sasl {
+            connection.authenticate(sasl).await?;
+        }
+
+        Ok(connection)
+    }
+
+    /// Sends a request with the v1 request header and returns the body of the response
+    async fn request(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Result<Response> {
+        self.correlation_id += 1;
+
+        let mut header = BytesMut::new();
+        header.put_i16(api_key);
+        header.put_i16(api_version);
+        header.put_i32(self.correlation_id);
+        put_string(&mut header, CLIENT_ID);
+
+        let mut request = BytesMut::with_capacity(4 + header.len() + body.len());
+        request.put_i32((header.len() + body.len()) as i32);
+        request.put_slice(&header);
+        request.put_slice(body);
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, async {
+            self.stream.write_all(&request).await?;
+            self.stream.flush().await?;
+
+            let size = self.stream.read_i32().await?;
+            let mut response = vec![0; usize::try_from(size)?];
+            self.stream.read_exact(&mut response).await?;
+            anyhow::Ok(response)
+        })
+        .await
+        .map_err(|_| anyhow!("timed out waiting for response to request {}", api_key))??;
+
+        let mut response = Response(Bytes::from(response));
+        let correlation_id = response.i32()?;
+        if correlation_id != self.correlation_id {
+            bail!(
+                "received response for request {}, expected {}",
+                correlation_id,
+                self.correlation_id
+            );
+        }
+
+        Ok(response)
+    }
+
+    async fn authenticate(&mut self, sasl: &Sasl) -> Result<()> {
+        let mut body = BytesMut::new();
+        put_string(&mut body, sasl.mechanism.name());
+        let mut response = self.request(SASL_HANDSHAKE, 1, &body).await?;
+        if response.i16()? != 0 {
+            bail!(
+                "broker does not support SASL mechanism {}",
+                sasl.mechanism.name()
+            );
+        }
+
+        if sasl.mechanism == SaslMechanism::Plain {
+            let auth = format!("\0{}\0{}", sasl.username, sasl.password);
+            self.sasl_authenticate(auth.as_bytes()).await?;
+            return Ok(());
+        }
+
+        let nonce: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        let mut scram = ScramClient::new(sasl.mechanism, &sasl.username, &sasl.password, nonce);
+
+        let server_first = self
+            .sasl_authenticate(scram.client_first().as_bytes())
+            .await?;
+        let client_final = scram.client_final(std::str::from_utf8(&server_first)?)?;
+        let server_final = self.sasl_authenticate(client_final.as_bytes()).await?;
+        scram.verify(std::str::from_utf8(&server_final)?)
+    }
+
+    async fn sasl_authenticate(&mut self, auth_bytes: &[u8]) -> Result<Bytes> {
+        let mut body = BytesMut::new();
+        body.put_i32(auth_bytes.len() as i32);
+        body.put_slice(auth_bytes);
+
+        let mut response = self.request(SASL_AUTHENTICATE, 0, &body).await?;
+        let error_code = response.i16()?;
+        let error_message = response.nullable_string()?;
+        if error_code != 0 {
+            bail!(
+                "SASL authentication failed (error code {}): {}",
+                error_code,
+                error_message.unwrap_or_default()
+            );
+        }
+
+        response.bytes()
+    }
+}
+
+struct Response(Bytes);
+
+impl Response {
+    fn check(&self, len: usize) -> Result<()> {
+        if self.0.remaining() < len {
+            bail!("response from broker was truncated");
+        }
+        Ok(())
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        self.check(2)?;
+        Ok(self.0.get_i16())
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        self.check(4)?;
+        Ok(self.0.get_i32())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.nullable_string()?
+            .ok_or_else(|| anyhow!("unexpected null string in response from broker"))
+    }
+
+    fn nullable_string(&mut self) -> Result<Option<String>> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        self.check(len as usize)?;
+        Ok(Some(String::from_utf8(
+            self.0.split_to(len as usize).to_vec(),
+        )?))
+    }
+
+    fn bytes(&mut self) -> Result<Bytes> {
+        let len = self.i32()?.max(0) as usize;
+        self.check(len)?;
+        Ok(self.0.split_to(len))
+    }
+}
+
+fn put_string(buf: &mut BytesMut, s: &str) {
+    buf.put_i16(s.len() as i16);
+    buf.put_slice(s.as_bytes());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SaslMechanism {
+    Plain,
+    ScramSha256,
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    fn name(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+
+    fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            SaslMechanism::ScramSha512 => {
+                let mut mac =
+                    Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            _ => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            SaslMechanism::ScramSha512 => Sha512::digest(data).to_vec(),
+            _ => Sha256::digest(data).to_vec(),
+        }
+    }
+}
+
+/// The client side of a SCRAM exchange (RFC 5802), using one of the SCRAM mechanisms
+struct ScramClient {
+    mechanism: SaslMechanism,
+    password: String,
+    client_first_bare: String,
+    nonce: String,
+    server_signature: Option<Vec<u8>>,
+}
+
+impl ScramClient {
+    fn new(mechanism: SaslMechanism, username: &str, password: &str, nonce: String) -> Self {
+        let username = username.replace('=', "=3D").replace(',', "=2C");
+        Self {
+            mechanism,
+            password: password.to_string(),
+            client_first_bare: format!("n={},r={}", username, nonce),
+            nonce,
+            server_signature: None,
+        }
+    }
+
+    fn client_first(&self) -> String {
+        format!("n,,{}", self.client_first_bare)
+    }
+
+    fn client_final(&mut self, server_first: &str) -> Result<String> {
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attribute in server_first.split(',') {
+            match attribute.split_once('=') {
+                Some(("r", v)) => nonce = Some(v),
+                Some(("s", v)) => salt = Some(base64::decode(v)?),
+                Some(("i", v)) => iterations = Some(v.parse::<u32>()?),
+                _ => {}
+            }
+        }
+
+        let (Some(nonce), Some(salt), Some(iterations)) = (nonce, salt, iterations) else {
+            bail!("invalid SCRAM server-first message");
+        };
+
+        if !nonce.starts_with(&self.nonce) {
+            bail!("SCRAM server nonce does not extend the client nonce");
+        }
+
+        let salted_password = self.salted_password(&salt, iterations);
+        let client_key = self.mechanism.hmac(&salted_password, b"Client Key");
+        let stored_key = self.mechanism.hash(&client_key);
+
+        let client_final_without_proof = format!("c=biws,r={}", nonce);
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, server_first, client_final_without_proof
+        );
+
+        let client_signature = self.mechanism.hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(k, s)| k ^ s)
+            .collect();
+
+        let server_key = self.mechanism.hmac(&salted_password, b"Server Key");
+        self.server_signature = Some(self.mechanism.hmac(&server_key, auth_message.as_bytes()));
+
+        Ok(format!(
+            "{},p={}",
+            client_final_without_proof,
+            base64::encode(proof)
+        ))
+    }
+
+    fn verify(&self, server_final: &str) -> Result<()> {
+        let expected = self
+            .server_signature
+            .as_ref()
+            .map(|s| format!("v={}", base64::encode(s)));
+
+        if expected.as_deref() != Some(server_final) {
+            bail!("SCRAM server signature is invalid: {}", server_final);
+        }
+
+        Ok(())
+    }
+
+    /// The PBKDF2 `Hi` function from RFC 5802
+    fn salted_password(&self, salt: &[u8], iterations: u32) -> Vec<u8> {
+        let password = self.password.as_bytes();
+        let mut u = self
+            .mechanism
+            .hmac(password, &[salt, &1u32.to_be_bytes()[..]].concat());
+        let mut result = u.clone();
+        for _ in 1..iterations {
+            u = self.mechanism.hmac(password, &u);
+            result.iter_mut().zip(&u).for_each(|(r, u)| *r ^= u);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scram_sha_256() {
+        // the example exchange from RFC 7677
+        let mut scram = ScramClient::new(
+            SaslMechanism::ScramSha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO".to_string(),
+        );
+
+        assert_eq!(scram.client_first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+
+        let client_final = scram
+            .client_final(
+                "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+            )
+            .unwrap();
+        assert_eq!(
+            client_final,
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+            p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+
+        scram
+            .verify("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .unwrap();
+        assert!(scram.verify("v=AAAA").is_err());
+    }
+
+    #[test]
+    fn test_parse_server() {
+        assert_eq!(
+            parse_server("localhost:9092").unwrap(),
+            ("localhost".to_string(), 9092)
+        );
+        assert_eq!(
+            parse_server("SASL_SSL://broker-1.example.com:9093").unwrap(),
+            ("broker-1.example.com".to_string(), 9093)
+        );
+        assert_eq!(
+            parse_server("[::1]:9092").unwrap(),
+            ("::1".to_string(), 9092)
+        );
+        assert!(parse_server("localhost").is_err());
+    }
+
+    #[test]
+    fn test_check_security() {
+        let config = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert!(check_security(&config(&[])).is_ok());
+        assert!(check_security(&config(&[
+            ("security.protocol", "SASL_SSL"),
+            ("sasl.mechanism", "SCRAM-SHA-512")
+        ]))
+        .is_ok());
+        // librdkafka defaults to GSSAPI
+        assert!(check_security(&config(&[("security.protocol", "SASL_PLAINTEXT")])).is_err());
+        assert!(check_security(&config(&[
+            ("security.protocol", "SASL_SSL"),
+            ("sasl.mechanism", "OAUTHBEARER")
+        ]))
+        .is_err());
+    }
+}
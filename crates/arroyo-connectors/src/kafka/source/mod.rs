@@ -1,7 +1,7 @@
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::schema_resolver::SchemaResolver;
-use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
+use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp, MetadataField};
 
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
@@ -11,6 +11,7 @@ use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use governor::{Quota, RateLimiter as GovernorRateLimiter};
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Headers};
 use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
@@ -32,6 +33,7 @@ pub struct KafkaSourceFunc {
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
+    pub metadata_fields: Vec<MetadataField>,
     pub schema_resolver: Arc<dyn SchemaResolver + Sync>,
     pub client_configs: HashMap<String, String>,
     pub messages_per_second: NonZeroU32,
@@ -132,6 +134,36 @@ impl KafkaSourceFunc {
         Ok(consumer)
     }
 
+    /// Collects the metadata of a message that's selected into the table's metadata fields
+    fn message_metadata<'a>(
+        &'a self,
+        msg: &'a BorrowedMessage<'_>,
+        timestamp: i64,
+        headers: Option<&'a str>,
+    ) -> Option<HashMap<&'a str, MetadataValue<'a>>> {
+        if self.metadata_fields.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.metadata_fields
+                .iter()
+                .filter_map(|f| {
+                    let value = match f.key.as_str() {
+                        "key" => MetadataValue::Bytes(msg.key()?),
+                        "topic" => MetadataValue::String(msg.topic()),
+                        "partition" => MetadataValue::Int32(msg.partition()),
+                        "offset" => MetadataValue::Int64(msg.offset()),
+                        "timestamp" => MetadataValue::Timestamp(from_millis(timestamp as u64)),
+                        "headers" => MetadataValue::String(headers?),
+                        _ => return None,
+                    };
+                    Some((f.key.as_str(), value))
+                })
+                .collect(),
+        )
+    }
+
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
         let consumer = self
             .get_consumer(ctx)
@@ -154,9 +186,12 @@ impl KafkaSourceFunc {
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &self.metadata_fields,
             self.schema_resolver.clone(),
         );
 
+        let include_headers = self.metadata_fields.iter().any(|f| f.key == "headers");
+
         let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
         flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
@@ -170,7 +205,10 @@ impl KafkaSourceFunc {
                                     .ok_or_else(|| UserError::new("Failed to read timestamp from Kafka record",
                                         "The message read from Kafka did not contain a message timestamp"))?;
 
-                                ctx.deserialize_slice(v, from_millis(timestamp as u64)).await?;
+                                let headers = include_headers.then(|| headers_json(&msg));
+                                let metadata = self.message_metadata(&msg, timestamp, headers.as_deref());
+
+                                ctx.deserialize_slice(v, from_millis(timestamp as u64), metadata.as_ref()).await?;
 
                                 if ctx.should_flush() {
                                     ctx.flush_buffer().await?;
@@ -244,6 +282,28 @@ impl KafkaSourceFunc {
     }
 }
 
+/// Encodes the headers of a message as a JSON object; values that aren't valid UTF-8 are
+/// converted lossily
+fn headers_json(msg: &BorrowedMessage<'_>) -> String {
+    let headers: serde_json::Map<String, serde_json::Value> = msg
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|h| {
+                    let value = h
+                        .value
+                        .map(|v| serde_json::Value::String(String::from_utf8_lossy(v).to_string()))
+                        .unwrap_or(serde_json::Value::Null);
+                    (h.key.to_string(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    serde_json::Value::Object(headers).to_string()
+}
+
 #[async_trait]
 impl SourceOperator for KafkaSourceFunc {
     async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
@@ -85,6 +85,7 @@ impl KafkaTopicTester {
             format: Format::RawString(RawStringFormat {}),
             framing: None,
             bad_data: None,
+            metadata_fields: vec![],
             schema_resolver: Arc::new(FailingSchemaResolver::new()),
             client_configs: HashMap::new(),
             messages_per_second: NonZeroU32::new(100).unwrap(),
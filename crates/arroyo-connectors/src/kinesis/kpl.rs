@@ -0,0 +1,170 @@
+//! Support for the record aggregation format of the Kinesis Producer Library (KPL), which packs
+//! many user records into a single Kinesis record. An aggregated record is made up of a magic
+//! prefix, a protobuf-encoded `AggregatedRecord`, and the MD5 digest of the protobuf bytes; see
+//! https://github.com/awslabs/amazon-kinesis-producer/blob/master/aggregation-format.md
+
+use md5::{Digest, Md5};
+use prost::Message;
+use uuid::Uuid;
+
+const MAGIC: [u8; 4] = [0xF3, 0x89, 0x9A, 0xC2];
+const DIGEST_SIZE: usize = 16;
+
+/// The default maximum size of an aggregated record, which matches the KPL's default
+pub const DEFAULT_MAX_AGGREGATED_SIZE: usize = 51_200;
+
+#[derive(Clone, PartialEq, Message)]
+struct AggregatedRecord {
+    #[prost(string, repeated, tag = "1")]
+    partition_key_table: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    explicit_hash_key_table: Vec<String>,
+    #[prost(message, repeated, tag = "3")]
+    records: Vec<Record>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Record {
+    #[prost(uint64, required, tag = "1")]
+    partition_key_index: u64,
+    #[prost(uint64, optional, tag = "2")]
+    explicit_hash_key_index: Option<u64>,
+    #[prost(bytes = "vec", required, tag = "3")]
+    data: Vec<u8>,
+}
+
+/// Returns the user records packed into `data` if it's a KPL-aggregated record, or None if it's
+/// a plain record (including one that happens to start with the magic prefix but doesn't have a
+/// valid digest)
+pub fn deaggregate(data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    if data.len() < MAGIC.len() + DIGEST_SIZE || data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    let (message, digest) = data[MAGIC.len()..].split_at(data.len() - MAGIC.len() - DIGEST_SIZE);
+    if Md5::digest(message).as_slice() != digest {
+        return None;
+    }
+
+    let aggregated = AggregatedRecord::decode(message).ok()?;
+    Some(aggregated.records.into_iter().map(|r| r.data).collect())
+}
+
+/// Packs user records into KPL-aggregated records of up to a maximum size
+pub struct Aggregator {
+    max_size: usize,
+    records: Vec<Record>,
+    size: usize,
+}
+
+impl Aggregator {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            records: vec![],
+            size: 0,
+        }
+    }
+
+    fn record_size(record: &Record) -> usize {
+        let len = record.encoded_len();
+        // the tag and length prefix of the record within the aggregated record
+        1 + prost::length_delimiter_len(len) + len
+    }
+
+    /// Adds a record, returning the previous aggregated record and its partition key if this one
+    /// didn't fit into it
+    pub fn add(&mut self, data: Vec<u8>) -> Option<(String, Vec<u8>)> {
+        let record = Record {
+            partition_key_index: 0,
+            explicit_hash_key_index: None,
+            data,
+        };
+        let size = Self::record_size(&record);
+
+        let finished = if !self.records.is_empty() && self.size + size > self.max_size {
+            self.finish()
+        } else {
+            None
+        };
+
+        self.size += size;
+        self.records.push(record);
+        finished
+    }
+
+    /// Encodes the buffered records into an aggregated record, returning it along with the
+    /// partition key to write it with
+    pub fn finish(&mut self) -> Option<(String, Vec<u8>)> {
+        if self.records.is_empty() {
+            return None;
+        }
+
+        // the partition key of the kinesis record determines the shard, so all of the user
+        // records in it share that key
+        let key = Uuid::new_v4().to_string();
+        let aggregated = AggregatedRecord {
+            partition_key_table: vec![key.clone()],
+            explicit_hash_key_table: vec![],
+            records: std::mem::take(&mut self.records),
+        };
+        self.size = 0;
+
+        let message = aggregated.encode_to_vec();
+        let mut data = Vec::with_capacity(MAGIC.len() + message.len() + DIGEST_SIZE);
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&message);
+        data.extend_from_slice(Md5::digest(&message).as_slice());
+        Some((key, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deaggregate, Aggregator};
+
+    #[test]
+    fn test_aggregate_roundtrip() {
+        let mut aggregator = Aggregator::new(1024);
+        assert!(aggregator.add(b"{\"a\": 1}".to_vec()).is_none());
+        assert!(aggregator.add(b"{\"a\": 2}".to_vec()).is_none());
+
+        let (_, aggregated) = aggregator.finish().unwrap();
+        assert!(aggregator.finish().is_none());
+        assert_eq!(
+            deaggregate(&aggregated).unwrap(),
+            vec![b"{\"a\": 1}".to_vec(), b"{\"a\": 2}".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_max_size() {
+        let mut aggregator = Aggregator::new(100);
+        let mut aggregated = vec![];
+        for i in 0..20u8 {
+            aggregated.extend(aggregator.add(vec![i; 30]));
+        }
+        aggregated.extend(aggregator.finish());
+
+        // each aggregate holds as many records as fit
+        assert_eq!(aggregated.len(), 10);
+        let records: Vec<_> = aggregated
+            .iter()
+            .flat_map(|(_, a)| deaggregate(a).unwrap())
+            .collect();
+        assert_eq!(records, (0..20u8).map(|i| vec![i; 30]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_plain_records() {
+        assert!(deaggregate(b"{\"a\": 1}").is_none());
+
+        // a record with the magic prefix but an invalid digest is passed through as is
+        let mut aggregator = Aggregator::new(1024);
+        aggregator.add(b"hello".to_vec());
+        let (_, mut aggregated) = aggregator.finish().unwrap();
+        let last = aggregated.len() - 1;
+        aggregated[last] ^= 0xFF;
+        assert!(deaggregate(&aggregated).is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, bail, Result};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use typify::import_types;
 
 use arroyo_formats::ser::ArrowSerializer;
@@ -10,6 +10,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{pull_opt, pull_option_to_i64, ConnectionSchema, ConnectionType, EmptyConfig};
 
+use crate::kinesis::kpl::{Aggregator, DEFAULT_MAX_AGGREGATED_SIZE};
 use crate::kinesis::sink::{FlushConfig, KinesisSinkFunc};
 use crate::kinesis::source::KinesisSourceFunc;
 use arroyo_operator::connector::Connector;
@@ -20,6 +21,7 @@ const ICON: &str = include_str!("./kinesis.svg");
 
 import_types!(schema = "src/kinesis/table.json");
 
+mod kpl;
 mod sink;
 mod source;
 
@@ -111,6 +113,7 @@ impl Connector for KinesisConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -149,10 +152,16 @@ impl Connector for KinesisConnector {
                 let batch_max_buffer_size =
                     pull_option_to_i64("sink.max_bytes_per_batch", options)?;
                 let records_per_batch = pull_option_to_i64("sink.max_records_per_batch", options)?;
+                let aggregate = options
+                    .remove("sink.aggregate")
+                    .map(|t| t.parse::<bool>())
+                    .transpose()
+                    .map_err(|_| anyhow!("sink.aggregate must be 'true' or 'false'"))?;
                 TableType::Sink {
                     batch_flush_interval_millis,
                     batch_max_buffer_size,
                     records_per_batch,
+                    aggregate,
                 }
             }
             _ => {
@@ -183,6 +192,7 @@ impl Connector for KinesisConnector {
                     aws_region: table.aws_region,
                     offset,
                     shards: HashMap::new(),
+                    initial_shards: HashSet::new(),
                     format: config
                         .format
                         .ok_or_else(|| anyhow!("format required for kinesis source"))?,
@@ -194,6 +204,7 @@ impl Connector for KinesisConnector {
                 batch_flush_interval_millis,
                 batch_max_buffer_size,
                 records_per_batch,
+                aggregate,
             } => {
                 let flush_config = FlushConfig::new(
                     batch_flush_interval_millis,
@@ -203,12 +214,16 @@ impl Connector for KinesisConnector {
                 Ok(OperatorNode::from_operator(Box::new(KinesisSinkFunc {
                     client: None,
                     in_progress_batch: None,
+                    aggregator: aggregate
+                        .unwrap_or_default()
+                        .then(|| Aggregator::new(DEFAULT_MAX_AGGREGATED_SIZE)),
                     aws_region: table.aws_region,
                     name: table.stream_name,
-                    serializer: ArrowSerializer::new(
+                    serializer: ArrowSerializer::with_framing(
                         config
                             .format
                             .ok_or_else(|| anyhow!("Format must be defined for KinesisSink"))?,
+                        config.framing,
                     ),
                     flush_config,
                 })))
@@ -15,10 +15,14 @@ use aws_sdk_kinesis::{
 use tracing::warn;
 use uuid::Uuid;
 
+use super::kpl::Aggregator;
+
 pub struct KinesisSinkFunc {
     pub client: Option<KinesisClient>,
     pub aws_region: Option<String>,
     pub in_progress_batch: Option<BatchRecordPreparer>,
+    // if set, records are packed into KPL-aggregated records before being added to the batch
+    pub aggregator: Option<Aggregator>,
     pub flush_config: FlushConfig,
     pub serializer: ArrowSerializer,
     pub name: String,
@@ -51,10 +55,18 @@ impl ArrowOperator for KinesisSinkFunc {
         };
 
         for v in self.serializer.serialize(&batch) {
-            batch_preparer.add_record(Uuid::new_v4().to_string(), v);
+            match &mut self.aggregator {
+                Some(aggregator) => {
+                    if let Some((key, aggregated)) = aggregator.add(v) {
+                        batch_preparer.add_record(key, aggregated);
+                    }
+                }
+                None => batch_preparer.add_record(Uuid::new_v4().to_string(), v),
+            }
         }
 
         if self.flush_config.should_flush(&batch_preparer) {
+            self.finish_aggregate(&mut batch_preparer);
             self.flush_with_retries(batch_preparer)
                 .await
                 .expect("failed to flush batch during processing");
@@ -64,7 +76,8 @@ impl ArrowOperator for KinesisSinkFunc {
     }
 
     async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _: &mut ArrowContext) {
-        if let Some(batch_preparer) = self.in_progress_batch.take() {
+        if let Some(mut batch_preparer) = self.in_progress_batch.take() {
+            self.finish_aggregate(&mut batch_preparer);
             batch_preparer
                 .flush()
                 .await
@@ -80,7 +93,8 @@ impl ArrowOperator for KinesisSinkFunc {
         if !self.flush_config.should_flush(batch_preparer) {
             return;
         }
-        let in_progress_batch = self.in_progress_batch.take().unwrap();
+        let mut in_progress_batch = self.in_progress_batch.take().unwrap();
+        self.finish_aggregate(&mut in_progress_batch);
 
         self.flush_with_retries(in_progress_batch)
             .await
@@ -89,6 +103,13 @@ impl ArrowOperator for KinesisSinkFunc {
 }
 
 impl KinesisSinkFunc {
+    /// Adds the partially-filled aggregated record, if any, to the batch
+    fn finish_aggregate(&mut self, batch_preparer: &mut BatchRecordPreparer) {
+        if let Some((key, aggregated)) = self.aggregator.as_mut().and_then(|a| a.finish()) {
+            batch_preparer.add_record(key, aggregated);
+        }
+    }
+
     async fn flush_with_retries(
         &mut self,
         mut record_batch_preparer: BatchRecordPreparer,
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fmt::Debug,
     hash::{Hash, Hasher},
     pin::Pin,
@@ -33,7 +33,7 @@ use tokio::{
 };
 use tracing::{debug, info, warn};
 
-use super::SourceOffset;
+use super::{kpl, SourceOffset};
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
 pub enum KinesisOffset {
@@ -51,6 +51,10 @@ pub struct KinesisSourceFunc {
     pub kinesis_client: Option<KinesisClient>,
     pub aws_region: Option<String>,
     pub shards: HashMap<String, ShardState>,
+    /// the shards that were in the stream when the source first started, which are read from the
+    /// configured offset; shards created later by resharding are read from the beginning. These
+    /// are kept in state so that they're known after a restore.
+    pub initial_shards: HashSet<String>,
     pub offset: SourceOffset,
 }
 
@@ -85,6 +89,49 @@ impl TryFrom<(String, Shard, SourceOffset)> for ShardState {
 }
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
+/// Returns the subtask that reads a shard. The hash key space is divided into a contiguous range
+/// for each subtask, and a shard is read by the subtask whose range contains its starting hash
+/// key. As splits divide a shard's range and merges combine adjacent ranges, children are read by
+/// the same subtask as their parents (both parents, for a merge) unless the reshard crosses the
+/// boundary between two subtasks' ranges, so that subtask can hold off on reading the children
+/// until the parents have been read to the end. This only depends on the shard itself, so every
+/// subtask agrees on the owner of each shard, and the owners are spread evenly across the hash
+/// key space rather than following the shards that the stream started with.
+fn shard_owner(shard_id: &str, shards: &HashMap<String, Shard>, parallelism: usize) -> usize {
+    let starting_hash_key = shards
+        .get(shard_id)
+        .and_then(|shard| shard.hash_key_range())
+        .and_then(|range| range.starting_hash_key())
+        .and_then(|key| key.parse::<u128>().ok());
+
+    match starting_hash_key {
+        // hash keys are 128-bit, so the top 64 bits are enough to place the shard
+        Some(key) => (((key >> 64) * parallelism as u128) >> 64) as usize,
+        // shards that have been trimmed from the stream are no longer in the listing, so they're
+        // spread across the subtasks by the sequence number in their ids (`shardId-000000000005`)
+        None => match shard_id
+            .rsplit('-')
+            .next()
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            Some(n) => n % parallelism,
+            None => {
+                let mut hasher = DefaultHasher::new();
+                shard_id.hash(&mut hasher);
+                hasher.finish() as usize % parallelism
+            }
+        },
+    }
+}
+
+/// Returns the shards in the stream by id
+fn shards_by_id(shards: &[Shard]) -> HashMap<String, Shard> {
+    shards
+        .iter()
+        .map(|shard| (shard.shard_id().unwrap().to_string(), shard.clone()))
+        .collect()
+}
+
 impl ShardState {
     fn new(stream_name: String, shard: Shard, source_offset: SourceOffset) -> Self {
         Self {
@@ -165,7 +212,9 @@ impl SourceOperator for KinesisSourceFunc {
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
-        global_table_config("k", "kinesis source state")
+        let mut tables = global_table_config("k", "kinesis source state");
+        tables.extend(global_table_config("i", "kinesis initial shards"));
+        tables
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
@@ -189,35 +238,50 @@ impl SourceOperator for KinesisSourceFunc {
 
 impl KinesisSourceFunc {
     /// Initializes the shards for the operator. First shards are read out of state,
-    /// then `sync_shards()` is called to find any new shards.
+    /// then `assign_shards()` is called to find any new shards.
     /// It returns a future for each shard to fetch the next shard iterator id.
     async fn init_shards(
         &mut self,
         ctx: &mut ArrowContext,
     ) -> anyhow::Result<Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>> {
+        let shards = self.get_splits().await?;
+        let by_id = shards_by_id(&shards);
+
         let mut futures = Vec::new();
+        let initial_shards: &mut GlobalKeyedView<String, ()> = ctx
+            .table_manager
+            .get_global_keyed_state("i")
+            .await
+            .expect("failed to get state for kinesis source");
+        self.initial_shards = initial_shards.get_all().keys().cloned().collect();
+
         let s: &mut GlobalKeyedView<String, ShardState> = ctx
             .table_manager
             .get_global_keyed_state("k")
             .await
             .expect("failed to get state for kinesis source");
+        if s.get_all().is_empty() {
+            self.initial_shards = by_id.keys().cloned().collect();
+        }
         for (shard_id, shard_state) in s
             .get_all()
             .values()
             .map(|shard_state| (shard_state.shard_id.clone(), shard_state.clone()))
-            .filter(|(shard_id, _shard_state)| {
-                let mut hasher = DefaultHasher::new();
-                shard_id.hash(&mut hasher);
-                let shard_hash = hasher.finish() as usize;
-                shard_hash % ctx.task_info.parallelism == ctx.task_info.task_index
-            })
         {
-            futures.push(
-                shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
-            );
+            if shard_owner(&shard_id, &by_id, ctx.task_info.parallelism) != ctx.task_info.task_index
+            {
+                continue;
+            }
+
+            if !shard_state.closed {
+                futures.push(
+                    shard_state
+                        .get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
+                );
+            }
             self.shards.insert(shard_id, shard_state);
         }
-        let new_futures = self.sync_shards(ctx).await?;
+        let new_futures = self.assign_shards(shards, ctx);
         futures.extend(new_futures.into_iter());
 
         Ok(futures)
@@ -399,6 +463,14 @@ impl KinesisSourceFunc {
                             for (shard_id, shard_state) in &self.shards {
                                 s.insert(shard_id.clone(), shard_state.clone()).await;
                             }
+                            // every subtask knows the initial shards, so only the first stores them
+                            if ctx.task_info.task_index == 0 {
+                                let initial_shards: &mut GlobalKeyedView<String, ()> =
+                                    ctx.table_manager.get_global_keyed_state("i").await.unwrap();
+                                for shard_id in &self.initial_shards {
+                                    initial_shards.insert(shard_id.clone(), ()).await;
+                                }
+                            }
                             if self.start_checkpoint(c, ctx).await {
                                 return Ok(SourceFinishType::Immediate);
                             }
@@ -438,10 +510,20 @@ impl KinesisSourceFunc {
         let records = get_records_output.records.unwrap_or_default();
         for record in records {
             let data = record.data.unwrap().into_inner();
-            let timestamp = record.approximate_arrival_timestamp.unwrap();
-
-            ctx.deserialize_slice(&data, from_nanos(timestamp.as_nanos() as u128))
-                .await?;
+            let timestamp =
+                from_nanos(record.approximate_arrival_timestamp.unwrap().as_nanos() as u128);
+
+            // records written by the KPL may pack many messages into a single kinesis record
+            match kpl::deaggregate(&data) {
+                Some(messages) => {
+                    for message in messages {
+                        ctx.deserialize_slice(&message, timestamp, None).await?;
+                    }
+                }
+                None => {
+                    ctx.deserialize_slice(&data, timestamp, None).await?;
+                }
+            }
 
             if ctx.should_flush() {
                 ctx.flush_buffer().await?
@@ -454,27 +536,91 @@ impl KinesisSourceFunc {
         &mut self,
         ctx: &mut ArrowContext,
     ) -> Result<Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>> {
+        let shards = self.get_splits().await?;
+        Ok(self.assign_shards(shards, ctx))
+    }
+
+    /// Starts reading any shards assigned to this subtask that we aren't yet reading. Children of
+    /// a split or merge aren't read until their parents have been read to the end, so that records
+    /// with the same partition key are read in order.
+    fn assign_shards(
+        &mut self,
+        shards: Vec<Shard>,
+        ctx: &ArrowContext,
+    ) -> Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>> {
+        let by_id = shards_by_id(&shards);
         let mut futures = Vec::new();
-        for shard in self.get_splits().await? {
-            // check hash
+        for shard in shards {
             let shard_id = shard.shard_id().unwrap().to_string();
-            let mut hasher = DefaultHasher::new();
-            shard_id.hash(&mut hasher);
-            let shard_hash = hasher.finish() as usize;
 
             if self.shards.contains_key(&shard_id)
-                || shard_hash % ctx.task_info.parallelism != ctx.task_info.task_index
+                || shard_owner(&shard_id, &by_id, ctx.task_info.parallelism)
+                    != ctx.task_info.task_index
             {
                 continue;
             }
-            let shard_state = ShardState::new(self.stream_name.clone(), shard, self.offset);
+
+            if !shard
+                .parent_shard_id()
+                .into_iter()
+                .chain(shard.adjacent_parent_shard_id())
+                .all(|p| self.parent_exhausted(p, &by_id, ctx))
+            {
+                continue;
+            }
+
+            // everything written to the stream after a reshard is in the new shards, so they're
+            // read from the beginning regardless of the configured offset
+            let offset = if self.initial_shards.contains(&shard_id) {
+                self.offset
+            } else {
+                SourceOffset::Earliest
+            };
+
+            let shard_state = ShardState::new(self.stream_name.clone(), shard, offset);
 
             futures.push(
                 shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
             );
             self.shards.insert(shard_id, shard_state);
         }
-        Ok(futures)
+        futures
+    }
+
+    /// Whether a parent shard has been read to the end. Parents that have been trimmed from the
+    /// stream have nothing left to read. A parent whose range is on the other side of a
+    /// subtask boundary is read by another subtask, which we can't wait on, so once the parent is
+    /// closed to writes ordering across the reshard is best-effort.
+    fn parent_exhausted(
+        &self,
+        parent_id: &str,
+        shards: &HashMap<String, Shard>,
+        ctx: &ArrowContext,
+    ) -> bool {
+        if let Some(shard_state) = self.shards.get(parent_id) {
+            return shard_state.closed;
+        }
+
+        let Some(parent) = shards.get(parent_id) else {
+            return true;
+        };
+
+        if shard_owner(parent_id, shards, ctx.task_info.parallelism) == ctx.task_info.task_index {
+            // we haven't started reading it yet
+            return false;
+        }
+
+        let closed = parent
+            .sequence_number_range()
+            .and_then(|r| r.ending_sequence_number())
+            .is_some();
+        if closed {
+            warn!(
+                "shard {} is read by another subtask, so records may be read out of order across its reshard",
+                parent_id
+            );
+        }
+        closed
     }
 
     async fn get_splits(&mut self) -> Result<Vec<Shard>> {
@@ -520,3 +666,60 @@ impl KinesisSourceFunc {
         Ok(shard_collect)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_kinesis::model::{HashKeyRange, Shard};
+
+    use super::{shard_owner, shards_by_id};
+
+    fn shard(id: &str, parent: Option<&str>, start: u128, end: u128) -> Shard {
+        Shard::builder()
+            .shard_id(id)
+            .set_parent_shard_id(parent.map(|p| p.to_string()))
+            .hash_key_range(
+                HashKeyRange::builder()
+                    .starting_hash_key(start.to_string())
+                    .ending_hash_key(end.to_string())
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_shard_owners() {
+        let half = u128::MAX / 2;
+        let quarter = u128::MAX / 4;
+        let shards = shards_by_id(&[
+            shard("shard-0", None, 0, half),
+            shard("shard-1", None, half + 1, u128::MAX),
+            // shard-0 is split in two
+            shard("shard-2", Some("shard-0"), 0, quarter),
+            shard("shard-3", Some("shard-0"), quarter + 1, half),
+        ]);
+
+        // shards are spread across the subtasks by their hash keys
+        assert_eq!(shard_owner("shard-0", &shards, 2), 0);
+        assert_eq!(shard_owner("shard-1", &shards, 2), 1);
+
+        // children are read by the subtask that reads their parent, unless the split crosses
+        // the boundary between subtasks
+        assert_eq!(shard_owner("shard-2", &shards, 2), 0);
+        assert_eq!(shard_owner("shard-3", &shards, 2), 0);
+        assert_eq!(shard_owner("shard-2", &shards, 4), 0);
+        assert_eq!(shard_owner("shard-3", &shards, 4), 1);
+
+        let owners: Vec<_> = ["shard-0", "shard-1", "shard-2", "shard-3"]
+            .iter()
+            .map(|id| shard_owner(id, &shards, 7))
+            .collect();
+        assert!(owners.iter().all(|o| *o < 7));
+
+        // trimmed shards are assigned by their sequence numbers
+        let empty = HashMap::new();
+        assert_eq!(shard_owner("shardId-000000000005", &empty, 3), 2);
+        assert_eq!(shard_owner("shardId-000000000006", &empty, 3), 0);
+    }
+}
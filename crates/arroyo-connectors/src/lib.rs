@@ -1,9 +1,11 @@
 use crate::confluent::ConfluentConnector;
 use crate::filesystem::delta::DeltaLakeConnector;
+use crate::filesystem::iceberg::IcebergConnector;
 use crate::filesystem::FileSystemConnector;
 use crate::kinesis::KinesisConnector;
 use crate::mqtt::MqttConnector;
 use crate::polling_http::PollingHTTPConnector;
+use crate::postgres::PostgresConnector;
 use crate::preview::PreviewConnector;
 use crate::redis::RedisConnector;
 use crate::single_file::SingleFileConnector;
@@ -44,6 +46,7 @@ pub mod mqtt;
 pub mod nats;
 pub mod nexmark;
 pub mod polling_http;
+pub mod postgres;
 pub mod preview;
 pub mod redis;
 pub mod single_file;
@@ -58,6 +61,7 @@ pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
         Box::new(DeltaLakeConnector {}),
         Box::new(FileSystemConnector {}),
         Box::new(FluvioConnector {}),
+        Box::new(IcebergConnector {}),
         Box::new(ImpulseConnector {}),
         Box::new(KafkaConnector {}),
         Box::new(KinesisConnector {}),
@@ -65,6 +69,7 @@ pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
         Box::new(NatsConnector {}),
         Box::new(NexmarkConnector {}),
         Box::new(PollingHTTPConnector {}),
+        Box::new(PostgresConnector {}),
         Box::new(PreviewConnector {}),
         Box::new(RedisConnector {}),
         Box::new(SingleFileConnector {}),
@@ -135,6 +140,7 @@ pub(crate) fn source_field(name: &str, field_type: FieldType) -> SourceField {
             r#type: field_type,
         },
         nullable: false,
+        metadata_key: None,
     }
 }
 
@@ -181,6 +181,7 @@ impl Connector for MqttConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -286,10 +287,11 @@ impl Connector for MqttConnector {
                 qos,
                 topic: table.topic,
                 retain,
-                serializer: ArrowSerializer::new(
+                serializer: ArrowSerializer::with_framing(
                     config
                         .format
                         .ok_or_else(|| anyhow!("format is required for mqtt sink"))?,
+                    config.framing,
                 ),
                 stopped: Arc::new(AtomicBool::new(false)),
                 client: None,
@@ -295,6 +295,7 @@ impl Connector for NatsConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -372,8 +373,9 @@ impl Connector for NatsConnector {
                     connection: profile.clone(),
                     table: table.clone(),
                     publisher: None,
-                    serializer: ArrowSerializer::new(
+                    serializer: ArrowSerializer::with_framing(
                         config.format.expect("Format must be set for NATS source"),
+                        config.framing,
                     ),
                 }))
             }
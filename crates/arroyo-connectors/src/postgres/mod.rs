@@ -0,0 +1,389 @@
+mod pgoutput;
+mod sink;
+mod source;
+
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, FieldType, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::var_str::VarStr;
+use arroyo_rpc::OperatorConfig;
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot::Receiver;
+use tokio_postgres::Client;
+use tracing::warn;
+use typify::import_types;
+
+use crate::postgres::sink::PostgresSinkFunc;
+use crate::postgres::source::PostgresSourceFunc;
+use crate::{pull_opt, pull_option_to_u64};
+
+const CONFIG_SCHEMA: &str = include_str!("./profile.json");
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+const ICON: &str = include_str!("./postgres.svg");
+
+import_types!(
+    schema = "src/postgres/profile.json",
+    convert = {
+        {type = "string", format = "var-str"} = VarStr
+    }
+);
+import_types!(schema = "src/postgres/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+
+impl PostgresConfig {
+    fn pg_config(&self) -> anyhow::Result<tokio_postgres::Config> {
+        let mut config = tokio_postgres::Config::new();
+        config
+            .host(&self.host)
+            .port(
+                self.port
+                    .unwrap_or(5432)
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid port {:?}", self.port))?,
+            )
+            .dbname(&self.database)
+            .user(&self.user.sub_env_vars()?)
+            .application_name("arroyo")
+            .ssl_mode(match self.ssl_mode {
+                Some(SslMode::Disable) => tokio_postgres::config::SslMode::Disable,
+                Some(SslMode::Prefer) | None => tokio_postgres::config::SslMode::Prefer,
+                Some(SslMode::Require) => tokio_postgres::config::SslMode::Require,
+            });
+
+        if let Some(password) = &self.password {
+            config.password(password.sub_env_vars()?);
+        }
+
+        Ok(config)
+    }
+
+    pub(crate) async fn connect(&self) -> anyhow::Result<Client> {
+        let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let (client, connection) = self
+            .pg_config()?
+            .connect(tls)
+            .await
+            .map_err(|e| anyhow!("failed to connect to Postgres at {}: {}", self.host, e))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres connection closed with error: {:?}", e);
+            }
+        });
+
+        Ok(client)
+    }
+}
+
+async fn test_inner(
+    c: PostgresConfig,
+    tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+) -> anyhow::Result<String> {
+    tx.send(TestSourceMessage::info("Connecting to Postgres"))
+        .await
+        .unwrap();
+
+    let client = c.connect().await?;
+
+    tx.send(TestSourceMessage::info(
+        "Connected successfully, running query",
+    ))
+    .await
+    .unwrap();
+
+    client
+        .simple_query("SELECT 1")
+        .await
+        .map_err(|e| anyhow!("failed to run query: {}", e))?;
+
+    Ok("Successfully connected to Postgres".to_string())
+}
+
+pub struct PostgresConnector {}
+
+impl Connector for PostgresConnector {
+    type ProfileT = PostgresConfig;
+    type TableT = PostgresTable;
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "postgres".to_string(),
+            name: "Postgres".to_string(),
+            icon: ICON.to_string(),
+            description: "Read changes from a Postgres table, or write results to one".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.table_type {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn get_schema(
+        &self,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        s: Option<&ConnectionSchema>,
+    ) -> Option<ConnectionSchema> {
+        s.cloned()
+    }
+
+    fn test_profile(&self, profile: Self::ProfileT) -> Option<Receiver<TestSourceMessage>> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            let (itx, _rx) = tokio::sync::mpsc::channel(8);
+            let message = match test_inner(profile, itx).await {
+                Ok(_) => TestSourceMessage::done("Successfully connected to Postgres"),
+                Err(e) => {
+                    TestSourceMessage::fail(format!("Failed to connect to Postgres: {:?}", e))
+                }
+            };
+
+            tx.send(message).unwrap();
+        });
+
+        Some(rx)
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        c: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let resp = match test_inner(c, tx.clone()).await {
+                Ok(c) => TestSourceMessage::done(c),
+                Err(e) => TestSourceMessage::fail(e.to_string()),
+            };
+
+            tx.send(resp).await.unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let connection_config = match profile {
+            Some(connection_profile) => {
+                serde_json::from_value(connection_profile.config.clone())
+                    .map_err(|e| anyhow!("Failed to parse connection config: {:?}", e))?
+            }
+            None => PostgresConfig {
+                host: pull_opt("host", options)?,
+                port: options
+                    .remove("port")
+                    .map(|p| p.parse())
+                    .transpose()
+                    .map_err(|_| anyhow!("port must be a number"))?,
+                database: pull_opt("database", options)?,
+                user: VarStr::new(pull_opt("user", options)?),
+                password: options.remove("password").map(VarStr::new),
+                ssl_mode: match options.remove("ssl_mode").as_deref() {
+                    Some("disable") => Some(SslMode::Disable),
+                    Some("prefer") | None => Some(SslMode::Prefer),
+                    Some("require") => Some(SslMode::Require),
+                    Some(s) => {
+                        bail!("'{}' is not a valid value for ssl_mode; must be one of 'disable', 'prefer', or 'require'", s);
+                    }
+                },
+            },
+        };
+
+        let table_type = match pull_opt("type", options)?.as_str() {
+            "source" => TableType::Source {
+                slot_name: pull_opt("source.slot_name", options)?,
+                publication: pull_opt("source.publication", options)?,
+                poll_interval_ms: pull_option_to_u64("source.poll_interval_ms", options)?
+                    .map(|i| i.try_into())
+                    .transpose()
+                    .map_err(|_| anyhow!("source.poll_interval_ms must be greater than 0"))?,
+            },
+            "sink" => TableType::Sink {
+                primary_key: options
+                    .remove("sink.primary_key")
+                    .map(|k| k.split(',').map(|c| c.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                commit_mode: match options.remove("sink.commit_mode").as_deref() {
+                    Some("at_least_once") | None => CommitMode::AtLeastOnce,
+                    Some("exactly_once") => CommitMode::ExactlyOnce,
+                    Some(s) => {
+                        bail!("'{}' is not a valid value for sink.commit_mode; must be one of 'at_least_once' or 'exactly_once'", s);
+                    }
+                },
+                batch_size: pull_option_to_u64("sink.batch_size", options)?
+                    .map(|b| b.try_into())
+                    .transpose()
+                    .map_err(|_| anyhow!("sink.batch_size must be greater than 0"))?,
+            },
+            s => {
+                bail!("'{}' is not a valid type; must be `source` or `sink`", s);
+            }
+        };
+
+        let table = PostgresTable {
+            table_name: pull_opt("table_name", options)?,
+            table_type,
+        };
+
+        self.from_config(None, name, connection_config, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Postgres connection"))?;
+
+        let updating = schema.format.as_ref().is_some_and(|f| f.is_updating());
+
+        let (connection_type, description) = match &table.table_type {
+            TableType::Source { .. } => {
+                // changes are read as debezium-style rows, which the planner turns into
+                // updates and retractions
+                if !matches!(
+                    schema.format,
+                    Some(Format::Json(JsonFormat { debezium: true, .. }))
+                ) {
+                    bail!("Postgres sources must use the 'debezium_json' format");
+                }
+
+                (ConnectionType::Source, "PostgresSource")
+            }
+            TableType::Sink { primary_key, .. } => {
+                // for updating queries, the values of the rows are in the `after` field
+                let fields = if updating {
+                    schema
+                        .fields
+                        .iter()
+                        .find(|f| f.field_name == "after")
+                        .and_then(|f| match &f.field_type.r#type {
+                            FieldType::Struct(s) => Some(&s.fields),
+                            _ => None,
+                        })
+                        .unwrap_or(&schema.fields)
+                } else {
+                    &schema.fields
+                };
+
+                if updating && primary_key.is_empty() {
+                    bail!("sink.primary_key must be set to write an updating query to Postgres");
+                }
+
+                for key in primary_key {
+                    if !fields.iter().any(|f| &f.field_name == key) {
+                        bail!("primary key column '{}' is not a column of the table", key);
+                    }
+                }
+
+                (ConnectionType::Sink, "PostgresSink")
+            }
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            format: schema.format.clone(),
+            bad_data: schema.bad_data.clone(),
+            framing: schema.framing.clone(),
+            ..Default::default()
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description: description.to_string(),
+        })
+    }
+
+    fn make_operator(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        match table.table_type {
+            TableType::Source {
+                slot_name,
+                publication,
+                poll_interval_ms,
+            } => Ok(OperatorNode::from_source(Box::new(
+                PostgresSourceFunc::new(
+                    profile,
+                    table.table_name,
+                    slot_name,
+                    publication,
+                    Duration::from_millis(
+                        poll_interval_ms
+                            .map(NonZeroU64::get)
+                            .unwrap_or(DEFAULT_POLL_INTERVAL_MS),
+                    ),
+                    config
+                        .format
+                        .ok_or_else(|| anyhow!("format required for Postgres source"))?,
+                    config.framing,
+                    config.bad_data,
+                ),
+            ))),
+            TableType::Sink {
+                primary_key,
+                commit_mode,
+                batch_size,
+            } => Ok(OperatorNode::from_operator(Box::new(
+                PostgresSinkFunc::new(
+                    profile,
+                    table.table_name,
+                    primary_key,
+                    commit_mode,
+                    batch_size
+                        .map(NonZeroU64::get)
+                        .map(|b| b as usize)
+                        .unwrap_or(DEFAULT_BATCH_SIZE),
+                ),
+            ))),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Decoding for the messages of the `pgoutput` logical decoding plugin (protocol version 1), as
+//! described in https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html
+
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TupleValue {
+    Null,
+    // a TOASTed value that wasn't changed, so isn't included in the message
+    Unchanged,
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogicalMessage {
+    Begin {
+        final_lsn: u64,
+        // microseconds since 2000-01-01
+        commit_time: i64,
+    },
+    Commit {
+        commit_lsn: u64,
+    },
+    Relation {
+        id: u32,
+        namespace: String,
+        name: String,
+        // the names of the columns
+        columns: Vec<String>,
+    },
+    Insert {
+        relation: u32,
+        new: Vec<TupleValue>,
+    },
+    Update {
+        relation: u32,
+        // only sent if the table has REPLICA IDENTITY FULL or the key was changed
+        old: Option<Vec<TupleValue>>,
+        new: Vec<TupleValue>,
+    },
+    Delete {
+        relation: u32,
+        old: Vec<TupleValue>,
+    },
+    Truncate {
+        relations: Vec<u32>,
+    },
+    // origin, type, and logical decoding messages, which we don't need
+    Other(u8),
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            bail!("unexpected end of pgoutput message");
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> Result<String> {
+        let end = self
+            .buf
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow!("unterminated string in pgoutput message"))?;
+        let s = String::from_utf8(self.bytes(end)?.to_vec())?;
+        self.bytes(1)?;
+        Ok(s)
+    }
+
+    fn tuple(&mut self) -> Result<Vec<TupleValue>> {
+        let columns = self.i16()?;
+        (0..columns)
+            .map(|_| match self.u8()? {
+                b'n' => Ok(TupleValue::Null),
+                b'u' => Ok(TupleValue::Unchanged),
+                b't' => {
+                    let len = self.i32()?;
+                    Ok(TupleValue::Text(String::from_utf8(
+                        self.bytes(len as usize)?.to_vec(),
+                    )?))
+                }
+                k => bail!("unsupported tuple value kind '{}'", k as char),
+            })
+            .collect()
+    }
+}
+
+pub fn parse(buf: &[u8]) -> Result<LogicalMessage> {
+    let mut r = Reader { buf };
+
+    Ok(match r.u8()? {
+        b'B' => {
+            let final_lsn = r.u64()?;
+            let commit_time = r.i64()?;
+            LogicalMessage::Begin {
+                final_lsn,
+                commit_time,
+            }
+        }
+        b'C' => {
+            let _flags = r.u8()?;
+            let commit_lsn = r.u64()?;
+            let _end_lsn = r.u64()?;
+            LogicalMessage::Commit { commit_lsn }
+        }
+        b'R' => {
+            let id = r.u32()?;
+            let namespace = r.cstr()?;
+            let name = r.cstr()?;
+            let _replica_identity = r.u8()?;
+            let columns = (0..r.i16()?)
+                .map(|_| {
+                    let _flags = r.u8()?;
+                    let name = r.cstr()?;
+                    let _type_oid = r.u32()?;
+                    let _type_modifier = r.i32()?;
+                    Ok(name)
+                })
+                .collect::<Result<_>>()?;
+            LogicalMessage::Relation {
+                id,
+                namespace,
+                name,
+                columns,
+            }
+        }
+        b'I' => {
+            let relation = r.u32()?;
+            if r.u8()? != b'N' {
+                bail!("expected new tuple in insert message");
+            }
+            LogicalMessage::Insert {
+                relation,
+                new: r.tuple()?,
+            }
+        }
+        b'U' => {
+            let relation = r.u32()?;
+            let old = match r.u8()? {
+                b'K' | b'O' => {
+                    let old = r.tuple()?;
+                    if r.u8()? != b'N' {
+                        bail!("expected new tuple in update message");
+                    }
+                    Some(old)
+                }
+                b'N' => None,
+                k => bail!("unexpected tuple kind '{}' in update message", k as char),
+            };
+            LogicalMessage::Update {
+                relation,
+                old,
+                new: r.tuple()?,
+            }
+        }
+        b'D' => {
+            let relation = r.u32()?;
+            match r.u8()? {
+                b'K' | b'O' => {}
+                k => bail!("unexpected tuple kind '{}' in delete message", k as char),
+            }
+            LogicalMessage::Delete {
+                relation,
+                old: r.tuple()?,
+            }
+        }
+        b'T' => {
+            let count = r.u32()?;
+            let _options = r.u8()?;
+            LogicalMessage::Truncate {
+                relations: (0..count).map(|_| r.u32()).collect::<Result<_>>()?,
+            }
+        }
+        t => LogicalMessage::Other(t),
+    })
+}
+
+/// Formats an LSN in its textual form, like `16/B374D848`
+pub fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(values: &[Option<&str>]) -> Vec<u8> {
+        let mut buf = (values.len() as i16).to_be_bytes().to_vec();
+        for v in values {
+            match v {
+                Some(v) => {
+                    buf.push(b't');
+                    buf.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                    buf.extend_from_slice(v.as_bytes());
+                }
+                None => buf.push(b'n'),
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn test_parse_relation() {
+        let mut buf = vec![b'R'];
+        buf.extend_from_slice(&16385u32.to_be_bytes());
+        buf.extend_from_slice(b"public\0orders\0");
+        buf.push(b'f');
+        buf.extend_from_slice(&2i16.to_be_bytes());
+        for (name, oid) in [("id", 20u32), ("status", 25)] {
+            buf.push(0);
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&oid.to_be_bytes());
+            buf.extend_from_slice(&(-1i32).to_be_bytes());
+        }
+
+        assert_eq!(
+            parse(&buf).unwrap(),
+            LogicalMessage::Relation {
+                id: 16385,
+                namespace: "public".to_string(),
+                name: "orders".to_string(),
+                columns: vec!["id".to_string(), "status".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_update() {
+        let mut buf = vec![b'U'];
+        buf.extend_from_slice(&16385u32.to_be_bytes());
+        buf.push(b'O');
+        buf.extend(tuple(&[Some("1"), Some("pending")]));
+        buf.push(b'N');
+        buf.extend(tuple(&[Some("1"), None]));
+
+        assert_eq!(
+            parse(&buf).unwrap(),
+            LogicalMessage::Update {
+                relation: 16385,
+                old: Some(vec![
+                    TupleValue::Text("1".to_string()),
+                    TupleValue::Text("pending".to_string())
+                ]),
+                new: vec![TupleValue::Text("1".to_string()), TupleValue::Null],
+            }
+        );
+
+        // truncated messages are errors rather than panics
+        assert!(parse(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_lsn() {
+        assert_eq!(format_lsn(0x16_B374_D848), "16/B374D848");
+        assert_eq!(format_lsn(0x0000_0001_0000_00A0), "1/A0");
+        assert_eq!(format_lsn(0), "0/0");
+    }
+}
@@ -0,0 +1,920 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch, StructArray};
+use arrow::compute::{cast, interleave};
+use arrow::datatypes::{
+    DataType, Date32Type, Field, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Schema,
+    TimeUnit, TimestampMicrosecondType,
+};
+use arrow::row::{OwnedRow, RowConverter, SortField};
+use arrow::temporal_conversions::{date32_to_datetime, timestamp_us_to_datetime};
+use arroyo_formats::ser::ArrowSerializer;
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::grpc::{GlobalKeyedTableConfig, TableConfig, TableEnum};
+use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp, TIMESTAMP_FIELD};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_types::{CheckpointBarrier, SignalMessage, TaskInfo};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use itertools::Itertools;
+use prost::Message;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, Statement};
+use tracing::{info, warn};
+
+use super::{CommitMode, PostgresConfig};
+
+pub struct PostgresSinkFunc {
+    config: PostgresConfig,
+    table_name: String,
+    primary_key: Vec<String>,
+    commit_mode: CommitMode,
+    batch_size: usize,
+    client: Option<Client>,
+    writer: Option<TableWriter>,
+    // whether a transaction has been started for the current checkpoint
+    in_transaction: bool,
+    // with exactly-once commits, the transactions prepared by checkpoints, which are committed
+    // once those checkpoints complete
+    pending_transactions: Vec<PendingTransaction>,
+}
+
+/// A transaction that was prepared as part of a checkpoint, and will be committed once that
+/// checkpoint is complete
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct PendingTransaction {
+    gid: String,
+    epoch: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Upsert,
+    Delete,
+}
+
+/// The changes received since the last write. With a primary key, only the latest change to
+/// each key is kept, since it would overwrite the earlier ones within the transaction anyway.
+#[derive(Default)]
+struct ChangeBuffer {
+    batches: Vec<RecordBatch>,
+    // the change and the (batch, row) that holds its values
+    changes: Vec<(Change, usize, usize)>,
+    latest: HashMap<OwnedRow, usize>,
+    received: usize,
+}
+
+impl ChangeBuffer {
+    fn add(&mut self, change: Change, batch: usize, row: usize, key: Option<OwnedRow>) {
+        self.received += 1;
+        let Some(key) = key else {
+            self.changes.push((change, batch, row));
+            return;
+        };
+
+        match self.latest.entry(key) {
+            Entry::Occupied(e) => {
+                self.changes[*e.get()] = (change, batch, row);
+            }
+            Entry::Vacant(e) => {
+                e.insert(self.changes.len());
+                self.changes.push((change, batch, row));
+            }
+        }
+    }
+
+    fn rows(&self, change: Change) -> Vec<(usize, usize)> {
+        self.changes
+            .iter()
+            .filter(|(c, _, _)| *c == change)
+            .map(|(_, batch, row)| (*batch, *row))
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.batches.clear();
+        self.changes.clear();
+        self.latest.clear();
+        self.received = 0;
+    }
+}
+
+/// Writes the input to the table, using statements that take each column as an array, so that a
+/// whole batch of rows is written by a single statement
+struct TableWriter {
+    // whether the input is the debezium before/after/op form of an updating query
+    updating: bool,
+    // the columns of the input that are written to the table, if it's not updating
+    projection: Vec<usize>,
+    key_indices: Vec<usize>,
+    key_converter: Option<RowConverter>,
+    column_types: Vec<Type>,
+    insert: Statement,
+    delete: Option<Statement>,
+    buffer: ChangeBuffer,
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_table_name(name: &str) -> String {
+    name.split('.').map(quote_identifier).join(".")
+}
+
+/// Returns the subtask that prepared a transaction of this operator, from its gid
+/// (`<prefix><subtask>-<epoch>`)
+fn transaction_owner(gid: &str, prefix: &str) -> Option<usize> {
+    let (owner, _) = gid.strip_prefix(prefix)?.split_once('-')?;
+    owner.parse().ok()
+}
+
+/// Whether values for a column of this type are sent as arrays of the type itself; other types
+/// are sent as text and cast by Postgres
+fn is_native(ty: &Type) -> bool {
+    matches!(
+        *ty,
+        Type::BOOL
+            | Type::INT2
+            | Type::INT4
+            | Type::INT8
+            | Type::FLOAT4
+            | Type::FLOAT8
+            | Type::TEXT
+            | Type::VARCHAR
+            | Type::BPCHAR
+            | Type::NAME
+            | Type::BYTEA
+            | Type::TIMESTAMP
+            | Type::TIMESTAMPTZ
+            | Type::DATE
+    )
+}
+
+fn type_name(ty: &Type) -> String {
+    format!(
+        "{}.{}",
+        quote_identifier(ty.schema()),
+        quote_identifier(ty.name())
+    )
+}
+
+/// Selects the given columns from arrays passed as parameters, one row per element
+fn unnest_select(types: &[&Type]) -> String {
+    let arrays = types
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| {
+            if is_native(ty) {
+                format!("${}::{}[]", i + 1, type_name(ty))
+            } else {
+                format!("${}::text[]", i + 1)
+            }
+        })
+        .join(", ");
+    let columns = types
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| {
+            if is_native(ty) {
+                format!("c{}", i)
+            } else {
+                format!("c{}::{}", i, type_name(ty))
+            }
+        })
+        .join(", ");
+    let aliases = (0..types.len()).map(|i| format!("c{}", i)).join(", ");
+
+    format!(
+        "SELECT {} FROM UNNEST({}) AS v({})",
+        columns, arrays, aliases
+    )
+}
+
+impl TableWriter {
+    async fn new(
+        client: &Client,
+        table_name: &str,
+        primary_key: &[String],
+        input: &Schema,
+    ) -> Result<Self> {
+        let updating = input.column_with_name("op").is_some()
+            && input
+                .column_with_name("after")
+                .is_some_and(|(_, f)| matches!(f.data_type(), DataType::Struct(_)));
+
+        let (schema, projection) = if updating {
+            let DataType::Struct(fields) = input.field_with_name("after")?.data_type() else {
+                unreachable!()
+            };
+            (Schema::new(fields.clone()), vec![])
+        } else {
+            let projection: Vec<usize> = input
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.name() != TIMESTAMP_FIELD)
+                .map(|(i, _)| i)
+                .collect();
+            (input.project(&projection)?, projection)
+        };
+
+        if updating && primary_key.is_empty() {
+            bail!("a primary key must be set to write an updating query to Postgres");
+        }
+
+        let key_indices = primary_key
+            .iter()
+            .map(|k| {
+                schema
+                    .index_of(k)
+                    .map_err(|_| anyhow!("primary key column '{}' is not in the table", k))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let key_converter = if key_indices.is_empty() {
+            None
+        } else {
+            Some(RowConverter::new(
+                key_indices
+                    .iter()
+                    .map(|i| SortField::new(schema.field(*i).data_type().clone()))
+                    .collect(),
+            )?)
+        };
+
+        let table = quote_table_name(table_name);
+        let columns: Vec<String> = schema
+            .fields()
+            .iter()
+            .map(|f| quote_identifier(f.name()))
+            .collect();
+
+        // preparing an insert of a single row tells us the types of the columns in the table
+        let probe = client
+            .prepare(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                columns.join(", "),
+                (1..=columns.len()).map(|i| format!("${}", i)).join(", ")
+            ))
+            .await
+            .map_err(|e| anyhow!("failed to prepare insert into {}: {}", table, e))?;
+        let column_types = probe.params().to_vec();
+
+        let mut insert = format!(
+            "INSERT INTO {} ({}) {}",
+            table,
+            columns.join(", "),
+            unnest_select(&column_types.iter().collect::<Vec<_>>())
+        );
+        if !key_indices.is_empty() {
+            let keys = key_indices.iter().map(|i| &columns[*i]).join(", ");
+            let updates = columns
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !key_indices.contains(i))
+                .map(|(_, c)| format!("{} = EXCLUDED.{}", c, c))
+                .join(", ");
+            if updates.is_empty() {
+                insert.push_str(&format!(" ON CONFLICT ({}) DO NOTHING", keys));
+            } else {
+                insert.push_str(&format!(
+                    " ON CONFLICT ({}) DO UPDATE SET {}",
+                    keys, updates
+                ));
+            }
+        }
+        let insert = client
+            .prepare(&insert)
+            .await
+            .map_err(|e| anyhow!("failed to prepare upsert into {}: {}", table, e))?;
+
+        let delete = if key_indices.is_empty() {
+            None
+        } else {
+            let sql = format!(
+                "DELETE FROM {} WHERE ({}) IN ({})",
+                table,
+                key_indices.iter().map(|i| &columns[*i]).join(", "),
+                unnest_select(
+                    &key_indices
+                        .iter()
+                        .map(|i| &column_types[*i])
+                        .collect::<Vec<_>>()
+                )
+            );
+            Some(
+                client
+                    .prepare(&sql)
+                    .await
+                    .map_err(|e| anyhow!("failed to prepare delete from {}: {}", table, e))?,
+            )
+        };
+
+        Ok(Self {
+            updating,
+            projection,
+            key_indices,
+            key_converter,
+            column_types,
+            insert,
+            delete,
+            buffer: ChangeBuffer::default(),
+        })
+    }
+
+    fn add_batch(
+        &mut self,
+        change: Option<Change>,
+        values: RecordBatch,
+        ops: &[Change],
+    ) -> Result<()> {
+        let batch_index = self.buffer.batches.len();
+        let keys = match &self.key_converter {
+            Some(converter) => Some(
+                converter.convert_columns(
+                    &self
+                        .key_indices
+                        .iter()
+                        .map(|i| values.column(*i).clone())
+                        .collect::<Vec<_>>(),
+                )?,
+            ),
+            None => None,
+        };
+
+        for (row, op) in ops.iter().enumerate() {
+            if change.is_some_and(|c| c != *op) {
+                continue;
+            }
+            let key = keys.as_ref().map(|keys| keys.row(row).owned());
+            self.buffer.add(*op, batch_index, row, key);
+        }
+        self.buffer.batches.push(values);
+        Ok(())
+    }
+
+    fn push(&mut self, batch: &RecordBatch) -> Result<()> {
+        if !self.updating {
+            let ops = vec![Change::Upsert; batch.num_rows()];
+            return self.add_batch(None, batch.project(&self.projection)?, &ops);
+        }
+
+        let ops: Vec<Change> = batch
+            .column_by_name("op")
+            .ok_or_else(|| anyhow!("updating input is missing the op column"))?
+            .as_string::<i32>()
+            .iter()
+            .map(|op| match op {
+                Some("d") => Change::Delete,
+                _ => Change::Upsert,
+            })
+            .collect();
+
+        // deletes take their key from the row before the change, and upserts from the row after
+        for (column, change) in [("before", Change::Delete), ("after", Change::Upsert)] {
+            if !ops.contains(&change) {
+                continue;
+            }
+            let values = batch
+                .column_by_name(column)
+                .ok_or_else(|| anyhow!("updating input is missing the {} column", column))?
+                .as_struct()
+                .clone();
+            self.add_batch(Some(change), struct_to_batch(values)?, &ops)?;
+        }
+
+        Ok(())
+    }
+
+    fn buffered(&self) -> usize {
+        self.buffer.received
+    }
+
+    async fn flush(&mut self, client: &Client) -> Result<()> {
+        let deletes = self.buffer.rows(Change::Delete);
+        if !deletes.is_empty() {
+            let params = self.params(&deletes, &self.key_indices)?;
+            client
+                .execute(self.delete.as_ref().unwrap(), &param_refs(&params))
+                .await?;
+        }
+
+        let upserts = self.buffer.rows(Change::Upsert);
+        if !upserts.is_empty() {
+            let columns: Vec<usize> = (0..self.column_types.len()).collect();
+            let params = self.params(&upserts, &columns)?;
+            client.execute(&self.insert, &param_refs(&params)).await?;
+        }
+
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Gathers the given rows of the given columns from the buffered batches, as parameters
+    fn params(
+        &self,
+        rows: &[(usize, usize)],
+        columns: &[usize],
+    ) -> Result<Vec<Box<dyn ToSql + Sync>>> {
+        columns
+            .iter()
+            .map(|c| {
+                let arrays: Vec<&dyn Array> = self
+                    .buffer
+                    .batches
+                    .iter()
+                    .map(|b| b.column(*c).as_ref())
+                    .collect();
+                column_param(&interleave(&arrays, rows)?, &self.column_types[*c])
+            })
+            .collect()
+    }
+}
+
+fn struct_to_batch(array: StructArray) -> Result<RecordBatch> {
+    // the nulls of the struct mark the rows that don't have a before or after, which are skipped
+    let (fields, columns, _) = array.into_parts();
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+fn param_refs(params: &[Box<dyn ToSql + Sync>]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|p| p.as_ref()).collect()
+}
+
+/// Converts a column into an array parameter for a column of the given Postgres type
+fn column_param(column: &ArrayRef, ty: &Type) -> Result<Box<dyn ToSql + Sync>> {
+    Ok(match *ty {
+        Type::BOOL => Box::new(
+            cast(column, &DataType::Boolean)?
+                .as_boolean()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::INT2 => Box::new(
+            cast(column, &DataType::Int16)?
+                .as_primitive::<Int16Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::INT4 => Box::new(
+            cast(column, &DataType::Int32)?
+                .as_primitive::<Int32Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::INT8 => Box::new(
+            cast(column, &DataType::Int64)?
+                .as_primitive::<Int64Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::FLOAT4 => Box::new(
+            cast(column, &DataType::Float32)?
+                .as_primitive::<Float32Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::FLOAT8 => Box::new(
+            cast(column, &DataType::Float64)?
+                .as_primitive::<Float64Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+        ),
+        Type::BYTEA => Box::new(
+            cast(column, &DataType::Binary)?
+                .as_binary::<i32>()
+                .iter()
+                .map(|v| v.map(|v| v.to_vec()))
+                .collect::<Vec<_>>(),
+        ),
+        Type::TIMESTAMP | Type::TIMESTAMPTZ => {
+            let timestamps = cast(column, &DataType::Timestamp(TimeUnit::Microsecond, None))?;
+            let timestamps = timestamps
+                .as_primitive::<TimestampMicrosecondType>()
+                .iter()
+                .map(|t| t.and_then(timestamp_us_to_datetime));
+            if *ty == Type::TIMESTAMP {
+                Box::new(timestamps.collect::<Vec<_>>())
+            } else {
+                Box::new(
+                    timestamps
+                        .map(|t| t.map(|t| t.and_utc()))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        }
+        Type::DATE => Box::new(
+            cast(column, &DataType::Date32)?
+                .as_primitive::<Date32Type>()
+                .iter()
+                .map(|d| d.and_then(date32_to_datetime).map(|d| d.date()))
+                .collect::<Vec<_>>(),
+        ),
+        // text, and the types that are sent as text and cast by Postgres
+        _ => Box::new(text_values(column)?),
+    })
+}
+
+/// Converts a column to text, with nested types written as JSON
+fn text_values(column: &ArrayRef) -> Result<Vec<Option<String>>> {
+    if matches!(
+        column.data_type(),
+        DataType::Struct(_) | DataType::List(_) | DataType::LargeList(_) | DataType::Map(_, _)
+    ) {
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                "v",
+                column.data_type().clone(),
+                true,
+            )])),
+            vec![column.clone()],
+        )?;
+
+        let mut serializer = ArrowSerializer::new(Format::Json(JsonFormat::default()));
+        return serializer
+            .serialize(&batch)
+            .enumerate()
+            .map(|(i, row)| {
+                if column.is_null(i) {
+                    return Ok(None);
+                }
+                let mut value: serde_json::Value = serde_json::from_slice(&row)?;
+                Ok(value.get_mut("v").map(|v| v.take().to_string()))
+            })
+            .collect();
+    }
+
+    Ok(cast(column, &DataType::Utf8)?
+        .as_string::<i32>()
+        .iter()
+        .map(|v| v.map(|v| v.to_string()))
+        .collect())
+}
+
+impl PostgresSinkFunc {
+    pub fn new(
+        config: PostgresConfig,
+        table_name: String,
+        primary_key: Vec<String>,
+        commit_mode: CommitMode,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            config,
+            table_name,
+            primary_key,
+            commit_mode,
+            batch_size,
+            client: None,
+            writer: None,
+            in_transaction: false,
+            pending_transactions: vec![],
+        }
+    }
+
+    fn transaction_prefix(task_info: &TaskInfo) -> String {
+        format!("arroyo-{}-{}-", task_info.job_id, task_info.operator_id)
+    }
+
+    /// Commits the transactions that were prepared for the checkpoint we restored from, which
+    /// may not have been committed before the pipeline stopped, and rolls back those that were
+    /// prepared for later checkpoints that never completed.
+    ///
+    /// Epochs are numbered from the restored checkpoint again, so a transaction left over from
+    /// an incomplete checkpoint has the same gid as one we'll prepare; each subtask rolls back its
+    /// own before it prepares anything, which means we never roll back a transaction that belongs
+    /// to this run. Subtask 0 also commits the restored transactions and rolls back those of
+    /// subtasks that no longer exist.
+    async fn recover_transactions(&self, client: &Client, ctx: &mut ArrowContext) -> Result<()> {
+        let state: &mut GlobalKeyedView<String, PendingTransaction> = ctx
+            .table_manager
+            .get_global_keyed_state("p")
+            .await
+            .expect("should be able to get postgres sink state");
+        let restored: Vec<String> = state.get_all().values().map(|t| t.gid.clone()).collect();
+
+        let task_index = ctx.task_info.task_index;
+        let parallelism = ctx.task_info.parallelism;
+
+        if task_index == 0 {
+            for gid in &restored {
+                info!("committing recovered transaction {}", gid);
+                match client
+                    .batch_execute(&format!("COMMIT PREPARED '{}'", gid.replace('\'', "''")))
+                    .await
+                {
+                    Ok(_) => {}
+                    // already committed
+                    Err(e) if e.code() == Some(&SqlState::UNDEFINED_OBJECT) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        let prefix = Self::transaction_prefix(&ctx.task_info);
+        for row in client
+            .query(
+                "SELECT gid FROM pg_prepared_xacts WHERE database = current_database()",
+                &[],
+            )
+            .await?
+        {
+            let gid: String = row.get(0);
+            if restored.contains(&gid) {
+                continue;
+            }
+
+            let Some(owner) = transaction_owner(&gid, &prefix) else {
+                continue;
+            };
+
+            if owner == task_index || (task_index == 0 && owner >= parallelism) {
+                warn!(
+                    "rolling back transaction {} from an incomplete checkpoint",
+                    gid
+                );
+                client
+                    .batch_execute(&format!("ROLLBACK PREPARED '{}'", gid.replace('\'', "''")))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered changes to the table, as part of the transaction for the current
+    /// checkpoint
+    async fn write(&mut self) -> Result<()> {
+        let (Some(client), Some(writer)) = (&self.client, &mut self.writer) else {
+            bail!("postgres sink has not been started");
+        };
+
+        if writer.buffered() == 0 {
+            return Ok(());
+        }
+
+        if !self.in_transaction {
+            client.batch_execute("BEGIN").await?;
+            self.in_transaction = true;
+        }
+
+        writer.flush(client).await
+    }
+
+    /// Ends the transaction for the current checkpoint, either by committing it or, for
+    /// exactly-once commits, by preparing it to be committed once the checkpoint completes
+    async fn end_transaction(&mut self, epoch: Option<u32>, ctx: &mut ArrowContext) -> Result<()> {
+        if !self.in_transaction {
+            return Ok(());
+        }
+        let client = self.client.as_ref().unwrap();
+
+        match (self.commit_mode, epoch) {
+            (CommitMode::ExactlyOnce, Some(epoch)) => {
+                let gid = format!(
+                    "{}{}-{}",
+                    Self::transaction_prefix(&ctx.task_info),
+                    ctx.task_info.task_index,
+                    epoch
+                );
+                client
+                    .batch_execute(&format!(
+                        "PREPARE TRANSACTION '{}'",
+                        gid.replace('\'', "''")
+                    ))
+                    .await?;
+                self.pending_transactions
+                    .push(PendingTransaction { gid, epoch });
+            }
+            _ => {
+                client.batch_execute("COMMIT").await?;
+            }
+        }
+
+        self.in_transaction = false;
+        Ok(())
+    }
+
+    async fn start(&mut self, ctx: &mut ArrowContext) -> Result<()> {
+        let client = self.config.connect().await?;
+        let writer = TableWriter::new(
+            &client,
+            &self.table_name,
+            &self.primary_key,
+            &ctx.in_schemas[0].schema,
+        )
+        .await?;
+
+        // finish the commits of the previous run
+        if self.commit_mode == CommitMode::ExactlyOnce {
+            self.recover_transactions(&client, ctx).await?;
+        }
+
+        self.client = Some(client);
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    async fn fail(ctx: &mut ArrowContext, message: &str, e: anyhow::Error) {
+        ctx.report_error(message, format!("{:?}", e)).await;
+        panic!("{}: {:?}", message, e);
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for PostgresSinkFunc {
+    fn name(&self) -> String {
+        format!("postgres-sink-{}", self.table_name)
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        if self.commit_mode != CommitMode::ExactlyOnce {
+            return HashMap::new();
+        }
+
+        HashMap::from([(
+            "p".to_string(),
+            TableConfig {
+                table_type: TableEnum::GlobalKeyValue.into(),
+                config: GlobalKeyedTableConfig {
+                    table_name: "p".into(),
+                    description: "prepared transactions".into(),
+                    uses_two_phase_commit: true,
+                }
+                .encode_to_vec(),
+            },
+        )])
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        if let Err(e) = self.start(ctx).await {
+            Self::fail(ctx, "Failed to start Postgres sink", e).await;
+        }
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        if let Err(e) = self.writer.as_mut().unwrap().push(&batch) {
+            Self::fail(ctx, "Failed to write to Postgres", e).await;
+        }
+
+        if self.writer.as_ref().unwrap().buffered() >= self.batch_size {
+            if let Err(e) = self.write().await {
+                Self::fail(ctx, "Failed to write to Postgres", e).await;
+            }
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        if let Err(e) = self.write().await {
+            Self::fail(ctx, "Failed to write to Postgres", e).await;
+        }
+        if let Err(e) = self.end_transaction(Some(barrier.epoch), ctx).await {
+            Self::fail(ctx, "Failed to commit Postgres transaction", e).await;
+        }
+
+        if self.commit_mode == CommitMode::ExactlyOnce {
+            let state: &mut GlobalKeyedView<String, PendingTransaction> = ctx
+                .table_manager
+                .get_global_keyed_state("p")
+                .await
+                .expect("should be able to get postgres sink state");
+            for pending in &self.pending_transactions {
+                state.insert(pending.gid.clone(), pending.clone()).await;
+            }
+            ctx.table_manager
+                .insert_committing_data("p", vec![])
+                .await
+                .expect("should be able to send committing data");
+        }
+    }
+
+    async fn handle_commit(
+        &mut self,
+        epoch: u32,
+        _commit_data: &HashMap<String, HashMap<u32, Vec<u8>>>,
+        ctx: &mut ArrowContext,
+    ) {
+        let (committing, pending): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending_transactions)
+                .into_iter()
+                .partition(|pending| pending.epoch <= epoch);
+        self.pending_transactions = pending;
+
+        for pending in committing {
+            let client = self.client.as_ref().unwrap();
+            if let Err(e) = client
+                .batch_execute(&format!(
+                    "COMMIT PREPARED '{}'",
+                    pending.gid.replace('\'', "''")
+                ))
+                .await
+            {
+                // the transaction will be recovered from the checkpoint when we restart
+                Self::fail(ctx, "Failed to commit Postgres transaction", e.into()).await;
+            }
+        }
+
+        let checkpoint_event = ControlResp::CheckpointEvent(CheckpointEvent {
+            checkpoint_epoch: epoch,
+            operator_id: ctx.task_info.operator_id.clone(),
+            subtask_index: ctx.task_info.task_index as u32,
+            time: SystemTime::now(),
+            event_type: arroyo_rpc::grpc::TaskCheckpointEventType::FinishedCommit,
+        });
+        ctx.control_tx
+            .send(checkpoint_event)
+            .await
+            .expect("sent commit event");
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        // anything written since the last checkpoint won't be covered by another one
+        if let Err(e) = self.write().await {
+            Self::fail(ctx, "Failed to write to Postgres", e).await;
+        }
+        if let Err(e) = self.end_transaction(None, ctx).await {
+            Self::fail(ctx, "Failed to commit Postgres transaction", e).await;
+        }
+
+        if self.pending_transactions.is_empty() {
+            return;
+        }
+        if let Some(ControlMessage::Commit { epoch, commit_data }) = ctx.control_rx.recv().await {
+            self.handle_commit(epoch, &commit_data, ctx).await;
+        } else {
+            warn!("no commit message received, not committing")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote_table_name, transaction_owner, unnest_select, Change, ChangeBuffer};
+    use arrow::array::{ArrayRef, Int64Array};
+    use arrow::datatypes::DataType;
+    use arrow::row::{RowConverter, SortField};
+    use std::sync::Arc;
+    use tokio_postgres::types::Type;
+
+    #[test]
+    fn test_quote_table_name() {
+        assert_eq!(quote_table_name("orders"), "\"orders\"");
+        assert_eq!(quote_table_name("public.orders"), "\"public\".\"orders\"");
+        assert_eq!(quote_table_name("my\"table"), "\"my\"\"table\"");
+    }
+
+    #[test]
+    fn test_transaction_owner() {
+        let prefix = "arroyo-job_1-op_2-";
+        assert_eq!(transaction_owner("arroyo-job_1-op_2-3-15", prefix), Some(3));
+        assert_eq!(transaction_owner("arroyo-job_1-op_3-3-15", prefix), None);
+        assert_eq!(transaction_owner("arroyo-job_1-op_2-x-15", prefix), None);
+    }
+
+    #[test]
+    fn test_unnest_select() {
+        assert_eq!(
+            unnest_select(&[&Type::INT8, &Type::NUMERIC]),
+            "SELECT c0, c1::\"pg_catalog\".\"numeric\" FROM UNNEST($1::\"pg_catalog\".\"int8\"[], $2::text[]) AS v(c0, c1)"
+        );
+    }
+
+    #[test]
+    fn test_buffer_keeps_latest_change_per_key() {
+        let converter = RowConverter::new(vec![SortField::new(DataType::Int64)]).unwrap();
+        let keys: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 1, 3, 2]));
+        let rows = converter.convert_columns(&[keys]).unwrap();
+
+        let mut buffer = ChangeBuffer::default();
+        let ops = [
+            Change::Upsert,
+            Change::Upsert,
+            Change::Delete,
+            Change::Upsert,
+            Change::Delete,
+        ];
+        for (i, op) in ops.iter().enumerate() {
+            buffer.add(*op, 0, i, Some(rows.row(i).owned()));
+        }
+        buffer.add(Change::Upsert, 1, 0, Some(rows.row(0).owned()));
+
+        assert_eq!(buffer.received, 6);
+        assert_eq!(buffer.rows(Change::Upsert), vec![(1, 0), (0, 3)]);
+        assert_eq!(buffer.rows(Change::Delete), vec![(0, 4)]);
+
+        buffer.clear();
+        assert!(buffer.changes.is_empty() && buffer.latest.is_empty());
+    }
+}
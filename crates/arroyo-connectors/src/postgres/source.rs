@@ -0,0 +1,628 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use arrow::datatypes::DataType;
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::{GlobalKeyedTableConfig, StopMode, TableConfig, TableEnum};
+use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use chrono::DateTime;
+use futures::{pin_mut, TryStreamExt};
+use prost::Message;
+use serde_json::{json, Value};
+use tokio::select;
+use tokio::time::MissedTickBehavior;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::pgoutput::{self, format_lsn, LogicalMessage, TupleValue};
+use super::PostgresConfig;
+
+// the number of seconds between the unix epoch and the postgres epoch of 2000-01-01
+const POSTGRES_EPOCH_OFFSET_SECS: u64 = 946_684_800;
+
+// the number of changes decoded by each read from the slot; more may be returned to finish the
+// last transaction
+const MAX_CHANGES_PER_POLL: i32 = 10_000;
+
+pub struct PostgresSourceFunc {
+    config: PostgresConfig,
+    table_name: String,
+    slot_name: String,
+    publication: String,
+    poll_interval: Duration,
+    format: Format,
+    framing: Option<Framing>,
+    bad_data: Option<BadData>,
+    state: PostgresSourceState,
+    // the LSNs at each checkpoint, which the slot is advanced to once that checkpoint commits
+    checkpointed: Vec<(u32, u64)>,
+    client: Option<Client>,
+    // the temporary copy of the slot that changes are consumed from
+    read_slot: Option<String>,
+    // the relation id and column names of our table, from the last relation message
+    relation: Option<(u32, Vec<String>)>,
+    field_types: HashMap<String, DataType>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Default)]
+pub struct PostgresSourceState {
+    // the commit LSN of the last transaction that was read
+    lsn: u64,
+}
+
+impl PostgresSourceFunc {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: PostgresConfig,
+        table_name: String,
+        slot_name: String,
+        publication: String,
+        poll_interval: Duration,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+    ) -> Self {
+        Self {
+            config,
+            table_name,
+            slot_name,
+            publication,
+            poll_interval,
+            format,
+            framing,
+            bad_data,
+            state: PostgresSourceState::default(),
+            checkpointed: vec![],
+            client: None,
+            read_slot: None,
+            relation: None,
+            field_types: HashMap::new(),
+        }
+    }
+
+    fn schema_and_table(&self) -> (&str, &str) {
+        self.table_name
+            .split_once('.')
+            .unwrap_or(("public", &self.table_name))
+    }
+
+    /// Creates the replication slot if it doesn't exist, and checks that the table is set up
+    /// for us to read its changes
+    async fn prepare(&self, client: &Client) -> anyhow::Result<()> {
+        let slot = client
+            .query_opt(
+                "SELECT plugin FROM pg_replication_slots WHERE slot_name = $1",
+                &[&self.slot_name],
+            )
+            .await?;
+        match slot {
+            Some(row) => {
+                let plugin: Option<String> = row.get(0);
+                if plugin.as_deref() != Some("pgoutput") {
+                    anyhow::bail!(
+                        "replication slot '{}' must use the pgoutput plugin, not {:?}",
+                        self.slot_name,
+                        plugin
+                    );
+                }
+            }
+            None => {
+                info!("creating replication slot {}", self.slot_name);
+                client
+                    .execute(
+                        "SELECT pg_create_logical_replication_slot($1, 'pgoutput')",
+                        &[&self.slot_name],
+                    )
+                    .await?;
+            }
+        }
+
+        if client
+            .query_opt(
+                "SELECT 1 FROM pg_publication WHERE pubname = $1",
+                &[&self.publication],
+            )
+            .await?
+            .is_none()
+        {
+            anyhow::bail!(
+                "publication '{}' does not exist; create it with `CREATE PUBLICATION {} FOR TABLE {}`",
+                self.publication,
+                self.publication,
+                self.table_name
+            );
+        }
+
+        // updates and deletes can only be retracted if they include the full old row
+        let identity: String = client
+            .query_one(
+                "SELECT relreplident::text FROM pg_class WHERE oid = $1::text::regclass",
+                &[&self.table_name],
+            )
+            .await?
+            .get(0);
+        if identity != "f" {
+            anyhow::bail!(
+                "table {} must have full replica identity to be read by Arroyo; set it with `ALTER TABLE {} REPLICA IDENTITY FULL`",
+                self.table_name,
+                self.table_name
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Advances the slot to the given LSN, which lets Postgres discard the WAL before it
+    async fn advance_slot(&self, client: &Client, lsn: u64) -> anyhow::Result<()> {
+        debug!("advancing slot {} to {}", self.slot_name, format_lsn(lsn));
+        // the slot can't be moved backwards, which would happen if we restored from a
+        // checkpoint that was committed before
+        client
+            .execute(
+                "SELECT pg_replication_slot_advance(slot_name, GREATEST($2::text::pg_lsn, confirmed_flush_lsn)) \
+                 FROM pg_replication_slots WHERE slot_name = $1",
+                &[&self.slot_name, &format_lsn(lsn)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Creates a temporary copy of the slot for this session to consume changes from. The slot
+    /// itself is only advanced when checkpoints commit, so that changes since the last checkpoint
+    /// can be read again after a failure, while the copy is advanced by every read so that each
+    /// change is only decoded once.
+    async fn create_read_slot(&self, client: &Client) -> anyhow::Result<String> {
+        let read_slot = format!("arroyo_tmp_{}", Uuid::new_v4().simple());
+        client
+            .execute(
+                "SELECT pg_copy_logical_replication_slot($1, $2, true)",
+                &[&self.slot_name, &read_slot],
+            )
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "failed to copy replication slot {} (Postgres 12 or later is required): {}",
+                    self.slot_name,
+                    e
+                )
+            })?;
+        Ok(read_slot)
+    }
+
+    fn row_json(
+        &self,
+        columns: &[String],
+        values: &[TupleValue],
+        old: Option<&[TupleValue]>,
+    ) -> Value {
+        Value::Object(
+            columns
+                .iter()
+                .zip(values)
+                .enumerate()
+                .filter_map(|(i, (name, value))| {
+                    let data_type = self.field_types.get(name)?;
+                    let value = match value {
+                        TupleValue::Null => Value::Null,
+                        TupleValue::Text(text) => json_value(text, data_type),
+                        // unchanged values are only in the old row, if anywhere
+                        TupleValue::Unchanged => match old.and_then(|old| old.get(i)) {
+                            Some(TupleValue::Text(text)) => json_value(text, data_type),
+                            _ => Value::Null,
+                        },
+                    };
+                    Some((name.clone(), value))
+                })
+                .collect(),
+        )
+    }
+
+    /// Consumes the next changes from the read slot, skipping transactions that were read before
+    /// the checkpoint we restored from, and emits them as debezium-style rows. Returns whether
+    /// there may be more changes to read.
+    async fn poll(&mut self, ctx: &mut ArrowContext) -> Result<bool, UserError> {
+        let client = self.client.as_ref().unwrap();
+        let read_slot = self.read_slot.as_ref().unwrap();
+        let params: [&(dyn ToSql + Sync); 3] =
+            [read_slot, &MAX_CHANGES_PER_POLL, &self.publication];
+        let rows = client
+            .query_raw(
+                "SELECT data FROM pg_logical_slot_get_binary_changes($1, NULL, $2, \
+                 'proto_version', '1', 'publication_names', $3)",
+                params,
+            )
+            .await
+            .map_err(|e| UserError::new("failed to read changes from Postgres", e.to_string()))?;
+        pin_mut!(rows);
+
+        let (schema, table) = self.schema_and_table();
+        let (schema, table) = (schema.to_string(), table.to_string());
+
+        // whether we've already read the current transaction
+        let mut skipping = false;
+        let mut commit_time = SystemTime::now();
+        let mut read = self.state.lsn;
+        let mut changes = 0;
+
+        while let Some(row) = rows
+            .try_next()
+            .await
+            .map_err(|e| UserError::new("failed to read changes from Postgres", e.to_string()))?
+        {
+            changes += 1;
+            let message = pgoutput::parse(row.get(0)).map_err(|e| {
+                UserError::new("failed to decode change from Postgres", e.to_string())
+            })?;
+
+            let (op, relation, old, new) = match message {
+                LogicalMessage::Begin {
+                    final_lsn,
+                    commit_time: t,
+                } => {
+                    skipping = final_lsn <= self.state.lsn;
+                    commit_time = UNIX_EPOCH
+                        + Duration::from_secs(POSTGRES_EPOCH_OFFSET_SECS)
+                        + Duration::from_micros(t.max(0) as u64);
+                    continue;
+                }
+                LogicalMessage::Commit { commit_lsn, .. } => {
+                    if !skipping {
+                        read = commit_lsn;
+                    }
+                    skipping = false;
+                    continue;
+                }
+                LogicalMessage::Relation {
+                    id,
+                    namespace,
+                    name,
+                    columns,
+                } => {
+                    if namespace == schema && name == table {
+                        self.relation = Some((id, columns));
+                    }
+                    continue;
+                }
+                _ if skipping => continue,
+                LogicalMessage::Insert { relation, new } => ("c", relation, None, Some(new)),
+                LogicalMessage::Update {
+                    relation,
+                    old: Some(old),
+                    new,
+                } => ("u", relation, Some(old), Some(new)),
+                LogicalMessage::Update { old: None, .. } => {
+                    return Err(UserError::new(
+                        "failed to read changes from Postgres",
+                        format!(
+                            "received an update to {} without its old row; the table must have REPLICA IDENTITY FULL",
+                            self.table_name
+                        ),
+                    ));
+                }
+                LogicalMessage::Delete { relation, old } => ("d", relation, Some(old), None),
+                LogicalMessage::Truncate { relations } => {
+                    if self
+                        .relation
+                        .as_ref()
+                        .is_some_and(|(id, _)| relations.contains(id))
+                    {
+                        warn!(
+                            "{} was truncated; the rows it removed can't be retracted",
+                            self.table_name
+                        );
+                    }
+                    continue;
+                }
+                LogicalMessage::Other(_) => continue,
+            };
+
+            // the publication may include other tables
+            let Some((_, columns)) = self.relation.as_ref().filter(|(id, _)| *id == relation)
+            else {
+                continue;
+            };
+
+            let old_json = old.as_deref().map(|old| self.row_json(columns, old, None));
+            let new_json = new
+                .as_deref()
+                .map(|new| self.row_json(columns, new, old.as_deref()));
+
+            let record = json!({
+                "before": old_json,
+                "after": new_json,
+                "op": op,
+            });
+
+            ctx.deserialize_slice(&serde_json::to_vec(&record).unwrap(), commit_time, None)
+                .await?;
+
+            if ctx.should_flush() {
+                ctx.flush_buffer().await?;
+            }
+        }
+
+        self.state.lsn = read;
+        Ok(changes >= MAX_CHANGES_PER_POLL)
+    }
+
+    async fn commit(&mut self, epoch: u32, ctx: &mut ArrowContext) {
+        let lsn = self
+            .checkpointed
+            .iter()
+            .filter(|(e, _)| *e <= epoch)
+            .map(|(_, lsn)| *lsn)
+            .max();
+        self.checkpointed.retain(|(e, _)| *e > epoch);
+
+        if let (Some(client), Some(lsn)) = (&self.client, lsn) {
+            if lsn > 0 {
+                if let Err(e) = self.advance_slot(client, lsn).await {
+                    // the slot will be advanced on the next commit, or when we restart
+                    warn!(
+                        "failed to advance replication slot {}: {:?}",
+                        self.slot_name, e
+                    );
+                }
+            }
+        }
+
+        ctx.control_tx
+            .send(ControlResp::CheckpointEvent(CheckpointEvent {
+                checkpoint_epoch: epoch,
+                operator_id: ctx.task_info.operator_id.clone(),
+                subtask_index: ctx.task_info.task_index as u32,
+                time: SystemTime::now(),
+                event_type: arroyo_rpc::grpc::TaskCheckpointEventType::FinishedCommit,
+            }))
+            .await
+            .expect("sent commit event");
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut ArrowContext,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if ctx.task_info.task_index == 0 {
+                    let s: &mut GlobalKeyedView<(), PostgresSourceState> = ctx
+                        .table_manager
+                        .get_global_keyed_state("s")
+                        .await
+                        .expect("should be able to get postgres state");
+                    s.insert((), self.state.clone()).await;
+                    self.checkpointed.push((c.epoch, self.state.lsn));
+                }
+                ctx.table_manager
+                    .insert_committing_data("s", vec![])
+                    .await
+                    .expect("should be able to send committing data");
+
+                if self.start_checkpoint(c, ctx).await {
+                    // the final checkpoint is still committed before we stop
+                    if let Some(ControlMessage::Commit { epoch, .. }) = ctx.control_rx.recv().await
+                    {
+                        self.commit(epoch, ctx).await;
+                    } else {
+                        warn!("no commit message received, not advancing replication slot");
+                    }
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping postgres source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch, .. } => {
+                self.commit(epoch, ctx).await;
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        ctx.initialize_deserializer(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+        );
+
+        if ctx.task_info.task_index != 0 {
+            // a replication slot can only be read by one consumer, so the other tasks are idle
+            ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
+                Watermark::Idle,
+            )))
+            .await;
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        if let Some(DataType::Struct(fields)) = ctx
+            .out_schema
+            .as_ref()
+            .and_then(|s| s.schema.field_with_name("after").ok())
+            .map(|f| f.data_type().clone())
+        {
+            self.field_types = fields
+                .iter()
+                .map(|f| (f.name().clone(), f.data_type().clone()))
+                .collect();
+        }
+
+        let client = self
+            .config
+            .connect()
+            .await
+            .map_err(|e| UserError::new("failed to connect to Postgres", e.to_string()))?;
+        self.prepare(&client)
+            .await
+            .map_err(|e| UserError::new("failed to set up Postgres source", e.to_string()))?;
+
+        // finish the commit of the checkpoint we restored from, which may have been interrupted
+        if self.state.lsn > 0 {
+            self.advance_slot(&client, self.state.lsn)
+                .await
+                .map_err(|e| UserError::new("failed to advance replication slot", e.to_string()))?;
+        }
+        self.read_slot = Some(
+            self.create_read_slot(&client)
+                .await
+                .map_err(|e| UserError::new("failed to set up Postgres source", e.to_string()))?,
+        );
+        self.client = Some(client);
+
+        let mut timer = tokio::time::interval(self.poll_interval);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            select! {
+                _ = timer.tick() => {
+                    if self.poll(ctx).await? {
+                        timer.reset_immediately();
+                    }
+                    ctx.flush_buffer().await?;
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Converts a value in Postgres' text format to the JSON value that will be decoded into a
+/// field of the given type
+fn json_value(text: &str, data_type: &DataType) -> Value {
+    match data_type {
+        DataType::Boolean => Value::Bool(text == "t"),
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float32
+        | DataType::Float64 => serde_json::from_str::<serde_json::Number>(text)
+            .map(Value::Number)
+            // like NaN and Infinity, which are parsed from strings
+            .unwrap_or_else(|_| Value::String(text.to_string())),
+        // Postgres writes offsets like `+00`, which aren't valid RFC 3339
+        DataType::Timestamp(_, _) => Value::String(
+            DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%#z")
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|_| text.to_string()),
+        ),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+#[async_trait]
+impl SourceOperator for PostgresSourceFunc {
+    fn name(&self) -> String {
+        format!("postgres-source-{}", self.table_name)
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        // the slot is advanced when checkpoints commit, so the table is two-phase
+        HashMap::from([(
+            "s".to_string(),
+            TableConfig {
+                table_type: TableEnum::GlobalKeyValue.into(),
+                config: GlobalKeyedTableConfig {
+                    table_name: "s".into(),
+                    description: "postgres replication state".into(),
+                    uses_two_phase_commit: true,
+                }
+                .encode_to_vec(),
+            },
+        )])
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let s: &mut GlobalKeyedView<(), PostgresSourceState> = ctx
+            .table_manager
+            .get_global_keyed_state("s")
+            .await
+            .expect("should be able to read postgres state");
+
+        if let Some(state) = s.get(&()) {
+            self.state = state.clone();
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_value;
+    use arrow::datatypes::{DataType, TimeUnit};
+    use serde_json::json;
+
+    #[test]
+    fn test_json_value() {
+        assert_eq!(json_value("t", &DataType::Boolean), json!(true));
+        assert_eq!(json_value("f", &DataType::Boolean), json!(false));
+        assert_eq!(json_value("42", &DataType::Int64), json!(42));
+        assert_eq!(json_value("1.5", &DataType::Float64), json!(1.5));
+        assert_eq!(json_value("NaN", &DataType::Float64), json!("NaN"));
+        assert_eq!(
+            json_value(
+                "2024-03-01 12:30:00.25+00",
+                &DataType::Timestamp(TimeUnit::Nanosecond, None)
+            ),
+            json!("2024-03-01T12:30:00.250+00:00")
+        );
+        assert_eq!(
+            json_value(
+                "2024-03-01 12:30:00",
+                &DataType::Timestamp(TimeUnit::Nanosecond, None)
+            ),
+            json!("2024-03-01 12:30:00")
+        );
+        assert_eq!(
+            json_value("{\"a\": 1}", &DataType::Utf8),
+            json!("{\"a\": 1}")
+        );
+    }
+}
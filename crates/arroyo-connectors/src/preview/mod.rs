@@ -96,6 +96,7 @@ impl Connector for PreviewConnector {
             format: None,
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -3,8 +3,9 @@ mod operator;
 use std::collections::HashMap;
 
 use anyhow::{anyhow, bail};
+use arrow::datatypes::DataType;
 use arroyo_formats::ser::ArrowSerializer;
-use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::connector::{Connection, Connector, LookupConnector, MetadataDef};
 use arroyo_operator::operator::OperatorNode;
 use arroyo_rpc::var_str::VarStr;
 use redis::aio::ConnectionManager;
@@ -20,7 +21,9 @@ use arroyo_rpc::api_types::connections::{
 };
 use arroyo_rpc::OperatorConfig;
 
+use crate::redis::operator::lookup::RedisLookup;
 use crate::redis::operator::sink::{GeneralConnection, RedisSinkFunc};
+use crate::redis::operator::source::RedisStreamSourceFunc;
 use crate::{pull_opt, pull_option_to_u64};
 
 pub struct RedisConnector {}
@@ -29,6 +32,17 @@ const CONFIG_SCHEMA: &str = include_str!("./profile.json");
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./redis.svg");
 
+static METADATA_DEFS: [MetadataDef; 2] = [
+    MetadataDef {
+        name: "key",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "id",
+        data_type: DataType::Utf8,
+    },
+];
+
 import_types!(
     schema = "src/redis/profile.json",
     convert = {
@@ -161,9 +175,11 @@ impl Connector for RedisConnector {
             id: "redis".to_string(),
             name: "Redis".to_string(),
             icon: ICON.to_string(),
-            description: "Write results to Redis".to_string(),
+            description:
+                "Read from Redis Streams, write results to Redis, or enrich streams with lookups into Redis"
+                    .to_string(),
             enabled: true,
-            source: false,
+            source: true,
             sink: true,
             testing: false,
             hidden: false,
@@ -173,8 +189,16 @@ impl Connector for RedisConnector {
         }
     }
 
-    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
-        ConnectionType::Source
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        &METADATA_DEFS
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.connector_type {
+            TableType::Source(_) => ConnectionType::Source,
+            TableType::Target(_) => ConnectionType::Sink,
+            TableType::Lookup(_) => ConnectionType::Lookup,
+        }
     }
 
     fn get_schema(
@@ -287,7 +311,7 @@ impl Connector for RedisConnector {
             Ok(column)
         }
 
-        let sink = match typ.as_str() {
+        let connector_type = match typ.as_str() {
             "sink" => TableType::Target(match pull_opt("target", options)?.as_str() {
                 "string" => Target::StringTable {
                     key_prefix: pull_opt("target.key_prefix", options)?,
@@ -330,12 +354,49 @@ impl Connector for RedisConnector {
                         .transpose()?,
                     hash_key_prefix: pull_opt("target.key_prefix", options)?,
                 },
+                "stream" => Target::StreamTable {
+                    stream_key_prefix: pull_opt("target.key_prefix", options)?,
+                    stream_key_column: options
+                        .remove("target.key_column")
+                        .map(|name| validate_column(schema, name, "target.key_column"))
+                        .transpose()?,
+                    stream_value_field: options.remove("target.value_field"),
+                    stream_max_length: pull_option_to_u64("target.max_length", options)?
+                        .map(|t| t.try_into())
+                        .transpose()
+                        .map_err(|_| anyhow!("target.max_length must be greater than 0"))?,
+                    approximate_trim: options
+                        .remove("target.approximate_trim")
+                        .map(|t| t.parse::<bool>())
+                        .transpose()
+                        .map_err(|_| {
+                            anyhow!("target.approximate_trim must be 'true' or 'false'")
+                        })?,
+                },
                 s => {
                     bail!("'{}' is not a valid redis target", s);
                 }
             }),
+            "source" => TableType::Source(StreamSource {
+                stream_key: pull_opt("source.stream_key", options)?,
+                consumer_group: pull_opt("source.consumer_group", options)?,
+                start_offset: match options.remove("source.offset").as_deref() {
+                    Some("earliest") => Some(StartOffset::Earliest),
+                    Some("latest") | None => Some(StartOffset::Latest),
+                    Some(s) => {
+                        bail!("'{}' is not a valid value for source.offset; must be one of 'earliest' or 'latest'", s);
+                    }
+                },
+                value_field: options.remove("source.value_field"),
+            }),
+            "lookup" => TableType::Lookup(LookupTable {
+                key_prefix: options.remove("lookup.key_prefix"),
+            }),
             s => {
-                bail!("'{}' is not a valid type; must be `sink`", s);
+                bail!(
+                    "'{}' is not a valid type; must be `source`, `sink` or `lookup`",
+                    s
+                );
             }
         };
 
@@ -343,9 +404,7 @@ impl Connector for RedisConnector {
             None,
             name,
             connection_config,
-            RedisTable {
-                connector_type: sink,
-            },
+            RedisTable { connector_type },
             s,
         )
     }
@@ -370,6 +429,12 @@ impl Connector for RedisConnector {
 
         let _ = RedisClient::new(&config)?;
 
+        let (connection_type, description) = match &table.connector_type {
+            TableType::Source(_) => (ConnectionType::Source, "RedisStreamSource"),
+            TableType::Target(_) => (ConnectionType::Sink, "RedisSink"),
+            TableType::Lookup(_) => (ConnectionType::Lookup, "RedisLookup"),
+        };
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -377,16 +442,18 @@ impl Connector for RedisConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: schema.metadata_fields(),
+            ..Default::default()
         };
 
         Ok(Connection {
             id,
             connector: self.name(),
             name: name.to_string(),
-            connection_type: ConnectionType::Sink,
+            connection_type,
             schema,
             config: serde_json::to_string(&config).unwrap(),
-            description: "RedisSink".to_string(),
+            description: description.to_string(),
         })
     }
 
@@ -398,6 +465,26 @@ impl Connector for RedisConnector {
     ) -> anyhow::Result<OperatorNode> {
         let client = RedisClient::new(&profile)?;
 
+        let target = match table.connector_type {
+            TableType::Source(source) => {
+                return Ok(OperatorNode::from_source(Box::new(RedisStreamSourceFunc {
+                    client,
+                    stream_key: source.stream_key,
+                    consumer_group: source.consumer_group,
+                    start_offset: source.start_offset.unwrap_or(StartOffset::Latest),
+                    value_field: source.value_field.unwrap_or_else(|| "value".to_string()),
+                    format: config.format.expect("redis table must have a format"),
+                    framing: config.framing,
+                    bad_data: config.bad_data,
+                    metadata_fields: config.metadata_fields,
+                })));
+            }
+            TableType::Target(target) => target,
+            TableType::Lookup(_) => {
+                bail!("Redis lookup tables can only be used in lookup joins");
+            }
+        };
+
         let (tx, cmd_rx) = tokio::sync::mpsc::channel(128);
         let (cmd_tx, rx) = tokio::sync::mpsc::channel(128);
 
@@ -405,7 +492,7 @@ impl Connector for RedisConnector {
             serializer: ArrowSerializer::new(
                 config.format.expect("redis table must have a format"),
             ),
-            table,
+            target,
             client,
             cmd_q: Some((cmd_tx, cmd_rx)),
             tx,
@@ -414,4 +501,21 @@ impl Connector for RedisConnector {
             hash_index: None,
         })))
     }
+
+    fn make_lookup(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        _: OperatorConfig,
+    ) -> anyhow::Result<Box<dyn LookupConnector>> {
+        let TableType::Lookup(lookup) = table.connector_type else {
+            bail!("only Redis lookup tables can be used in lookup joins");
+        };
+
+        Ok(Box::new(RedisLookup {
+            client: RedisClient::new(&profile)?,
+            connection: None,
+            key_prefix: lookup.key_prefix.unwrap_or_default(),
+        }))
+    }
 }
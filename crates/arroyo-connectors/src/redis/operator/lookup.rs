@@ -0,0 +1,68 @@
+use crate::redis::operator::sink::GeneralConnection;
+use crate::redis::RedisClient;
+use anyhow::anyhow;
+use arroyo_operator::connector::LookupConnector;
+use async_trait::async_trait;
+use futures::future::try_join_all;
+
+pub struct RedisLookup {
+    pub(crate) client: RedisClient,
+    pub(crate) connection: Option<GeneralConnection>,
+    pub(crate) key_prefix: String,
+}
+
+impl RedisLookup {
+    async fn connection(&mut self) -> anyhow::Result<&mut GeneralConnection> {
+        if self.connection.is_none() {
+            self.connection = Some(
+                self.client
+                    .get_connection()
+                    .await
+                    .map_err(|e| anyhow!("failed to connect to Redis: {:?}", e))?,
+            );
+        }
+
+        Ok(self.connection.as_mut().unwrap())
+    }
+}
+
+#[async_trait]
+impl LookupConnector for RedisLookup {
+    fn name(&self) -> String {
+        "RedisLookup".to_string()
+    }
+
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        let keys: Vec<_> = keys
+            .iter()
+            .map(|k| format!("{}{}", self.key_prefix, k))
+            .collect();
+
+        let result: Result<Vec<Option<Vec<u8>>>, redis::RedisError> =
+            match self.connection().await? {
+                GeneralConnection::Standard(connection) => {
+                    redis::cmd("MGET").arg(&keys).query_async(connection).await
+                }
+                GeneralConnection::Clustered(connection) => {
+                    // the keys may live on different nodes, so they can't be fetched with a single
+                    // MGET
+                    try_join_all(keys.iter().map(|key| {
+                        let mut connection = connection.clone();
+                        async move {
+                            redis::cmd("GET")
+                                .arg(key)
+                                .query_async(&mut connection)
+                                .await
+                        }
+                    }))
+                    .await
+                }
+            };
+
+        result.map_err(|e| {
+            // reconnect on the next lookup
+            self.connection = None;
+            anyhow!("failed to read from Redis: {:?}", e)
+        })
+    }
+}
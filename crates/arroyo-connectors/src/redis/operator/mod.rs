@@ -1 +1,3 @@
+pub mod lookup;
 pub mod sink;
+pub mod source;
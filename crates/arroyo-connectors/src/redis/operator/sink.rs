@@ -1,4 +1,4 @@
-use crate::redis::{ListOperation, RedisClient, RedisTable, TableType, Target};
+use crate::redis::{ListOperation, RedisClient, Target};
 use arrow::array::{AsArray, RecordBatch};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::{ArrowContext, ErrorReporter};
@@ -7,6 +7,7 @@ use arroyo_types::CheckpointBarrier;
 use async_trait::async_trait;
 use redis::aio::{ConnectionLike, ConnectionManager};
 use redis::cluster_async::ClusterConnection;
+use redis::streams::StreamMaxlen;
 use redis::{Cmd, Pipeline, RedisFuture};
 use std::collections::HashSet;
 use std::time::{Duration, Instant};
@@ -19,7 +20,7 @@ const FLUSH_BYTES: usize = 10 * 1024 * 1024;
 
 pub struct RedisSinkFunc {
     pub serializer: ArrowSerializer,
-    pub table: RedisTable,
+    pub target: Target,
     pub client: RedisClient,
     pub cmd_q: Option<(Sender<u32>, Receiver<RedisCmd>)>,
 
@@ -42,11 +43,20 @@ impl RedisSinkFunc {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 enum RedisBehavior {
-    Set { ttl: Option<usize> },
-    Push { append: bool, max: Option<usize> },
+    Set {
+        ttl: Option<usize>,
+    },
+    Push {
+        append: bool,
+        max: Option<usize>,
+    },
     Hash,
+    Stream {
+        field: String,
+        max: Option<StreamMaxlen>,
+    },
 }
 
 pub enum RedisCmd {
@@ -132,11 +142,11 @@ impl RedisWriter {
                             Some(RedisCmd::Data { key, value }) => {
                                 self.size_estimate += key.len() + value.len();
 
-                                match self.behavior {
+                                match &self.behavior {
                                     RedisBehavior::Set {ttl } => {
                                         // TODO: resolve duplicates before sending
                                         if let Some(ttl) = ttl {
-                                            self.pipeline.set_ex(key, value, ttl as u64);
+                                            self.pipeline.set_ex(key, value, *ttl as u64);
                                         } else {
                                             self.pipeline.set(key, value);
                                         }
@@ -146,12 +156,23 @@ impl RedisWriter {
                                             self.max_push_keys.insert(key.clone());
                                         }
 
-                                        if append {
+                                        if *append {
                                             self.pipeline.rpush(key, value);
                                         } else {
                                             self.pipeline.lpush(key, value);
                                         }
                                     }
+                                    RedisBehavior::Stream { field, max } => {
+                                        match max {
+                                            Some(max) => {
+                                                self.pipeline
+                                                    .xadd_maxlen(key, *max, "*", &[(field, value)]);
+                                            }
+                                            None => {
+                                                self.pipeline.xadd(key, "*", &[(field, value)]);
+                                            }
+                                        }
+                                    }
                                     RedisBehavior::Hash => {
                                         unreachable!();
                                     }
@@ -233,19 +254,23 @@ impl ArrowOperator for RedisSinkFunc {
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
-        match &self.table.connector_type {
-            TableType::Target(Target::ListTable {
+        match &self.target {
+            Target::ListTable {
                 list_key_column: Some(key),
                 ..
-            })
-            | TableType::Target(Target::StringTable {
+            }
+            | Target::StringTable {
                 key_column: Some(key),
                 ..
-            })
-            | TableType::Target(Target::HashTable {
+            }
+            | Target::HashTable {
                 hash_key_column: Some(key),
                 ..
-            }) => {
+            }
+            | Target::StreamTable {
+                stream_key_column: Some(key),
+                ..
+            } => {
                 self.key_index = Some(
                     ctx.in_schemas
                         .first()
@@ -262,9 +287,9 @@ impl ArrowOperator for RedisSinkFunc {
             _ => {}
         }
 
-        if let TableType::Target(Target::HashTable {
+        if let Target::HashTable {
             hash_field_column, ..
-        }) = &self.table.connector_type
+        } = &self.target
         {
             self.hash_index = Some(ctx.in_schemas.first().expect("no in-schema for redis sink!")
                 .schema
@@ -286,17 +311,15 @@ impl ArrowOperator for RedisSinkFunc {
                         size_estimate: 0,
                         last_flushed: Instant::now(),
                         max_push_keys: HashSet::new(),
-                        behavior: match self.table.connector_type {
-                            TableType::Target(Target::StringTable { ttl_secs, .. }) => {
-                                RedisBehavior::Set {
-                                    ttl: ttl_secs.map(|t| t.get() as usize),
-                                }
-                            }
-                            TableType::Target(Target::ListTable {
+                        behavior: match &self.target {
+                            Target::StringTable { ttl_secs, .. } => RedisBehavior::Set {
+                                ttl: ttl_secs.map(|t| t.get() as usize),
+                            },
+                            Target::ListTable {
                                 max_length,
                                 operation,
                                 ..
-                            }) => {
+                            } => {
                                 let max = max_length.map(|x| x.get() as usize);
                                 match operation {
                                     ListOperation::Append => {
@@ -307,7 +330,24 @@ impl ArrowOperator for RedisSinkFunc {
                                     }
                                 }
                             }
-                            TableType::Target(Target::HashTable { .. }) => RedisBehavior::Hash,
+                            Target::HashTable { .. } => RedisBehavior::Hash,
+                            Target::StreamTable {
+                                stream_value_field,
+                                stream_max_length,
+                                approximate_trim,
+                                ..
+                            } => RedisBehavior::Stream {
+                                field: stream_value_field
+                                    .clone()
+                                    .unwrap_or_else(|| "value".to_string()),
+                                max: stream_max_length.map(|max| {
+                                    if approximate_trim.unwrap_or(false) {
+                                        StreamMaxlen::Approx(max.get() as usize)
+                                    } else {
+                                        StreamMaxlen::Equals(max.get() as usize)
+                                    }
+                                }),
+                            },
                         },
                     }
                     .start();
@@ -327,39 +367,41 @@ impl ArrowOperator for RedisSinkFunc {
 
     async fn process_batch(&mut self, batch: RecordBatch, _: &mut ArrowContext) {
         for (i, value) in self.serializer.serialize(&batch).enumerate() {
-            match &self.table.connector_type {
-                TableType::Target(target) => match &target {
-                    Target::StringTable { key_prefix, .. } => {
-                        let key = self.make_key(key_prefix, &batch, i);
-                        self.tx
-                            .send(RedisCmd::Data { key, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                    Target::ListTable { list_prefix, .. } => {
-                        let key = self.make_key(list_prefix, &batch, i);
-
-                        self.tx
-                            .send(RedisCmd::Data { key, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                    Target::HashTable {
-                        hash_key_prefix, ..
-                    } => {
-                        let key = self.make_key(hash_key_prefix, &batch, i);
-                        let field = batch
-                            .column(self.hash_index.expect("no hash index"))
-                            .as_string::<i32>()
-                            .value(i)
-                            .to_string();
-
-                        self.tx
-                            .send(RedisCmd::HData { key, field, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                },
+            match &self.target {
+                Target::StringTable { key_prefix, .. } => {
+                    let key = self.make_key(key_prefix, &batch, i);
+                    self.tx
+                        .send(RedisCmd::Data { key, value })
+                        .await
+                        .expect("Redis writer panicked");
+                }
+                Target::ListTable { list_prefix, .. }
+                | Target::StreamTable {
+                    stream_key_prefix: list_prefix,
+                    ..
+                } => {
+                    let key = self.make_key(list_prefix, &batch, i);
+
+                    self.tx
+                        .send(RedisCmd::Data { key, value })
+                        .await
+                        .expect("Redis writer panicked");
+                }
+                Target::HashTable {
+                    hash_key_prefix, ..
+                } => {
+                    let key = self.make_key(hash_key_prefix, &batch, i);
+                    let field = batch
+                        .column(self.hash_index.expect("no hash index"))
+                        .as_string::<i32>()
+                        .value(i)
+                        .to_string();
+
+                    self.tx
+                        .send(RedisCmd::HData { key, field, value })
+                        .await
+                        .expect("Redis writer panicked");
+                }
             };
         }
     }
@@ -0,0 +1,366 @@
+use crate::redis::operator::sink::GeneralConnection;
+use crate::redis::{RedisClient, StartOffset};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::{StopMode, TableConfig};
+use arroyo_rpc::schema_resolver::FailingSchemaResolver;
+use arroyo_rpc::{ControlMessage, ControlResp, MetadataField};
+use arroyo_types::{MetadataValue, UserError};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use redis::streams::{StreamInfoConsumersReply, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+const READ_COUNT: usize = 1000;
+const BLOCK_MILLIS: usize = 100;
+
+pub struct RedisStreamSourceFunc {
+    pub client: RedisClient,
+    pub stream_key: String,
+    pub consumer_group: String,
+    pub start_offset: StartOffset,
+    pub value_field: String,
+    pub format: Format,
+    pub framing: Option<Framing>,
+    pub bad_data: Option<BadData>,
+    pub metadata_fields: Vec<MetadataField>,
+}
+
+/// The last entry that each consumer had read as of a checkpoint
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct RedisStreamState {
+    consumer: String,
+    last_id: String,
+}
+
+/// Parses a stream entry ID (`<millis>-<seq>`) so that IDs can be compared
+fn parse_id(id: &str) -> Option<(u64, u64)> {
+    let (millis, seq) = id.split_once('-')?;
+    Some((millis.parse().ok()?, seq.parse().ok()?))
+}
+
+fn is_after(id: &str, last: Option<&str>) -> bool {
+    match last {
+        Some(last) => parse_id(id) > parse_id(last),
+        None => true,
+    }
+}
+
+#[async_trait]
+impl SourceOperator for RedisStreamSourceFunc {
+    fn name(&self) -> String {
+        format!("redis-stream-{}", self.stream_key)
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        arroyo_state::global_table_config("r", "redis stream source state")
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: ctx.task_info.operator_id.clone(),
+                        task_index: ctx.task_info.task_index,
+                        message: e.name.clone(),
+                        details: e.details.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+}
+
+impl RedisStreamSourceFunc {
+    async fn create_group(&self, connection: &mut GeneralConnection) -> Result<(), UserError> {
+        let start = match self.start_offset {
+            StartOffset::Earliest => "0",
+            StartOffset::Latest => "$",
+        };
+
+        let result: Result<(), redis::RedisError> = connection
+            .xgroup_create_mkstream(&self.stream_key, &self.consumer_group, start)
+            .await;
+
+        match result {
+            Ok(_) => {
+                info!(
+                    "created consumer group {} for stream {}",
+                    self.consumer_group, self.stream_key
+                );
+                Ok(())
+            }
+            // the group already exists, so we continue from its position
+            Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+            Err(e) => Err(UserError::new(
+                "failed to create Redis consumer group",
+                format!(
+                    "could not create consumer group {} for stream {}: {:?}",
+                    self.consumer_group, self.stream_key, e
+                ),
+            )),
+        }
+    }
+
+    /// Returns the consumers from a previous run at a higher parallelism that still have pending
+    /// entries and that this subtask takes over; nothing else would ever read those entries
+    async fn orphaned_consumers(
+        &self,
+        connection: &mut GeneralConnection,
+        ctx: &ArrowContext,
+    ) -> Result<Vec<String>, UserError> {
+        let reply: StreamInfoConsumersReply = connection
+            .xinfo_consumers(&self.stream_key, &self.consumer_group)
+            .await
+            .map_err(|e| {
+                UserError::new(
+                    "failed to list Redis stream consumers",
+                    format!(
+                        "could not list the consumers of group {} for stream {}: {:?}",
+                        self.consumer_group, self.stream_key, e
+                    ),
+                )
+            })?;
+
+        let prefix = format!("arroyo-{}-", ctx.task_info.job_id);
+        let parallelism = ctx.task_info.parallelism;
+
+        Ok(reply
+            .consumers
+            .into_iter()
+            .filter(|c| c.pending > 0)
+            .filter_map(|c| {
+                let index: usize = c.name.strip_prefix(&prefix)?.parse().ok()?;
+                (index >= parallelism && index % parallelism == ctx.task_info.task_index)
+                    .then_some(c.name)
+            })
+            .collect())
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        ctx.initialize_deserializer_with_resolver(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+            &self.metadata_fields,
+            Arc::new(FailingSchemaResolver::new()),
+        );
+
+        let mut connection = self.client.get_connection().await.map_err(|e| {
+            UserError::new(
+                "failed to connect to Redis",
+                format!("could not connect to Redis: {:?}", e),
+            )
+        })?;
+
+        self.create_group(&mut connection).await?;
+
+        // the consumer name needs to be stable across restarts, so that we can find the entries
+        // that were delivered to us but not yet acknowledged
+        let consumer = format!(
+            "arroyo-{}-{}",
+            ctx.task_info.job_id, ctx.task_info.task_index
+        );
+
+        let restored: HashMap<String, String> = ctx
+            .table_manager
+            .get_global_keyed_state::<String, RedisStreamState>("r")
+            .await
+            .map_err(|e| UserError::new("failed to get global key value", e.to_string()))?
+            .get_all()
+            .values()
+            .map(|s| (s.consumer.clone(), s.last_id.clone()))
+            .collect();
+
+        // if the job was scaled down, we also read the pending entries of the consumers that no
+        // longer have a subtask, reading as those consumers so that we can tell which of their
+        // entries were processed before the checkpoint the same way we do for our own
+        let orphans = self.orphaned_consumers(&mut connection, ctx).await?;
+        if !orphans.is_empty() {
+            info!(
+                "reading pending entries of redis stream consumers {:?} as {}",
+                orphans, consumer
+            );
+        }
+
+        let mut pending: VecDeque<String> = orphans.into_iter().collect();
+        pending.push_back(consumer.clone());
+
+        // the last entry read by each consumer we're reading as, which is stored in checkpoints
+        let mut last_ids: HashMap<String, String> = pending
+            .iter()
+            .filter_map(|c| Some((c.clone(), restored.get(c)?.clone())))
+            .collect();
+
+        if let Some(id) = last_ids.get(&consumer) {
+            info!("restoring redis stream consumer {} from {}", consumer, id);
+        }
+
+        // We start by re-reading the entries that were delivered to these consumers before a
+        // restart but never acknowledged, and then switch to reading new entries (`>`) once there
+        // are no more. Entries are acknowledged one checkpoint late, so that everything read before
+        // the last completed checkpoint is still pending if we restore from it.
+        let mut cursor = Some("0".to_string());
+        let mut since_checkpoint: Vec<String> = vec![];
+        let mut to_ack: Vec<String> = vec![];
+
+        loop {
+            let reader = pending.front().cloned().unwrap_or_else(|| consumer.clone());
+            let options = StreamReadOptions::default()
+                .group(&self.consumer_group, &reader)
+                .count(READ_COUNT)
+                .block(BLOCK_MILLIS);
+
+            let reply: Option<StreamReadReply> = connection
+                .xread_options(
+                    &[&self.stream_key],
+                    &[cursor.as_deref().unwrap_or(">")],
+                    &options,
+                )
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "failed to read from Redis stream",
+                        format!("error reading from stream {}: {:?}", self.stream_key, e),
+                    )
+                })?;
+
+            let entries = reply
+                .and_then(|r| r.keys.into_iter().next())
+                .map(|k| k.ids)
+                .unwrap_or_default();
+
+            if cursor.is_some() {
+                match entries.last() {
+                    Some(entry) => cursor = Some(entry.id.clone()),
+                    None => {
+                        debug!("finished reading pending entries for {}", reader);
+                        pending.pop_front();
+                        cursor = (!pending.is_empty()).then(|| "0".to_string());
+                    }
+                }
+            }
+
+            for entry in entries {
+                // pending entries that were read before the checkpoint we restored from have
+                // already been processed
+                if !is_after(&entry.id, restored.get(&reader).map(|s| s.as_str())) {
+                    to_ack.push(entry.id);
+                    continue;
+                }
+
+                match entry.get::<Vec<u8>>(&self.value_field) {
+                    Some(value) => {
+                        let metadata = HashMap::from([
+                            ("key", MetadataValue::String(&self.stream_key)),
+                            ("id", MetadataValue::String(&entry.id)),
+                        ]);
+                        ctx.deserialize_slice(&value, SystemTime::now(), Some(&metadata))
+                            .await?;
+                    }
+                    None => {
+                        warn!(
+                            "entry {} in stream {} has no field '{}'; skipping",
+                            entry.id, self.stream_key, self.value_field
+                        );
+                    }
+                }
+
+                last_ids.insert(reader.clone(), entry.id.clone());
+                since_checkpoint.push(entry.id);
+            }
+
+            if ctx.should_flush() {
+                ctx.flush_buffer().await?;
+            }
+
+            match ctx.control_rx.try_recv() {
+                Ok(ControlMessage::Checkpoint(c)) => {
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    let state = ctx
+                        .table_manager
+                        .get_global_keyed_state("r")
+                        .await
+                        .map_err(|e| {
+                            UserError::new("failed to get global key value", e.to_string())
+                        })?;
+                    for (reader, last_id) in &last_ids {
+                        state
+                            .insert(
+                                reader.clone(),
+                                RedisStreamState {
+                                    consumer: reader.clone(),
+                                    last_id: last_id.clone(),
+                                },
+                            )
+                            .await;
+                    }
+
+                    // checkpoints run one at a time, so by the time we see this barrier the
+                    // previous checkpoint has completed and its entries can be acknowledged
+                    if !to_ack.is_empty() {
+                        let result: Result<usize, redis::RedisError> = connection
+                            .xack(&self.stream_key, &self.consumer_group, &to_ack[..])
+                            .await;
+                        if let Err(e) = result {
+                            // the entries will be acknowledged after the next restart instead
+                            warn!("failed to acknowledge Redis stream entries: {:?}", e);
+                        }
+                    }
+                    to_ack = std::mem::take(&mut since_checkpoint);
+
+                    if self.start_checkpoint(c, ctx).await {
+                        return Ok(SourceFinishType::Immediate);
+                    }
+                }
+                Ok(ControlMessage::Stop { mode }) => {
+                    info!("Stopping Redis stream source: {:?}", mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return Ok(SourceFinishType::Graceful);
+                        }
+                        StopMode::Immediate => {
+                            return Ok(SourceFinishType::Immediate);
+                        }
+                    }
+                }
+                Ok(ControlMessage::Commit { .. }) => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                Ok(ControlMessage::LoadCompacted { compacted }) => {
+                    ctx.load_compacted(compacted).await;
+                }
+                Ok(ControlMessage::NoOp) => {}
+                Err(_) => {
+                    // no messages
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_after;
+
+    #[test]
+    fn test_stream_id_ordering() {
+        assert!(is_after("1700000000000-0", None));
+        assert!(is_after("1700000000001-0", Some("1700000000000-5")));
+        assert!(is_after("1700000000000-10", Some("1700000000000-9")));
+        assert!(!is_after("1700000000000-9", Some("1700000000000-9")));
+        assert!(!is_after("999-0", Some("1000-0")));
+    }
+}
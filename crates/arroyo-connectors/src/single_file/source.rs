@@ -52,7 +52,7 @@ impl SingleFileSourceFunc {
                 continue;
             }
 
-            ctx.deserialize_slice(s.as_bytes(), SystemTime::now())
+            ctx.deserialize_slice(s.as_bytes(), SystemTime::now(), None)
                 .await
                 .unwrap();
             if ctx.should_flush() {
@@ -1,10 +1,12 @@
 mod operator;
 
 use std::collections::HashMap;
+use std::num::NonZeroU64;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use arroyo_rpc::formats::{BadData, Format};
 use arroyo_rpc::OperatorConfig;
 
 use arroyo_formats::ser::ArrowSerializer;
@@ -16,13 +18,13 @@ use arroyo_rpc::var_str::VarStr;
 use reqwest::{Client, Request};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{unbounded_channel, Sender};
 use tokio::sync::{Mutex, Semaphore};
 use typify::import_types;
 
-use crate::{construct_http_client, pull_opt, EmptyConfig};
+use crate::{construct_http_client, pull_opt, pull_option_to_u64, EmptyConfig};
 
-use crate::webhook::operator::WebhookSinkFunc;
+use crate::webhook::operator::{BatchConfig, WebhookSender, WebhookSinkFunc};
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
@@ -33,12 +35,26 @@ const ICON: &str = include_str!("./webhook.svg");
 
 const MAX_INFLIGHT: u32 = 50;
 
+const DEFAULT_BATCH_MAX_RECORDS: u64 = 100;
+const DEFAULT_BATCH_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_BATCH_LINGER_MS: u64 = 1000;
+
+impl WebhookTable {
+    fn method(&self) -> reqwest::Method {
+        match self.method {
+            None | Some(Method::Post) => reqwest::Method::POST,
+            Some(Method::Put) => reqwest::Method::PUT,
+            Some(Method::Patch) => reqwest::Method::PATCH,
+        }
+    }
+}
+
 pub struct WebhookConnector {}
 
 impl WebhookConnector {
     fn construct_test_request(client: &Client, config: &WebhookTable) -> anyhow::Result<Request> {
         let req = client
-            .post(config.endpoint.sub_env_vars()?)
+            .request(config.method(), config.endpoint.sub_env_vars()?)
             // TODO: use the schema to construct a correctly-formatted message
             .body(
                 serde_json::to_string(&json! {{
@@ -155,6 +171,11 @@ impl Connector for WebhookConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for webhook connection"))?;
 
+        if table.batch_format == Some(BatchFormat::JsonArray) && !matches!(format, Format::Json(_))
+        {
+            bail!("batch_format 'json_array' can only be used with the 'json' format");
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -162,6 +183,7 @@ impl Connector for WebhookConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            ..Default::default()
         };
 
         Ok(Connection {
@@ -186,9 +208,37 @@ impl Connector for WebhookConnector {
 
         let headers = options.remove("headers").map(VarStr::new);
 
+        let method: Option<Method> = options
+            .remove("method")
+            .map(|s| s.try_into())
+            .transpose()
+            .map_err(|_| anyhow!("invalid value for 'method'"))?;
+
+        let batch_format: Option<BatchFormat> = options
+            .remove("batch_format")
+            .map(|s| s.try_into())
+            .transpose()
+            .map_err(|_| {
+                anyhow!("invalid value for 'batch_format'; must be 'json_array' or 'ndjson'")
+            })?;
+
+        let non_zero = |name: &str, options: &mut HashMap<String, String>| {
+            pull_option_to_u64(name, options)?
+                .map(NonZeroU64::try_from)
+                .transpose()
+                .map_err(|_| anyhow!("{} must be greater than 0", name))
+        };
+
         let table = WebhookTable {
             endpoint: VarStr::new(endpoint),
             headers,
+            method,
+            batch_format,
+            batch_max_records: non_zero("batch_max_records", options)?,
+            batch_max_bytes: non_zero("batch_max_bytes", options)?,
+            batch_linger_ms: non_zero("batch_linger_ms", options)?,
+            max_retries: pull_option_to_u64("max_retries", options)?,
+            signing_secret: options.remove("signing_secret").map(VarStr::new),
         };
 
         let client = construct_http_client(
@@ -211,23 +261,64 @@ impl Connector for WebhookConnector {
         config: OperatorConfig,
     ) -> anyhow::Result<OperatorNode> {
         let url = table.endpoint.sub_env_vars()?;
-        Ok(OperatorNode::from_operator(Box::new(WebhookSinkFunc {
-            url: Arc::new(url.clone()),
-            client: construct_http_client(
-                &url,
+        let batching = table.batch_format.map(|format| BatchConfig {
+            format,
+            max_records: table
+                .batch_max_records
+                .map(NonZeroU64::get)
+                .unwrap_or(DEFAULT_BATCH_MAX_RECORDS) as usize,
+            max_bytes: table
+                .batch_max_bytes
+                .map(NonZeroU64::get)
+                .unwrap_or(DEFAULT_BATCH_MAX_BYTES) as usize,
+            linger: Duration::from_millis(
                 table
-                    .headers
+                    .batch_linger_ms
+                    .map(NonZeroU64::get)
+                    .unwrap_or(DEFAULT_BATCH_LINGER_MS),
+            ),
+        });
+
+        let dead_letter_table = match config.bad_data {
+            Some(BadData::DeadLetter { source, .. }) => Some(source),
+            _ => None,
+        };
+        let (dead_letter_tx, dead_letter_rx) = unbounded_channel();
+
+        Ok(OperatorNode::from_operator(Box::new(WebhookSinkFunc {
+            sender: Arc::new(WebhookSender {
+                url: url.clone(),
+                method: table.method(),
+                client: construct_http_client(
+                    &url,
+                    table
+                        .headers
+                        .as_ref()
+                        .map(|s| s.sub_env_vars())
+                        .transpose()?,
+                )?,
+                signing_secret: table
+                    .signing_secret
                     .as_ref()
                     .map(|s| s.sub_env_vars())
                     .transpose()?,
-            )?,
+                max_retries: table.max_retries,
+            }),
             semaphore: Arc::new(Semaphore::new(MAX_INFLIGHT as usize)),
-            serializer: ArrowSerializer::new(
+            serializer: ArrowSerializer::with_framing(
                 config
                     .format
                     .expect("No format configured for webhook sink"),
+                config.framing,
             ),
             last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+            batching,
+            buffer: vec![],
+            buffer_bytes: 0,
+            buffer_started: None,
+            dead_letter_table,
+            dead_letter_tx,
+            dead_letter_rx: Arc::new(Mutex::new(dead_letter_rx)),
         })))
     }
 }
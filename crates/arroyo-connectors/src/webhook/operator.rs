@@ -1,14 +1,23 @@
 use arrow::array::RecordBatch;
 use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use reqwest::{Method, StatusCode};
+use sha2::Sha256;
+use std::any::Any;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::{
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
-use arroyo_types::CheckpointBarrier;
+use arroyo_types::{CheckpointBarrier, SignalMessage};
 
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
 use tracing::warn;
 
 use crate::webhook::MAX_INFLIGHT;
@@ -19,12 +28,292 @@ use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlResp;
 use arroyo_state::global_table_config;
 
+use super::BatchFormat;
+
+const SIGNATURE_HEADER: &str = "X-Arroyo-Signature";
+const TIMESTAMP_HEADER: &str = "X-Arroyo-Timestamp";
+
+pub struct BatchConfig {
+    pub format: BatchFormat,
+    pub max_records: usize,
+    pub max_bytes: usize,
+    pub linger: Duration,
+}
+
+/// Sends requests to the webhook endpoint, shared by the tasks that deliver each request
+pub struct WebhookSender {
+    pub url: String,
+    pub method: Method,
+    pub client: reqwest::Client,
+    pub signing_secret: Option<String>,
+    pub max_retries: Option<u64>,
+}
+
 pub struct WebhookSinkFunc {
-    pub url: Arc<String>,
+    pub sender: Arc<WebhookSender>,
     pub semaphore: Arc<Semaphore>,
-    pub client: reqwest::Client,
     pub serializer: ArrowSerializer,
     pub last_reported_error_at: Arc<Mutex<SystemTime>>,
+    pub batching: Option<BatchConfig>,
+    pub buffer: Vec<Vec<u8>>,
+    pub buffer_bytes: usize,
+    /// when the first record in the buffer was added, which starts the linger
+    pub buffer_started: Option<Instant>,
+    /// the name of this table, if requests that can't be delivered are sent to the dead-letter
+    /// table rather than dropped
+    pub dead_letter_table: Option<String>,
+    pub dead_letter_tx: UnboundedSender<DeadLetter>,
+    pub dead_letter_rx: Arc<Mutex<UnboundedReceiver<DeadLetter>>>,
+}
+
+/// A request that couldn't be delivered, which is sent from the delivery task back to the
+/// operator to be written to the dead-letter table
+pub struct DeadLetter {
+    error: String,
+    body: bytes::Bytes,
+}
+
+enum WebhookEvent {
+    Linger,
+    DeadLetter(DeadLetter),
+}
+
+struct RequestError {
+    retryable: bool,
+    details: String,
+}
+
+/// Reports errors from delivery tasks to the controller, at most once a second
+#[derive(Clone)]
+struct ErrorReporter {
+    control_tx: Sender<ControlResp>,
+    operator_id: String,
+    task_index: usize,
+    last_reported_at: Arc<Mutex<SystemTime>>,
+}
+
+impl ErrorReporter {
+    async fn report(&self, message: String, details: String) {
+        warn!("{}: {}", message, details);
+        if let Ok(mut last_reported) = self.last_reported_at.try_lock() {
+            if last_reported.elapsed().unwrap_or_default() > Duration::from_secs(1) {
+                self.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: self.operator_id.clone(),
+                        task_index: self.task_index,
+                        message,
+                        details,
+                    })
+                    .await
+                    .unwrap();
+
+                *last_reported = SystemTime::now();
+            }
+        }
+    }
+}
+
+/// Computes the signature of a request, as the hex-encoded HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn batch_body(format: BatchFormat, records: &[Vec<u8>]) -> Vec<u8> {
+    let len = records.iter().map(|r| r.len() + 1).sum::<usize>() + 2;
+    let mut body = Vec::with_capacity(len);
+    match format {
+        BatchFormat::JsonArray => {
+            body.push(b'[');
+            for (i, record) in records.iter().enumerate() {
+                if i > 0 {
+                    body.push(b',');
+                }
+                body.extend_from_slice(record);
+            }
+            body.push(b']');
+        }
+        BatchFormat::Ndjson => {
+            for record in records {
+                body.extend_from_slice(record);
+                body.push(b'\n');
+            }
+        }
+    }
+    body
+}
+
+fn backoff(retries: u64) -> Duration {
+    let max = (50u64 << retries.min(10)).min(5_000);
+    // add jitter so that many failed requests don't all retry at the same time
+    Duration::from_millis(rand::thread_rng().gen_range(max / 2..=max))
+}
+
+impl WebhookSender {
+    async fn send(&self, body: bytes::Bytes) -> Result<(), RequestError> {
+        let mut req = self.client.request(self.method.clone(), &self.url);
+
+        if let Some(secret) = &self.signing_secret {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .to_string();
+            req = req
+                .header(SIGNATURE_HEADER, sign(secret, &timestamp, &body))
+                .header(TIMESTAMP_HEADER, timestamp);
+        }
+
+        match req.body(body).send().await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => {
+                let status = resp.status();
+                Err(RequestError {
+                    // other client errors won't succeed if we try again
+                    retryable: status.is_server_error()
+                        || status == StatusCode::TOO_MANY_REQUESTS
+                        || status == StatusCode::REQUEST_TIMEOUT,
+                    details: format!("server responded with error code: {}", status.as_u16()),
+                })
+            }
+            Err(e) => Err(RequestError {
+                retryable: true,
+                details: e.to_string(),
+            }),
+        }
+    }
+
+    /// Sends a request, retrying failures; returns the last error if we give up on it
+    async fn deliver(&self, body: bytes::Bytes, errors: &ErrorReporter) -> Result<(), String> {
+        let mut retries = 0;
+        loop {
+            let Err(e) = self.send(body.clone()).await else {
+                return Ok(());
+            };
+
+            if !e.retryable || self.max_retries.is_some_and(|max| retries >= max) {
+                return Err(format!(
+                    "webhook failed after {} retries: {}",
+                    retries, e.details
+                ));
+            }
+
+            errors
+                .report(format!("webhook failed (retry {})", retries), e.details)
+                .await;
+
+            retries += 1;
+            tokio::time::sleep(backoff(retries)).await;
+        }
+    }
+}
+
+impl WebhookSinkFunc {
+    async fn send(&mut self, body: Vec<u8>, ctx: &mut ArrowContext) {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("websink semaphore closed");
+
+        let sender = self.sender.clone();
+        let dead_letter_tx = self
+            .dead_letter_table
+            .is_some()
+            .then(|| self.dead_letter_tx.clone());
+        let errors = ErrorReporter {
+            control_tx: ctx.control_tx.clone(),
+            operator_id: ctx.task_info.operator_id.clone(),
+            task_index: ctx.task_info.task_index,
+            last_reported_at: self.last_reported_error_at.clone(),
+        };
+
+        tokio::task::spawn(async move {
+            // move the permit into the task
+            let _permit = permit;
+            let body: bytes::Bytes = body.into();
+            let Err(error) = sender.deliver(body.clone(), &errors).await else {
+                return;
+            };
+
+            match dead_letter_tx {
+                Some(tx) => {
+                    errors
+                        .report(
+                            "sending request to the dead-letter table".to_string(),
+                            error.clone(),
+                        )
+                        .await;
+                    // the operator only goes away once all inflight requests have finished
+                    let _ = tx.send(DeadLetter { error, body });
+                }
+                None => {
+                    errors.report("dropping request".to_string(), error).await;
+                }
+            }
+        });
+    }
+
+    async fn flush_buffer(&mut self, ctx: &mut ArrowContext) {
+        let Some(batching) = &self.batching else {
+            return;
+        };
+
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let body = batch_body(batching.format, &self.buffer);
+        self.buffer.clear();
+        self.buffer_bytes = 0;
+        self.buffer_started = None;
+        self.send(body, ctx).await;
+    }
+
+    async fn wait_for_inflight(&self) {
+        // wait to acquire all of the permits (effectively blocking until all inflight requests are done)
+        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+    }
+
+    async fn write_dead_letters(&mut self, dead_letters: Vec<DeadLetter>, ctx: &mut ArrowContext) {
+        let Some(table) = &self.dead_letter_table else {
+            return;
+        };
+
+        if dead_letters.is_empty() {
+            return;
+        }
+
+        let batch = ArrowContext::dead_letter_batch(
+            table,
+            dead_letters
+                .into_iter()
+                .map(|d| (d.error, Some(d.body.to_vec()), None))
+                .collect(),
+        );
+        ctx.collector.collect_dead_letters(batch).await;
+    }
+
+    /// Waits for all inflight requests to finish, and writes out any that failed, so that nothing
+    /// is lost if we restore from the following checkpoint
+    async fn finish_inflight(&mut self, ctx: &mut ArrowContext) {
+        self.flush_buffer(ctx).await;
+        self.wait_for_inflight().await;
+
+        let mut dead_letters = vec![];
+        {
+            let mut rx = self.dead_letter_rx.lock().await;
+            while let Ok(dead_letter) = rx.try_recv() {
+                dead_letters.push(dead_letter);
+            }
+        }
+        self.write_dead_letters(dead_letters, ctx).await;
+    }
 }
 
 #[async_trait]
@@ -37,86 +326,100 @@ impl ArrowOperator for WebhookSinkFunc {
         global_table_config("s", "webhook sink state")
     }
 
+    fn future_to_poll(
+        &mut self,
+    ) -> Option<Pin<Box<dyn Future<Output = Box<dyn Any + Send>> + Send>>> {
+        let linger_deadline = self
+            .buffer_started
+            .zip(self.batching.as_ref())
+            .map(|(started, batching)| started + batching.linger);
+        let dead_letter_rx = self.dead_letter_rx.clone();
+
+        Some(Box::pin(async move {
+            let linger = async {
+                match linger_deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => futures::future::pending().await,
+                }
+            };
+
+            let mut dead_letter_rx = dead_letter_rx.lock().await;
+            let event = tokio::select! {
+                Some(dead_letter) = dead_letter_rx.recv() => WebhookEvent::DeadLetter(dead_letter),
+                _ = linger => WebhookEvent::Linger,
+            };
+            Box::new(event) as Box<dyn Any + Send>
+        }))
+    }
+
+    async fn handle_future_result(&mut self, result: Box<dyn Any + Send>, ctx: &mut ArrowContext) {
+        let event: Box<WebhookEvent> = result.downcast().expect("invalid data in future");
+        match *event {
+            WebhookEvent::Linger => self.flush_buffer(ctx).await,
+            WebhookEvent::DeadLetter(dead_letter) => {
+                self.write_dead_letters(vec![dead_letter], ctx).await
+            }
+        }
+    }
+
     async fn process_batch(&mut self, record: RecordBatch, ctx: &mut ArrowContext) {
         for body in self.serializer.serialize(&record) {
-            let permit = self
-                .semaphore
-                .clone()
-                .acquire_owned()
-                .await
-                .expect("websink semaphore closed");
+            let Some(batching) = &self.batching else {
+                self.send(body, ctx).await;
+                continue;
+            };
 
-            let body: bytes::Bytes = body.into();
+            let (max_records, max_bytes) = (batching.max_records, batching.max_bytes);
 
-            let client = self.client.clone();
-            let control_tx = ctx.control_tx.clone();
-            let error_lock = self.last_reported_error_at.clone();
-            let url = self.url.clone();
-
-            // these are just used for (potential) error reporting and we don't need to clone them
-            let operator_id = ctx.task_info.operator_id.clone();
-            let task_index = ctx.task_info.task_index;
-
-            tokio::task::spawn(async move {
-                // move the permit into the task
-                let _permit = permit;
-                let mut retries = 0;
-                loop {
-                    let req = client
-                        .post(&*url)
-                        .body(body.clone())
-                        .build()
-                        .expect("failed to build request");
-
-                    match client.execute(req).await {
-                        Ok(_) => break,
-                        Err(e) => {
-                            if let Ok(mut last_reported) = error_lock.try_lock() {
-                                if last_reported.elapsed().unwrap_or_default()
-                                    > Duration::from_secs(1)
-                                {
-                                    warn!("websink request failed: {:?}", e);
-
-                                    let details = if let Some(status) = e.status() {
-                                        format!(
-                                            "server responded with error code: {}",
-                                            status.as_u16()
-                                        )
-                                    } else {
-                                        e.to_string()
-                                    };
-
-                                    control_tx
-                                        .send(ControlResp::Error {
-                                            operator_id: operator_id.clone(),
-                                            task_index,
-                                            message: format!("webhook failed (retry {})", retries),
-                                            details,
-                                        })
-                                        .await
-                                        .unwrap();
-
-                                    *last_reported = SystemTime::now();
-                                }
-                            }
-
-                            retries += 1;
-
-                            tokio::time::sleep(Duration::from_millis(
-                                (50 * (1 << retries)).min(5_000),
-                            ))
-                            .await
-                        }
-                    }
-                }
-            });
+            if !self.buffer.is_empty() && self.buffer_bytes + body.len() > max_bytes {
+                self.flush_buffer(ctx).await;
+            }
+
+            self.buffer_bytes += body.len();
+            self.buffer.push(body);
+            self.buffer_started.get_or_insert_with(Instant::now);
+
+            if self.buffer.len() >= max_records || self.buffer_bytes >= max_bytes {
+                self.flush_buffer(ctx).await;
+            }
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _ctx: &mut ArrowContext) {
-        // wait to acquire all of the permits (effectively blocking until all inflight requests are done)
-        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.finish_inflight(ctx).await;
 
         // TODO: instead of blocking checkpoints on in-progress (or failing) requests, we should store them to state
     }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.finish_inflight(ctx).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{batch_body, sign};
+    use crate::webhook::BatchFormat;
+
+    #[test]
+    fn test_batch_body() {
+        let records = vec![b"{\"a\":1}".to_vec(), b"{\"a\":2}".to_vec()];
+
+        assert_eq!(
+            batch_body(BatchFormat::JsonArray, &records),
+            b"[{\"a\":1},{\"a\":2}]"
+        );
+        assert_eq!(
+            batch_body(BatchFormat::Ndjson, &records),
+            b"{\"a\":1}\n{\"a\":2}\n"
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("secret", "1700000000", b"[{\"a\":1}]"),
+            "sha256=516856209dea79008d72e48f00c1c767cd294a440ae742a16140cd7375ced090"
+        );
+    }
 }
@@ -122,7 +122,7 @@ impl WebsocketSourceFunc {
         msg: &[u8],
         ctx: &mut ArrowContext,
     ) -> Result<(), UserError> {
-        ctx.deserialize_slice(msg, SystemTime::now()).await?;
+        ctx.deserialize_slice(msg, SystemTime::now(), None).await?;
 
         if ctx.should_flush() {
             ctx.flush_buffer().await?;
@@ -19,6 +19,7 @@ use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hasher;
+use std::time::Duration;
 use strum::{Display, EnumString};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, EnumString, Display)]
@@ -29,6 +30,7 @@ pub enum OperatorName {
     AsyncUdf,
     Join,
     InstantJoin,
+    LookupJoin,
     WindowFunction,
     TumblingWindowAggregate,
     SlidingWindowAggregate,
@@ -44,6 +46,8 @@ pub enum LogicalEdgeType {
     Shuffle,
     LeftJoin,
     RightJoin,
+    /// Carries records that a source failed to deserialize to a dead-letter sink
+    DeadLetter,
 }
 
 impl Display for LogicalEdgeType {
@@ -53,6 +57,7 @@ impl Display for LogicalEdgeType {
             LogicalEdgeType::Shuffle => write!(f, "⤨"),
             LogicalEdgeType::LeftJoin => write!(f, "-[left]⤨"),
             LogicalEdgeType::RightJoin => write!(f, "-[right]⤨"),
+            LogicalEdgeType::DeadLetter => write!(f, "-[dead letter]⤨"),
         }
     }
 }
@@ -65,6 +70,7 @@ impl From<arroyo_rpc::grpc::api::EdgeType> for LogicalEdgeType {
             EdgeType::Shuffle => LogicalEdgeType::Shuffle,
             EdgeType::LeftJoin => LogicalEdgeType::LeftJoin,
             EdgeType::RightJoin => LogicalEdgeType::RightJoin,
+            EdgeType::DeadLetter => LogicalEdgeType::DeadLetter,
         }
     }
 }
@@ -76,6 +82,7 @@ impl From<LogicalEdgeType> for api::EdgeType {
             LogicalEdgeType::Shuffle => EdgeType::Shuffle,
             LogicalEdgeType::LeftJoin => EdgeType::LeftJoin,
             LogicalEdgeType::RightJoin => EdgeType::RightJoin,
+            LogicalEdgeType::DeadLetter => EdgeType::DeadLetter,
         }
     }
 }
@@ -188,6 +195,9 @@ pub struct DylibUdfConfig {
 #[derive(Clone, Debug, Default)]
 pub struct ProgramConfig {
     pub udf_dylibs: HashMap<String, DylibUdfConfig>,
+    /// overrides for the worker's batch size and linger, from the pipeline's SQL settings
+    pub batch_size: Option<usize>,
+    pub batch_linger: Option<Duration>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -271,6 +281,15 @@ impl LogicalProgram {
                 | OperatorName::ArrowKey => continue,
                 OperatorName::Join => "join-with-expiration".to_string(),
                 OperatorName::InstantJoin => "windowed-join".to_string(),
+                OperatorName::LookupJoin => {
+                    let Ok(config) = api::LookupJoinOperator::decode(&t.operator_config[..]) else {
+                        continue;
+                    };
+                    let Some(connector_op) = config.connector else {
+                        continue;
+                    };
+                    format!("{}-lookup", connector_op.connector)
+                }
                 OperatorName::WindowFunction => "sql-window-function".to_string(),
                 OperatorName::TumblingWindowAggregate => {
                     "sql-tumbling-window-aggregate".to_string()
@@ -339,12 +358,7 @@ impl TryFrom<ArrowProgram> for LogicalProgram {
             );
         }
 
-        let program_config = value
-            .program_config
-            .unwrap_or_else(|| ArrowProgramConfig {
-                udf_dylibs: HashMap::new(),
-            })
-            .into();
+        let program_config = value.program_config.unwrap_or_default().into();
 
         Ok(LogicalProgram::new(graph, program_config))
     }
@@ -404,6 +418,8 @@ impl From<ProgramConfig> for ArrowProgramConfig {
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
+            batch_size: from.batch_size.map(|size| size as u64),
+            batch_linger_micros: from.batch_linger.map(|linger| linger.as_micros() as u64),
         }
     }
 }
@@ -416,6 +432,8 @@ impl From<ArrowProgramConfig> for ProgramConfig {
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
+            batch_size: from.batch_size.map(|size| size as usize),
+            batch_linger: from.batch_linger_micros.map(Duration::from_micros),
         }
     }
 }
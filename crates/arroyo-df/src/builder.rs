@@ -34,7 +34,7 @@ use crate::physical::{
     ToDebeziumExec,
 };
 use crate::schemas::add_timestamp_field_arrow;
-use crate::ArroyoSchemaProvider;
+use crate::{ArroyoSchemaProvider, SqlConfig};
 use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
 use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
 use datafusion_proto::{
@@ -93,6 +93,10 @@ impl<'a> Planner<'a> {
         }
     }
 
+    pub(crate) fn sql_config(&self) -> &SqlConfig {
+        &self.schema_provider.sql_config
+    }
+
     pub(crate) fn sync_plan(&self, plan: &LogicalPlan) -> DFResult<Arc<dyn ExecutionPlan>> {
         let fut = self.planner.create_physical_plan(plan, &self.session_state);
         let (tx, mut rx) = oneshot::channel();
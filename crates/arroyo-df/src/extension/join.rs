@@ -2,16 +2,19 @@ use crate::builder::{NamedNode, Planner};
 use crate::extension::{ArroyoExtension, NodeWithIncomingEdges};
 use crate::physical::ArroyoPhysicalExtensionCodec;
 use anyhow::bail;
+use arrow_schema::DataType;
 use arroyo_datastream::logical::{LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName};
 use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
-use arroyo_rpc::grpc::api::JoinOperator;
-use datafusion::common::DFSchemaRef;
+use arroyo_rpc::grpc::api::{self, JoinOperator};
+use arroyo_rpc::IS_RETRACT_FIELD;
+use datafusion::common::{DFField, DFSchema, DFSchemaRef, JoinType, Result as DFResult};
 use datafusion::logical_expr::expr::Expr;
 use datafusion::logical_expr::{LogicalPlan, UserDefinedLogicalNodeCore};
 use datafusion_proto::generated::datafusion::PhysicalPlanNode;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 use prost::Message;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) const JOIN_NODE_NAME: &str = "JoinNode";
 
@@ -19,6 +22,52 @@ pub(crate) const JOIN_NODE_NAME: &str = "JoinNode";
 pub struct JoinExtension {
     pub(crate) rewritten_join: LogicalPlan,
     pub(crate) is_instant: bool,
+    // whether either input contains retractions
+    pub(crate) is_updating: bool,
+    pub(crate) join_type: JoinType,
+    pub(crate) schema: DFSchemaRef,
+    // how long rows are kept on each side, if they can be derived from the join condition
+    pub(crate) left_ttl: Option<Duration>,
+    pub(crate) right_ttl: Option<Duration>,
+}
+
+impl JoinExtension {
+    pub(crate) fn new(
+        rewritten_join: LogicalPlan,
+        is_instant: bool,
+        is_updating: bool,
+        join_type: JoinType,
+        left_ttl: Option<Duration>,
+        right_ttl: Option<Duration>,
+    ) -> DFResult<Self> {
+        // outer joins without windows retract rows that were emitted without a match once one
+        // arrives, and joins over updating inputs retract the results for retracted rows, so
+        // their output is updating
+        let schema = if !is_instant && (is_updating || join_type != JoinType::Inner) {
+            let mut fields = rewritten_join.schema().fields().clone();
+            fields.push(DFField::new_unqualified(
+                IS_RETRACT_FIELD,
+                DataType::Boolean,
+                false,
+            ));
+            Arc::new(DFSchema::new_with_metadata(
+                fields,
+                rewritten_join.schema().metadata().clone(),
+            )?)
+        } else {
+            rewritten_join.schema().clone()
+        };
+
+        Ok(Self {
+            rewritten_join,
+            is_instant,
+            is_updating,
+            join_type,
+            schema,
+            left_ttl,
+            right_ttl,
+        })
+    }
 }
 
 impl ArroyoExtension for JoinExtension {
@@ -43,6 +92,16 @@ impl ArroyoExtension for JoinExtension {
             join_plan.clone(),
             &ArroyoPhysicalExtensionCodec::default(),
         )?;
+        let join_type = match self.join_type {
+            JoinType::Inner => api::JoinType::Inner,
+            JoinType::Left => api::JoinType::Left,
+            JoinType::Right => api::JoinType::Right,
+            JoinType::Full => api::JoinType::Full,
+            // windowed joins compute the whole join once the window closes, so they can handle
+            // any type of join
+            _ if self.is_instant => api::JoinType::Inner,
+            join_type => bail!("unsupported join type: {}", join_type),
+        };
         let operator_name = if self.is_instant {
             OperatorName::InstantJoin
         } else {
@@ -54,6 +113,17 @@ impl ArroyoExtension for JoinExtension {
             right_schema: Some(right_schema.as_ref().clone().try_into()?),
             output_schema: Some(self.output_schema().try_into()?),
             join_plan: physical_plan_node.encode_to_vec(),
+            join_type: join_type as i32,
+            left_ttl_micros: Some(
+                self.left_ttl
+                    .unwrap_or(planner.sql_config().join_ttl)
+                    .as_micros() as u64,
+            ),
+            right_ttl_micros: Some(
+                self.right_ttl
+                    .unwrap_or(planner.sql_config().join_ttl)
+                    .as_micros() as u64,
+            ),
         };
         let logical_node = LogicalNode {
             operator_id: format!("join_{}", index),
@@ -87,7 +157,7 @@ impl UserDefinedLogicalNodeCore for JoinExtension {
     }
 
     fn schema(&self) -> &DFSchemaRef {
-        self.rewritten_join.schema()
+        &self.schema
     }
 
     fn expressions(&self) -> Vec<Expr> {
@@ -108,9 +178,14 @@ impl UserDefinedLogicalNodeCore for JoinExtension {
     }
 
     fn from_template(&self, _exprs: &[Expr], inputs: &[LogicalPlan]) -> Self {
-        Self {
-            rewritten_join: inputs[0].clone(),
-            is_instant: self.is_instant,
-        }
+        Self::new(
+            inputs[0].clone(),
+            self.is_instant,
+            self.is_updating,
+            self.join_type,
+            self.left_ttl,
+            self.right_ttl,
+        )
+        .expect("join schema should be valid")
     }
 }
@@ -6,6 +6,7 @@ use arroyo_datastream::logical::{LogicalEdge, LogicalEdgeType, LogicalNode, Oper
 use arroyo_rpc::{
     df::{ArroyoSchema, ArroyoSchemaRef},
     grpc::api::KeyPlanOperator,
+    IS_RETRACT_FIELD,
 };
 use datafusion::common::{DFSchema, DFSchemaRef};
 
@@ -35,6 +36,8 @@ pub(crate) struct KeyCalculationExtension {
 }
 
 impl KeyCalculationExtension {
+    /// Creates a key calculation whose schema omits the keys and the retraction flag, which are
+    /// consumed by the operator it feeds into rather than being visible to its plan
     pub fn new_named_and_trimmed(input: LogicalPlan, keys: Vec<usize>, name: String) -> Self {
         let output_fields: Vec<_> = input
             .schema()
@@ -42,7 +45,7 @@ impl KeyCalculationExtension {
             .iter()
             .enumerate()
             .filter_map(|(index, field)| {
-                if !keys.contains(&index) {
+                if !keys.contains(&index) && field.name() != IS_RETRACT_FIELD {
                     Some(field.clone())
                 } else {
                     None
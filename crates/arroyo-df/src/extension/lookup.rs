@@ -0,0 +1,200 @@
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_datastream::logical::{LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName};
+use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
+use arroyo_rpc::grpc::api::{self, LookupJoinOperator};
+use datafusion::common::{DFSchemaRef, JoinType};
+use datafusion::logical_expr::{Expr, LogicalPlan, UserDefinedLogicalNodeCore};
+use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+use prost::Message;
+
+use crate::builder::{NamedNode, Planner};
+use crate::tables::ConnectorTable;
+
+use super::{ArroyoExtension, NodeWithIncomingEdges};
+
+pub(crate) const LOOKUP_SOURCE_NAME: &str = "LookupSource";
+pub(crate) const LOOKUP_JOIN_NAME: &str = "LookupJoinExtension";
+
+/// Placeholder for a scan of a lookup table. Lookup tables can't be read as streams, so this
+/// must be replaced by a [LookupJoinExtension] when planning the join it appears in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LookupSource {
+    pub(crate) table: ConnectorTable,
+    pub(crate) schema: DFSchemaRef,
+}
+
+impl UserDefinedLogicalNodeCore for LookupSource {
+    fn name(&self) -> &str {
+        LOOKUP_SOURCE_NAME
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn fmt_for_explain(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "LookupSource: {}", self.table.name)
+    }
+
+    fn from_template(&self, _exprs: &[Expr], _inputs: &[LogicalPlan]) -> Self {
+        self.clone()
+    }
+}
+
+impl ArroyoExtension for LookupSource {
+    fn node_name(&self) -> Option<NamedNode> {
+        None
+    }
+
+    fn plan_node(
+        &self,
+        _planner: &Planner,
+        _index: usize,
+        _input_schemas: Vec<ArroyoSchemaRef>,
+    ) -> Result<NodeWithIncomingEdges> {
+        bail!(
+            "lookup table {} can only be used on the right side of a JOIN",
+            self.table.name
+        )
+    }
+
+    fn output_schema(&self) -> ArroyoSchema {
+        ArroyoSchema::from_fields(
+            self.schema
+                .fields()
+                .iter()
+                .map(|f| (**f.field()).clone())
+                .collect(),
+        )
+    }
+}
+
+/// Joins each row of the input with the row of the lookup table whose key is the value of
+/// `key_expr`, which is fetched from the external table (or a cache in front of it)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LookupJoinExtension {
+    pub(crate) input: LogicalPlan,
+    pub(crate) table: ConnectorTable,
+    pub(crate) lookup_schema: DFSchemaRef,
+    pub(crate) key_expr: Expr,
+    pub(crate) join_type: JoinType,
+    pub(crate) schema: DFSchemaRef,
+}
+
+impl UserDefinedLogicalNodeCore for LookupJoinExtension {
+    fn name(&self) -> &str {
+        LOOKUP_JOIN_NAME
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![&self.input]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn fmt_for_explain(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "LookupJoinExtension({}, {} = key): {}",
+            self.table.name,
+            self.key_expr,
+            self.schema
+                .fields()
+                .iter()
+                .map(|f| f.qualified_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn from_template(&self, _exprs: &[Expr], inputs: &[LogicalPlan]) -> Self {
+        Self {
+            input: inputs[0].clone(),
+            ..self.clone()
+        }
+    }
+}
+
+impl ArroyoExtension for LookupJoinExtension {
+    fn node_name(&self) -> Option<NamedNode> {
+        None
+    }
+
+    fn plan_node(
+        &self,
+        planner: &Planner,
+        index: usize,
+        input_schemas: Vec<ArroyoSchemaRef>,
+    ) -> Result<NodeWithIncomingEdges> {
+        if input_schemas.len() != 1 {
+            bail!("lookup join should have exactly one input");
+        }
+        let input_schema = input_schemas[0].clone();
+
+        let key_expr = planner
+            .create_physical_expr(&self.key_expr, self.input.schema())
+            .and_then(|e| serialize_physical_expr(e, &DefaultPhysicalExtensionCodec {}))
+            .map_err(|e| anyhow!("failed to plan lookup join key: {:?}", e))?;
+
+        let lookup_schema = ArroyoSchema::from_fields(
+            self.lookup_schema
+                .fields()
+                .iter()
+                .map(|f| (**f.field()).clone())
+                .collect(),
+        );
+
+        let join_type = match self.join_type {
+            JoinType::Inner => api::JoinType::Inner,
+            JoinType::Left => api::JoinType::Left,
+            join_type => bail!("unsupported join type for lookup join: {}", join_type),
+        };
+
+        let config = LookupJoinOperator {
+            name: format!("lookup_join_{}", index),
+            input_schema: Some(input_schema.as_ref().clone().try_into()?),
+            lookup_schema: Some(lookup_schema.try_into()?),
+            connector: Some(self.table.connector_op()),
+            key_expr: key_expr.encode_to_vec(),
+            join_type: join_type as i32,
+            max_cache_entries: self.table.lookup_cache_max_entries.map(|n| n as u64),
+            cache_ttl_micros: self.table.lookup_cache_ttl.map(|t| t.as_micros() as u64),
+        };
+
+        let node = LogicalNode {
+            operator_id: format!("lookup_join_{}", index),
+            description: format!("lookup_join<{}>", self.table.name),
+            operator_name: OperatorName::LookupJoin,
+            operator_config: config.encode_to_vec(),
+            parallelism: 1,
+        };
+
+        let edge = LogicalEdge::project_all(LogicalEdgeType::Forward, (*input_schema).clone());
+        Ok(NodeWithIncomingEdges {
+            node,
+            edges: vec![edge],
+        })
+    }
+
+    fn output_schema(&self) -> ArroyoSchema {
+        ArroyoSchema::from_schema_unkeyed(Arc::new(self.schema.as_ref().into())).unwrap()
+    }
+}
@@ -29,6 +29,7 @@ use crate::ASYNC_RESULT_FIELD;
 use join::JoinExtension;
 
 use self::debezium::{DebeziumUnrollingExtension, ToDebeziumExtension};
+use self::lookup::{LookupJoinExtension, LookupSource};
 use self::updating_aggregate::UpdatingAggregateExtension;
 use self::{
     aggregate::AggregateExtension, key_calculation::KeyCalculationExtension,
@@ -40,6 +41,7 @@ pub(crate) mod aggregate;
 pub(crate) mod debezium;
 pub(crate) mod join;
 pub(crate) mod key_calculation;
+pub(crate) mod lookup;
 pub(crate) mod remote_table;
 pub(crate) mod sink;
 pub(crate) mod table_source;
@@ -91,6 +93,8 @@ impl<'a> TryFrom<&'a dyn UserDefinedLogicalNode> for &'a dyn ArroyoExtension {
             .or_else(|_| try_from_t::<ToDebeziumExtension>(node))
             .or_else(|_| try_from_t::<DebeziumUnrollingExtension>(node))
             .or_else(|_| try_from_t::<UpdatingAggregateExtension>(node))
+            .or_else(|_| try_from_t::<LookupSource>(node))
+            .or_else(|_| try_from_t::<LookupJoinExtension>(node))
             .map_err(|_| DataFusionError::Plan(format!("unexpected node: {}", node.name())))
     }
 }
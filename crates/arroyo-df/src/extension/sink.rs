@@ -5,7 +5,8 @@ use anyhow::{bail, Result};
 use arroyo_datastream::logical::{LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName};
 use arroyo_rpc::{
     df::{ArroyoSchema, ArroyoSchemaRef},
-    IS_RETRACT_FIELD,
+    formats::BadData,
+    OperatorConfig, IS_RETRACT_FIELD,
 };
 use datafusion::common::{
     plan_err, DFSchemaRef, DataFusionError, OwnedTableReference, Result as DFResult,
@@ -47,6 +48,10 @@ impl SinkExtension {
             .has_column_with_unqualified_name(IS_RETRACT_FIELD);
         match &table {
             Table::ConnectorTable(connector_table) => {
+                connector_table
+                    .validate_sink_schema(&schema.as_ref().into())
+                    .map_err(|e| DataFusionError::Plan(e.to_string()))?;
+
                 match (input_is_updating, connector_table.is_updating()) {
                     (_, true) => {
                         let to_debezium_extension =
@@ -170,16 +175,24 @@ impl ArroyoExtension for SinkExtension {
         // should have exactly one input
         let input_schema = input_schemas[0].clone();
 
-        let operator_config = (self.table.connector_op().map_err(|e| {
+        let mut connector_op = self.table.connector_op().map_err(|e| {
             DataFusionError::Plan(format!("failed to calculate connector op error: {}", e))
-        })?)
-        .encode_to_vec();
+        })?;
+
+        // dead letters record the name of the table that they were written to
+        if let Ok(mut config) = serde_json::from_str::<OperatorConfig>(&connector_op.config) {
+            if let Some(BadData::DeadLetter { source, .. }) = &mut config.bad_data {
+                *source = self.name.to_string();
+                connector_op.config = serde_json::to_string(&config)?;
+            }
+        }
+
         let node = LogicalNode {
             operator_id: format!("sink_{}_{}", self.name, index),
-            description: self.table.connector_op().unwrap().description.clone(),
+            description: connector_op.description.clone(),
             operator_name: OperatorName::ConnectorSink,
             parallelism: 1,
-            operator_config,
+            operator_config: connector_op.encode_to_vec(),
         };
         let edge = LogicalEdge::project_all(LogicalEdgeType::Forward, (*input_schema).clone());
         Ok(NodeWithIncomingEdges {
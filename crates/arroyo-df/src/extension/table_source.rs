@@ -1,14 +1,20 @@
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 
 use arroyo_datastream::logical::{LogicalNode, OperatorName};
 use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
+use arroyo_rpc::formats::BadData;
+use arroyo_rpc::OperatorConfig;
 use datafusion::common::{DFField, DFSchema, DFSchemaRef, DataFusionError, OwnedTableReference};
 
+use datafusion::logical_expr::utils::split_conjunction;
 use datafusion::logical_expr::{Expr, LogicalPlan, UserDefinedLogicalNodeCore};
 
+use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
 use prost::Message;
+use tracing::debug;
 
 use crate::{
     builder::{NamedNode, Planner},
@@ -24,15 +30,18 @@ pub(crate) struct TableSourceExtension {
     pub(crate) name: OwnedTableReference,
     pub(crate) table: ConnectorTable,
     pub(crate) schema: DFSchemaRef,
+    // filters from the query that were pushed down into the scan of this table
+    pub(crate) filters: Vec<Expr>,
 }
 
 impl TableSourceExtension {
-    pub fn new(name: OwnedTableReference, table: ConnectorTable) -> Self {
+    pub fn new(name: OwnedTableReference, table: ConnectorTable, filters: Vec<Expr>) -> Self {
         let physical_fields = table
             .fields
             .iter()
             .filter_map(|field| match field {
-                crate::tables::FieldSpec::StructField(field) => {
+                crate::tables::FieldSpec::StructField(field)
+                | crate::tables::FieldSpec::MetadataField { field, .. } => {
                     Some(DFField::from_qualified(&name, Arc::new(field.clone())))
                 }
                 crate::tables::FieldSpec::VirtualField { .. } => None,
@@ -40,19 +49,50 @@ impl TableSourceExtension {
             .collect::<Vec<_>>();
         let base_schema =
             Arc::new(DFSchema::new_with_metadata(physical_fields, HashMap::new()).unwrap());
-        let schema = if table.is_updating() {
-            DebeziumUnrollingExtension::as_debezium_schema(&base_schema, Some(name.clone()))
-                .unwrap()
+        // filters on updating tables refer to the unrolled rows, not what the source reads
+        let (schema, filters) = if table.is_updating() {
+            (
+                DebeziumUnrollingExtension::as_debezium_schema(&base_schema, Some(name.clone()))
+                    .unwrap(),
+                vec![],
+            )
         } else {
-            base_schema
+            (base_schema, filters)
         };
         let schema = add_timestamp_field(schema, Some(name.clone())).unwrap();
         Self {
             name,
             table,
             schema,
+            filters,
         }
     }
+
+    /// Fills in the parts of the source's config that are only known when planning: the
+    /// pushed-down filters, skipping any that can't be evaluated against the rows it reads (for
+    /// example, because they refer to virtual fields), and the table name for dead letters
+    fn planned_config(&self, planner: &Planner, config: &str) -> Result<String> {
+        let mut config: OperatorConfig = serde_json::from_str(config)
+            .map_err(|e| anyhow!("invalid config for source {}: {:?}", self.name, e))?;
+
+        if let Some(BadData::DeadLetter { source, .. }) = &mut config.bad_data {
+            source.clone_from(&self.table.name);
+        }
+
+        for filter in self.filters.iter().flat_map(split_conjunction) {
+            match planner.create_physical_expr(filter, &self.schema) {
+                Ok(expr) => config.filters.push(
+                    serialize_physical_expr(expr, &DefaultPhysicalExtensionCodec {})?
+                        .encode_to_vec(),
+                ),
+                Err(e) => {
+                    debug!("not pushing filter {} down to source: {:?}", filter, e);
+                }
+            }
+        }
+
+        Ok(serde_json::to_string(&config)?)
+    }
 }
 
 impl UserDefinedLogicalNodeCore for TableSourceExtension {
@@ -90,6 +130,7 @@ impl UserDefinedLogicalNodeCore for TableSourceExtension {
             name: self.name.clone(),
             table: self.table.clone(),
             schema: self.schema.clone(),
+            filters: self.filters.clone(),
         }
     }
 }
@@ -101,7 +142,7 @@ impl ArroyoExtension for TableSourceExtension {
 
     fn plan_node(
         &self,
-        _planner: &Planner,
+        planner: &Planner,
         index: usize,
         input_schemas: Vec<ArroyoSchemaRef>,
     ) -> Result<NodeWithIncomingEdges> {
@@ -111,11 +152,13 @@ impl ArroyoExtension for TableSourceExtension {
         let sql_source = self.table.as_sql_source().map_err(|e| {
             DataFusionError::Plan(format!("Error turning table into a SQL source: {}", e))
         })?;
+        let mut config = sql_source.source.config;
+        config.config = self.planned_config(planner, &config.config)?;
         let node = LogicalNode {
             operator_id: format!("source_{}_{}", self.name, index),
-            description: sql_source.source.config.description.clone(),
+            description: config.description.clone(),
             operator_name: OperatorName::ConnectorSource,
-            operator_config: sql_source.source.config.encode_to_vec(),
+            operator_config: config.encode_to_vec(),
             parallelism: 1,
         };
         Ok(NodeWithIncomingEdges {
@@ -1,11 +1,12 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use anyhow::{bail, Result};
 use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use arroyo_datastream::logical::{LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName};
-use arroyo_rpc::{df::ArroyoSchema, grpc::api::UpdatingAggregateOperator, TIMESTAMP_FIELD};
-use arroyo_types::UPDATE_AGGREGATE_FLUSH_MS_ENV;
-use datafusion::common::{plan_err, DFSchemaRef, OwnedTableReference};
+use arroyo_rpc::{
+    df::ArroyoSchema, grpc::api::UpdatingAggregateOperator, IS_RETRACT_FIELD, TIMESTAMP_FIELD,
+};
+use datafusion::common::{DFSchemaRef, OwnedTableReference};
 use datafusion::logical_expr::{Extension, LogicalPlan, UserDefinedLogicalNodeCore};
 use datafusion_proto::protobuf::{physical_plan_node::PhysicalPlanType, PhysicalPlanNode};
 
@@ -144,20 +145,20 @@ impl ArroyoExtension for UpdatingAggregateExtension {
             physical_plan_type: Some(PhysicalPlanType::Aggregate(Box::new(combine_aggregate))),
         };
 
-        let flush_interval = std::env::var(UPDATE_AGGREGATE_FLUSH_MS_ENV)
-            .ok()
-            .map(|s| {
-                let Ok(millis): Result<u64, _> = s.parse() else {
-                    return plan_err!(
-                        "Failed to parse {} to a number for {}",
-                        s,
-                        UPDATE_AGGREGATE_FLUSH_MS_ENV
-                    );
-                };
-                Ok(Duration::from_millis(millis))
-            })
-            .transpose()?
-            .unwrap_or_else(|| Duration::from_secs(1));
+        let flush_interval = planner.sql_config().updating_aggregate_flush_interval()?;
+        let ttl = planner.sql_config().updating_aggregate_ttl;
+
+        let updating_input_schema = if input_schema.schema.index_of(IS_RETRACT_FIELD).is_ok() {
+            // the input is stored by key, and a global aggregate has no key columns to sort by
+            let input_schema = if self.key_fields.is_empty() {
+                ArroyoSchema::new_unkeyed(input_schema.schema.clone(), input_schema.timestamp_index)
+            } else {
+                input_schema.as_ref().clone()
+            };
+            Some(input_schema.try_into()?)
+        } else {
+            None
+        };
 
         let config = UpdatingAggregateOperator {
             name: "UpdatingAggregate".to_string(),
@@ -168,6 +169,8 @@ impl ArroyoExtension for UpdatingAggregateExtension {
             combine_plan: combine_plan.encode_to_vec(),
             final_aggregation_plan: finish_plan.encode_to_vec(),
             flush_interval_micros: flush_interval.as_micros() as u64,
+            updating_input_schema,
+            ttl_micros: Some(ttl.as_micros() as u64),
         };
         let node = LogicalNode {
             operator_id: format!("updating_aggregate_{}", index),
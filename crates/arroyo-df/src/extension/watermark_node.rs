@@ -12,6 +12,7 @@ use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
 use prost::Message;
 use std::fmt::Formatter;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) const WATERMARK_NODE_NAME: &str = "WatermarkNode";
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -20,6 +21,7 @@ pub struct WatermarkNode {
     pub qualifier: OwnedTableReference,
     pub watermark_expression: Expr,
     pub schema: DFSchemaRef,
+    pub idle_time: Option<Duration>,
     timestamp_index: usize,
 }
 
@@ -68,6 +70,7 @@ impl UserDefinedLogicalNodeCore for WatermarkNode {
             qualifier: self.qualifier.clone(),
             watermark_expression: exprs[0].clone(),
             schema: self.schema.clone(),
+            idle_time: self.idle_time,
             timestamp_index,
         }
     }
@@ -93,7 +96,7 @@ impl ArroyoExtension for WatermarkNode {
             parallelism: 1,
             operator_config: ExpressionWatermarkConfig {
                 period_micros: 1_000_000,
-                idle_time_micros: None,
+                idle_time_micros: self.idle_time.map(|t| t.as_micros() as u64),
                 expression: expression.encode_to_vec(),
                 input_schema: Some(self.arroyo_schema().try_into().unwrap()),
             }
@@ -116,6 +119,7 @@ impl WatermarkNode {
         input: LogicalPlan,
         qualifier: OwnedTableReference,
         watermark_expression: Expr,
+        idle_time: Option<Duration>,
     ) -> anyhow::Result<Self> {
         let schema = add_timestamp_field(input.schema().clone(), Some(qualifier.clone()))?;
         let timestamp_index = schema
@@ -126,6 +130,7 @@ impl WatermarkNode {
             qualifier,
             watermark_expression,
             schema,
+            idle_time,
             timestamp_index,
         })
     }
@@ -22,13 +22,17 @@ use arrow::datatypes::{self, DataType};
 use arrow_schema::Schema;
 use arroyo_datastream::WindowType;
 
-use datafusion::common::{plan_err, DFField, OwnedTableReference, Result as DFResult, ScalarValue};
+use datafusion::common::{
+    plan_err, DFField, DFSchema, OwnedTableReference, Result as DFResult, ScalarValue,
+};
 use datafusion::datasource::DefaultTableSource;
 #[allow(deprecated)]
 use datafusion::physical_plan::functions::make_scalar_function;
 
 use datafusion::prelude::create_udf;
 
+use datafusion::sql::planner::{PlannerContext, SqlToRel};
+use datafusion::sql::sqlparser::ast::Statement;
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::sql::{planner::ContextProvider, TableReference};
@@ -44,14 +48,24 @@ use datafusion::logical_expr::{AggregateUDF, TableSource};
 use logical::LogicalBatchInput;
 
 use schemas::window_arrow_struct;
-use tables::{Insert, Table};
+use tables::{ConnectorTable, Insert, Table};
 
 use crate::builder::PlanToGraphVisitor;
 use crate::extension::sink::SinkExtension;
 use crate::plan::ArroyoRewriter;
-use arroyo_datastream::logical::{DylibUdfConfig, ProgramConfig};
-use arroyo_rpc::api_types::connections::ConnectionProfile;
+use arroyo_datastream::logical::{
+    DylibUdfConfig, LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName,
+    ProgramConfig,
+};
+use arroyo_rpc::api_types::connections::{ConnectionProfile, ConnectionType};
+use arroyo_rpc::df::dead_letter_schema;
+use arroyo_rpc::formats::BadData;
+use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::UPDATE_AGGREGATE_FLUSH_MS_ENV;
 use datafusion::common::DataFusionError;
+use petgraph::graph::NodeIndex;
+use prost::Message;
 use std::collections::HashSet;
 use std::fmt::Debug;
 
@@ -94,6 +108,7 @@ pub struct ArroyoSchemaProvider {
     config_options: datafusion::config::ConfigOptions,
     pub dylib_udfs: HashMap<String, DylibUdfConfig>,
     pub function_rewriters: Vec<Arc<dyn FunctionRewrite + Send + Sync>>,
+    pub(crate) sql_config: SqlConfig,
 }
 
 impl ArroyoSchemaProvider {
@@ -293,8 +308,16 @@ impl ArroyoSchemaProvider {
     }
 }
 
-fn create_table(table_name: String, schema: Arc<Schema>) -> Arc<dyn TableSource> {
-    let table_provider = LogicalBatchInput { table_name, schema };
+fn create_table(
+    table_name: String,
+    schema: Arc<Schema>,
+    supports_filter_pushdown: bool,
+) -> Arc<dyn TableSource> {
+    let table_provider = LogicalBatchInput {
+        table_name,
+        schema,
+        supports_filter_pushdown,
+    };
     let wrapped = Arc::new(table_provider);
     let provider = DefaultTableSource::new(wrapped);
     Arc::new(provider)
@@ -311,7 +334,15 @@ impl ContextProvider for ArroyoSchemaProvider {
 
         let fields = table.get_fields();
         let schema = Arc::new(Schema::new_with_metadata(fields, HashMap::new()));
-        Ok(create_table(name.to_string(), schema))
+        let supports_filter_pushdown = match table {
+            Table::ConnectorTable(table) => table.supports_filter_pushdown(),
+            _ => false,
+        };
+        Ok(create_table(
+            name.to_string(),
+            schema,
+            supports_filter_pushdown,
+        ))
     }
 
     fn get_function_meta(&self, name: &str) -> Option<Arc<ScalarUDF>> {
@@ -395,19 +426,130 @@ impl FunctionRegistry for ArroyoSchemaProvider {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Settings for a single pipeline, which may be overridden in its SQL with `SET key = value`
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SqlConfig {
     pub default_parallelism: usize,
+    /// how long rows are kept in join state, when it can't be derived from the join condition.
+    /// This also applies to updating inputs, whose rows can then no longer be retracted from
+    /// the join's results once they've expired
+    pub join_ttl: Duration,
+    /// how often updating aggregates emit their changes; if unset, this is read from the
+    /// `UPDATE_AGGREGATE_FLUSH_MS` environment variable, defaulting to one second
+    pub updating_aggregate_flush_interval: Option<Duration>,
+    /// how long updating aggregates keep the state for a group after it was last updated
+    pub updating_aggregate_ttl: Duration,
+    /// how long a source may go without data before it's considered idle; None disables idleness
+    pub idle_time: Option<Duration>,
+    /// overrides for the number of rows and amount of time that operators buffer before emitting
+    pub batch_size: Option<usize>,
+    pub batch_linger: Option<Duration>,
 }
 
 impl Default for SqlConfig {
     fn default() -> Self {
         Self {
             default_parallelism: 4,
+            join_ttl: Duration::from_secs(60 * 60),
+            updating_aggregate_flush_interval: None,
+            updating_aggregate_ttl: Duration::from_secs(60 * 60 * 24),
+            idle_time: DEFAULT_IDLE_TIME,
+            batch_size: None,
+            batch_linger: None,
         }
     }
 }
 
+impl SqlConfig {
+    pub fn updating_aggregate_flush_interval(&self) -> DFResult<Duration> {
+        if let Some(interval) = self.updating_aggregate_flush_interval {
+            return Ok(interval);
+        }
+
+        let Ok(s) = std::env::var(UPDATE_AGGREGATE_FLUSH_MS_ENV) else {
+            return Ok(Duration::from_secs(1));
+        };
+        let Ok(millis) = s.parse() else {
+            return plan_err!(
+                "Failed to parse {} to a number for {}",
+                s,
+                UPDATE_AGGREGATE_FLUSH_MS_ENV
+            );
+        };
+        Ok(Duration::from_millis(millis))
+    }
+
+    /// Applies a `SET key = value` statement to the config
+    pub fn set(&mut self, key: &str, value: &Expr) -> Result<()> {
+        match key {
+            "join_ttl" => {
+                self.join_ttl = get_duration(value)?;
+            }
+            "updating_aggregate_flush_interval" => {
+                let interval = get_duration(value)?;
+                if interval.is_zero() {
+                    bail!("updating_aggregate_flush_interval must be greater than zero");
+                }
+                self.updating_aggregate_flush_interval = Some(interval);
+            }
+            "updating_aggregate_ttl" => {
+                let ttl = get_duration(value)?;
+                if ttl.is_zero() {
+                    bail!("updating_aggregate_ttl must be greater than zero");
+                }
+                self.updating_aggregate_ttl = ttl;
+            }
+            "idle_time" => {
+                // setting the idle time to zero disables idle detection
+                self.idle_time = Some(get_duration(value)?).filter(|t| !t.is_zero());
+            }
+            "batch_size" => match value {
+                Expr::Literal(ScalarValue::Int64(Some(size))) if *size > 0 => {
+                    self.batch_size = Some(*size as usize);
+                }
+                _ => bail!("batch_size must be a positive integer, not {}", value),
+            },
+            "batch_linger" => {
+                self.batch_linger = Some(get_duration(value)?);
+            }
+            _ => bail!(
+                "unknown setting '{}'; expected one of join_ttl, updating_aggregate_flush_interval, \
+                updating_aggregate_ttl, idle_time, batch_size, batch_linger",
+                key
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn apply_statement(
+        &mut self,
+        statement: &Statement,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<bool> {
+        let Statement::SetVariable {
+            variable, value, ..
+        } = statement
+        else {
+            return Ok(false);
+        };
+
+        let key = variable.to_string().to_lowercase();
+        let [value] = value.as_slice() else {
+            bail!("SET {} must have exactly one value", key);
+        };
+
+        let value = SqlToRel::new(schema_provider).sql_to_expr(
+            value.clone(),
+            &DFSchema::empty(),
+            &mut PlannerContext::new(),
+        )?;
+
+        self.set(&key, &value)?;
+        Ok(true)
+    }
+}
+
 pub async fn parse_and_get_program(
     query: &str,
     schema_provider: ArroyoSchemaProvider,
@@ -516,12 +658,20 @@ pub fn rewrite_plan(
 pub async fn parse_and_get_arrow_program(
     query: String,
     mut schema_provider: ArroyoSchemaProvider,
-    // TODO: use config
-    _config: SqlConfig,
+    mut config: SqlConfig,
 ) -> Result<CompiledSql> {
     let dialect = PostgreSqlDialect {};
-    let mut inserts = vec![];
+    let mut statements = vec![];
     for statement in Parser::parse_sql(&dialect, &query)? {
+        // settings apply to the whole pipeline, regardless of where they appear in the query
+        if !config.apply_statement(&statement, &schema_provider)? {
+            statements.push(statement);
+        }
+    }
+    schema_provider.sql_config = config;
+
+    let mut inserts = vec![];
+    for statement in statements {
         if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
@@ -599,11 +749,15 @@ pub async fn parse_and_get_arrow_program(
     for extension in extensions {
         plan_to_graph_visitor.add_plan(extension)?;
     }
-    let graph = plan_to_graph_visitor.into_graph();
+    let mut graph = plan_to_graph_visitor.into_graph();
+    add_dead_letter_sinks(&mut graph, &schema_provider, &mut used_connections)?;
+
     let program = LogicalProgram::new(
         graph,
         ProgramConfig {
             udf_dylibs: schema_provider.dylib_udfs.clone(),
+            batch_size: schema_provider.sql_config.batch_size,
+            batch_linger: schema_provider.sql_config.batch_linger,
         },
     );
 
@@ -613,6 +767,123 @@ pub async fn parse_and_get_arrow_program(
     })
 }
 
+/// Connects sources and sinks that are configured to send bad data to a dead-letter table to a
+/// sink for that table; for sources this is data that can't be deserialized, and for sinks data
+/// that can't be delivered. Operators that share a dead-letter table share a single sink.
+fn add_dead_letter_sinks(
+    graph: &mut LogicalGraph,
+    schema_provider: &ArroyoSchemaProvider,
+    used_connections: &mut HashSet<i64>,
+) -> Result<()> {
+    let mut sinks: HashMap<String, NodeIndex> = HashMap::new();
+
+    let connectors: Vec<_> = graph
+        .node_indices()
+        .filter(|idx| {
+            matches!(
+                graph[*idx].operator_name,
+                OperatorName::ConnectorSource | OperatorName::ConnectorSink
+            )
+        })
+        .collect();
+
+    for connector in connectors {
+        let op = ConnectorOp::decode(&graph[connector].operator_config[..])?;
+        let Ok(config) = serde_json::from_str::<OperatorConfig>(&op.config) else {
+            continue;
+        };
+
+        let Some(BadData::DeadLetter { table, .. }) = config.bad_data else {
+            continue;
+        };
+
+        let sink = match sinks.get(&table) {
+            Some(sink) => *sink,
+            None => {
+                let Some(Table::ConnectorTable(connector_table)) =
+                    schema_provider.get_table(&table)
+                else {
+                    bail!(
+                        "dead-letter table '{}' for '{}' does not exist or is not a connector table",
+                        table,
+                        graph[connector].description
+                    );
+                };
+
+                if connector_table.connection_type != ConnectionType::Sink {
+                    bail!("dead-letter table '{}' must be a sink", table);
+                }
+
+                check_dead_letter_fields(&table, &connector_table)?;
+
+                if let Some(id) = connector_table.id {
+                    used_connections.insert(id);
+                }
+
+                let op = connector_table.connector_op();
+                let sink = graph.add_node(LogicalNode {
+                    operator_id: format!("sink_{}_dead_letter", table),
+                    description: op.description.clone(),
+                    operator_name: OperatorName::ConnectorSink,
+                    operator_config: op.encode_to_vec(),
+                    parallelism: 1,
+                });
+                sinks.insert(table, sink);
+                sink
+            }
+        };
+
+        graph.add_edge(
+            connector,
+            sink,
+            LogicalEdge::project_all(LogicalEdgeType::DeadLetter, dead_letter_schema()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that the declared fields of a dead-letter table match the schema of the dead letters
+/// that are written to it; tables without declared fields take the dead-letter schema as-is
+fn check_dead_letter_fields(name: &str, table: &ConnectorTable) -> Result<()> {
+    if table.fields.is_empty() {
+        return Ok(());
+    }
+
+    let dead_letter_schema = dead_letter_schema();
+    let expected: Vec<_> = dead_letter_schema
+        .schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != dead_letter_schema.timestamp_index)
+        .map(|(_, f)| f)
+        .collect();
+
+    let fields: Vec<_> = table.fields.iter().map(|f| f.field()).collect();
+
+    let matches = fields.len() == expected.len()
+        && expected.iter().all(|e| {
+            fields
+                .iter()
+                .any(|f| f.name() == e.name() && f.data_type() == e.data_type())
+        });
+
+    if !matches {
+        bail!(
+            "dead-letter table '{}' must have the fields {}",
+            name,
+            expected
+                .iter()
+                .map(|f| format!("{} {}", f.name(), f.data_type()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct TestStruct {
     pub non_nullable_i32: i32,
@@ -2,7 +2,7 @@ use std::{any::Any, sync::Arc};
 
 use arrow_schema::SchemaRef;
 use datafusion::common::Result as DFResult;
-use datafusion::logical_expr::{Expr, TableType};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown, TableType};
 use datafusion::{
     datasource::TableProvider, execution::context::SessionState, physical_plan::ExecutionPlan,
 };
@@ -14,6 +14,8 @@ use crate::physical::ArroyoMemExec;
 pub struct LogicalBatchInput {
     pub table_name: String,
     pub schema: SchemaRef,
+    // whether the source can use filters pushed down into the scan
+    pub supports_filter_pushdown: bool,
 }
 
 #[async_trait::async_trait]
@@ -32,6 +34,20 @@ impl TableProvider for LogicalBatchInput {
         TableType::Temporary
     }
 
+    /// Filters are pushed down into the scan of sources that can use them to skip data, but as
+    /// sources aren't required to apply them they're also kept in the plan.
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DFResult<Vec<TableProviderFilterPushDown>> {
+        let support = if self.supports_filter_pushdown {
+            TableProviderFilterPushDown::Inexact
+        } else {
+            TableProviderFilterPushDown::Unsupported
+        };
+        Ok(vec![support; filters.len()])
+    }
+
     /// Create an ExecutionPlan that will scan the table.
     /// The table provider will be usually responsible for grouping
     /// the source data into partitions that can be efficiently
@@ -3,7 +3,7 @@ use crate::extension::key_calculation::KeyCalculationExtension;
 use crate::extension::updating_aggregate::UpdatingAggregateExtension;
 use crate::plan::WindowDetectingVisitor;
 use crate::{find_window, WindowBehavior};
-use arroyo_rpc::{IS_RETRACT_FIELD, TIMESTAMP_FIELD};
+use arroyo_rpc::TIMESTAMP_FIELD;
 use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRewriter};
 use datafusion::common::{
     not_impl_err, plan_err, DFField, DFSchema, DataFusionError, Result as DFResult,
@@ -25,13 +25,6 @@ impl AggregateRewriter {
         mut aggr_expr: Vec<Expr>,
         schema: Arc<DFSchema>,
     ) -> DFResult<Transformed<LogicalPlan>> {
-        if input
-            .schema()
-            .has_column_with_unqualified_name(IS_RETRACT_FIELD)
-        {
-            return plan_err!("can't currently nest updating aggregates");
-        }
-
         let key_count = key_fields.len();
         key_fields.extend(input.schema().fields().clone());
 
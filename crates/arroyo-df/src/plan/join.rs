@@ -1,8 +1,13 @@
 use crate::extension::join::JoinExtension;
 use crate::extension::key_calculation::KeyCalculationExtension;
+use crate::extension::lookup::{LookupJoinExtension, LookupSource};
+use crate::extension::remote_table::RemoteTableExtension;
+use crate::extension::ArroyoExtension;
+use crate::get_duration;
 use crate::plan::WindowDetectingVisitor;
+use crate::tables::FieldSpec;
 use arroyo_datastream::WindowType;
-use arroyo_rpc::IS_RETRACT_FIELD;
+use arroyo_rpc::{IS_RETRACT_FIELD, TIMESTAMP_FIELD};
 use datafusion::common::tree_node::{Transformed, TreeNodeRewriter};
 use datafusion::common::{
     plan_err, Column, DFSchema, DataFusionError, JoinConstraint, JoinType, OwnedTableReference,
@@ -10,26 +15,218 @@ use datafusion::common::{
 };
 use datafusion::logical_expr;
 use datafusion::logical_expr::expr::{Alias, ScalarFunction};
+use datafusion::logical_expr::utils::split_conjunction;
 use datafusion::logical_expr::{
-    BinaryExpr, BuiltinScalarFunction, Case, Expr, Extension, Join, LogicalPlan, Projection,
+    Between, BinaryExpr, BuiltinScalarFunction, Case, Expr, Extension, Join, LogicalPlan, Operator,
+    Projection,
 };
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) struct JoinRewriter {}
 
+/// Bounds on how far apart the event times of matching rows can be, in nanoseconds of the right
+/// side's event time minus the left side's, taken from join conditions like
+/// `b.ts BETWEEN a.ts - INTERVAL '5' MINUTE AND a.ts + INTERVAL '10' MINUTE`
+#[derive(Debug, Default)]
+struct IntervalBounds {
+    lower: Option<i128>,
+    upper: Option<i128>,
+}
+
+impl IntervalBounds {
+    fn from_join(join: &Join) -> Self {
+        let mut bounds = Self::default();
+        for (left, right) in &join.on {
+            bounds.add_comparison(join, left, Operator::Eq, right);
+        }
+        if let Some(filter) = &join.filter {
+            for expr in split_conjunction(filter) {
+                match expr {
+                    Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+                        bounds.add_comparison(join, left, *op, right)
+                    }
+                    Expr::Between(Between {
+                        expr,
+                        negated: false,
+                        low,
+                        high,
+                    }) => {
+                        bounds.add_comparison(join, expr, Operator::GtEq, low);
+                        bounds.add_comparison(join, expr, Operator::LtEq, high);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        bounds
+    }
+
+    fn add_comparison(&mut self, join: &Join, left: &Expr, op: Operator, right: &Expr) {
+        let (Some((left_is_left, left_offset)), Some((right_is_left, right_offset))) = (
+            event_time_offset(join, left),
+            event_time_offset(join, right),
+        ) else {
+            return;
+        };
+
+        // normalize to `right time - left time <op> offset`
+        let (op, offset) = match (left_is_left, right_is_left) {
+            (true, false) => match op.swap() {
+                Some(op) => (op, left_offset - right_offset),
+                None => return,
+            },
+            (false, true) => (op, right_offset - left_offset),
+            _ => return,
+        };
+
+        // strict comparisons are treated as inclusive, which can only make the bounds looser
+        if matches!(op, Operator::Gt | Operator::GtEq | Operator::Eq) {
+            self.lower = Some(self.lower.map_or(offset, |lower| lower.max(offset)));
+        }
+        if matches!(op, Operator::Lt | Operator::LtEq | Operator::Eq) {
+            self.upper = Some(self.upper.map_or(offset, |upper| upper.min(offset)));
+        }
+    }
+
+    /// How long rows need to be kept on the left and right sides to find all of their matches:
+    /// a left row can match right rows up to `upper` after it, and a right row can match left
+    /// rows up to `-lower` after it
+    fn ttls(&self) -> (Option<Duration>, Option<Duration>) {
+        let to_duration = |nanos: i128| Duration::from_nanos(nanos.max(0) as u64);
+        (
+            self.upper.map(to_duration),
+            self.lower.map(|lower| to_duration(-lower)),
+        )
+    }
+}
+
+/// Returns whether an expression is an event time column from the left (true) or right (false)
+/// side of the join, plus its offset in nanoseconds, for expressions like
+/// `a.ts + INTERVAL '1' MINUTE`
+fn event_time_offset(join: &Join, expr: &Expr) -> Option<(bool, i128)> {
+    let duration = |expr: &Expr| get_duration(expr).ok().map(|d| d.as_nanos() as i128);
+    match expr {
+        Expr::Column(column) => {
+            if is_event_time(&join.left, column) {
+                Some((true, 0))
+            } else if is_event_time(&join.right, column) {
+                Some((false, 0))
+            } else {
+                None
+            }
+        }
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Plus,
+            right,
+        }) => {
+            if let Some(offset) = duration(right) {
+                event_time_offset(join, left).map(|(side, o)| (side, o + offset))
+            } else {
+                let offset = duration(left)?;
+                event_time_offset(join, right).map(|(side, o)| (side, o + offset))
+            }
+        }
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Minus,
+            right,
+        }) => {
+            let offset = duration(right)?;
+            event_time_offset(join, left).map(|(side, o)| (side, o - offset))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a column of the plan's output has the same value as its `_timestamp` column, which is
+/// what state expiration and watermarks are based on
+fn is_event_time(plan: &LogicalPlan, column: &Column) -> bool {
+    let schema = plan.schema();
+    let Ok(index) = schema.index_of_column(column) else {
+        return false;
+    };
+    let Some(timestamp_index) = schema
+        .fields()
+        .iter()
+        .position(|f| f.name() == TIMESTAMP_FIELD)
+    else {
+        return false;
+    };
+    same_value(plan, index, timestamp_index)
+}
+
+fn same_value(plan: &LogicalPlan, a: usize, b: usize) -> bool {
+    if a == b {
+        return true;
+    }
+    match plan {
+        LogicalPlan::Projection(projection) => {
+            let (Some(a), Some(b)) = (projection.expr.get(a), projection.expr.get(b)) else {
+                return false;
+            };
+            let (a, b) = (a.clone().unalias(), b.clone().unalias());
+            if a == b {
+                return true;
+            }
+            let (Expr::Column(a), Expr::Column(b)) = (a, b) else {
+                return false;
+            };
+            let input_schema = projection.input.schema();
+            match (
+                input_schema.index_of_column(&a),
+                input_schema.index_of_column(&b),
+            ) {
+                (Ok(a), Ok(b)) => same_value(&projection.input, a, b),
+                _ => false,
+            }
+        }
+        _ => {
+            // nodes like filters, aliases and watermarks pass their input's columns through as is
+            let inputs = plan.inputs();
+            let [input] = inputs.as_slice() else {
+                return false;
+            };
+            let fields = plan.schema().fields();
+            let input_fields = input.schema().fields();
+            fields.len() == input_fields.len()
+                && fields
+                    .iter()
+                    .zip(input_fields.iter())
+                    .all(|(f, i)| f.name() == i.name())
+                && same_value(input, a, b)
+        }
+    }
+}
+
 impl JoinRewriter {
     fn check_join_windowing(join: &Join) -> DFResult<bool> {
         let left_window = WindowDetectingVisitor::get_window(&join.left)?;
         let right_window = WindowDetectingVisitor::get_window(&join.right)?;
         match (left_window, right_window) {
             (None, None) => {
-                if join.join_type == JoinType::Inner {
-                    Ok(false)
-                } else {
-                    Err(DataFusionError::NotImplemented(
-                        "can't handle non-inner joins without windows".into(),
-                    ))
+                match join.join_type {
+                    JoinType::Inner => {}
+                    // outer joins without windows emit retractions when a late match arrives for a
+                    // row that was emitted without one, which requires knowing which rows match
+                    // from their keys alone
+                    JoinType::Left | JoinType::Right | JoinType::Full => {
+                        if join.filter.is_some() {
+                            return Err(DataFusionError::NotImplemented(
+                                "can't handle non-equality conditions in outer joins without windows"
+                                    .into(),
+                            ));
+                        }
+                    }
+                    join_type => {
+                        return Err(DataFusionError::NotImplemented(format!(
+                            "can't handle {} joins without windows",
+                            join_type
+                        )));
+                    }
                 }
+                Ok(false)
             }
             (None, Some(_)) => Err(DataFusionError::NotImplemented(
                 "can't handle mixed windowing between left (non-windowed) and right (windowed)."
@@ -57,20 +254,9 @@ impl JoinRewriter {
         }
     }
 
-    fn check_updating(left: &LogicalPlan, right: &LogicalPlan) -> DFResult<()> {
-        if left
-            .schema()
+    fn is_updating(plan: &LogicalPlan) -> bool {
+        plan.schema()
             .has_column_with_unqualified_name(IS_RETRACT_FIELD)
-        {
-            return plan_err!("can't handle updating left side of join");
-        }
-        if right
-            .schema()
-            .has_column_with_unqualified_name(IS_RETRACT_FIELD)
-        {
-            return plan_err!("can't handle updating right side of join");
-        }
-        Ok(())
     }
 
     fn create_join_key_plan(
@@ -183,6 +369,128 @@ impl JoinRewriter {
             output_schema.clone(),
         )?))
     }
+
+    /// Returns the lookup table that this plan reads from, if it is a (possibly aliased) scan of one
+    fn lookup_source(plan: &LogicalPlan) -> Option<&LookupSource> {
+        match plan {
+            LogicalPlan::Extension(Extension { node }) => {
+                node.as_any().downcast_ref::<LookupSource>()
+            }
+            LogicalPlan::SubqueryAlias(alias) => Self::lookup_source(&alias.input),
+            _ => None,
+        }
+    }
+
+    /// Plans a join against a lookup table, which must appear on the right side of the join and be
+    /// joined on equality between its key and an expression over the left side
+    fn rewrite_lookup_join(join: Join, lookup: LookupSource) -> DFResult<LogicalPlan> {
+        if !matches!(join.join_type, JoinType::Inner | JoinType::Left) {
+            return plan_err!(
+                "lookup joins must be INNER or LEFT joins, not {}",
+                join.join_type
+            );
+        }
+
+        if join.join_constraint != JoinConstraint::On {
+            return plan_err!("lookup joins must use an ON clause");
+        }
+
+        if join
+            .left
+            .schema()
+            .has_column_with_unqualified_name(IS_RETRACT_FIELD)
+        {
+            return plan_err!("can't handle updating left side of a lookup join");
+        }
+
+        let Some(key_field) = lookup.table.fields.iter().find_map(|f| match f {
+            FieldSpec::MetadataField { field, key } if key == "key" => Some(field.name().clone()),
+            _ => None,
+        }) else {
+            return plan_err!(
+                "lookup table {} must have a key column to join on, defined like `key TEXT GENERATED ALWAYS AS (metadata('key')) STORED`",
+                lookup.table.name
+            );
+        };
+
+        let mut conditions: Vec<(Expr, Expr)> = join.on.clone();
+        if let Some(filter) = &join.filter {
+            for e in split_conjunction(filter) {
+                let Expr::BinaryExpr(BinaryExpr {
+                    left,
+                    op: Operator::Eq,
+                    right,
+                }) = e
+                else {
+                    return plan_err!(
+                        "lookup joins only support equality conditions on the key, but found {}",
+                        e
+                    );
+                };
+                conditions.push((*left.clone(), *right.clone()));
+            }
+        }
+
+        let [(left, right)] = &conditions[..] else {
+            return plan_err!(
+                "lookup joins must have exactly one condition of the form `{}.{} = <expression>`",
+                lookup.table.name,
+                key_field
+            );
+        };
+
+        let is_key = |e: &Expr| match e {
+            Expr::Column(c) => c.name == key_field && join.right.schema().has_column(c),
+            _ => false,
+        };
+        let is_left = |e: &Expr| {
+            e.to_columns()
+                .map(|cols| cols.iter().all(|c| join.left.schema().has_column(c)))
+                .unwrap_or(false)
+        };
+
+        let key_expr = if is_key(right) && is_left(left) {
+            left.clone()
+        } else if is_key(left) && is_left(right) {
+            right.clone()
+        } else {
+            return plan_err!(
+                "lookup joins must join on the key of the lookup table (`{}.{}`) equal to an expression over the other side",
+                lookup.table.name,
+                key_field
+            );
+        };
+
+        let mut input = join.left.as_ref().clone();
+        let needs_remote = match &input {
+            LogicalPlan::Extension(Extension { node }) => {
+                let extension: &dyn ArroyoExtension = node.try_into()?;
+                extension.transparent()
+            }
+            _ => true,
+        };
+        if needs_remote {
+            input = LogicalPlan::Extension(Extension {
+                node: Arc::new(RemoteTableExtension {
+                    schema: input.schema().clone(),
+                    input,
+                    name: OwnedTableReference::bare("lookup_join_input"),
+                    materialize: false,
+                }),
+            });
+        }
+
+        Ok(LogicalPlan::Extension(Extension {
+            node: Arc::new(LookupJoinExtension {
+                input,
+                lookup_schema: join.right.schema().clone(),
+                table: lookup.table,
+                key_expr,
+                join_type: join.join_type,
+                schema: join.schema,
+            }),
+        }))
+    }
 }
 
 impl TreeNodeRewriter for JoinRewriter {
@@ -192,7 +500,20 @@ impl TreeNodeRewriter for JoinRewriter {
         let LogicalPlan::Join(join) = node else {
             return Ok(Transformed::no(node));
         };
+
+        if Self::lookup_source(&join.left).is_some() {
+            return plan_err!("lookup tables can only be used on the right side of a JOIN");
+        }
+        if let Some(lookup) = Self::lookup_source(&join.right).cloned() {
+            return Ok(Transformed::yes(Self::rewrite_lookup_join(join, lookup)?));
+        }
+
         let is_instant = Self::check_join_windowing(&join)?;
+        let (left_ttl, right_ttl) = if is_instant {
+            (None, None)
+        } else {
+            IntervalBounds::from_join(&join).ttls()
+        };
 
         let Join {
             left,
@@ -209,29 +530,55 @@ impl TreeNodeRewriter for JoinRewriter {
                 "can't handle join constraint other than ON".into(),
             ));
         };
-        Self::check_updating(&left, &right)?;
+
+        let is_updating = Self::is_updating(&left) || Self::is_updating(&right);
+        if is_updating && is_instant {
+            return Err(DataFusionError::NotImplemented(
+                "can't handle updating inputs to windowed joins".into(),
+            ));
+        }
 
         let (left_expressions, right_expressions): (Vec<_>, Vec<_>) =
             on.clone().into_iter().unzip();
         let left_input = self.create_join_key_plan(left.clone(), left_expressions, "left")?;
         let right_input = self.create_join_key_plan(right.clone(), right_expressions, "right")?;
+
+        // retractions are handled by the join operator, so the join itself never sees them
+        let schema = if is_updating {
+            Arc::new(DFSchema::new_with_metadata(
+                schema
+                    .fields()
+                    .iter()
+                    .filter(|field| field.name() != IS_RETRACT_FIELD)
+                    .cloned()
+                    .collect(),
+                schema.metadata().clone(),
+            )?)
+        } else {
+            schema
+        };
+
         let rewritten_join = LogicalPlan::Join(Join {
             left: Arc::new(left_input),
             right: Arc::new(right_input),
             on,
             join_type,
             join_constraint: JoinConstraint::On,
-            schema: schema.clone(),
+            schema,
             null_equals_null: false,
             filter,
         });
 
         let final_logical_plan = self.post_join_timestamp_projection(rewritten_join)?;
 
-        let join_extension = JoinExtension {
-            rewritten_join: final_logical_plan,
+        let join_extension = JoinExtension::new(
+            final_logical_plan,
             is_instant,
-        };
+            is_updating,
+            join_type,
+            left_ttl,
+            right_ttl,
+        )?;
 
         Ok(Transformed::yes(LogicalPlan::Extension(Extension {
             node: Arc::new(join_extension),
@@ -1,4 +1,6 @@
 use crate::extension::debezium::DebeziumUnrollingExtension;
+use crate::extension::lookup::LookupJoinExtension;
+use crate::extension::lookup::LookupSource;
 use crate::extension::remote_table::RemoteTableExtension;
 use crate::extension::sink::SinkExtension;
 use crate::extension::table_source::TableSourceExtension;
@@ -10,6 +12,7 @@ use crate::tables::Table;
 use crate::{ArroyoSchemaProvider, ASYNC_RESULT_FIELD};
 
 use arrow_schema::DataType;
+use arroyo_rpc::api_types::connections::ConnectionType;
 use arroyo_rpc::IS_RETRACT_FIELD;
 use arroyo_rpc::TIMESTAMP_FIELD;
 
@@ -48,10 +51,13 @@ impl<'a> SourceRewriter<'a> {
                 .find_map(|f| {
                     if f.field().name() == &watermark_field {
                         return match f {
-                            FieldSpec::StructField(f) => Some(Expr::Column(Column {
-                                relation: None,
-                                name: f.name().to_string(),
-                            })),
+                            FieldSpec::StructField(f)
+                            | FieldSpec::MetadataField { field: f, .. } => {
+                                Some(Expr::Column(Column {
+                                    relation: None,
+                                    name: f.name().to_string(),
+                                }))
+                            }
                             FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
                         };
                     }
@@ -83,10 +89,12 @@ impl<'a> SourceRewriter<'a> {
             .fields
             .iter()
             .map(|field| match field {
-                FieldSpec::StructField(f) => Expr::Column(Column {
-                    relation: Some(qualifier.clone()),
-                    name: f.name().to_string(),
-                }),
+                FieldSpec::StructField(f) | FieldSpec::MetadataField { field: f, .. } => {
+                    Expr::Column(Column {
+                        relation: Some(qualifier.clone()),
+                        name: f.name().to_string(),
+                    })
+                }
                 FieldSpec::VirtualField { field, expression } => expression
                     .clone()
                     .alias_qualified(Some(qualifier.clone()), field.name().to_string()),
@@ -105,10 +113,13 @@ impl<'a> SourceRewriter<'a> {
                 .find_map(|f| {
                     if f.field().name() == &event_time_field {
                         return match f {
-                            FieldSpec::StructField(f) => Some(Expr::Column(Column {
-                                relation: Some(qualifier.clone()),
-                                name: f.name().to_string(),
-                            })),
+                            FieldSpec::StructField(f)
+                            | FieldSpec::MetadataField { field: f, .. } => {
+                                Some(Expr::Column(Column {
+                                    relation: Some(qualifier.clone()),
+                                    name: f.name().to_string(),
+                                }))
+                            }
                             FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
                         };
                     }
@@ -146,6 +157,7 @@ impl<'a> SourceRewriter<'a> {
             node: Arc::new(TableSourceExtension::new(
                 qualifier.to_owned(),
                 table.clone(),
+                table_scan.filters.clone(),
             )),
         });
 
@@ -175,6 +187,17 @@ impl<'a> SourceRewriter<'a> {
         table_scan: &TableScan,
         table: &ConnectorTable,
     ) -> DFResult<Transformed<LogicalPlan>> {
+        if table.connection_type == ConnectionType::Lookup {
+            // lookup tables aren't read as streams; the join they appear in is responsible for
+            // planning them
+            return Ok(Transformed::yes(LogicalPlan::Extension(Extension {
+                node: Arc::new(LookupSource {
+                    table: table.clone(),
+                    schema: table_scan.projected_schema.clone(),
+                }),
+            })));
+        }
+
         let input = self.projection(table_scan, table)?;
 
         let schema = input.schema().clone();
@@ -191,6 +214,11 @@ impl<'a> SourceRewriter<'a> {
             remote,
             table_scan.table_name.clone(),
             Self::watermark_expression(table)?,
+            // a table's own idle time takes precedence over the pipeline's; zero disables it
+            table
+                .idle_time
+                .or(self.schema_provider.sql_config.idle_time)
+                .filter(|t| !t.is_zero()),
         )
         .map_err(|err| {
             DataFusionError::Internal(format!("failed to create watermark expression: {}", err))
@@ -581,6 +609,11 @@ impl<'a> SourceMetadataVisitor<'a> {
                 let SinkExtension { name, .. } = node.as_any().downcast_ref::<SinkExtension>()?;
                 name.to_string()
             }
+            "LookupJoinExtension" => {
+                let LookupJoinExtension { table, .. } =
+                    node.as_any().downcast_ref::<LookupJoinExtension>()?;
+                return table.id;
+            }
             _ => return None,
         };
         let table = self.schema_provider.get_table(&table_name)?;
@@ -7,12 +7,14 @@ use arrow_schema::{DataType, Field, FieldRef, Schema};
 use arroyo_connectors::connector_for_type;
 
 use arroyo_datastream::preview_sink;
+use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, SourceField,
 };
-use arroyo_rpc::formats::{BadData, Format, Framing, JsonFormat};
+use arroyo_rpc::formats::{BadData, Format, Framing, ProtobufFormat};
 use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::OperatorConfig;
 use arroyo_types::ArroyoExtensionType;
 use datafusion::common::Column;
 use datafusion::common::{config::ConfigOptions, DFField, DFSchema};
@@ -47,17 +49,17 @@ use datafusion::{
     optimizer::{analyzer::Analyzer, optimizer::Optimizer, OptimizerContext},
     sql::{
         planner::SqlToRel,
-        sqlparser::ast::{ColumnDef, ColumnOption, Statement, Value},
+        sqlparser::ast::{ColumnDef, ColumnOption, FunctionArg, FunctionArgExpr, Statement, Value},
     },
 };
 
 use crate::extension::remote_table::RemoteTableExtension;
+use crate::rewrite_plan;
 use crate::types::convert_data_type;
 use crate::{
     external::{ProcessingMode, SqlSource},
     ArroyoSchemaProvider,
 };
-use crate::{rewrite_plan, DEFAULT_IDLE_TIME};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectorTable {
@@ -71,7 +73,10 @@ pub struct ConnectorTable {
     pub format: Option<Format>,
     pub event_time_field: Option<String>,
     pub watermark_field: Option<String>,
+    // falls back to the pipeline's idle_time when unset; zero disables idleness for the table
     pub idle_time: Option<Duration>,
+    pub lookup_cache_max_entries: Option<usize>,
+    pub lookup_cache_ttl: Option<Duration>,
 
     pub inferred_fields: Option<Vec<DFField>>,
 }
@@ -80,19 +85,28 @@ pub struct ConnectorTable {
 pub enum FieldSpec {
     StructField(Field),
     VirtualField { field: Field, expression: Expr },
+    MetadataField { field: Field, key: String },
 }
 
 impl FieldSpec {
     fn is_virtual(&self) -> bool {
         match self {
-            FieldSpec::StructField(_) => false,
+            FieldSpec::StructField(_) | FieldSpec::MetadataField { .. } => false,
             FieldSpec::VirtualField { .. } => true,
         }
     }
+
+    fn metadata_key(&self) -> Option<&str> {
+        match self {
+            FieldSpec::MetadataField { key, .. } => Some(key.as_str()),
+            _ => None,
+        }
+    }
+
     pub fn field(&self) -> &Field {
         match self {
             FieldSpec::StructField(f) => f,
-            FieldSpec::VirtualField { field, .. } => field,
+            FieldSpec::VirtualField { field, .. } | FieldSpec::MetadataField { field, .. } => field,
         }
     }
 }
@@ -176,14 +190,22 @@ impl From<Connection> for ConnectorTable {
                 .schema
                 .fields
                 .iter()
-                .map(|f| FieldSpec::StructField(f.clone().into()))
+                .map(|f| match &f.metadata_key {
+                    Some(key) => FieldSpec::MetadataField {
+                        field: f.clone().into(),
+                        key: key.clone(),
+                    },
+                    None => FieldSpec::StructField(f.clone().into()),
+                })
                 .collect(),
             config: value.config,
             description: value.description,
             format: value.schema.format.clone(),
             event_time_field: None,
             watermark_field: None,
-            idle_time: DEFAULT_IDLE_TIME,
+            idle_time: None,
+            lookup_cache_max_entries: None,
+            lookup_cache_ttl: None,
             inferred_fields: None,
         }
     }
@@ -210,8 +232,10 @@ impl ConnectorTable {
                         }
                         _ => field_spec,
                     },
-                    FieldSpec::VirtualField { .. } => {
-                        unreachable!("delta lake is only a sink, can't have virtual fields")
+                    FieldSpec::VirtualField { .. } | FieldSpec::MetadataField { .. } => {
+                        unreachable!(
+                            "delta lake is only a sink, can't have virtual or metadata fields"
+                        )
                     }
                 })
                 .collect();
@@ -225,11 +249,14 @@ impl ConnectorTable {
 
         let mut input_to_schema_fields = fields.clone();
 
-        if let Some(Format::Json(JsonFormat { debezium: true, .. })) = &format {
+        if format.as_ref().map(|f| f.is_updating()).unwrap_or(false) {
             // check that there are no virtual fields in fields
             if fields.iter().any(|f| f.is_virtual()) {
                 bail!("can't use virtual fields with debezium format")
             }
+            if fields.iter().any(|f| f.metadata_key().is_some()) {
+                bail!("can't use metadata fields with debezium format")
+            }
             let df_struct_type =
                 DataType::Struct(fields.iter().map(|f| f.field().clone()).collect());
             let before_field_spec =
@@ -245,15 +272,49 @@ impl ConnectorTable {
             .filter(|f| !f.is_virtual())
             .map(|f| {
                 let struct_field = f.field();
-                struct_field.clone().try_into().map_err(|_| {
-                    anyhow!(
-                        "field '{}' has a type '{:?}' that cannot be used in a connection table",
-                        struct_field.name(),
-                        struct_field.data_type()
-                    )
-                })
+                let mut source_field: SourceField =
+                    struct_field.clone().try_into().map_err(|_| {
+                        anyhow!(
+                            "field '{}' has a type '{:?}' that cannot be used in a connection table",
+                            struct_field.name(),
+                            struct_field.data_type()
+                        )
+                    })?;
+                source_field.metadata_key = f.metadata_key().map(|k| k.to_string());
+                Ok(source_field)
             })
             .collect::<Result<_>>()?;
+
+        for field in &fields {
+            let FieldSpec::MetadataField { field, key } = field else {
+                continue;
+            };
+
+            let def = connector
+                .metadata_defs()
+                .iter()
+                .find(|def| def.name == key)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "connector '{}' does not provide metadata '{}'",
+                        connector.name(),
+                        key
+                    )
+                })?;
+
+            // binary metadata can also be read as text, which is null where it isn't valid UTF-8
+            let compatible = &def.data_type == field.data_type()
+                || (def.data_type == DataType::Binary && field.data_type() == &DataType::Utf8);
+
+            if !compatible {
+                bail!(
+                    "metadata field '{}' must have type {:?}, but was declared as {:?}",
+                    field.name(),
+                    def.data_type,
+                    field.data_type()
+                );
+            }
+        }
         let bad_data =
             BadData::from_opts(options).map_err(|e| anyhow!("Invalid bad_data: '{e}'"))?;
 
@@ -270,11 +331,40 @@ impl ConnectorTable {
         let connection =
             connector.from_options(name, options, Some(&schema), connection_profile)?;
 
+        if connection.connection_type == ConnectionType::Sink
+            && fields.iter().any(|f| f.metadata_key().is_some())
+        {
+            bail!("metadata fields can only be used in source tables");
+        }
+
+        if let Some(Format::Csv(csv)) = &connection.schema.format {
+            // only files have a header row; messages from other sources are single records
+            if (csv.has_header || csv.map_by_header)
+                && (connector.name() != "filesystem"
+                    || connection.connection_type != ConnectionType::Source)
+            {
+                bail!(
+                    "'csv.has_header' and 'csv.map_by_header' are only supported for filesystem sources"
+                );
+            }
+        }
+
         let mut table: ConnectorTable = connection.into();
         if !fields.is_empty() {
             table.fields = fields;
         }
 
+        if table.connection_type == ConnectionType::Sink && !table.fields.is_empty() {
+            table.validate_sink_schema(&Schema::new(
+                table
+                    .fields
+                    .iter()
+                    .filter(|f| !f.is_virtual())
+                    .map(|f| f.field().clone())
+                    .collect::<Vec<_>>(),
+            ))?;
+        }
+
         table.event_time_field = options.remove("event_time_field");
         table.watermark_field = options.remove("watermark_field");
 
@@ -283,9 +373,26 @@ impl ConnectorTable {
             .map(|t| i64::from_str(&t))
             .transpose()
             .map_err(|_| anyhow!("idle_micros must be set to a number"))?
-            .or_else(|| DEFAULT_IDLE_TIME.map(|t| t.as_micros() as i64))
-            .filter(|t| *t <= 0)
-            .map(|t| Duration::from_micros(t as u64));
+            .map(|t| Duration::from_micros(t.max(0) as u64));
+
+        table.lookup_cache_max_entries = options
+            .remove("lookup.cache.max_entries")
+            .map(|t| usize::from_str(&t))
+            .transpose()
+            .map_err(|_| anyhow!("lookup.cache.max_entries must be set to a number"))?;
+
+        table.lookup_cache_ttl = options
+            .remove("lookup.cache.ttl_secs")
+            .map(|t| u64::from_str(&t))
+            .transpose()
+            .map_err(|_| anyhow!("lookup.cache.ttl_secs must be set to a number"))?
+            .map(Duration::from_secs);
+
+        if table.connection_type != ConnectionType::Lookup
+            && (table.lookup_cache_max_entries.is_some() || table.lookup_cache_ttl.is_some())
+        {
+            bail!("lookup.cache options can only be set on lookup tables");
+        }
 
         if !options.is_empty() {
             let keys: Vec<String> = options.keys().map(|s| format!("'{}'", s)).collect();
@@ -369,7 +476,7 @@ impl ConnectorTable {
         )
     }
 
-    fn connector_op(&self) -> ConnectorOp {
+    pub(crate) fn connector_op(&self) -> ConnectorOp {
         ConnectorOp {
             connector: self.connector.clone(),
             config: self.config.clone(),
@@ -391,6 +498,9 @@ impl ConnectorTable {
             ConnectionType::Sink => {
                 bail!("cannot read from sink")
             }
+            ConnectionType::Lookup => {
+                bail!("lookup tables can only be used on the right side of a JOIN")
+            }
         };
 
         if self.is_update() && self.has_virtual_fields() {
@@ -406,7 +516,11 @@ impl ConnectorTable {
                 .fields
                 .iter()
                 .filter_map(|field| match field {
-                    FieldSpec::StructField(struct_field) => Some(Arc::new(struct_field.clone())),
+                    FieldSpec::StructField(struct_field)
+                    | FieldSpec::MetadataField {
+                        field: struct_field,
+                        ..
+                    } => Some(Arc::new(struct_field.clone())),
                     FieldSpec::VirtualField { .. } => None,
                 })
                 .collect(),
@@ -424,10 +538,41 @@ impl ConnectorTable {
     }
 
     pub(crate) fn is_updating(&self) -> bool {
-        matches!(
-            &self.format,
-            Some(Format::Json(JsonFormat { debezium: true, .. }))
-        )
+        self.format
+            .as_ref()
+            .map(|f| f.is_updating())
+            .unwrap_or(false)
+    }
+
+    /// Checks that rows with the given schema can be written in the sink's format and by its
+    /// connector
+    /// Whether filters from the query can be pushed down into this table's source
+    pub fn supports_filter_pushdown(&self) -> bool {
+        self.connection_type == ConnectionType::Source
+            && connector_for_type(&self.connector)
+                .map(|c| c.supports_filter_pushdown())
+                .unwrap_or_default()
+    }
+
+    pub(crate) fn validate_sink_schema(&self, schema: &Schema) -> Result<()> {
+        if let Some(Format::Protobuf(ProtobufFormat {
+            compiled_schema: None,
+            ..
+        })) = &self.format
+        {
+            ArrowSerializer::protobuf_schema(schema)
+                .map_err(|e| anyhow!("can't write to protobuf sink '{}': {}", self.name, e))?;
+        }
+
+        if let Some(connector) = connector_for_type(&self.connector) {
+            let config: OperatorConfig = serde_json::from_str(&self.config)
+                .map_err(|e| anyhow!("invalid config for sink '{}': {:?}", self.name, e))?;
+            connector
+                .validate_sink_schema(&config, schema)
+                .map_err(|e| anyhow!("can't write to sink '{}': {}", self.name, e))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -466,6 +611,24 @@ fn value_to_inner_string(value: &Value) -> Result<String> {
     }
 }
 
+/// Returns the key if the expression is a call to `metadata('<key>')`
+fn metadata_key(expr: &sqlparser::ast::Expr) -> Result<Option<String>> {
+    let sqlparser::ast::Expr::Function(function) = expr else {
+        return Ok(None);
+    };
+
+    if !function.name.to_string().eq_ignore_ascii_case("metadata") {
+        return Ok(None);
+    }
+
+    match &function.args[..] {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(sqlparser::ast::Expr::Value(
+            Value::SingleQuotedString(key),
+        )))] => Ok(Some(key.clone())),
+        _ => bail!("metadata() takes a single string literal naming the metadata key"),
+    }
+}
+
 impl Table {
     fn schema_from_columns(
         columns: &[ColumnDef],
@@ -496,14 +659,24 @@ impl Table {
                         None
                     }
                 });
-                Ok((struct_field, generating_expression))
+
+                // fields generated by `metadata('<key>')` are filled by the source
+                let metadata_key = generating_expression
+                    .as_ref()
+                    .map(metadata_key)
+                    .transpose()?
+                    .flatten();
+                let generating_expression =
+                    generating_expression.filter(|_| metadata_key.is_none());
+
+                Ok((struct_field, generating_expression, metadata_key))
             })
             .collect::<Result<Vec<_>>>()?;
 
         let physical_fields: Vec<_> = struct_field_pairs
             .iter()
             .filter_map(
-                |(field, generating_expression)| match generating_expression {
+                |(field, generating_expression, _)| match generating_expression {
                     Some(_) => None,
                     None => Some(field.clone()),
                 },
@@ -527,8 +700,13 @@ impl Table {
         let sql_to_rel = SqlToRel::new(schema_provider);
         struct_field_pairs
             .into_iter()
-            .map(|(struct_field, generating_expression)| {
-                if let Some(generating_expression) = generating_expression {
+            .map(|(struct_field, generating_expression, metadata_key)| {
+                if let Some(key) = metadata_key {
+                    Ok(FieldSpec::MetadataField {
+                        field: struct_field,
+                        key,
+                    })
+                } else if let Some(generating_expression) = generating_expression {
                     // TODO: Implement automatic type coercion here, as we have elsewhere.
                     // It is done by calling the Analyzer which inserts CAST operators where necessary.
 
@@ -7,6 +7,7 @@ use arroyo_connectors::{
 };
 use arroyo_operator::connector::Connector;
 use arroyo_udf_host::parse::NullableType;
+use std::time::Duration;
 use test_log::test;
 
 use crate::{parse_and_get_program, ArroyoSchemaProvider, SqlConfig};
@@ -58,3 +59,30 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+#[test(tokio::test)]
+async fn test_set_statements() {
+    let sql = "
+    SET batch_size = 100;
+    SET batch_linger = INTERVAL '250 milliseconds';
+    SELECT bid FROM nexmark;";
+
+    let program = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap()
+        .program;
+
+    assert_eq!(program.program_config.batch_size, Some(100));
+    assert_eq!(
+        program.program_config.batch_linger,
+        Some(Duration::from_millis(250))
+    );
+
+    let sql = "SET batch_size = 'large'; SELECT bid FROM nexmark;";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("batch_size must be a positive integer"));
+}
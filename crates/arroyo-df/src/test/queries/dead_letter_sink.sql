@@ -0,0 +1,39 @@
+CREATE TABLE orders (
+    id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json',
+    bad_data = 'dead_letter',
+    'bad_data.dead_letter_table' = 'orders_dlq'
+);
+
+CREATE TABLE orders_dlq (
+    source text,
+    error text,
+    raw_data bytea,
+    ingest_time timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'orders_dlq',
+    format = 'json'
+);
+
+CREATE TABLE large_orders (
+    id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'large_orders',
+    format = 'json'
+);
+
+INSERT INTO large_orders
+SELECT id, amount FROM orders WHERE amount > 1000;
@@ -0,0 +1,26 @@
+CREATE TABLE customers (
+    id int,
+    email text,
+    region text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'customers',
+    format = 'debezium_avro',
+    'avro.confluent_schema_registry' = 'true'
+);
+
+CREATE TABLE region_counts (
+    region text,
+    count bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'region_counts',
+    format = 'debezium_avro'
+);
+
+INSERT INTO region_counts
+SELECT region, count(*) FROM customers GROUP BY region;
@@ -0,0 +1,18 @@
+CREATE TABLE orders (
+    order_id bigint,
+    customer_id bigint,
+    amount double,
+    dt text
+) WITH (
+    connector = 'delta',
+    type = 'source',
+    path = 'file:///tmp/arroyo/delta/orders',
+    format = 'parquet',
+    'source.read_snapshot' = 'true',
+    'source.monitor_interval_secs' = '5'
+);
+
+SELECT customer_id, count(*), sum(amount)
+FROM orders
+WHERE dt >= '2024-01-01'
+GROUP BY customer_id, tumble(interval '1 minute');
@@ -0,0 +1,15 @@
+--fail='csv.has_header' and 'csv.map_by_header' are only supported for filesystem sources
+CREATE TABLE orders (
+    id int,
+    amount float
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'csv',
+    'csv.has_header' = 'true',
+    'csv.map_by_header' = 'true'
+);
+
+SELECT * FROM orders;
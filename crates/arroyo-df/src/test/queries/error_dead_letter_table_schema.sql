@@ -0,0 +1,26 @@
+--fail=dead-letter table 'orders_dlq' must have the fields source Utf8, error Utf8, raw_data Binary, ingest_time Timestamp(Nanosecond, None)
+CREATE TABLE orders (
+    id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json',
+    bad_data = 'dead_letter',
+    'bad_data.dead_letter_table' = 'orders_dlq'
+);
+
+CREATE TABLE orders_dlq (
+    error text,
+    payload text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'orders_dlq',
+    format = 'json'
+);
+
+SELECT * FROM orders;
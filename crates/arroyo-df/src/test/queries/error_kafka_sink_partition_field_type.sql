@@ -0,0 +1,23 @@
+--fail=can't write to sink 'orders_by_customer': partition field 'customer' must be an integer, not Utf8
+CREATE TABLE orders (
+    id bigint,
+    customer text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE orders_by_customer WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'orders_by_customer',
+    format = 'json',
+    'sink.partition_field' = 'customer'
+);
+
+INSERT INTO orders_by_customer
+SELECT id, customer FROM orders;
@@ -0,0 +1,25 @@
+--fail=lookup joins must join on the key of the lookup table
+CREATE TABLE orders (
+    id bigint,
+    customer_name text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE customers (
+    customer_id text GENERATED ALWAYS AS (metadata('key')) STORED,
+    name text
+) WITH (
+    connector = 'redis',
+    address = 'redis://localhost:6379',
+    type = 'lookup',
+    format = 'json'
+);
+
+SELECT o.id, c.customer_id
+FROM orders o
+JOIN customers c ON o.customer_name = c.name;
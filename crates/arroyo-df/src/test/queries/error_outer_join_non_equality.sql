@@ -0,0 +1,26 @@
+--fail=This feature is not implemented: can't handle non-equality conditions in outer joins without windows
+CREATE TABLE orders (
+    order_id bigint,
+    amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE payments (
+    order_id bigint,
+    paid_amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'payments',
+    format = 'json'
+);
+
+SELECT o.order_id, o.amount, p.paid_amount
+FROM orders o
+LEFT JOIN payments p ON o.order_id = p.order_id AND p.paid_amount < o.amount;
@@ -0,0 +1,12 @@
+--fail=protobuf is not supported for FileSystem sources
+CREATE TABLE logs (
+    host text,
+    status int
+) WITH (
+    connector = 'filesystem',
+    type = 'source',
+    path = 'file:///tmp/arroyo/logs',
+    format = 'protobuf'
+);
+
+SELECT * FROM logs;
@@ -0,0 +1,16 @@
+--fail=can't write to protobuf sink 'prices': field 'price' has type Decimal128(10, 2), which is not supported in protobuf
+CREATE TABLE impulse WITH (
+    connector = 'impulse',
+    event_rate = '10'
+);
+
+CREATE TABLE prices WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'prices',
+    format = 'protobuf'
+);
+
+INSERT INTO prices
+SELECT counter, CAST(counter AS DECIMAL(10, 2)) as price FROM impulse;
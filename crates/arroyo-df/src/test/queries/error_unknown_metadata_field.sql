@@ -0,0 +1,13 @@
+--fail=connector 'kafka' does not provide metadata 'sequence'
+CREATE TABLE orders (
+    id bigint,
+    seq bigint GENERATED ALWAYS AS (metadata('sequence')) STORED
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+SELECT * FROM orders;
@@ -0,0 +1,4 @@
+--fail=unknown setting 'join_timeout'
+SET join_timeout = INTERVAL '1 hour';
+
+SELECT bid FROM nexmark;
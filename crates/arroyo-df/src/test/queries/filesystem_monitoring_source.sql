@@ -0,0 +1,18 @@
+CREATE TABLE logs (
+    host text,
+    status int,
+    bytes bigint
+) WITH (
+    connector = 'filesystem',
+    type = 'source',
+    path = 'file:///tmp/arroyo/logs',
+    format = 'json',
+    'source.regex-pattern' = '.*\.json',
+    'source.monitor_interval_secs' = '10',
+    'source.file_retention_secs' = '86400'
+);
+
+SELECT host, count(*), sum(bytes)
+FROM logs
+WHERE status >= 500
+GROUP BY host, tumble(interval '1 minute');
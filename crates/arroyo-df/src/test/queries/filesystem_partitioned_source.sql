@@ -0,0 +1,16 @@
+CREATE TABLE events (
+    dt text,
+    hour int,
+    user_id text,
+    amount bigint
+) WITH (
+    connector = 'filesystem',
+    type = 'source',
+    path = 'file:///tmp/arroyo/events',
+    format = 'parquet',
+    partition_fields = 'dt,hour'
+);
+
+SELECT user_id, amount
+FROM events
+WHERE dt >= '2024-01-01' AND hour IN (3, 4) AND amount > 100;
@@ -0,0 +1,27 @@
+CREATE TABLE impulse_source (
+    timestamp TIMESTAMP,
+    counter bigint unsigned not null,
+    subtask_index bigint unsigned not null
+) WITH (
+    connector = 'single_file',
+    path = '$input_dir/impulse.json',
+    format = 'json',
+    type = 'source',
+    event_time_field = 'timestamp'
+);
+
+CREATE TABLE events (
+    counter bigint,
+    subtask_index bigint,
+    timestamp TIMESTAMP
+) WITH (
+    connector = 'iceberg',
+    path = 'file:///tmp/arroyo/warehouse/events',
+    format = 'parquet',
+    'catalog.type' = 'hadoop',
+    rollover_seconds = '60'
+);
+
+INSERT INTO events
+SELECT counter, subtask_index, timestamp
+FROM impulse_source;
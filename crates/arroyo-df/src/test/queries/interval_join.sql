@@ -0,0 +1,31 @@
+CREATE TABLE impressions (
+    ad_id bigint,
+    user_id bigint,
+    impression_time timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'impressions',
+    format = 'json',
+    event_time_field = 'impression_time'
+);
+
+CREATE TABLE clicks (
+    ad_id bigint,
+    user_id bigint,
+    click_time timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'clicks',
+    format = 'json',
+    event_time_field = 'click_time'
+);
+
+SELECT i.ad_id, i.user_id, i.impression_time, c.click_time
+FROM impressions i
+JOIN clicks c ON i.ad_id = c.ad_id AND i.user_id = c.user_id
+  AND c.click_time BETWEEN i.impression_time - INTERVAL '5' MINUTE
+  AND i.impression_time + INTERVAL '2' DAY;
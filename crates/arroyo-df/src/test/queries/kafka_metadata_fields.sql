@@ -0,0 +1,28 @@
+CREATE TABLE orders (
+    id bigint,
+    amount double,
+    order_key text GENERATED ALWAYS AS (metadata('key')) STORED,
+    kafka_partition int GENERATED ALWAYS AS (metadata('partition')) STORED,
+    kafka_offset bigint GENERATED ALWAYS AS (metadata('offset')) STORED
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE order_totals (
+    order_key text,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'order_totals',
+    format = 'json'
+);
+
+INSERT INTO order_totals
+SELECT order_key, amount FROM orders
+WHERE kafka_partition = 0 AND kafka_offset > 1000;
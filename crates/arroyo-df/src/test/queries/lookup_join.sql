@@ -0,0 +1,43 @@
+CREATE TABLE orders (
+    id bigint,
+    customer_id text,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE customers (
+    customer_id text GENERATED ALWAYS AS (metadata('key')) STORED,
+    name text,
+    country text
+) WITH (
+    connector = 'redis',
+    address = 'redis://localhost:6379',
+    type = 'lookup',
+    format = 'json',
+    'lookup.key_prefix' = 'customer:',
+    'lookup.cache.max_entries' = '1000',
+    'lookup.cache.ttl_secs' = '60'
+);
+
+CREATE TABLE enriched_orders (
+    id bigint,
+    amount double,
+    name text,
+    country text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'enriched_orders',
+    format = 'json'
+);
+
+INSERT INTO enriched_orders
+SELECT o.id, o.amount, c.name, c.country
+FROM orders o
+LEFT JOIN customers c ON o.customer_id = c.customer_id;
@@ -0,0 +1,35 @@
+CREATE TABLE orders (
+    id bigint,
+    customer_id bigint,
+    amount double,
+    status text
+) WITH (
+    connector = 'postgres',
+    host = 'localhost',
+    database = 'shop',
+    user = 'arroyo',
+    type = 'source',
+    table_name = 'public.orders',
+    format = 'debezium_json',
+    'source.slot_name' = 'arroyo_orders',
+    'source.publication' = 'arroyo_orders'
+);
+
+CREATE TABLE customer_totals (
+    customer_id bigint,
+    total double
+) WITH (
+    connector = 'postgres',
+    host = 'localhost',
+    database = 'analytics',
+    user = 'arroyo',
+    type = 'sink',
+    table_name = 'customer_totals',
+    format = 'debezium_json',
+    'sink.primary_key' = 'customer_id'
+);
+
+INSERT INTO customer_totals
+SELECT customer_id, sum(amount) FROM orders
+WHERE status != 'cancelled'
+GROUP BY customer_id;
@@ -0,0 +1,29 @@
+CREATE TABLE orders (
+    customer_id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE customer_totals (
+    customer_id bigint,
+    orders bigint,
+    total double
+) WITH (
+    connector = 'postgres',
+    host = 'localhost',
+    database = 'analytics',
+    user = 'arroyo',
+    type = 'sink',
+    table_name = 'public.customer_totals',
+    format = 'debezium_json',
+    'sink.primary_key' = 'customer_id',
+    'sink.commit_mode' = 'exactly_once'
+);
+
+INSERT INTO customer_totals
+SELECT customer_id, count(*), sum(amount) FROM orders GROUP BY customer_id;
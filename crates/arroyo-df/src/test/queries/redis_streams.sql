@@ -0,0 +1,31 @@
+CREATE TABLE events (
+    user_id text,
+    event_type text,
+    entry_id text GENERATED ALWAYS AS (metadata('id')) STORED
+) WITH (
+    connector = 'redis',
+    address = 'redis://localhost:6379',
+    type = 'source',
+    format = 'json',
+    'source.stream_key' = 'edge-events',
+    'source.consumer_group' = 'arroyo',
+    'source.offset' = 'earliest'
+);
+
+CREATE TABLE clicks (
+    user_id text,
+    entry_id text
+) WITH (
+    connector = 'redis',
+    address = 'redis://localhost:6379',
+    type = 'sink',
+    format = 'json',
+    target = 'stream',
+    'target.key_prefix' = 'clicks',
+    'target.max_length' = '10000',
+    'target.approximate_trim' = 'true'
+);
+
+INSERT INTO clicks
+SELECT user_id, entry_id FROM events
+WHERE event_type = 'click';
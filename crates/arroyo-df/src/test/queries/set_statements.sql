@@ -0,0 +1,33 @@
+SET join_ttl = INTERVAL '2 days';
+SET updating_aggregate_flush_interval = INTERVAL '5 seconds';
+SET updating_aggregate_ttl = INTERVAL '12 hours';
+SET idle_time = INTERVAL '0 seconds';
+SET batch_size = 1024;
+SET batch_linger = INTERVAL '50 milliseconds';
+
+CREATE TABLE orders (
+    order_id bigint,
+    customer_id bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE customers (
+    customer_id bigint,
+    name text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'customers',
+    format = 'json'
+);
+
+SELECT o.customer_id, count(*)
+FROM orders o
+JOIN customers c ON o.customer_id = c.customer_id
+GROUP BY o.customer_id;
@@ -0,0 +1,39 @@
+CREATE TABLE orders (
+    order_id bigint,
+    customer_id bigint,
+    amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE payments (
+    order_id bigint,
+    paid_amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'payments',
+    format = 'json'
+);
+
+CREATE TABLE order_payments (
+    order_id bigint,
+    amount bigint,
+    paid_amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'order_payments',
+    format = 'debezium_json'
+);
+
+INSERT INTO order_payments
+SELECT o.order_id, o.amount, p.paid_amount
+FROM orders o
+FULL OUTER JOIN payments p ON o.order_id = p.order_id;
@@ -0,0 +1,41 @@
+CREATE TABLE customers (
+    id bigint,
+    name text,
+    region text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'customers',
+    format = 'debezium_json'
+);
+
+CREATE TABLE orders (
+    id bigint,
+    customer_id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'debezium_json'
+);
+
+CREATE TABLE customer_orders (
+    order_id bigint,
+    name text,
+    region text,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'customer_orders',
+    format = 'debezium_json'
+);
+
+INSERT INTO customer_orders
+SELECT o.id, c.name, c.region, o.amount
+FROM orders o
+LEFT JOIN customers c ON o.customer_id = c.id;
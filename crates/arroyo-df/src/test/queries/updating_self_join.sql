@@ -1,4 +1,3 @@
---fail=Error during planning: can't handle updating left side of join
 CREATE TABLE nexmark (
     auction bigint,
     bidder bigint,
@@ -0,0 +1,43 @@
+CREATE TABLE orders (
+    id bigint,
+    amount double
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'source',
+    topic = 'orders',
+    format = 'json'
+);
+
+CREATE TABLE order_hooks (
+    id bigint,
+    amount double
+) WITH (
+    connector = 'webhook',
+    endpoint = 'https://example.com/hooks/orders',
+    format = 'json',
+    method = 'PUT',
+    batch_format = 'json_array',
+    batch_max_records = '50',
+    batch_linger_ms = '500',
+    max_retries = '5',
+    signing_secret = '{{ WEBHOOK_SECRET }}',
+    bad_data = 'dead_letter',
+    'bad_data.dead_letter_table' = 'failed_hooks'
+);
+
+CREATE TABLE failed_hooks (
+    source text,
+    error text,
+    raw_data bytea,
+    ingest_time timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    type = 'sink',
+    topic = 'failed_hooks',
+    format = 'json'
+);
+
+INSERT INTO order_hooks
+SELECT id, amount FROM orders;
@@ -1,3 +1,4 @@
+use crate::de::confluent_header;
 use apache_avro::types::{Value, Value as AvroValue};
 use apache_avro::{from_avro_datum, AvroResult, Reader, Schema};
 use arroyo_rpc::formats::AvroFormat;
@@ -16,17 +17,8 @@ pub(crate) async fn avro_messages(
     mut msg: &[u8],
 ) -> Result<Vec<AvroResult<Value>>, SourceError> {
     let id = if format.confluent_schema_registry {
-        let magic_byte = msg[0];
-        if magic_byte != 0 {
-            return Err(SourceError::bad_data(format!(
-                "data was not encoded with schema registry wire format; \
-                magic byte has unexpected value: {}",
-                magic_byte
-            )));
-        }
-
-        let id = u32::from_be_bytes([msg[1], msg[2], msg[3], msg[4]]);
-        msg = &msg[5..];
+        let (id, rest) = confluent_header(msg)?;
+        msg = rest;
         id
     } else {
         // this should be kept in sync with the id configured when we construct the
@@ -81,7 +73,7 @@ pub(crate) async fn avro_messages(
     Ok(messages)
 }
 
-fn convert_float(f: f64) -> JsonValue {
+pub(crate) fn convert_float(f: f64) -> JsonValue {
     match serde_json::Number::from_f64(f) {
         Some(n) => JsonValue::Number(n),
         None => JsonValue::String(
@@ -97,7 +89,7 @@ fn convert_float(f: f64) -> JsonValue {
     }
 }
 
-fn encode_vec(v: Vec<u8>) -> JsonValue {
+pub(crate) fn encode_vec(v: Vec<u8>) -> JsonValue {
     JsonValue::String(v.into_iter().map(char::from).collect())
 }
 
@@ -147,6 +139,7 @@ pub(crate) fn avro_to_json(value: AvroValue) -> JsonValue {
 mod tests {
     use crate::avro::schema::to_arrow;
     use crate::de::ArrowDeserializer;
+    use apache_avro::types::Value as AvroValue;
     use arrow_array::builder::{make_builder, ArrayBuilder};
     use arrow_array::RecordBatch;
     use arrow_schema::{DataType, Field, Schema, TimeUnit};
@@ -278,6 +271,7 @@ mod tests {
                 Format::Avro(format),
                 None,
                 arroyo_schema.clone(),
+                &[],
                 BadData::Fail {},
                 resolver,
             ),
@@ -295,7 +289,7 @@ mod tests {
             deserializer_with_schema(format.clone(), writer_schema);
 
         let errors = deserializer
-            .deserialize_slice(&mut builders, message, SystemTime::now())
+            .deserialize_slice(&mut builders, message, SystemTime::now(), None)
             .await;
         assert_eq!(errors, vec![]);
 
@@ -529,4 +523,68 @@ mod tests {
             expected
         );
     }
+
+    #[tokio::test]
+    async fn test_debezium_envelope() {
+        let schema_str = r#"{
+            "type": "record",
+            "name": "Envelope",
+            "namespace": "dbserver.inventory.customers",
+            "fields": [
+                {"name": "before", "type": ["null", {
+                    "type": "record",
+                    "name": "Value",
+                    "fields": [
+                        {"name": "id", "type": "int"},
+                        {"name": "email", "type": ["null", "string"]}
+                    ]}], "default": null},
+                {"name": "after", "type": ["null", "Value"], "default": null},
+                {"name": "op", "type": "string"}
+            ]
+        }"#;
+
+        let schema = apache_avro::Schema::parse_str(schema_str).unwrap();
+
+        let arrow_schema = to_arrow(schema_str).unwrap();
+        let DataType::Struct(after) = arrow_schema.field_with_name("after").unwrap().data_type()
+        else {
+            panic!("after should be a struct");
+        };
+        assert_eq!(after.len(), 2);
+
+        let mut value = apache_avro::types::Record::new(&schema).unwrap();
+        value.put("before", AvroValue::Union(0, Box::new(AvroValue::Null)));
+        value.put(
+            "after",
+            AvroValue::Union(
+                1,
+                Box::new(AvroValue::Record(vec![
+                    ("id".to_string(), AvroValue::Int(5)),
+                    (
+                        "email".to_string(),
+                        AvroValue::Union(
+                            1,
+                            Box::new(AvroValue::String("bob@example.com".to_string())),
+                        ),
+                    ),
+                ])),
+            ),
+        );
+        value.put("op", "c");
+        let data = apache_avro::to_avro_datum(&schema, value).unwrap();
+
+        let mut format = AvroFormat::new(false, true, false);
+        format.debezium = true;
+        format.add_reader_schema(schema);
+        let row = deserialize_with_schema(format, Some(schema_str), &data)
+            .await
+            .remove(0);
+
+        assert_eq!(row.get("before"), None);
+        assert_eq!(
+            *row.get("after").unwrap(),
+            json!({"id": 5, "email": "bob@example.com"})
+        );
+        assert_eq!(*row.get("op").unwrap(), json!("c"));
+    }
 }
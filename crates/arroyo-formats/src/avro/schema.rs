@@ -1,4 +1,5 @@
 use anyhow::{anyhow, bail};
+use apache_avro::schema::{Name, NamesRef, ResolvedSchema};
 use apache_avro::Schema;
 use arrow_schema::{DataType, Field, Fields, TimeUnit};
 use arroyo_rpc::formats::AvroFormat;
@@ -24,7 +25,10 @@ pub fn to_arrow(schema: &str) -> anyhow::Result<arrow_schema::Schema> {
     let schema =
         Schema::parse_str(schema).map_err(|e| anyhow!("avro schema is not valid: {:?}", e))?;
 
-    let (dt, _, _) = to_arrow_datatype(&schema);
+    let resolved = ResolvedSchema::try_from(&schema)
+        .map_err(|e| anyhow!("avro schema is not valid: {:?}", e))?;
+
+    let (dt, _, _) = to_arrow_datatype(&schema, resolved.get_names(), &mut vec![]);
     let fields = match dt {
         DataType::Struct(fields) => fields,
         _ => {
@@ -115,7 +119,14 @@ fn arrow_to_avro(name: &str, dt: &DataType) -> serde_json::value::Value {
     })
 }
 
-fn to_arrow_datatype(schema: &Schema) -> (DataType, bool, Option<ArroyoExtensionType>) {
+/// Converts an avro schema to an arrow type. `names` is used to resolve references to named
+/// types, and `parents` holds the records we're currently inside of; recursive references
+/// can't be represented in arrow, so those are encoded as JSON.
+fn to_arrow_datatype(
+    schema: &Schema,
+    names: &NamesRef,
+    parents: &mut Vec<Name>,
+) -> (DataType, bool, Option<ArroyoExtensionType>) {
     match schema {
         Schema::Null => (DataType::Null, false, None),
         Schema::Boolean => (DataType::Boolean, false, None),
@@ -146,27 +157,33 @@ fn to_arrow_datatype(schema: &Schema) -> (DataType, bool, Option<ArroyoExtension
                 .partition(|v| matches!(v, Schema::Null));
 
             if nulls.len() == 1 && not_nulls.len() == 1 {
-                let (dt, _, ext) = to_arrow_datatype(not_nulls[0]);
+                let (dt, _, ext) = to_arrow_datatype(not_nulls[0], names, parents);
                 (dt, true, ext)
             } else {
                 (DataType::Utf8, false, Some(ArroyoExtensionType::JSON))
             }
         }
         Schema::Record(record) => {
+            parents.push(record.name.clone());
             let fields = record
                 .fields
                 .iter()
                 .map(|f| {
-                    let (dt, nullable, extension) = to_arrow_datatype(&f.schema);
+                    let (dt, nullable, extension) = to_arrow_datatype(&f.schema, names, parents);
                     Arc::new(ArroyoExtensionType::add_metadata(
                         extension,
                         Field::new(&f.name, dt, nullable),
                     ))
                 })
                 .collect();
+            parents.pop();
 
             (DataType::Struct(fields), false, None)
         }
+        Schema::Ref { name } if !parents.contains(name) => match names.get(name) {
+            Some(schema) => to_arrow_datatype(schema, names, parents),
+            None => (DataType::Utf8, false, Some(ArroyoExtensionType::JSON)),
+        },
         _ => (DataType::Utf8, false, Some(ArroyoExtensionType::JSON)),
     }
 }
@@ -0,0 +1,261 @@
+use ::csv_core::ReadRecordResult;
+use arrow_schema::{DataType, Fields};
+use arroyo_rpc::formats::CsvFormat;
+use arroyo_types::SourceError;
+use serde_json::{Map, Value as JsonValue};
+
+fn reader(format: &CsvFormat, msg: &[u8]) -> ::csv::Reader<&[u8]> {
+    ::csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(format.delimiter as u8)
+        .quote(format.quote as u8)
+        .escape(format.escape.map(|c| c as u8))
+        .double_quote(format.escape.is_none())
+        .from_reader(msg)
+}
+
+/// Splits CSV input that arrives in chunks into the raw bytes of each record. Unlike splitting
+/// on newlines, this respects quoting, so quoted fields may contain newlines.
+pub struct RecordSplitter {
+    reader: ::csv_core::Reader,
+    record: Vec<u8>,
+    // the parsed fields aren't needed, but the reader requires space to write them to
+    output: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl RecordSplitter {
+    pub fn new(format: &CsvFormat) -> Self {
+        Self {
+            reader: ::csv_core::ReaderBuilder::new()
+                .delimiter(format.delimiter as u8)
+                .quote(format.quote as u8)
+                .escape(format.escape.map(|c| c as u8))
+                .double_quote(format.escape.is_none())
+                .build(),
+            record: vec![],
+            output: vec![0; 4096],
+            ends: vec![0; 128],
+        }
+    }
+
+    /// Reads the next chunk of input, returning the records that it completes. An empty chunk
+    /// marks the end of the input, returning the final record if it wasn't terminated.
+    pub fn split(&mut self, mut input: &[u8]) -> Vec<Vec<u8>> {
+        let mut records = vec![];
+        loop {
+            let (result, read, _, _) =
+                self.reader
+                    .read_record(input, &mut self.output, &mut self.ends);
+            self.record.extend_from_slice(&input[..read]);
+            input = &input[read..];
+
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => {
+                    return records;
+                }
+                ReadRecordResult::OutputFull | ReadRecordResult::OutputEndsFull => {}
+                ReadRecordResult::Record => {
+                    let record = std::mem::take(&mut self.record);
+                    // the raw bytes may include the terminators around the record, and blank
+                    // lines aren't records
+                    let is_content = |b: &u8| *b != b'\n' && *b != b'\r';
+                    if let (Some(start), Some(end)) = (
+                        record.iter().position(is_content),
+                        record.iter().rposition(is_content),
+                    ) {
+                        records.push(record[start..=end].to_vec());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Computes the mapping from the fields of the table to the columns of the input from the
+/// header row; fields that don't appear in the header are mapped to `None`
+pub(crate) fn header_mapping(
+    format: &CsvFormat,
+    fields: &Fields,
+    header: &[u8],
+) -> Result<Vec<Option<usize>>, SourceError> {
+    let header = reader(format, header)
+        .records()
+        .next()
+        .transpose()
+        .map_err(|e| SourceError::bad_data(format!("invalid CSV header: {}", e)))?
+        .ok_or_else(|| SourceError::bad_data("CSV header row is empty"))?;
+
+    Ok(fields
+        .iter()
+        .map(|f| header.iter().position(|h| h.trim() == f.name()))
+        .collect())
+}
+
+/// Parses the CSV records in `msg` into JSON objects with the fields of the table. Columns are
+/// assigned to fields using `mapping` if provided, otherwise by position.
+pub(crate) fn deserialize_csv(
+    format: &CsvFormat,
+    fields: &Fields,
+    mapping: Option<&[Option<usize>]>,
+    msg: &[u8],
+) -> Result<Vec<JsonValue>, SourceError> {
+    reader(format, msg)
+        .records()
+        .map(|record| {
+            let record =
+                record.map_err(|e| SourceError::bad_data(format!("invalid CSV: {}", e)))?;
+
+            let mut object = Map::new();
+            for (i, field) in fields.iter().enumerate() {
+                let column = match mapping {
+                    Some(mapping) => mapping[i],
+                    None => Some(i),
+                };
+
+                let Some(value) = column.and_then(|c| record.get(c)) else {
+                    continue;
+                };
+
+                object.insert(
+                    field.name().clone(),
+                    convert_value(format, field.name(), field.data_type(), value)?,
+                );
+            }
+
+            Ok(JsonValue::Object(object))
+        })
+        .collect()
+}
+
+fn convert_value(
+    format: &CsvFormat,
+    name: &str,
+    data_type: &DataType,
+    value: &str,
+) -> Result<JsonValue, SourceError> {
+    if format.null_value.as_deref() == Some(value)
+        || (value.is_empty() && *data_type != DataType::Utf8)
+    {
+        return Ok(JsonValue::Null);
+    }
+
+    let invalid = || {
+        SourceError::bad_data(format!(
+            "invalid value '{}' for field '{}' of type {}",
+            value, name, data_type
+        ))
+    };
+
+    Ok(match data_type {
+        DataType::Boolean => match value.trim().to_lowercase().as_str() {
+            "true" | "t" | "1" => JsonValue::Bool(true),
+            "false" | "f" | "0" => JsonValue::Bool(false),
+            _ => return Err(invalid()),
+        },
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float16
+        | DataType::Float32
+        | DataType::Float64 => JsonValue::Number(value.trim().parse().map_err(|_| invalid())?),
+        // everything else (strings, timestamps, decimals, etc.) is parsed by the json decoder
+        _ => JsonValue::String(value.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::Field;
+    use serde_json::json;
+
+    fn fields() -> Fields {
+        vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("active", DataType::Boolean, true),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn test_deserialize_csv() {
+        let format = CsvFormat {
+            null_value: Some("\\N".to_string()),
+            ..Default::default()
+        };
+
+        let result = deserialize_csv(&format, &fields(), None, b"1,\"hello, world\",true").unwrap();
+        assert_eq!(
+            result,
+            vec![json!({"id": 1, "name": "hello, world", "active": true})]
+        );
+
+        let result = deserialize_csv(&format, &fields(), None, b"2,\\N,").unwrap();
+        assert_eq!(result, vec![json!({"id": 2, "name": null, "active": null})]);
+
+        assert!(deserialize_csv(&format, &fields(), None, b"x,y,true").is_err());
+    }
+
+    #[test]
+    fn test_record_splitter() {
+        let mut splitter = RecordSplitter::new(&CsvFormat::default());
+
+        let input = b"1,\"multi\nline\",true\r\n\n2,\"a \"\"quoted\"\"\nvalue\",false\n3,last,";
+        let mut records = vec![];
+        // feed the input in small chunks so records span chunk boundaries
+        for chunk in input.chunks(5) {
+            records.extend(splitter.split(chunk));
+        }
+        records.extend(splitter.split(&[]));
+
+        assert_eq!(
+            records,
+            vec![
+                b"1,\"multi\nline\",true".to_vec(),
+                b"2,\"a \"\"quoted\"\"\nvalue\",false".to_vec(),
+                b"3,last,".to_vec(),
+            ]
+        );
+
+        let result = deserialize_csv(&CsvFormat::default(), &fields(), None, &records[0]).unwrap();
+        assert_eq!(
+            result,
+            vec![json!({"id": 1, "name": "multi\nline", "active": true})]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_tsv_with_header() {
+        let format = CsvFormat {
+            delimiter: '\t',
+            escape: Some('\\'),
+            has_header: true,
+            map_by_header: true,
+            ..Default::default()
+        };
+
+        let mapping = header_mapping(&format, &fields(), b"active\tother\tid\tname").unwrap();
+        assert_eq!(mapping, vec![Some(2), Some(3), Some(0)]);
+
+        let result = deserialize_csv(
+            &format,
+            &fields(),
+            Some(&mapping),
+            b"false\tignored\t5\t\"say \\\"hi\\\"\"",
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            vec![json!({"id": 5, "name": "say \"hi\"", "active": false})]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arrow_array::RecordBatch;
+use arroyo_rpc::formats::CsvFormat;
+
+/// Serializes each row of the batch as a single CSV record, without a trailing newline
+pub fn serialize(format: &CsvFormat, batch: &RecordBatch) -> Vec<Vec<u8>> {
+    let options = FormatOptions::new().with_null(format.null_value.as_deref().unwrap_or(""));
+
+    let formatters: Vec<_> = batch
+        .columns()
+        .iter()
+        .map(|c| {
+            ArrayFormatter::try_new(c.as_ref(), &options)
+                .expect("all arrow types should be displayable")
+        })
+        .collect();
+
+    (0..batch.num_rows())
+        .map(|row| {
+            let mut writer = ::csv::WriterBuilder::new()
+                .delimiter(format.delimiter as u8)
+                .quote(format.quote as u8)
+                .escape(format.escape.map(|c| c as u8).unwrap_or(b'\\'))
+                .double_quote(format.escape.is_none())
+                .terminator(::csv::Terminator::Any(b'\n'))
+                .from_writer(vec![]);
+
+            writer
+                .write_record(formatters.iter().map(|f| f.value(row).to_string()))
+                .expect("writing to a vec cannot fail");
+
+            let mut record = writer.into_inner().expect("writing to a vec cannot fail");
+            record.pop();
+            record
+        })
+        .collect()
+}
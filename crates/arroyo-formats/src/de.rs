@@ -1,14 +1,22 @@
 use crate::avro::de;
+use crate::proto::schema::{get_message_descriptor, schema_file_to_descriptor_with_dependencies};
 use arrow::compute::kernels;
 use arrow_array::builder::{
-    ArrayBuilder, GenericByteBuilder, StringBuilder, TimestampNanosecondBuilder,
+    make_builder, ArrayBuilder, BinaryBuilder, BooleanBuilder, Float64Builder, GenericByteBuilder,
+    Int32Builder, Int64Builder, StringBuilder, TimestampNanosecondBuilder,
 };
 use arrow_array::types::GenericBinaryType;
-use arrow_array::RecordBatch;
+use arrow_array::{ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{DataType, Fields, Schema, TimeUnit};
 use arroyo_rpc::df::ArroyoSchema;
-use arroyo_rpc::formats::{AvroFormat, BadData, Format, Framing, FramingMethod, JsonFormat};
+use arroyo_rpc::formats::{
+    AvroFormat, BadData, Format, Framing, FramingMethod, JsonFormat, LengthPrefix, ProtobufFormat,
+    TimestampFormat,
+};
 use arroyo_rpc::schema_resolver::{FailingSchemaResolver, FixedSchemaResolver, SchemaResolver};
-use arroyo_types::{should_flush, to_nanos, SourceError};
+use arroyo_rpc::MetadataField;
+use arroyo_types::{from_nanos, to_nanos, BatchingConfig, MetadataValue, SourceError};
+use prost_reflect::MessageDescriptor;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Instant, SystemTime};
@@ -55,6 +63,38 @@ impl<'a> Iterator for FramingIterator<'a> {
 
                         Some(&self.buf[prev..(prev + length)])
                     }
+                    FramingMethod::LengthPrefixed(prefixed) => {
+                        let rest = &self.buf[self.offset..];
+                        let Some((length, prefix_len)) = read_length_prefix(prefixed.prefix, rest)
+                        else {
+                            // the prefix is truncated; return the remaining bytes so that they're
+                            // reported as bad data
+                            self.offset = self.buf.len();
+                            return Some(rest);
+                        };
+
+                        let start = self.offset + prefix_len;
+                        let end = start
+                            .saturating_add(usize::try_from(length).unwrap_or(usize::MAX))
+                            .min(self.buf.len());
+                        self.offset = end;
+
+                        let length =
+                            (end - start).min(prefixed.max_length.unwrap_or(u64::MAX) as usize);
+
+                        Some(&self.buf[start..(start + length)])
+                    }
+                    FramingMethod::Delimited(delimited) => {
+                        let end =
+                            memchr::memmem::find(&self.buf[self.offset..], &delimited.delimiter)
+                                .map(|i| self.offset + i)
+                                .unwrap_or(self.buf.len());
+
+                        let prev = self.offset;
+                        self.offset = end + delimited.delimiter.len();
+
+                        Some(&self.buf[prev..end])
+                    }
                 }
             }
             None => {
@@ -65,6 +105,43 @@ impl<'a> Iterator for FramingIterator<'a> {
     }
 }
 
+/// Reads a length prefix from the start of `buf`, returning the length and the size of the
+/// prefix in bytes, or None if the buffer is too short to contain the prefix
+fn read_length_prefix(prefix: LengthPrefix, buf: &[u8]) -> Option<(u64, usize)> {
+    match prefix {
+        LengthPrefix::U32Be => Some((u32::from_be_bytes(buf.get(..4)?.try_into().ok()?) as u64, 4)),
+        LengthPrefix::U32Le => Some((u32::from_le_bytes(buf.get(..4)?.try_into().ok()?) as u64, 4)),
+        LengthPrefix::U64Be => Some((u64::from_be_bytes(buf.get(..8)?.try_into().ok()?), 8)),
+        LengthPrefix::U64Le => Some((u64::from_le_bytes(buf.get(..8)?.try_into().ok()?), 8)),
+        LengthPrefix::Varint => {
+            let mut value: u64 = 0;
+            for (i, b) in buf.iter().enumerate().take(10) {
+                value |= ((b & 0x7f) as u64) << (7 * i);
+                if b & 0x80 == 0 {
+                    return Some((value, i + 1));
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Splits a message encoded in the Confluent Schema Registry wire format into the id of the
+/// schema it was written with and the remaining payload
+pub(crate) fn confluent_header(msg: &[u8]) -> Result<(u32, &[u8]), SourceError> {
+    match msg {
+        [0, a, b, c, d, rest @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), rest)),
+        [magic_byte, ..] if *magic_byte != 0 => Err(SourceError::bad_data(format!(
+            "data was not encoded with schema registry wire format; \
+            magic byte has unexpected value: {}",
+            magic_byte
+        ))),
+        _ => Err(SourceError::bad_data(
+            "data was not encoded with schema registry wire format; message is too short",
+        )),
+    }
+}
+
 pub struct ArrowDeserializer {
     format: Arc<Format>,
     framing: Option<Arc<Framing>>,
@@ -74,7 +151,22 @@ pub struct ArrowDeserializer {
     buffered_count: usize,
     buffered_since: Instant,
     schema_registry: Arc<Mutex<HashMap<u32, apache_avro::schema::Schema>>>,
+    proto_descriptor: Option<MessageDescriptor>,
+    csv_fields: Option<Fields>,
+    csv_mapping: Option<Vec<Option<usize>>>,
     schema_resolver: Arc<dyn SchemaResolver + Sync>,
+    // json and protobuf schemas resolved from the schema registry by id; protobuf schemas are
+    // stored as a message descriptor of the schema's root file
+    writer_schemas: HashMap<u32, Option<MessageDescriptor>>,
+    // the raw messages buffered in the json decoder, kept only when bad data is sent to a
+    // dead-letter sink
+    buffered_raw: Vec<Vec<u8>>,
+    rejected: Vec<SourceError>,
+    // fields that are filled from the metadata of each message rather than its payload, as
+    // pairs of (column index, metadata key)
+    metadata_fields: Vec<(usize, String)>,
+    // builders for the metadata fields of records that go through the json decoder
+    metadata_builders: Vec<Box<dyn ArrayBuilder>>,
 }
 
 impl ArrowDeserializer {
@@ -95,36 +187,89 @@ impl ArrowDeserializer {
             Arc::new(FailingSchemaResolver::new()) as Arc<dyn SchemaResolver + Sync>
         };
 
-        Self::with_schema_resolver(format, framing, schema, bad_data, resolver)
+        Self::with_schema_resolver(format, framing, schema, &[], bad_data, resolver)
     }
 
     pub fn with_schema_resolver(
         format: Format,
         framing: Option<Framing>,
         schema: ArroyoSchema,
+        metadata_fields: &[MetadataField],
         bad_data: BadData,
         schema_resolver: Arc<dyn SchemaResolver + Sync>,
     ) -> Self {
+        let proto_descriptor = if let Format::Protobuf(ProtobufFormat {
+            compiled_schema: Some(compiled_schema),
+            message_name,
+            ..
+        }) = &format
+        {
+            Some(
+                get_message_descriptor(compiled_schema, message_name.as_deref())
+                    .expect("invalid compiled protobuf schema"),
+            )
+        } else {
+            None
+        };
+
+        let metadata_fields: Vec<_> = metadata_fields
+            .iter()
+            .map(|f| {
+                let idx = schema
+                    .schema
+                    .index_of(&f.field_name)
+                    .unwrap_or_else(|_| panic!("no field '{}' for metadata", f.field_name));
+                (idx, f.key.clone())
+            })
+            .collect();
+
+        let metadata_builders = metadata_fields
+            .iter()
+            .map(|(idx, _)| make_builder(schema.schema.field(*idx).data_type(), 16))
+            .collect();
+
+        // the fields that are read from the payload, which excludes the timestamp and
+        // metadata fields
+        let payload_schema = Schema::new(
+            schema
+                .schema
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    *i != schema.timestamp_index && !metadata_fields.iter().any(|(m, _)| m == i)
+                })
+                .map(|(_, f)| f.clone())
+                .collect::<Vec<_>>(),
+        );
+
+        let csv_fields = matches!(format, Format::Csv(_)).then(|| payload_schema.fields().clone());
+
         Self {
             json_decoder: matches!(
                 format,
                 Format::Json(..)
+                    | Format::Csv(..)
                     | Format::Avro(AvroFormat {
                         into_unstructured_json: false,
                         ..
                     })
+                    | Format::Protobuf(ProtobufFormat {
+                        into_unstructured_json: false,
+                        ..
+                    })
             )
             .then(|| {
-                // exclude the timestamp field
                 (
-                    arrow_json::reader::ReaderBuilder::new(Arc::new(
-                        schema.schema_without_timestamp(),
-                    ))
-                    .with_limit_to_batch_size(false)
-                    .with_strict_mode(false)
-                    .with_allow_bad_data(matches!(bad_data, BadData::Drop { .. }))
-                    .build_decoder()
-                    .unwrap(),
+                    arrow_json::reader::ReaderBuilder::new(Arc::new(payload_schema))
+                        .with_limit_to_batch_size(false)
+                        .with_strict_mode(false)
+                        .with_allow_bad_data(matches!(
+                            bad_data,
+                            BadData::Drop { .. } | BadData::DeadLetter { .. }
+                        ))
+                        .build_decoder()
+                        .unwrap(),
                     TimestampNanosecondBuilder::new(),
                 )
             }),
@@ -132,8 +277,16 @@ impl ArrowDeserializer {
             framing: framing.map(Arc::new),
             schema,
             schema_registry: Arc::new(Mutex::new(HashMap::new())),
+            proto_descriptor,
+            csv_fields,
+            csv_mapping: None,
             bad_data,
             schema_resolver,
+            writer_schemas: HashMap::new(),
+            buffered_raw: vec![],
+            rejected: vec![],
+            metadata_fields,
+            metadata_builders,
             buffered_count: 0,
             buffered_since: Instant::now(),
         }
@@ -144,24 +297,134 @@ impl ArrowDeserializer {
         buffer: &mut [Box<dyn ArrayBuilder>],
         msg: &[u8],
         timestamp: SystemTime,
+        metadata: Option<&HashMap<&str, MetadataValue<'_>>>,
     ) -> Vec<SourceError> {
         match &*self.format {
-            Format::Avro(_) => self.deserialize_slice_avro(buffer, msg, timestamp).await,
-            _ => FramingIterator::new(self.framing.clone(), msg)
-                .map(|t| self.deserialize_single(buffer, t, timestamp))
-                .filter_map(|t| t.err())
-                .collect(),
+            Format::Avro(_) => {
+                self.deserialize_slice_avro(buffer, msg, timestamp, metadata)
+                    .await
+            }
+            _ => {
+                let mut errors = vec![];
+                for t in FramingIterator::new(self.framing.clone(), msg) {
+                    let result = match self.resolve_writer_schema(t).await {
+                        Ok(()) => self.deserialize_single(buffer, t, timestamp, metadata),
+                        Err(e) => Err(e),
+                    };
+
+                    if let Err(e) = result {
+                        errors.push(e.with_raw_data(t).with_timestamp(timestamp));
+                    }
+                }
+                errors
+            }
         }
     }
 
-    pub fn should_flush(&self) -> bool {
-        should_flush(self.buffered_count, self.buffered_since)
+    /// For json and protobuf messages in the schema registry wire format, resolves the schema
+    /// that the message was written with if we haven't already
+    async fn resolve_writer_schema(&mut self, msg: &[u8]) -> Result<(), SourceError> {
+        let (id, is_proto) = match &*self.format {
+            Format::Json(JsonFormat {
+                confluent_schema_registry: true,
+                ..
+            }) => (confluent_header(msg)?.0, false),
+            Format::Protobuf(ProtobufFormat {
+                confluent_schema_registry: true,
+                ..
+            }) => (confluent_header(msg)?.0, true),
+            _ => return Ok(()),
+        };
+
+        if self.writer_schemas.contains_key(&id) {
+            return Ok(());
+        }
+
+        // the schema the table was created with doesn't need to be fetched
+        let table_schema = match &*self.format {
+            Format::Json(json) if json.schema_id == Some(id) => Some(None),
+            Format::Protobuf(proto) if proto.schema_id == Some(id) => {
+                self.proto_descriptor.clone().map(Some)
+            }
+            _ => None,
+        };
+
+        let writer_schema = match table_schema {
+            Some(schema) => schema,
+            None => {
+                let (schema, references) = self
+                    .schema_resolver
+                    .resolve_schema_with_references(id)
+                    .await
+                    .map_err(|e| SourceError::other("schema registry error", e))?
+                    .ok_or_else(|| {
+                        SourceError::bad_data(format!(
+                            "could not resolve schema for message with id {}",
+                            id
+                        ))
+                    })?;
+
+                if is_proto {
+                    let descriptor =
+                        schema_file_to_descriptor_with_dependencies(&schema, &references)
+                            .and_then(|compiled| get_message_descriptor(&compiled, None))
+                            .map_err(|e| {
+                                SourceError::other(
+                                    "schema registry error",
+                                    format!(
+                                        "protobuf schema {} from Confluent Schema Registry is not valid: {}",
+                                        id, e
+                                    ),
+                                )
+                            })?;
+                    Some(descriptor)
+                } else {
+                    serde_json::from_str::<serde_json::Value>(&schema).map_err(|e| {
+                        SourceError::other(
+                            "schema registry error",
+                            format!(
+                                "json schema {} from Confluent Schema Registry is not valid: {}",
+                                id, e
+                            ),
+                        )
+                    })?;
+                    None
+                }
+            }
+        };
+
+        self.writer_schemas.insert(id, writer_schema);
+        Ok(())
+    }
+
+    /// Sets the header row for CSV input that is configured to map columns by header name;
+    /// this should be called at the start of each file before its records are deserialized
+    pub fn set_csv_header(&mut self, header: &[u8]) -> Result<(), SourceError> {
+        if let (Format::Csv(csv), Some(fields)) = (&*self.format, &self.csv_fields) {
+            if csv.map_by_header {
+                self.csv_mapping = Some(crate::csv::de::header_mapping(csv, fields, header)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn should_flush(&self, batching: &BatchingConfig) -> bool {
+        batching.should_flush(self.buffered_count, self.buffered_since)
     }
 
     pub fn flush_buffer(&mut self) -> Option<Result<RecordBatch, SourceError>> {
         let (decoder, timestamp) = self.json_decoder.as_mut()?;
         self.buffered_since = Instant::now();
         self.buffered_count = 0;
+
+        let metadata: Vec<_> = self
+            .metadata_fields
+            .iter()
+            .zip(self.metadata_builders.iter_mut())
+            .map(|((idx, _), builder)| (*idx, builder.finish()))
+            .collect();
+
         match self.bad_data {
             BadData::Fail { .. } => Some(
                 decoder
@@ -171,30 +434,92 @@ impl ArrowDeserializer {
                     })
                     .transpose()?
                     .map(|batch| {
-                        let mut columns = batch.columns().to_vec();
-                        columns.insert(self.schema.timestamp_index, Arc::new(timestamp.finish()));
-                        RecordBatch::try_new(self.schema.schema.clone(), columns).unwrap()
+                        assemble_batch(
+                            &self.schema,
+                            batch.columns(),
+                            Arc::new(timestamp.finish()),
+                            metadata,
+                        )
                     }),
             ),
-            BadData::Drop { .. } => Some(
-                decoder
-                    .flush_with_bad_data()
-                    .map_err(|e| {
-                        SourceError::bad_data(format!(
-                            "Something went wrong decoding JSON: {:?}",
-                            e
-                        ))
-                    })
-                    .transpose()?
-                    .map(|(batch, mask, _)| {
-                        let mut columns = batch.columns().to_vec();
-                        let timestamp =
-                            kernels::filter::filter(&timestamp.finish(), &mask).unwrap();
+            BadData::Drop { .. } | BadData::DeadLetter { .. } => {
+                let raw = std::mem::take(&mut self.buffered_raw);
+                Some(
+                    decoder
+                        .flush_with_bad_data()
+                        .map_err(|e| {
+                            SourceError::bad_data(format!(
+                                "Something went wrong decoding JSON: {:?}",
+                                e
+                            ))
+                        })
+                        .transpose()?
+                        .map(|(batch, mask, _)| {
+                            let timestamp = timestamp.finish();
+
+                            // rows that were rejected by the decoder are masked out
+                            self.rejected.extend(
+                                mask.iter().zip(raw).zip(timestamp.iter()).filter_map(
+                                    |((valid, raw), t)| {
+                                        (!valid.unwrap_or(false)).then(|| {
+                                            let e =
+                                                SourceError::bad_data("JSON does not match schema")
+                                                    .with_raw_data(&raw);
+                                            match t {
+                                                Some(t) => e.with_timestamp(from_nanos(t as u128)),
+                                                None => e,
+                                            }
+                                        })
+                                    },
+                                ),
+                            );
+
+                            let timestamp = kernels::filter::filter(&timestamp, &mask).unwrap();
+
+                            assemble_batch(
+                                &self.schema,
+                                batch.columns(),
+                                timestamp,
+                                filter_metadata(metadata, &mask),
+                            )
+                        }),
+                )
+            }
+        }
+    }
 
-                        columns.insert(self.schema.timestamp_index, Arc::new(timestamp));
-                        RecordBatch::try_new(self.schema.schema.clone(), columns).unwrap()
-                    }),
-            ),
+    /// Returns the errors for records that were rejected when the buffer was flushed, which
+    /// are tracked when bad data is sent to a dead-letter sink
+    pub fn take_rejected(&mut self) -> Vec<SourceError> {
+        std::mem::take(&mut self.rejected)
+    }
+
+    /// Keeps the raw bytes of a message that was passed to the json decoder so that we can
+    /// send it to the dead-letter sink if the decoder rejects it
+    fn buffer_raw(&mut self, msg: &[u8]) {
+        if matches!(self.bad_data, BadData::DeadLetter { .. }) {
+            self.buffered_raw.push(msg.to_vec());
+        }
+    }
+
+    /// Appends the metadata fields for a single record, either to the output buffer or, for
+    /// formats that go through the json decoder (when `buffer` is None), to our own builders
+    fn add_metadata(
+        &mut self,
+        mut buffer: Option<&mut [Box<dyn ArrayBuilder>]>,
+        metadata: Option<&HashMap<&str, MetadataValue<'_>>>,
+    ) {
+        for (i, (idx, key)) in self.metadata_fields.iter().enumerate() {
+            let builder = match buffer.as_deref_mut() {
+                Some(buffer) => &mut buffer[*idx],
+                None => &mut self.metadata_builders[i],
+            };
+
+            append_metadata(
+                builder,
+                self.schema.schema.field(*idx).data_type(),
+                metadata.and_then(|m| m.get(key.as_str())),
+            );
         }
     }
 
@@ -203,6 +528,7 @@ impl ArrowDeserializer {
         buffer: &mut [Box<dyn ArrayBuilder>],
         msg: &[u8],
         timestamp: SystemTime,
+        metadata: Option<&HashMap<&str, MetadataValue<'_>>>,
     ) -> Result<(), SourceError> {
         match &*self.format {
             Format::RawString(_)
@@ -211,27 +537,117 @@ impl ArrowDeserializer {
             }) => {
                 self.deserialize_raw_string(buffer, msg);
                 add_timestamp(buffer, self.schema.timestamp_index, timestamp);
+                self.add_metadata(Some(&mut *buffer), metadata);
             }
             Format::RawBytes(_) => {
                 self.deserialize_raw_bytes(buffer, msg);
                 add_timestamp(buffer, self.schema.timestamp_index, timestamp);
+                self.add_metadata(Some(&mut *buffer), metadata);
             }
             Format::Json(json) => {
                 let msg = if json.confluent_schema_registry {
-                    &msg[5..]
+                    confluent_header(msg)?.1
                 } else {
                     msg
                 };
 
+                // the decoder only understands RFC3339 timestamps, so others are rewritten first
+                let normalized = if json.timestamp_format != TimestampFormat::RFC3339 {
+                    let mut value: serde_json::Value = serde_json::from_slice(msg)
+                        .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
+                    crate::json::timestamp::normalize_timestamps(
+                        json,
+                        self.schema.schema.fields(),
+                        &mut value,
+                    )
+                    .map_err(SourceError::bad_data)?;
+                    Some(value.to_string())
+                } else {
+                    None
+                };
+
                 let Some((decoder, timestamp_builder)) = &mut self.json_decoder else {
                     panic!("json decoder not initialized");
                 };
 
                 decoder
-                    .decode(msg)
+                    .decode(normalized.as_ref().map(|s| s.as_bytes()).unwrap_or(msg))
                     .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
                 timestamp_builder.append_value(to_nanos(timestamp) as i64);
                 self.buffered_count += 1;
+                self.buffer_raw(msg);
+                self.add_metadata(None, metadata);
+            }
+            Format::Protobuf(proto) => {
+                let json = if proto.confluent_schema_registry {
+                    // messages are read with the schema they were written with, which was
+                    // resolved by `resolve_writer_schema`
+                    let (id, indexes, msg) = crate::proto::de::read_confluent_header(msg)?;
+                    let writer_schema = self
+                        .writer_schemas
+                        .get(&id)
+                        .cloned()
+                        .flatten()
+                        .ok_or_else(|| {
+                            SourceError::other(
+                                "protobuf error",
+                                format!("no protobuf schema was resolved for id {}", id),
+                            )
+                        })?;
+                    let descriptor =
+                        crate::proto::de::message_for_indexes(&writer_schema, &indexes)?;
+                    crate::proto::de::deserialize_proto(&descriptor, msg)?
+                } else {
+                    let descriptor = self.proto_descriptor.as_ref().ok_or_else(|| {
+                        SourceError::other(
+                            "protobuf error",
+                            "no protobuf schema is configured for this source",
+                        )
+                    })?;
+                    crate::proto::de::deserialize_proto(descriptor, msg)?
+                };
+
+                if proto.into_unstructured_json {
+                    self.deserialize_raw_string(buffer, json.to_string().as_bytes());
+                    add_timestamp(buffer, self.schema.timestamp_index, timestamp);
+                    self.add_metadata(Some(&mut *buffer), metadata);
+                } else {
+                    let Some((decoder, timestamp_builder)) = &mut self.json_decoder else {
+                        panic!("json decoder not initialized");
+                    };
+
+                    decoder
+                        .decode(json.to_string().as_bytes())
+                        .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
+                    timestamp_builder.append_value(to_nanos(timestamp) as i64);
+                    self.buffered_count += 1;
+                    self.buffer_raw(msg);
+                    self.add_metadata(None, metadata);
+                }
+            }
+            Format::Csv(csv) => {
+                let records = crate::csv::de::deserialize_csv(
+                    csv,
+                    self.csv_fields
+                        .as_ref()
+                        .expect("csv fields not initialized"),
+                    self.csv_mapping.as_deref(),
+                    msg,
+                )?;
+
+                for record in records {
+                    let Some((decoder, timestamp_builder)) = &mut self.json_decoder else {
+                        panic!("json decoder not initialized");
+                    };
+
+                    decoder
+                        .decode(record.to_string().as_bytes())
+                        .map_err(|e| SourceError::bad_data(format!("invalid CSV: {:?}", e)))?;
+                    timestamp_builder.append_value(to_nanos(timestamp) as i64);
+                    self.buffered_count += 1;
+                    self.buffer_raw(msg);
+                    self.add_metadata(None, metadata);
+                }
             }
             Format::Avro(_) => unreachable!("this should not be called for avro"),
             Format::Parquet(_) => todo!("parquet is not supported as an input format"),
@@ -245,6 +661,7 @@ impl ArrowDeserializer {
         builders: &mut [Box<dyn ArrayBuilder>],
         msg: &'a [u8],
         timestamp: SystemTime,
+        metadata: Option<&HashMap<&str, MetadataValue<'_>>>,
     ) -> Vec<SourceError> {
         let Format::Avro(format) = &*self.format else {
             unreachable!("not avro");
@@ -260,7 +677,7 @@ impl ArrowDeserializer {
         {
             Ok(messages) => messages,
             Err(e) => {
-                return vec![e];
+                return vec![e.with_raw_data(msg).with_timestamp(timestamp)];
             }
         };
 
@@ -285,6 +702,7 @@ impl ArrowDeserializer {
 
                     array.append_value(de::avro_to_json(value).to_string());
                     add_timestamp(builders, self.schema.timestamp_index, timestamp);
+                    self.add_metadata(Some(&mut *builders), metadata);
                     self.buffered_count += 1;
                 } else {
                     // for now round-trip through json in order to handle unsupported avro features
@@ -300,11 +718,16 @@ impl ArrowDeserializer {
                         .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
                     self.buffered_count += 1;
                     timestamp_builder.append_value(to_nanos(timestamp) as i64);
+                    self.buffer_raw(msg);
+                    self.add_metadata(None, metadata);
                 }
 
                 Ok(())
             })
-            .filter_map(|r: Result<(), SourceError>| r.err())
+            .filter_map(|r: Result<(), SourceError>| {
+                r.err()
+                    .map(|e| e.with_raw_data(msg).with_timestamp(timestamp))
+            })
             .collect();
 
         errors
@@ -353,9 +776,108 @@ pub(crate) fn add_timestamp(
         .append_value(to_nanos(timestamp) as i64);
 }
 
+/// Builds an output batch from the columns produced by the json decoder along with the
+/// timestamp and metadata columns, which are built separately
+fn assemble_batch(
+    schema: &ArroyoSchema,
+    decoded: &[ArrayRef],
+    timestamp: ArrayRef,
+    mut metadata: Vec<(usize, ArrayRef)>,
+) -> RecordBatch {
+    metadata.push((schema.timestamp_index, timestamp));
+    metadata.sort_by_key(|(idx, _)| *idx);
+
+    let mut columns = decoded.to_vec();
+    for (idx, array) in metadata {
+        columns.insert(idx, array);
+    }
+
+    RecordBatch::try_new(schema.schema.clone(), columns).unwrap()
+}
+
+fn filter_metadata(
+    metadata: Vec<(usize, ArrayRef)>,
+    mask: &BooleanArray,
+) -> Vec<(usize, ArrayRef)> {
+    metadata
+        .into_iter()
+        .map(|(idx, array)| (idx, kernels::filter::filter(&array, mask).unwrap()))
+        .collect()
+}
+
+/// Appends a metadata value to the builder for its field. String values are parsed into numeric
+/// and boolean fields, for metadata that comes from text like the partition values in a path.
+fn append_metadata(
+    builder: &mut Box<dyn ArrayBuilder>,
+    data_type: &DataType,
+    value: Option<&MetadataValue<'_>>,
+) {
+    let builder = builder.as_any_mut();
+    match data_type {
+        DataType::Int32 => {
+            let builder = builder.downcast_mut::<Int32Builder>().unwrap();
+            match value {
+                Some(MetadataValue::Int32(v)) => builder.append_value(*v),
+                Some(MetadataValue::String(v)) => builder.append_option(v.parse().ok()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Int64 => {
+            let builder = builder.downcast_mut::<Int64Builder>().unwrap();
+            match value {
+                Some(MetadataValue::Int64(v)) => builder.append_value(*v),
+                Some(MetadataValue::Int32(v)) => builder.append_value(*v as i64),
+                Some(MetadataValue::String(v)) => builder.append_option(v.parse().ok()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Float64 => {
+            let builder = builder.downcast_mut::<Float64Builder>().unwrap();
+            match value {
+                Some(MetadataValue::String(v)) => builder.append_option(v.parse().ok()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Boolean => {
+            let builder = builder.downcast_mut::<BooleanBuilder>().unwrap();
+            match value {
+                Some(MetadataValue::String(v)) => builder.append_option(v.parse().ok()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Utf8 => {
+            let builder = builder.downcast_mut::<StringBuilder>().unwrap();
+            match value {
+                Some(MetadataValue::String(v)) => builder.append_value(v),
+                Some(MetadataValue::Bytes(v)) => builder.append_option(std::str::from_utf8(v).ok()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Binary => {
+            let builder = builder.downcast_mut::<BinaryBuilder>().unwrap();
+            match value {
+                Some(MetadataValue::Bytes(v)) => builder.append_value(v),
+                Some(MetadataValue::String(v)) => builder.append_value(v.as_bytes()),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            let builder = builder
+                .downcast_mut::<TimestampNanosecondBuilder>()
+                .unwrap();
+            match value {
+                Some(MetadataValue::Timestamp(v)) => builder.append_value(to_nanos(*v) as i64),
+                _ => builder.append_null(),
+            }
+        }
+        dt => unreachable!("unsupported type for metadata field: {:?}", dt),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::de::{ArrowDeserializer, FramingIterator};
+    use crate::de::{confluent_header, ArrowDeserializer, FramingIterator};
+    use crate::proto::schema::{get_message_descriptor, schema_file_to_descriptor};
     use arrow_array::builder::{make_builder, ArrayBuilder};
     use arrow_array::cast::AsArray;
     use arrow_array::types::{GenericBinaryType, Int64Type, TimestampNanosecondType};
@@ -363,11 +885,16 @@ mod tests {
     use arrow_schema::{Schema, TimeUnit};
     use arroyo_rpc::df::ArroyoSchema;
     use arroyo_rpc::formats::{
-        BadData, Format, Framing, FramingMethod, JsonFormat, NewlineDelimitedFraming,
-        RawBytesFormat,
+        BadData, DelimitedFraming, Format, Framing, FramingMethod, JsonFormat, LengthPrefix,
+        LengthPrefixedFraming, NewlineDelimitedFraming, ProtobufFormat, RawBytesFormat,
     };
-    use arroyo_types::{to_nanos, SourceError};
+    use arroyo_rpc::schema_resolver::{FailingSchemaResolver, SchemaResolver};
+    use arroyo_rpc::MetadataField;
+    use arroyo_types::{to_nanos, MetadataValue, SourceError};
+    use prost::Message;
+    use prost_reflect::{DynamicMessage, Value};
     use serde_json::json;
+    use std::collections::HashMap;
     use std::sync::Arc;
     use std::time::SystemTime;
 
@@ -418,6 +945,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_length_prefixed_framing() {
+        let framing = |prefix| {
+            Some(Arc::new(Framing {
+                method: FramingMethod::LengthPrefixed(LengthPrefixedFraming {
+                    prefix,
+                    max_length: None,
+                }),
+            }))
+        };
+
+        let result: Vec<_> = FramingIterator::new(
+            framing(LengthPrefix::U32Be),
+            &[
+                0, 0, 0, 3, b'o', b'n', b'e', 0, 0, 0, 0, 0, 0, 0, 2, b'h', b'i',
+            ],
+        )
+        .map(|t| t.to_vec())
+        .collect();
+        assert_eq!(result, vec![b"one".to_vec(), vec![], b"hi".to_vec()]);
+
+        let result: Vec<_> = FramingIterator::new(
+            framing(LengthPrefix::U64Le),
+            &[2, 0, 0, 0, 0, 0, 0, 0, b'h', b'i'],
+        )
+        .map(|t| t.to_vec())
+        .collect();
+        assert_eq!(result, vec![b"hi".to_vec()]);
+
+        let long = vec![b'x'; 200];
+        let mut buf = vec![0xc8, 0x01];
+        buf.extend(&long);
+        buf.extend([1, b'y']);
+        let result: Vec<_> = FramingIterator::new(framing(LengthPrefix::Varint), &buf)
+            .map(|t| t.to_vec())
+            .collect();
+        assert_eq!(result, vec![long, b"y".to_vec()]);
+
+        // truncated frames and prefixes are returned as-is
+        let result: Vec<_> =
+            FramingIterator::new(framing(LengthPrefix::U32Le), &[1, 0, 0, 0, b'a', 5, 0])
+                .map(|t| t.to_vec())
+                .collect();
+        assert_eq!(result, vec![b"a".to_vec(), vec![5, 0]]);
+
+        let result: Vec<_> =
+            FramingIterator::new(framing(LengthPrefix::U32Le), &[5, 0, 0, 0, b'a'])
+                .map(|t| t.to_vec())
+                .collect();
+        assert_eq!(result, vec![b"a".to_vec()]);
+    }
+
+    #[test]
+    fn test_delimited_framing() {
+        let framing = Some(Arc::new(Framing {
+            method: FramingMethod::Delimited(DelimitedFraming {
+                delimiter: b"\r\n".to_vec(),
+            }),
+        }));
+
+        let result: Vec<_> = FramingIterator::new(framing, b"one\r\ntwo\nstill two\r\nthree\r\n")
+            .map(|t| t.to_vec())
+            .collect();
+
+        assert_eq!(
+            result,
+            vec![
+                b"one".to_vec(),
+                b"two\nstill two".to_vec(),
+                b"three".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_confluent_header() {
+        let (id, rest) = confluent_header(&[0, 0, 0, 1, 2, b'{', b'}']).unwrap();
+        assert_eq!(id, 258);
+        assert_eq!(rest, b"{}");
+
+        assert!(confluent_header(b"{}").is_err());
+        assert!(confluent_header(&[0, 0, 1]).is_err());
+    }
+
     #[test]
     fn test_max_line_length() {
         let framing = Some(Arc::new(Framing {
@@ -467,6 +1078,7 @@ mod tests {
                 debezium: false,
                 unstructured: false,
                 timestamp_format: Default::default(),
+                timezone: None,
             }),
             schema,
             None,
@@ -487,7 +1099,8 @@ mod tests {
                 .deserialize_slice(
                     &mut arrays[..],
                     json!({ "x": 5 }).to_string().as_bytes(),
-                    now
+                    now,
+                    None
                 )
                 .await,
             vec![]
@@ -497,7 +1110,8 @@ mod tests {
                 .deserialize_slice(
                     &mut arrays[..],
                     json!({ "x": "hello" }).to_string().as_bytes(),
-                    now
+                    now,
+                    None
                 )
                 .await,
             vec![]
@@ -523,7 +1137,8 @@ mod tests {
                 .deserialize_slice(
                     &mut arrays[..],
                     json!({ "x": 5 }).to_string().as_bytes(),
-                    SystemTime::now()
+                    SystemTime::now(),
+                    None
                 )
                 .await,
             vec![]
@@ -533,7 +1148,8 @@ mod tests {
                 .deserialize_slice(
                     &mut arrays[..],
                     json!({ "x": "hello" }).to_string().as_bytes(),
-                    SystemTime::now()
+                    SystemTime::now(),
+                    None
                 )
                 .await,
             vec![]
@@ -544,6 +1160,230 @@ mod tests {
         assert!(matches!(err, SourceError::BadData { .. }));
     }
 
+    #[tokio::test]
+    async fn test_metadata_fields() {
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("key", arrow_schema::DataType::Utf8, true),
+            arrow_schema::Field::new("x", arrow_schema::DataType::Int64, true),
+            arrow_schema::Field::new("offset", arrow_schema::DataType::Int64, true),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let mut arrays: Vec<_> = schema
+            .fields
+            .iter()
+            .map(|f| make_builder(f.data_type(), 16))
+            .collect();
+
+        let mut deserializer = ArrowDeserializer::with_schema_resolver(
+            Format::Json(JsonFormat::default()),
+            None,
+            ArroyoSchema::from_schema_unkeyed(schema).unwrap(),
+            &[
+                MetadataField {
+                    field_name: "key".to_string(),
+                    key: "key".to_string(),
+                },
+                MetadataField {
+                    field_name: "offset".to_string(),
+                    key: "offset".to_string(),
+                },
+            ],
+            BadData::Drop {},
+            Arc::new(FailingSchemaResolver::new()),
+        );
+
+        let messages = [
+            (json!({ "x": 5 }), Some("a"), 1),
+            (json!({ "x": "hello" }), Some("b"), 2),
+            (json!({ "x": 7 }), None, 3),
+        ];
+
+        for (msg, key, offset) in messages {
+            let mut metadata = HashMap::new();
+            if let Some(key) = key {
+                metadata.insert("key", MetadataValue::String(key));
+            }
+            metadata.insert("offset", MetadataValue::Int64(offset));
+
+            let errors = deserializer
+                .deserialize_slice(
+                    &mut arrays[..],
+                    msg.to_string().as_bytes(),
+                    SystemTime::now(),
+                    Some(&metadata),
+                )
+                .await;
+            assert_eq!(errors, vec![]);
+        }
+
+        // the record that doesn't match the schema is dropped along with its metadata
+        let batch = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column(0).as_string::<i32>().value(0), "a");
+        assert!(batch.column(0).is_null(1));
+        assert_eq!(
+            batch.column(1).as_primitive::<Int64Type>().values()[..],
+            [5, 7]
+        );
+        assert_eq!(
+            batch.column(2).as_primitive::<Int64Type>().values()[..],
+            [1, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binary_metadata_key() {
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("raw_key", arrow_schema::DataType::Binary, true),
+            arrow_schema::Field::new("text_key", arrow_schema::DataType::Utf8, true),
+            arrow_schema::Field::new("x", arrow_schema::DataType::Int64, true),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let mut arrays: Vec<_> = schema
+            .fields
+            .iter()
+            .map(|f| make_builder(f.data_type(), 16))
+            .collect();
+
+        let mut deserializer = ArrowDeserializer::with_schema_resolver(
+            Format::Json(JsonFormat::default()),
+            None,
+            ArroyoSchema::from_schema_unkeyed(schema).unwrap(),
+            &[
+                MetadataField {
+                    field_name: "raw_key".to_string(),
+                    key: "key".to_string(),
+                },
+                MetadataField {
+                    field_name: "text_key".to_string(),
+                    key: "key".to_string(),
+                },
+            ],
+            BadData::Drop {},
+            Arc::new(FailingSchemaResolver::new()),
+        );
+
+        let keys: [&[u8]; 2] = [b"abc", &[0xff, 0x00, 0xfe]];
+        for key in keys {
+            let metadata = HashMap::from([("key", MetadataValue::Bytes(key))]);
+            let errors = deserializer
+                .deserialize_slice(
+                    &mut arrays[..],
+                    json!({ "x": 1 }).to_string().as_bytes(),
+                    SystemTime::now(),
+                    Some(&metadata),
+                )
+                .await;
+            assert_eq!(errors, vec![]);
+        }
+
+        // binary keys are passed through unchanged, and are null as text if they aren't UTF-8
+        let batch = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(batch.column(0).as_binary::<i32>().value(0), b"abc");
+        assert_eq!(
+            batch.column(0).as_binary::<i32>().value(1),
+            &[0xff, 0x00, 0xfe]
+        );
+        assert_eq!(batch.column(1).as_string::<i32>().value(0), "abc");
+        assert!(batch.column(1).is_null(1));
+    }
+
+    struct ProtoSchemaResolver {
+        id: u32,
+        schema: String,
+    }
+
+    #[async_trait::async_trait]
+    impl SchemaResolver for ProtoSchemaResolver {
+        async fn resolve_schema(&self, id: u32) -> Result<Option<String>, String> {
+            Ok((id == self.id).then(|| self.schema.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_writer_schema_from_registry() {
+        let reader_schema = r#"
+            syntax = "proto3";
+            message Event {
+                string name = 1;
+            }
+        "#;
+        let writer_schema = r#"
+            syntax = "proto3";
+            message Event {
+                int64 count = 1;
+                string name = 2;
+            }
+        "#;
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("name", arrow_schema::DataType::Utf8, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+        let arroyo_schema = ArroyoSchema::from_schema_unkeyed(schema).unwrap();
+        let mut builders = arroyo_schema.builders();
+
+        let mut deserializer = ArrowDeserializer::with_schema_resolver(
+            Format::Protobuf(ProtobufFormat {
+                confluent_schema_registry: true,
+                into_unstructured_json: false,
+                message_name: None,
+                compiled_schema: Some(schema_file_to_descriptor(reader_schema).unwrap()),
+                definition: None,
+                schema_id: Some(1),
+            }),
+            None,
+            arroyo_schema,
+            &[],
+            BadData::Fail {},
+            Arc::new(ProtoSchemaResolver {
+                id: 2,
+                schema: writer_schema.to_string(),
+            }),
+        );
+
+        let descriptor =
+            get_message_descriptor(&schema_file_to_descriptor(writer_schema).unwrap(), None)
+                .unwrap();
+        let mut message = DynamicMessage::new(descriptor);
+        message.set_field_by_name("count", Value::I64(3));
+        message.set_field_by_name("name", Value::String("a".to_string()));
+
+        // magic byte, schema id 2, and the message index of the first message
+        let mut msg = vec![0, 0, 0, 0, 2, 0];
+        msg.extend(message.encode_to_vec());
+
+        let errors = deserializer
+            .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
+            .await;
+        assert!(errors.is_empty());
+
+        // messages with ids that aren't in the registry are rejected
+        msg[4] = 3;
+        let errors = deserializer
+            .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
+            .await;
+        assert_eq!(errors.len(), 1);
+
+        let batch = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.column(0).as_string::<i32>().value(0), "a");
+    }
+
     #[tokio::test]
     async fn test_raw_bytes() {
         let schema = Arc::new(Schema::new(vec![
@@ -572,7 +1412,7 @@ mod tests {
 
         let time = SystemTime::now();
         let result = deserializer
-            .deserialize_slice(&mut arrays, &vec![0, 1, 2, 3, 4, 5], time)
+            .deserialize_slice(&mut arrays, &vec![0, 1, 2, 3, 4, 5], time, None)
             .await;
         assert!(result.is_empty());
 
@@ -6,6 +6,7 @@ use serde_json::{json, Value};
 use std::collections::HashMap;
 
 pub mod schema;
+pub mod timestamp;
 
 pub fn deserialize_slice_json(
     schema: &SchemaRef,
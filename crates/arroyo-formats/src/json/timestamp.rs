@@ -0,0 +1,385 @@
+use arrow::compute::cast;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type, TimestampNanosecondType};
+use arrow_array::{Array, ArrayRef, RecordBatch, StringArray, StructArray};
+use arrow_schema::{DataType, Field, Fields, Schema, TimeUnit};
+use arroyo_rpc::formats::{JsonFormat, TimestampFormat};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+
+fn timezone(format: &JsonFormat) -> Tz {
+    format
+        .timezone
+        .as_deref()
+        .and_then(|tz| Tz::from_str(tz).ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// Rewrites the timestamp fields of a JSON record (including those nested in structs and lists)
+/// from the configured timestamp format into RFC3339 strings, which the arrow JSON decoder
+/// can parse
+pub(crate) fn normalize_timestamps(
+    format: &JsonFormat,
+    fields: &Fields,
+    value: &mut Value,
+) -> Result<(), String> {
+    normalize_fields(&format.timestamp_format, &timezone(format), fields, value)
+}
+
+fn normalize_fields(
+    format: &TimestampFormat,
+    tz: &Tz,
+    fields: &Fields,
+    value: &mut Value,
+) -> Result<(), String> {
+    let Value::Object(object) = value else {
+        return Ok(());
+    };
+
+    for field in fields {
+        if let Some(v) = object.get_mut(field.name()) {
+            normalize_value(format, tz, field, v)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn normalize_value(
+    format: &TimestampFormat,
+    tz: &Tz,
+    field: &Field,
+    value: &mut Value,
+) -> Result<(), String> {
+    match field.data_type() {
+        DataType::Timestamp(_, _) if !value.is_null() => {
+            let nanos = parse_timestamp(format, tz, value).ok_or_else(|| {
+                format!(
+                    "invalid timestamp {} for field '{}'; expected {:?}",
+                    value,
+                    field.name(),
+                    format
+                )
+            })?;
+
+            *value = Value::String(
+                DateTime::from_timestamp_nanos(nanos).to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            );
+        }
+        DataType::Struct(fields) => normalize_fields(format, tz, fields, value)?,
+        DataType::List(item) | DataType::LargeList(item) => {
+            if let Value::Array(items) = value {
+                for v in items {
+                    normalize_value(format, tz, item, v)?;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Parses a JSON timestamp in the given format into nanoseconds since the epoch
+fn parse_timestamp(format: &TimestampFormat, tz: &Tz, value: &Value) -> Option<i64> {
+    let scale = match format {
+        TimestampFormat::RFC3339 => {
+            return DateTime::parse_from_rfc3339(value.as_str()?)
+                .ok()?
+                .timestamp_nanos_opt();
+        }
+        TimestampFormat::Pattern(pattern) => {
+            return parse_pattern(pattern, tz, value.as_str()?);
+        }
+        TimestampFormat::UnixSeconds | TimestampFormat::UnixSecondsFractional => 9,
+        TimestampFormat::UnixMillis => 6,
+        TimestampFormat::UnixMicros => 3,
+        TimestampFormat::UnixNanos => 0,
+    };
+
+    match value {
+        Value::Number(n) => parse_decimal(&n.to_string(), scale),
+        Value::String(s) => parse_decimal(s.trim(), scale),
+        _ => None,
+    }
+}
+
+/// Parses a decimal number in some unit into nanoseconds, where `scale` is the number of
+/// digits between that unit and nanoseconds. Parsing the digits directly rather than going
+/// through a float means we don't lose precision for epoch timestamps with fractional parts.
+fn parse_decimal(s: &str, scale: u32) -> Option<i64> {
+    if s.contains(['e', 'E']) {
+        let f: f64 = s.parse().ok()?;
+        return Some((f * 10f64.powi(scale as i32)).round() as i64);
+    }
+
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if int.is_empty()
+        || !int.bytes().all(|b| b.is_ascii_digit())
+        || !frac.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let mut frac: String = frac.chars().take(scale as usize).collect();
+    while frac.len() < scale as usize {
+        frac.push('0');
+    }
+
+    let nanos = int
+        .parse::<i64>()
+        .ok()?
+        .checked_mul(10i64.pow(scale))?
+        .checked_add(if frac.is_empty() {
+            0
+        } else {
+            frac.parse().ok()?
+        })?;
+
+    Some(if negative { -nanos } else { nanos })
+}
+
+/// Parses a timestamp with a strftime-style pattern; if the pattern doesn't include an offset
+/// the timestamp is interpreted in `tz`, and date-only patterns are taken as midnight
+fn parse_pattern(pattern: &str, tz: &Tz, s: &str) -> Option<i64> {
+    let datetime = match DateTime::parse_from_str(s, pattern) {
+        Ok(datetime) => datetime.with_timezone(&Utc),
+        Err(_) => {
+            let naive = NaiveDateTime::parse_from_str(s, pattern).ok().or_else(|| {
+                NaiveDate::parse_from_str(s, pattern)
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+            })?;
+
+            tz.from_local_datetime(&naive)
+                .earliest()?
+                .with_timezone(&Utc)
+        }
+    };
+
+    datetime.timestamp_nanos_opt()
+}
+
+/// Converts the timestamp columns of a batch (including those nested in structs) into the
+/// representation for the configured timestamp format, for formats that the arrow JSON
+/// encoder doesn't support directly
+pub(crate) fn format_timestamps(format: &JsonFormat, batch: &RecordBatch) -> RecordBatch {
+    let tz = timezone(format);
+
+    let (fields, columns): (Vec<_>, Vec<_>) = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(f, c)| format_column(&format.timestamp_format, &tz, f, c))
+        .unzip();
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+        .expect("formatted batch should be valid")
+}
+
+fn format_column(
+    format: &TimestampFormat,
+    tz: &Tz,
+    field: &Field,
+    array: &ArrayRef,
+) -> (Field, ArrayRef) {
+    let array: ArrayRef = match field.data_type() {
+        DataType::Timestamp(_, array_tz) => {
+            let nanos = cast(
+                array,
+                &DataType::Timestamp(TimeUnit::Nanosecond, array_tz.clone()),
+            )
+            .expect("timestamps can be cast to nanoseconds");
+            let nanos = nanos.as_primitive::<TimestampNanosecondType>();
+
+            match format {
+                TimestampFormat::UnixSeconds => {
+                    Arc::new(nanos.unary::<_, Int64Type>(|t| t.div_euclid(1_000_000_000)))
+                }
+                TimestampFormat::UnixMillis => {
+                    Arc::new(nanos.unary::<_, Int64Type>(|t| t.div_euclid(1_000_000)))
+                }
+                TimestampFormat::UnixMicros => {
+                    Arc::new(nanos.unary::<_, Int64Type>(|t| t.div_euclid(1_000)))
+                }
+                TimestampFormat::UnixNanos => Arc::new(nanos.unary::<_, Int64Type>(|t| t)),
+                TimestampFormat::UnixSecondsFractional => {
+                    Arc::new(nanos.unary::<_, Float64Type>(|t| t as f64 / 1_000_000_000.0))
+                }
+                TimestampFormat::RFC3339 => Arc::new(
+                    nanos
+                        .iter()
+                        .map(|t| {
+                            t.map(|t| {
+                                DateTime::from_timestamp_nanos(t)
+                                    .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                            })
+                        })
+                        .collect::<StringArray>(),
+                ),
+                TimestampFormat::Pattern(pattern) => Arc::new(
+                    nanos
+                        .iter()
+                        .map(|t| {
+                            t.map(|t| {
+                                DateTime::from_timestamp_nanos(t)
+                                    .with_timezone(tz)
+                                    .format(pattern)
+                                    .to_string()
+                            })
+                        })
+                        .collect::<StringArray>(),
+                ),
+            }
+        }
+        DataType::Struct(_) => {
+            let array = array.as_struct();
+            let (fields, columns): (Vec<_>, Vec<_>) = array
+                .fields()
+                .iter()
+                .zip(array.columns())
+                .map(|(f, c)| format_column(format, tz, f, c))
+                .unzip();
+
+            Arc::new(StructArray::new(
+                fields.into(),
+                columns,
+                array.nulls().cloned(),
+            ))
+        }
+        _ => return (field.clone(), array.clone()),
+    };
+
+    (
+        field.clone().with_data_type(array.data_type().clone()),
+        array,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::TimestampMillisecondArray;
+    use serde_json::json;
+
+    fn format(timestamp_format: TimestampFormat, timezone: Option<&str>) -> JsonFormat {
+        JsonFormat {
+            timestamp_format,
+            timezone: timezone.map(|s| s.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn normalize(format: &JsonFormat, value: Value) -> Result<Value, String> {
+        let fields: Fields = vec![Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            true,
+        )]
+        .into();
+
+        let mut value = json!({ "ts": value });
+        normalize_timestamps(format, &fields, &mut value)?;
+        Ok(value["ts"].clone())
+    }
+
+    #[test]
+    fn test_normalize_epoch_timestamps() {
+        let seconds = format(TimestampFormat::UnixSeconds, None);
+        assert_eq!(
+            normalize(&seconds, json!(1700000000)).unwrap(),
+            json!("2023-11-14T22:13:20Z")
+        );
+        assert_eq!(
+            normalize(&seconds, json!("1700000000")).unwrap(),
+            json!("2023-11-14T22:13:20Z")
+        );
+
+        let fractional = format(TimestampFormat::UnixSecondsFractional, None);
+        assert_eq!(
+            normalize(&fractional, json!(1700000000.123456)).unwrap(),
+            json!("2023-11-14T22:13:20.123456Z")
+        );
+
+        let micros = format(TimestampFormat::UnixMicros, None);
+        assert_eq!(
+            normalize(&micros, json!(1700000000000001i64)).unwrap(),
+            json!("2023-11-14T22:13:20.000001Z")
+        );
+
+        let nanos = format(TimestampFormat::UnixNanos, None);
+        assert_eq!(normalize(&nanos, json!(null)).unwrap(), json!(null));
+        assert!(normalize(&nanos, json!("yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_normalize_pattern_timestamps() {
+        let pattern = format(
+            TimestampFormat::pattern("%Y-%m-%d %H:%M:%S").unwrap(),
+            Some("America/New_York"),
+        );
+        assert_eq!(
+            normalize(&pattern, json!("2023-11-14 17:13:20")).unwrap(),
+            json!("2023-11-14T22:13:20Z")
+        );
+        assert!(normalize(&pattern, json!("2023-11-14T17:13:20")).is_err());
+
+        let with_offset = format(
+            TimestampFormat::pattern("%Y-%m-%d %H:%M:%S %z").unwrap(),
+            None,
+        );
+        assert_eq!(
+            normalize(&with_offset, json!("2023-11-14 23:13:20 +0100")).unwrap(),
+            json!("2023-11-14T22:13:20Z")
+        );
+    }
+
+    #[test]
+    fn test_format_timestamps() {
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                "ts",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            )])),
+            vec![Arc::new(TimestampMillisecondArray::from(vec![
+                Some(1700000000123),
+                None,
+            ]))],
+        )
+        .unwrap();
+
+        let seconds = format_timestamps(&format(TimestampFormat::UnixSeconds, None), &batch);
+        let seconds = seconds.column(0).as_primitive::<Int64Type>();
+        assert_eq!(seconds.value(0), 1700000000);
+        assert!(seconds.is_null(1));
+
+        let fractional = format_timestamps(
+            &format(TimestampFormat::UnixSecondsFractional, None),
+            &batch,
+        );
+        let fractional = fractional.column(0).as_primitive::<Float64Type>().value(0);
+        assert!((fractional - 1700000000.123).abs() < 1e-6);
+
+        let pattern = format_timestamps(
+            &format(
+                TimestampFormat::pattern("%Y-%m-%d %H:%M:%S").unwrap(),
+                Some("Asia/Tokyo"),
+            ),
+            &batch,
+        );
+        assert_eq!(
+            pattern.column(0).as_string::<i32>().value(0),
+            "2023-11-15 07:13:20"
+        );
+    }
+}
@@ -3,7 +3,9 @@ extern crate core;
 use serde_json::json;
 
 pub mod avro;
+pub mod csv;
 pub mod json;
+pub mod proto;
 
 pub mod de;
 pub mod ser;
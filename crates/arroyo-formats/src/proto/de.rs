@@ -0,0 +1,254 @@
+use crate::avro::de::{convert_float, encode_vec};
+use crate::de::confluent_header;
+use arroyo_types::SourceError;
+use chrono::{DateTime, SecondsFormat};
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor, Value};
+use serde_json::Value as JsonValue;
+
+pub(crate) fn deserialize_proto(
+    descriptor: &MessageDescriptor,
+    msg: &[u8],
+) -> Result<JsonValue, SourceError> {
+    let message = DynamicMessage::decode(descriptor.clone(), msg).map_err(|e| {
+        SourceError::bad_data(format!("failed to deserialize from protobuf: {:?}", e))
+    })?;
+
+    Ok(proto_to_json(&message))
+}
+
+/// Reads the header of a message encoded in the Confluent Schema Registry protobuf wire format,
+/// returning the schema id, the message indexes that identify which message type in the schema
+/// was used to write it, and the remaining payload
+pub(crate) fn read_confluent_header(msg: &[u8]) -> Result<(u32, Vec<usize>, &[u8]), SourceError> {
+    let (id, mut msg) = confluent_header(msg)?;
+
+    let count = read_zigzag_varint(&mut msg)?;
+    let indexes = if count == 0 {
+        // the common case of the first message in the file is encoded as a single 0
+        vec![0]
+    } else {
+        (0..count)
+            .map(|_| read_zigzag_varint(&mut msg).map(|i| i as usize))
+            .collect::<Result<_, _>>()?
+    };
+
+    Ok((id, indexes, msg))
+}
+
+fn read_zigzag_varint(msg: &mut &[u8]) -> Result<u32, SourceError> {
+    let mut value: u64 = 0;
+    for (i, b) in msg.iter().enumerate().take(5) {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            *msg = &msg[i + 1..];
+            let decoded = ((value >> 1) as i64) ^ -((value & 1) as i64);
+            return u32::try_from(decoded).map_err(|_| {
+                SourceError::bad_data(format!(
+                    "invalid message index in schema registry header: {}",
+                    decoded
+                ))
+            });
+        }
+    }
+
+    Err(SourceError::bad_data(
+        "invalid varint in schema registry protobuf header",
+    ))
+}
+
+/// Finds the message identified by a set of Confluent message indexes, which form a path
+/// through the (possibly nested) messages of the file that defines `descriptor`
+pub(crate) fn message_for_indexes(
+    descriptor: &MessageDescriptor,
+    indexes: &[usize],
+) -> Result<MessageDescriptor, SourceError> {
+    let not_found = || {
+        SourceError::bad_data(format!(
+            "message indexes {:?} do not refer to a message in the protobuf schema",
+            indexes
+        ))
+    };
+
+    let (first, rest) = indexes.split_first().ok_or_else(not_found)?;
+
+    let mut message = descriptor
+        .parent_file()
+        .messages()
+        .nth(*first)
+        .ok_or_else(not_found)?;
+
+    for i in rest {
+        message = message.child_messages().nth(*i).ok_or_else(not_found)?;
+    }
+
+    Ok(message)
+}
+
+pub(crate) fn proto_to_json(message: &DynamicMessage) -> JsonValue {
+    fields_to_json(
+        message,
+        &mut vec![message.descriptor().full_name().to_string()],
+    )
+}
+
+/// Converts a protobuf message into JSON matching the arrow schema computed by
+/// `schema::protobuf_to_arrow`; this differs from the canonical protobuf JSON mapping in
+/// that it uses the original field names, includes default values, and encodes
+/// fields that we represent as JSON as strings.
+fn fields_to_json(message: &DynamicMessage, parents: &mut Vec<String>) -> JsonValue {
+    JsonValue::Object(
+        message
+            .descriptor()
+            .fields()
+            .map(|field| {
+                let value = if field.supports_presence() && !message.has_field(&field) {
+                    JsonValue::Null
+                } else {
+                    field_to_json(&field, &message.get_field(&field), parents)
+                };
+
+                (field.name().to_string(), value)
+            })
+            .collect(),
+    )
+}
+
+fn field_to_json(field: &FieldDescriptor, value: &Value, parents: &mut Vec<String>) -> JsonValue {
+    match value {
+        Value::Map(map) => {
+            let Kind::Message(entry) = field.kind() else {
+                unreachable!("map fields must have a message kind");
+            };
+            let value_kind = entry.map_entry_value_field().kind();
+
+            let object: serde_json::Map<String, JsonValue> = map
+                .iter()
+                .map(|(k, v)| (map_key_to_string(k), value_to_json(&value_kind, v, None)))
+                .collect();
+
+            JsonValue::String(JsonValue::Object(object).to_string())
+        }
+        Value::List(items) => JsonValue::Array(
+            items
+                .iter()
+                .map(|v| value_to_json(&field.kind(), v, Some(&mut *parents)))
+                .collect(),
+        ),
+        v => value_to_json(&field.kind(), v, Some(parents)),
+    }
+}
+
+fn map_key_to_string(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(i) => i.to_string(),
+        MapKey::I64(i) => i.to_string(),
+        MapKey::U32(i) => i.to_string(),
+        MapKey::U64(i) => i.to_string(),
+        MapKey::String(s) => s.clone(),
+    }
+}
+
+/// Converts a single protobuf value into JSON. `parents` holds the names of the messages
+/// enclosing the value, and is `None` when the value is part of a field that is itself
+/// represented as JSON, in which case messages are embedded directly rather than as strings.
+fn value_to_json(kind: &Kind, value: &Value, mut parents: Option<&mut Vec<String>>) -> JsonValue {
+    match value {
+        Value::Bool(b) => JsonValue::Bool(*b),
+        Value::I32(i) => JsonValue::Number((*i).into()),
+        Value::I64(i) => JsonValue::Number((*i).into()),
+        Value::U32(i) => JsonValue::Number((*i).into()),
+        Value::U64(i) => JsonValue::Number((*i).into()),
+        Value::F32(f) => convert_float(*f as f64),
+        Value::F64(f) => convert_float(*f),
+        Value::String(s) => JsonValue::String(s.clone()),
+        Value::Bytes(b) => encode_vec(b.to_vec()),
+        Value::EnumNumber(n) => match kind {
+            Kind::Enum(e) => e
+                .get_value(*n)
+                .map(|v| JsonValue::String(v.name().to_string()))
+                .unwrap_or_else(|| JsonValue::String(n.to_string())),
+            _ => JsonValue::Number((*n).into()),
+        },
+        Value::Message(m) => message_to_json(m, parents),
+        Value::List(items) => JsonValue::Array(
+            items
+                .iter()
+                .map(|v| value_to_json(kind, v, parents.as_deref_mut()))
+                .collect(),
+        ),
+        Value::Map(map) => JsonValue::Object(
+            map.iter()
+                .map(|(k, v)| (map_key_to_string(k), value_to_json(kind, v, None)))
+                .collect(),
+        ),
+    }
+}
+
+fn message_to_json(message: &DynamicMessage, parents: Option<&mut Vec<String>>) -> JsonValue {
+    let get = |name: &str| {
+        message
+            .get_field_by_name(name)
+            .map(|v| v.into_owned())
+            .unwrap_or_else(|| Value::I64(0))
+    };
+
+    match message.descriptor().full_name() {
+        "google.protobuf.Timestamp" => {
+            let seconds = get("seconds").as_i64().unwrap_or_default();
+            let nanos = get("nanos").as_i32().unwrap_or_default();
+
+            DateTime::from_timestamp(seconds, nanos as u32)
+                .map(|t| JsonValue::String(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+                .unwrap_or(JsonValue::Null)
+        }
+        "google.protobuf.DoubleValue"
+        | "google.protobuf.FloatValue"
+        | "google.protobuf.Int64Value"
+        | "google.protobuf.UInt64Value"
+        | "google.protobuf.Int32Value"
+        | "google.protobuf.UInt32Value"
+        | "google.protobuf.BoolValue"
+        | "google.protobuf.StringValue"
+        | "google.protobuf.BytesValue" => {
+            let field = message
+                .descriptor()
+                .get_field_by_name("value")
+                .expect("wrapper types have a value field");
+            value_to_json(&field.kind(), &message.get_field(&field), parents)
+        }
+        name => {
+            let Some(parents) = parents else {
+                // we're inside of a value that's represented as json, so use the canonical
+                // protobuf json encoding
+                return serde_json::to_value(message).unwrap_or(JsonValue::Null);
+            };
+
+            // these need to be kept in sync with the json-encoded fields in schema::kind_to_arrow
+            let as_json = matches!(
+                name,
+                "google.protobuf.Duration"
+                    | "google.protobuf.Struct"
+                    | "google.protobuf.Value"
+                    | "google.protobuf.ListValue"
+                    | "google.protobuf.Any"
+                    | "google.protobuf.FieldMask"
+                    | "google.protobuf.Empty"
+            ) || message.descriptor().fields().next().is_none()
+                || parents.iter().any(|p| p == name);
+
+            if as_json {
+                JsonValue::String(
+                    serde_json::to_value(message)
+                        .unwrap_or(JsonValue::Null)
+                        .to_string(),
+                )
+            } else {
+                parents.push(name.to_string());
+                let json = fields_to_json(message, parents);
+                parents.pop();
+                json
+            }
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod de;
+pub mod schema;
+pub mod ser;
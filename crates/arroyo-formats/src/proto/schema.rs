@@ -0,0 +1,450 @@
+use anyhow::{anyhow, bail};
+use arrow_schema::{DataType, Field, Fields, TimeUnit};
+use arroyo_types::ArroyoExtensionType;
+use prost_reflect::{DescriptorPool, FieldDescriptor, Kind, MessageDescriptor};
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The name under which user-provided schemas are compiled
+pub const SCHEMA_FILE_NAME: &str = "arroyo_schema.proto";
+
+/// Resolves a single in-memory proto file, used to compile schemas that are provided as text
+struct InMemoryFileResolver {
+    name: String,
+    source: String,
+}
+
+impl FileResolver for InMemoryFileResolver {
+    fn resolve_path(&self, path: &Path) -> Option<String> {
+        (path.to_str()? == self.name).then(|| self.name.clone())
+    }
+
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        if name == self.name {
+            File::from_source(name, &self.source)
+        } else {
+            Err(protox::Error::file_not_found(name))
+        }
+    }
+}
+
+/// Compiles the text of a .proto file into an encoded FileDescriptorSet, which contains
+/// the descriptors for the file and all of its imports. The well-known types under
+/// `google/protobuf` may be imported.
+pub fn schema_file_to_descriptor(schema: &str) -> anyhow::Result<Vec<u8>> {
+    schema_file_to_descriptor_with_dependencies(schema, &[])
+}
+
+/// Compiles the text of a .proto file along with a set of (name, source) dependencies that
+/// it may import
+pub fn schema_file_to_descriptor_with_dependencies(
+    schema: &str,
+    dependencies: &[(String, String)],
+) -> anyhow::Result<Vec<u8>> {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(InMemoryFileResolver {
+        name: SCHEMA_FILE_NAME.to_string(),
+        source: schema.to_string(),
+    });
+    for (name, source) in dependencies {
+        resolver.add(InMemoryFileResolver {
+            name: name.clone(),
+            source: source.clone(),
+        });
+    }
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = protox::Compiler::with_file_resolver(resolver);
+    compiler.include_imports(true);
+    compiler
+        .open_file(SCHEMA_FILE_NAME)
+        .map_err(|e| anyhow!("invalid protobuf schema: {}", e))?;
+
+    Ok(compiler.encode_file_descriptor_set())
+}
+
+/// Finds the descriptor for the message we're reading or writing in an encoded
+/// FileDescriptorSet. If no message name is provided, the first message defined in the
+/// root schema file is used.
+pub fn get_message_descriptor(
+    compiled_schema: &[u8],
+    message_name: Option<&str>,
+) -> anyhow::Result<MessageDescriptor> {
+    let pool = DescriptorPool::decode(compiled_schema)
+        .map_err(|e| anyhow!("invalid compiled protobuf schema: {}", e))?;
+
+    match message_name {
+        Some(name) => pool
+            .get_message_by_name(name)
+            .ok_or_else(|| anyhow!("message '{}' not found in protobuf schema", name)),
+        None => pool
+            .files()
+            .last()
+            .and_then(|f| f.messages().next())
+            .ok_or_else(|| anyhow!("protobuf schema does not define any messages")),
+    }
+}
+
+/// Computes an arrow schema from a protobuf message descriptor
+pub fn protobuf_to_arrow(descriptor: &MessageDescriptor) -> anyhow::Result<arrow_schema::Schema> {
+    let fields = message_fields(descriptor, &mut vec![descriptor.full_name().to_string()]);
+
+    if fields.is_empty() {
+        bail!(
+            "protobuf message '{}' does not have any fields",
+            descriptor.full_name()
+        );
+    }
+
+    Ok(arrow_schema::Schema::new(fields))
+}
+
+fn message_fields(descriptor: &MessageDescriptor, parents: &mut Vec<String>) -> Fields {
+    descriptor
+        .fields()
+        .map(|f| Arc::new(field_to_arrow(&f, parents)))
+        .collect()
+}
+
+fn field_to_arrow(field: &FieldDescriptor, parents: &mut Vec<String>) -> Field {
+    // maps don't have a good representation in SQL, so we store them as JSON
+    let (dt, extension) = if field.is_map() {
+        (DataType::Utf8, Some(ArroyoExtensionType::JSON))
+    } else {
+        kind_to_arrow(&field.kind(), parents)
+    };
+
+    if field.is_list() {
+        let item = ArroyoExtensionType::add_metadata(extension, Field::new("item", dt, false));
+        return Field::new(field.name(), DataType::List(Arc::new(item)), true);
+    }
+
+    // in proto3 only messages, oneof members and `optional` scalars track presence; all other
+    // fields take their default value when unset
+    let nullable = field.is_map() || field.supports_presence();
+
+    ArroyoExtensionType::add_metadata(extension, Field::new(field.name(), dt, nullable))
+}
+
+fn kind_to_arrow(
+    kind: &Kind,
+    parents: &mut Vec<String>,
+) -> (DataType, Option<ArroyoExtensionType>) {
+    let dt = match kind {
+        Kind::Double => DataType::Float64,
+        Kind::Float => DataType::Float32,
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => DataType::Int32,
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => DataType::Int64,
+        Kind::Uint32 | Kind::Fixed32 => DataType::UInt32,
+        Kind::Uint64 | Kind::Fixed64 => DataType::UInt64,
+        Kind::Bool => DataType::Boolean,
+        Kind::String | Kind::Enum(_) => DataType::Utf8,
+        Kind::Bytes => DataType::Binary,
+        Kind::Message(message) => {
+            if let Some(dt) = well_known_to_arrow(message.full_name()) {
+                return dt;
+            }
+
+            // recursive messages can't be represented as arrow structs
+            if parents.iter().any(|p| p == message.full_name()) {
+                return (DataType::Utf8, Some(ArroyoExtensionType::JSON));
+            }
+
+            parents.push(message.full_name().to_string());
+            let fields = message_fields(message, parents);
+            parents.pop();
+
+            if fields.is_empty() {
+                return (DataType::Utf8, Some(ArroyoExtensionType::JSON));
+            }
+
+            DataType::Struct(fields)
+        }
+    };
+
+    (dt, None)
+}
+
+fn well_known_to_arrow(name: &str) -> Option<(DataType, Option<ArroyoExtensionType>)> {
+    let dt = match name {
+        "google.protobuf.Timestamp" => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        "google.protobuf.DoubleValue" => DataType::Float64,
+        "google.protobuf.FloatValue" => DataType::Float32,
+        "google.protobuf.Int64Value" => DataType::Int64,
+        "google.protobuf.UInt64Value" => DataType::UInt64,
+        "google.protobuf.Int32Value" => DataType::Int32,
+        "google.protobuf.UInt32Value" => DataType::UInt32,
+        "google.protobuf.BoolValue" => DataType::Boolean,
+        "google.protobuf.StringValue" => DataType::Utf8,
+        "google.protobuf.BytesValue" => DataType::Binary,
+        "google.protobuf.Duration"
+        | "google.protobuf.Struct"
+        | "google.protobuf.Value"
+        | "google.protobuf.ListValue"
+        | "google.protobuf.Any"
+        | "google.protobuf.FieldMask"
+        | "google.protobuf.Empty" => {
+            return Some((DataType::Utf8, Some(ArroyoExtensionType::JSON)));
+        }
+        _ => return None,
+    };
+
+    Some((dt, None))
+}
+
+/// Computes a proto3 schema (as the text of a .proto file) from an arrow schema, which is used
+/// when writing to a protobuf sink that doesn't have a user-provided schema. Fails if any of the
+/// fields have a type that can't be represented in protobuf.
+pub fn arrow_to_protobuf(name: &str, fields: &Fields) -> anyhow::Result<String> {
+    let mut out = String::new();
+    out.push_str("syntax = \"proto3\";\n\n");
+    out.push_str("package arroyo;\n\n");
+    out.push_str("import \"google/protobuf/timestamp.proto\";\n\n");
+    write_message(&mut out, name, fields, 0)?;
+    Ok(out)
+}
+
+fn write_message(
+    out: &mut String,
+    name: &str,
+    fields: &Fields,
+    indent: usize,
+) -> anyhow::Result<()> {
+    let pad = "  ".repeat(indent);
+    out.push_str(&format!("{pad}message {name} {{\n"));
+
+    for (i, field) in fields.iter().enumerate() {
+        let field_name = sanitize_field(field.name());
+        let (label, typ) = match field.data_type() {
+            DataType::List(item) => {
+                let typ = field_type(out, &field_name, item, indent + 1)?;
+                ("repeated ", typ)
+            }
+            _ => {
+                let typ = field_type(out, &field_name, field, indent + 1)?;
+                let label = if field.is_nullable()
+                    && !matches!(
+                        field.data_type(),
+                        DataType::Struct(_) | DataType::Timestamp(..)
+                    ) {
+                    "optional "
+                } else {
+                    ""
+                };
+                (label, typ)
+            }
+        };
+
+        out.push_str(&format!("{pad}  {label}{typ} {field_name} = {};\n", i + 1));
+    }
+
+    out.push_str(&format!("{pad}}}\n"));
+    Ok(())
+}
+
+fn field_type(
+    out: &mut String,
+    name: &str,
+    field: &Field,
+    indent: usize,
+) -> anyhow::Result<String> {
+    let typ = match field.data_type() {
+        DataType::Boolean => "bool",
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => "int32",
+        DataType::Int64 => "int64",
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 => "uint32",
+        DataType::UInt64 => "uint64",
+        DataType::Float16 | DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Utf8 | DataType::LargeUtf8 => "string",
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => "bytes",
+        DataType::Timestamp(_, _) => "google.protobuf.Timestamp",
+        DataType::Date32 => "int32",
+        DataType::Date64 => "int64",
+        DataType::Struct(fields) => {
+            let message_name = format!("{}Message", name);
+            write_message(out, &message_name, fields, indent)?;
+            return Ok(message_name);
+        }
+        DataType::List(_) | DataType::LargeList(_) | DataType::FixedSizeList(_, _) => {
+            bail!(
+                "field '{}' is a nested list, which is not supported in protobuf",
+                name
+            )
+        }
+        dt => bail!(
+            "field '{}' has type {:?}, which is not supported in protobuf",
+            name,
+            dt
+        ),
+    };
+
+    Ok(typ.to_string())
+}
+
+pub(crate) fn sanitize_field(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        syntax = "proto3";
+
+        package test;
+
+        import "google/protobuf/timestamp.proto";
+        import "google/protobuf/wrappers.proto";
+        import "google/protobuf/struct.proto";
+
+        message Order {
+          int64 id = 1;
+          string customer = 2;
+          repeated Item items = 3;
+          google.protobuf.Timestamp created_at = 4;
+          google.protobuf.StringValue coupon = 5;
+          oneof payment {
+            string card = 6;
+            string account = 7;
+          }
+          map<string, string> tags = 8;
+          Status status = 9;
+          google.protobuf.Struct extra = 10;
+          optional double discount = 11;
+          Order parent = 12;
+        }
+
+        message Item {
+          string name = 1;
+          uint32 quantity = 2;
+          bytes data = 3;
+        }
+
+        enum Status {
+          UNKNOWN = 0;
+          PLACED = 1;
+          SHIPPED = 2;
+        }
+    "#;
+
+    #[test]
+    fn test_protobuf_to_arrow() {
+        let compiled = schema_file_to_descriptor(SCHEMA).unwrap();
+        let descriptor = get_message_descriptor(&compiled, None).unwrap();
+        assert_eq!(descriptor.full_name(), "test.Order");
+
+        let schema = protobuf_to_arrow(&descriptor).unwrap();
+
+        let id = schema.field_with_name("id").unwrap();
+        assert_eq!(id.data_type(), &DataType::Int64);
+        assert!(!id.is_nullable());
+
+        let items = schema.field_with_name("items").unwrap();
+        let DataType::List(item) = items.data_type() else {
+            panic!("items should be a list");
+        };
+        let DataType::Struct(item_fields) = item.data_type() else {
+            panic!("items should be a list of structs");
+        };
+        assert_eq!(item_fields.len(), 3);
+        assert_eq!(item_fields[1].data_type(), &DataType::UInt32);
+        assert_eq!(item_fields[2].data_type(), &DataType::Binary);
+
+        assert_eq!(
+            schema.field_with_name("created_at").unwrap().data_type(),
+            &DataType::Timestamp(TimeUnit::Nanosecond, None)
+        );
+
+        let coupon = schema.field_with_name("coupon").unwrap();
+        assert_eq!(coupon.data_type(), &DataType::Utf8);
+        assert!(coupon.is_nullable());
+
+        assert!(schema.field_with_name("card").unwrap().is_nullable());
+        assert!(schema.field_with_name("account").unwrap().is_nullable());
+
+        // maps, Struct and recursive references are represented as json
+        for json_field in ["tags", "extra", "parent"] {
+            assert_eq!(
+                ArroyoExtensionType::from_map(
+                    schema.field_with_name(json_field).unwrap().metadata()
+                ),
+                Some(ArroyoExtensionType::JSON)
+            );
+        }
+
+        assert_eq!(
+            schema.field_with_name("status").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        assert!(schema.field_with_name("discount").unwrap().is_nullable());
+    }
+
+    #[test]
+    fn test_arrow_to_protobuf() {
+        let fields: Fields = vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, false))),
+                true,
+            ),
+            Field::new(
+                "inner",
+                DataType::Struct(vec![Field::new("x", DataType::Float64, false)].into()),
+                true,
+            ),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, None), false),
+        ]
+        .into();
+
+        let schema = arrow_to_protobuf("ArroyoProto", &fields).unwrap();
+        let compiled = schema_file_to_descriptor(&schema).unwrap();
+        let descriptor = get_message_descriptor(&compiled, Some("arroyo.ArroyoProto")).unwrap();
+
+        let round_tripped = protobuf_to_arrow(&descriptor).unwrap();
+        assert_eq!(round_tripped.fields().len(), 5);
+        assert!(round_tripped.field_with_name("name").unwrap().is_nullable());
+        assert!(matches!(
+            round_tripped.field_with_name("tags").unwrap().data_type(),
+            DataType::List(_)
+        ));
+        assert_eq!(
+            round_tripped.field_with_name("ts").unwrap().data_type(),
+            &DataType::Timestamp(TimeUnit::Nanosecond, None)
+        );
+    }
+
+    #[test]
+    fn test_arrow_to_protobuf_unsupported() {
+        let nested_list = DataType::List(Arc::new(Field::new(
+            "item",
+            DataType::List(Arc::new(Field::new("item", DataType::Int64, false))),
+            false,
+        )));
+
+        for (dt, message) in [
+            (
+                nested_list,
+                "field 'f' is a nested list, which is not supported in protobuf",
+            ),
+            (
+                DataType::Decimal128(10, 2),
+                "field 'f' has type Decimal128(10, 2), which is not supported in protobuf",
+            ),
+        ] {
+            let fields: Fields = vec![Field::new("f", dt, true)].into();
+            assert_eq!(
+                arrow_to_protobuf("ArroyoProto", &fields)
+                    .unwrap_err()
+                    .to_string(),
+                message
+            );
+        }
+    }
+}
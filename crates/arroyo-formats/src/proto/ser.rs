@@ -0,0 +1,353 @@
+use crate::proto::schema::sanitize_field;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Date32Type, Date64Type, Float16Type, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type,
+    Int8Type, TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+    TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Fields, TimeUnit};
+use prost::Message;
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor, Value};
+
+/// A single arrow value, prior to conversion into the protobuf type of the target field
+enum ArrowValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    TimestampNanos(i64),
+}
+
+/// Serializes each row of the batch as a protobuf message; columns are matched to fields
+/// in the descriptor by name, and columns without a corresponding field are ignored
+pub fn serialize(descriptor: &MessageDescriptor, batch: &RecordBatch) -> Vec<Vec<u8>> {
+    (0..batch.num_rows())
+        .map(|row| {
+            let mut message = DynamicMessage::new(descriptor.clone());
+            write_fields(&mut message, batch.schema().fields(), batch.columns(), row);
+            message.encode_to_vec()
+        })
+        .collect()
+}
+
+/// Computes the header for a message in the Confluent Schema Registry protobuf wire format,
+/// which consists of the magic byte, the schema id, and the indexes of the message within the
+/// schema
+pub fn confluent_header(schema_id: u32, descriptor: &MessageDescriptor) -> Vec<u8> {
+    let mut header = vec![0];
+    header.extend(schema_id.to_be_bytes());
+
+    let indexes = message_indexes(descriptor);
+    if indexes == [0] {
+        // the first message in the file is encoded as a single 0 rather than an array
+        header.push(0);
+    } else {
+        write_zigzag_varint(&mut header, indexes.len() as i64);
+        for i in indexes {
+            write_zigzag_varint(&mut header, i as i64);
+        }
+    }
+
+    header
+}
+
+/// Returns the path of indexes that identifies a message within the file that defines it,
+/// starting from the top-level messages and descending through nested messages
+fn message_indexes(descriptor: &MessageDescriptor) -> Vec<usize> {
+    let mut indexes = vec![];
+    let mut current = descriptor.clone();
+
+    loop {
+        let parent = current.parent_message();
+        let siblings: Vec<_> = match &parent {
+            Some(parent) => parent.child_messages().collect(),
+            None => current.parent_file().messages().collect(),
+        };
+
+        indexes.push(
+            siblings
+                .iter()
+                .position(|m| m.full_name() == current.full_name())
+                .expect("message must be defined in its parent"),
+        );
+
+        match parent {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+
+    indexes.reverse();
+    indexes
+}
+
+fn write_zigzag_varint(buf: &mut Vec<u8>, value: i64) {
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    while v >= 0x80 {
+        buf.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn write_fields(message: &mut DynamicMessage, fields: &Fields, columns: &[ArrayRef], row: usize) {
+    for (field, column) in fields.iter().zip(columns) {
+        if column.is_null(row) {
+            continue;
+        }
+
+        let descriptor = message.descriptor();
+        let Some(field_descriptor) = descriptor
+            .get_field_by_name(field.name())
+            .or_else(|| descriptor.get_field_by_name(&sanitize_field(field.name())))
+        else {
+            continue;
+        };
+
+        if let Some(value) = column_to_proto(column, row, &field_descriptor) {
+            message.set_field(&field_descriptor, value);
+        }
+    }
+}
+
+fn column_to_proto(column: &ArrayRef, row: usize, field: &FieldDescriptor) -> Option<Value> {
+    if field.is_map() {
+        // maps are stored as json objects
+        let Kind::Message(entry) = field.kind() else {
+            unreachable!("map fields must have a message kind");
+        };
+        let ArrowValue::String(json) = arrow_value(column, row)? else {
+            return None;
+        };
+        let serde_json::Value::Object(object) = serde_json::from_str(&json).ok()? else {
+            return None;
+        };
+
+        let key_kind = entry.map_entry_key_field().kind();
+        let value_field = entry.map_entry_value_field();
+        return Some(Value::Map(
+            object
+                .into_iter()
+                .filter_map(|(k, v)| {
+                    Some((
+                        map_key(&key_kind, k)?,
+                        json_to_proto(&value_field.kind(), v)?,
+                    ))
+                })
+                .collect(),
+        ));
+    }
+
+    if field.is_list() {
+        let list = match column.data_type() {
+            DataType::List(_) => column.as_list::<i32>().value(row),
+            DataType::LargeList(_) => column.as_list::<i64>().value(row),
+            _ => return None,
+        };
+
+        return Some(Value::List(
+            (0..list.len())
+                .filter(|i| !list.is_null(*i))
+                .filter_map(|i| value_to_proto(&list, i, &field.kind()))
+                .collect(),
+        ));
+    }
+
+    value_to_proto(column, row, &field.kind())
+}
+
+fn value_to_proto(column: &ArrayRef, row: usize, kind: &Kind) -> Option<Value> {
+    match kind {
+        Kind::Message(message) => message_to_proto(column, row, message),
+        Kind::Enum(e) => match arrow_value(column, row)? {
+            ArrowValue::String(s) => e
+                .get_value_by_name(&s)
+                .map(|v| Value::EnumNumber(v.number())),
+            ArrowValue::Int(i) => Some(Value::EnumNumber(i as i32)),
+            ArrowValue::UInt(i) => Some(Value::EnumNumber(i as i32)),
+            _ => None,
+        },
+        kind => coerce(arrow_value(column, row)?, kind),
+    }
+}
+
+fn message_to_proto(
+    column: &ArrayRef,
+    row: usize,
+    descriptor: &MessageDescriptor,
+) -> Option<Value> {
+    let mut message = DynamicMessage::new(descriptor.clone());
+
+    match descriptor.full_name() {
+        "google.protobuf.Timestamp" => {
+            let ArrowValue::TimestampNanos(nanos) = arrow_value(column, row)? else {
+                return None;
+            };
+            message.set_field_by_name("seconds", Value::I64(nanos.div_euclid(1_000_000_000)));
+            message.set_field_by_name("nanos", Value::I32(nanos.rem_euclid(1_000_000_000) as i32));
+        }
+        "google.protobuf.DoubleValue"
+        | "google.protobuf.FloatValue"
+        | "google.protobuf.Int64Value"
+        | "google.protobuf.UInt64Value"
+        | "google.protobuf.Int32Value"
+        | "google.protobuf.UInt32Value"
+        | "google.protobuf.BoolValue"
+        | "google.protobuf.StringValue"
+        | "google.protobuf.BytesValue" => {
+            let field = descriptor.get_field_by_name("value")?;
+            message.set_field(&field, coerce(arrow_value(column, row)?, &field.kind())?);
+        }
+        _ => match column.data_type() {
+            DataType::Struct(fields) => {
+                write_fields(&mut message, fields, column.as_struct().columns(), row);
+            }
+            _ => {
+                // other messages (including Struct, Any, Duration, etc.) are stored as json
+                let ArrowValue::String(json) = arrow_value(column, row)? else {
+                    return None;
+                };
+                let json: serde_json::Value = serde_json::from_str(&json).ok()?;
+                message = DynamicMessage::deserialize(descriptor.clone(), json).ok()?;
+            }
+        },
+    }
+
+    Some(Value::Message(message))
+}
+
+fn json_to_proto(kind: &Kind, value: serde_json::Value) -> Option<Value> {
+    let value = match (kind, value) {
+        (Kind::Message(descriptor), value) => {
+            return DynamicMessage::deserialize(descriptor.clone(), value)
+                .ok()
+                .map(Value::Message)
+        }
+        (Kind::Enum(e), serde_json::Value::String(s)) => {
+            return e
+                .get_value_by_name(&s)
+                .map(|v| Value::EnumNumber(v.number()))
+        }
+        (_, serde_json::Value::Bool(b)) => ArrowValue::Bool(b),
+        (_, serde_json::Value::Number(n)) => {
+            if let Some(i) = n.as_i64() {
+                ArrowValue::Int(i)
+            } else if let Some(i) = n.as_u64() {
+                ArrowValue::UInt(i)
+            } else {
+                ArrowValue::Float(n.as_f64()?)
+            }
+        }
+        (_, serde_json::Value::String(s)) => ArrowValue::String(s),
+        _ => return None,
+    };
+
+    coerce(value, kind)
+}
+
+fn map_key(kind: &Kind, key: String) -> Option<MapKey> {
+    Some(match kind {
+        Kind::Bool => MapKey::Bool(key.parse().ok()?),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => MapKey::I32(key.parse().ok()?),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => MapKey::I64(key.parse().ok()?),
+        Kind::Uint32 | Kind::Fixed32 => MapKey::U32(key.parse().ok()?),
+        Kind::Uint64 | Kind::Fixed64 => MapKey::U64(key.parse().ok()?),
+        _ => MapKey::String(key),
+    })
+}
+
+/// Converts an arrow value into a protobuf value of the given kind, casting between numeric
+/// types as needed
+fn coerce(value: ArrowValue, kind: &Kind) -> Option<Value> {
+    let as_i64 = |v: &ArrowValue| match v {
+        ArrowValue::Int(i) | ArrowValue::TimestampNanos(i) => Some(*i),
+        ArrowValue::UInt(i) => Some(*i as i64),
+        ArrowValue::Float(f) => Some(*f as i64),
+        ArrowValue::Bool(b) => Some(*b as i64),
+        ArrowValue::String(s) => s.parse().ok(),
+        ArrowValue::Bytes(_) => None,
+    };
+
+    let as_f64 = |v: &ArrowValue| match v {
+        ArrowValue::Float(f) => Some(*f),
+        ArrowValue::String(s) => s.parse().ok(),
+        v => as_i64(v).map(|i| i as f64),
+    };
+
+    Some(match kind {
+        Kind::Double => Value::F64(as_f64(&value)?),
+        Kind::Float => Value::F32(as_f64(&value)? as f32),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => Value::I32(as_i64(&value)? as i32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Value::I64(as_i64(&value)?),
+        Kind::Uint32 | Kind::Fixed32 => Value::U32(match value {
+            ArrowValue::UInt(i) => i as u32,
+            v => as_i64(&v)? as u32,
+        }),
+        Kind::Uint64 | Kind::Fixed64 => Value::U64(match value {
+            ArrowValue::UInt(i) => i,
+            v => as_i64(&v)? as u64,
+        }),
+        Kind::Bool => Value::Bool(match value {
+            ArrowValue::Bool(b) => b,
+            ArrowValue::String(s) => s.parse().ok()?,
+            v => as_i64(&v)? != 0,
+        }),
+        Kind::String => Value::String(match value {
+            ArrowValue::String(s) => s,
+            ArrowValue::Bytes(b) => String::from_utf8(b).ok()?,
+            ArrowValue::Bool(b) => b.to_string(),
+            ArrowValue::Int(i) | ArrowValue::TimestampNanos(i) => i.to_string(),
+            ArrowValue::UInt(i) => i.to_string(),
+            ArrowValue::Float(f) => f.to_string(),
+        }),
+        Kind::Bytes => Value::Bytes(match value {
+            ArrowValue::Bytes(b) => b.into(),
+            ArrowValue::String(s) => s.into_bytes().into(),
+            _ => return None,
+        }),
+        Kind::Message(_) | Kind::Enum(_) => return None,
+    })
+}
+
+fn arrow_value(column: &ArrayRef, row: usize) -> Option<ArrowValue> {
+    Some(match column.data_type() {
+        DataType::Boolean => ArrowValue::Bool(column.as_boolean().value(row)),
+        DataType::Int8 => ArrowValue::Int(column.as_primitive::<Int8Type>().value(row) as i64),
+        DataType::Int16 => ArrowValue::Int(column.as_primitive::<Int16Type>().value(row) as i64),
+        DataType::Int32 => ArrowValue::Int(column.as_primitive::<Int32Type>().value(row) as i64),
+        DataType::Int64 => ArrowValue::Int(column.as_primitive::<Int64Type>().value(row)),
+        DataType::UInt8 => ArrowValue::UInt(column.as_primitive::<UInt8Type>().value(row) as u64),
+        DataType::UInt16 => ArrowValue::UInt(column.as_primitive::<UInt16Type>().value(row) as u64),
+        DataType::UInt32 => ArrowValue::UInt(column.as_primitive::<UInt32Type>().value(row) as u64),
+        DataType::UInt64 => ArrowValue::UInt(column.as_primitive::<UInt64Type>().value(row)),
+        DataType::Float16 => {
+            ArrowValue::Float(column.as_primitive::<Float16Type>().value(row).to_f64())
+        }
+        DataType::Float32 => {
+            ArrowValue::Float(column.as_primitive::<Float32Type>().value(row) as f64)
+        }
+        DataType::Float64 => ArrowValue::Float(column.as_primitive::<Float64Type>().value(row)),
+        DataType::Date32 => ArrowValue::Int(column.as_primitive::<Date32Type>().value(row) as i64),
+        DataType::Date64 => ArrowValue::Int(column.as_primitive::<Date64Type>().value(row)),
+        DataType::Utf8 => ArrowValue::String(column.as_string::<i32>().value(row).to_string()),
+        DataType::LargeUtf8 => ArrowValue::String(column.as_string::<i64>().value(row).to_string()),
+        DataType::Binary => ArrowValue::Bytes(column.as_binary::<i32>().value(row).to_vec()),
+        DataType::LargeBinary => ArrowValue::Bytes(column.as_binary::<i64>().value(row).to_vec()),
+        DataType::Timestamp(unit, _) => ArrowValue::TimestampNanos(match unit {
+            TimeUnit::Second => {
+                column.as_primitive::<TimestampSecondType>().value(row) * 1_000_000_000
+            }
+            TimeUnit::Millisecond => {
+                column.as_primitive::<TimestampMillisecondType>().value(row) * 1_000_000
+            }
+            TimeUnit::Microsecond => {
+                column.as_primitive::<TimestampMicrosecondType>().value(row) * 1_000
+            }
+            TimeUnit::Nanosecond => column.as_primitive::<TimestampNanosecondType>().value(row),
+        }),
+        _ => return None,
+    })
+}
@@ -1,30 +1,56 @@
 use crate::avro::schema;
-use crate::{avro, json};
+use crate::{avro, csv, json, proto};
 use arrow_array::cast::AsArray;
 use arrow_array::types::GenericBinaryType;
 use arrow_array::RecordBatch;
 use arrow_json::writer::record_batch_to_vec;
 use arrow_schema::{DataType, Field};
 use arroyo_rpc::formats::{
-    AvroFormat, Format, JsonFormat, RawBytesFormat, RawStringFormat, TimestampFormat,
+    AvroFormat, Format, Framing, FramingMethod, JsonFormat, LengthPrefix, ProtobufFormat,
+    RawBytesFormat, RawStringFormat, TimestampFormat,
 };
 use arroyo_rpc::TIMESTAMP_FIELD;
+use prost_reflect::MessageDescriptor;
 use serde_json::Value;
 use std::sync::Arc;
 
 pub struct ArrowSerializer {
     kafka_schema: Option<Value>,
     avro_schema: Option<Arc<apache_avro::schema::Schema>>,
+    proto_descriptor: Option<MessageDescriptor>,
     format: Format,
+    framing: Option<Framing>,
     projection: Vec<usize>,
 }
 
 impl ArrowSerializer {
     pub fn new(format: Format) -> Self {
+        Self::with_framing(format, None)
+    }
+
+    /// Creates a serializer that, if `framing` is set, combines the rows of each batch into a
+    /// single framed payload rather than producing one message per row
+    pub fn with_framing(format: Format, framing: Option<Framing>) -> Self {
+        let proto_descriptor = if let Format::Protobuf(ProtobufFormat {
+            compiled_schema: Some(compiled_schema),
+            message_name,
+            ..
+        }) = &format
+        {
+            Some(
+                proto::schema::get_message_descriptor(compiled_schema, message_name.as_deref())
+                    .expect("invalid compiled protobuf schema"),
+            )
+        } else {
+            None
+        };
+
         Self {
             kafka_schema: None,
             avro_schema: None,
+            proto_descriptor,
             format,
+            framing,
             projection: vec![],
         }
     }
@@ -59,6 +85,17 @@ impl ArrowSerializer {
         json::arrow_to_kafka_json("ArroyoJson", &Self::projected_schema(schema).into())
     }
 
+    /// Generates a protobuf schema for the schema, failing if it has fields that can't be
+    /// represented in protobuf
+    pub fn protobuf_schema(schema: &arrow_schema::Schema) -> anyhow::Result<String> {
+        proto::schema::arrow_to_protobuf("ArroyoProto", &Self::projected_schema(schema).into())
+    }
+
+    fn protobuf_descriptor(schema: &arrow_schema::Schema) -> anyhow::Result<MessageDescriptor> {
+        let compiled = proto::schema::schema_file_to_descriptor(&Self::protobuf_schema(schema)?)?;
+        proto::schema::get_message_descriptor(&compiled, Some("arroyo.ArroyoProto"))
+    }
+
     pub fn serialize(&mut self, batch: &RecordBatch) -> Box<dyn Iterator<Item = Vec<u8>> + Send> {
         if self.projection.is_empty() {
             self.projection = Self::projection(&batch.schema());
@@ -72,16 +109,31 @@ impl ArrowSerializer {
             self.avro_schema = Some(Arc::new(Self::avro_schema(&batch.schema())));
         }
 
+        if matches!(self.format, Format::Protobuf(_)) && self.proto_descriptor.is_none() {
+            // unsupported types are rejected when the sink is planned
+            self.proto_descriptor = Some(
+                Self::protobuf_descriptor(&batch.schema())
+                    .expect("sink schema can't be represented in protobuf"),
+            );
+        }
+
         let batch = batch
             .project(&self.projection)
             .expect("batch has wrong number of columns");
 
-        match &self.format {
+        let rows = match &self.format {
             Format::Json(json) => self.serialize_json(json, &batch),
             Format::Avro(avro) => self.serialize_avro(avro, &batch),
+            Format::Protobuf(proto) => self.serialize_protobuf(proto, &batch),
+            Format::Csv(format) => Box::new(csv::ser::serialize(format, &batch).into_iter()),
             Format::Parquet(_) => todo!("parquet"),
             Format::RawString(RawStringFormat {}) => self.serialize_raw_string(&batch),
             Format::RawBytes(RawBytesFormat {}) => self.serialize_raw_bytes(&batch),
+        };
+
+        match &self.framing {
+            Some(framing) => Box::new(frame(&framing.method, rows).into_iter()),
+            None => rows,
         }
     }
 
@@ -101,14 +153,20 @@ impl ArrowSerializer {
             v
         });
 
-        let rows = record_batch_to_vec(
-            batch,
-            true,
-            match json.timestamp_format {
-                TimestampFormat::RFC3339 => arrow_json::writer::TimestampFormat::RFC3339,
-                TimestampFormat::UnixMillis => arrow_json::writer::TimestampFormat::UnixMillis,
-            },
-        )
+        let rows = match json.timestamp_format {
+            TimestampFormat::RFC3339 => {
+                record_batch_to_vec(batch, true, arrow_json::writer::TimestampFormat::RFC3339)
+            }
+            TimestampFormat::UnixMillis => {
+                record_batch_to_vec(batch, true, arrow_json::writer::TimestampFormat::UnixMillis)
+            }
+            _ => {
+                // the json writer doesn't support other formats, so we convert the timestamps
+                // ourselves first
+                let batch = json::timestamp::format_timestamps(json, batch);
+                record_batch_to_vec(&batch, true, arrow_json::writer::TimestampFormat::RFC3339)
+            }
+        }
         .unwrap();
 
         let include_schema = json.include_schema.then(|| self.kafka_schema.clone());
@@ -188,6 +246,36 @@ impl ArrowSerializer {
         Box::new(values.into_iter())
     }
 
+    fn serialize_protobuf(
+        &self,
+        format: &ProtobufFormat,
+        batch: &RecordBatch,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + Send> {
+        let descriptor = self
+            .proto_descriptor
+            .as_ref()
+            .expect("must have protobuf descriptor set for protobuf format");
+
+        let messages = proto::ser::serialize(descriptor, batch);
+
+        if format.confluent_schema_registry {
+            let header = proto::ser::confluent_header(
+                format
+                    .schema_id
+                    .expect("must have schema id for confluent schema registry"),
+                descriptor,
+            );
+
+            Box::new(messages.into_iter().map(move |message| {
+                let mut buf = header.clone();
+                buf.extend(message);
+                buf
+            }))
+        } else {
+            Box::new(messages.into_iter())
+        }
+    }
+
     fn serialize_avro(
         &self,
         format: &AvroFormat,
@@ -235,16 +323,169 @@ impl ArrowSerializer {
     }
 }
 
+/// Combines the serialized rows into a single payload using the framing method, or returns
+/// None if there are no rows
+fn frame(method: &FramingMethod, rows: impl Iterator<Item = Vec<u8>>) -> Option<Vec<u8>> {
+    let mut buf = vec![];
+    let mut empty = true;
+
+    for row in rows {
+        empty = false;
+        match method {
+            FramingMethod::Newline(_) => {
+                buf.extend(row);
+                buf.push(b'\n');
+            }
+            FramingMethod::LengthPrefixed(prefixed) => {
+                write_length_prefix(prefixed.prefix, row.len() as u64, &mut buf);
+                buf.extend(row);
+            }
+            FramingMethod::Delimited(delimited) => {
+                buf.extend(row);
+                buf.extend(&delimited.delimiter);
+            }
+        }
+    }
+
+    (!empty).then_some(buf)
+}
+
+fn write_length_prefix(prefix: LengthPrefix, length: u64, buf: &mut Vec<u8>) {
+    match prefix {
+        LengthPrefix::U32Be => buf.extend((length as u32).to_be_bytes()),
+        LengthPrefix::U32Le => buf.extend((length as u32).to_le_bytes()),
+        LengthPrefix::U64Be => buf.extend(length.to_be_bytes()),
+        LengthPrefix::U64Le => buf.extend(length.to_le_bytes()),
+        LengthPrefix::Varint => {
+            let mut v = length;
+            while v >= 0x80 {
+                buf.push((v as u8 & 0x7f) | 0x80);
+                v >>= 7;
+            }
+            buf.push(v as u8);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::de::{ArrowDeserializer, FramingIterator};
     use crate::ser::ArrowSerializer;
     use arrow_array::builder::TimestampNanosecondBuilder;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::Array;
     use arrow_schema::{Schema, TimeUnit};
-    use arroyo_rpc::formats::{Format, RawBytesFormat, RawStringFormat, TimestampFormat};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::{
+        BadData, CsvFormat, Format, Framing, FramingMethod, LengthPrefix, LengthPrefixedFraming,
+        ProtobufFormat, RawBytesFormat, RawStringFormat, TimestampFormat,
+    };
     use arroyo_types::to_nanos;
     use std::sync::Arc;
     use std::time::{Duration, SystemTime};
 
+    #[tokio::test]
+    async fn test_csv_round_trip() {
+        let format = CsvFormat {
+            null_value: Some("NULL".to_string()),
+            ..Default::default()
+        };
+        let mut serializer = ArrowSerializer::new(Format::Csv(format.clone()));
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("name", arrow_schema::DataType::Utf8, true),
+            arrow_schema::Field::new("count", arrow_schema::DataType::Int64, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(arrow_array::StringArray::from(vec![
+                    Some("a \"quoted\", value"),
+                    None,
+                ])),
+                Arc::new(arrow_array::Int64Array::from(vec![1, 2])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0, 0])),
+            ],
+        )
+        .unwrap();
+
+        let messages: Vec<_> = serializer.serialize(&batch).collect();
+        assert_eq!(
+            messages,
+            vec![br#""a ""quoted"", value",1"#.to_vec(), b"NULL,2".to_vec()]
+        );
+
+        let arroyo_schema = ArroyoSchema::from_schema_unkeyed(schema.clone()).unwrap();
+        let mut builders = arroyo_schema.builders();
+        let mut deserializer =
+            ArrowDeserializer::new(Format::Csv(format), arroyo_schema, None, BadData::Fail {});
+
+        for message in messages {
+            let errors = deserializer
+                .deserialize_slice(&mut builders, &message, SystemTime::now(), None)
+                .await;
+            assert!(errors.is_empty());
+        }
+
+        let result = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(
+            result.column(0).as_string::<i32>().value(0),
+            "a \"quoted\", value"
+        );
+        assert!(result.column(0).is_null(1));
+        assert_eq!(result.column(1).as_primitive::<Int64Type>().value(1), 2);
+    }
+
+    #[test]
+    fn test_framed_serialization() {
+        let framing = Framing {
+            method: FramingMethod::LengthPrefixed(LengthPrefixedFraming {
+                prefix: LengthPrefix::Varint,
+                max_length: None,
+            }),
+        };
+
+        let mut serializer = ArrowSerializer::with_framing(
+            Format::RawString(RawStringFormat {}),
+            Some(framing.clone()),
+        );
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("value", arrow_schema::DataType::Utf8, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(arrow_array::StringArray::from(vec!["a", "bc", ""])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0, 0, 0])),
+            ],
+        )
+        .unwrap();
+
+        let payloads: Vec<_> = serializer.serialize(&batch).collect();
+        assert_eq!(payloads, vec![vec![1, b'a', 2, b'b', b'c', 0]]);
+
+        let frames: Vec<_> = FramingIterator::new(Some(Arc::new(framing)), &payloads[0])
+            .map(|t| t.to_vec())
+            .collect();
+        assert_eq!(frames, vec![b"a".to_vec(), b"bc".to_vec(), vec![]]);
+
+        assert_eq!(serializer.serialize(&batch.slice(0, 0)).count(), 0);
+    }
+
     #[test]
     fn test_raw_string() {
         let mut serializer = ArrowSerializer::new(Format::RawString(RawStringFormat {}));
@@ -332,6 +573,7 @@ mod tests {
             debezium: false,
             unstructured: false,
             timestamp_format: Default::default(),
+            timezone: None,
         }));
 
         let text: Vec<_> = ["a", "b", "blah", "whatever"]
@@ -384,6 +626,7 @@ mod tests {
             debezium: false,
             unstructured: false,
             timestamp_format: TimestampFormat::UnixMillis,
+            timezone: None,
         }));
 
         let mut timestamp_array = TimestampNanosecondBuilder::new();
@@ -425,4 +668,194 @@ mod tests {
         assert_eq!(iter.next().unwrap(), br#"{"value":null}"#);
         assert_eq!(iter.next().unwrap(), br#"{"value":1712274910045}"#);
     }
+
+    #[test]
+    fn test_json_pattern_ts() {
+        let mut serializer = ArrowSerializer::new(Format::Json(arroyo_rpc::formats::JsonFormat {
+            timestamp_format: TimestampFormat::pattern("%Y-%m-%d %H:%M:%S%.3f").unwrap(),
+            timezone: Some("Europe/Berlin".to_string()),
+            ..Default::default()
+        }));
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new(
+                "value",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![
+                    Some(1612274910045331968),
+                    None,
+                    Some(1712274910045331968),
+                ])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0, 0, 0])),
+            ],
+        )
+        .unwrap();
+
+        let mut iter = serializer.serialize(&batch);
+        assert_eq!(
+            iter.next().unwrap(),
+            br#"{"value":"2021-02-02 15:08:30.045"}"#
+        );
+        assert_eq!(iter.next().unwrap(), br#"{"value":null}"#);
+        assert_eq!(
+            iter.next().unwrap(),
+            br#"{"value":"2024-04-05 01:55:10.045"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_round_trip() {
+        let mut serializer = ArrowSerializer::new(Format::Protobuf(ProtobufFormat {
+            confluent_schema_registry: false,
+            into_unstructured_json: false,
+            message_name: None,
+            compiled_schema: None,
+            definition: None,
+            schema_id: None,
+        }));
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("value", arrow_schema::DataType::Utf8, true),
+            arrow_schema::Field::new("number", arrow_schema::DataType::Int64, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(arrow_array::StringArray::from(vec![Some("a"), None])),
+                Arc::new(arrow_array::Int64Array::from(vec![1, 2])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0, 0])),
+            ],
+        )
+        .unwrap();
+
+        let messages: Vec<_> = serializer.serialize(&batch).collect();
+        assert_eq!(messages.len(), 2);
+
+        let compiled = crate::proto::schema::schema_file_to_descriptor(
+            &ArrowSerializer::protobuf_schema(&schema).unwrap(),
+        )
+        .unwrap();
+
+        let arroyo_schema = ArroyoSchema::from_schema_unkeyed(schema.clone()).unwrap();
+        let mut builders = arroyo_schema.builders();
+        let mut deserializer = ArrowDeserializer::new(
+            Format::Protobuf(ProtobufFormat {
+                confluent_schema_registry: false,
+                into_unstructured_json: false,
+                message_name: Some("arroyo.ArroyoProto".to_string()),
+                compiled_schema: Some(compiled),
+                definition: None,
+                schema_id: None,
+            }),
+            arroyo_schema,
+            None,
+            BadData::Fail {},
+        );
+
+        for message in messages {
+            let errors = deserializer
+                .deserialize_slice(&mut builders, &message, SystemTime::now(), None)
+                .await;
+            assert!(errors.is_empty());
+        }
+
+        let result = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(result.num_rows(), 2);
+        assert_eq!(result.column(0).as_string::<i32>().value(0), "a");
+        assert!(result.column(0).is_null(1));
+        assert_eq!(result.column(1).as_primitive::<Int64Type>().value(1), 2);
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_schema_registry_round_trip() {
+        let proto_schema = r#"
+            syntax = "proto3";
+            package test;
+
+            message Outer {
+                message Event {
+                    string name = 1;
+                    int64 count = 2;
+                }
+            }
+        "#;
+        let compiled = crate::proto::schema::schema_file_to_descriptor(proto_schema).unwrap();
+
+        let format = ProtobufFormat {
+            confluent_schema_registry: true,
+            into_unstructured_json: false,
+            message_name: Some("test.Outer.Event".to_string()),
+            compiled_schema: Some(compiled),
+            definition: None,
+            schema_id: Some(7),
+        };
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("name", arrow_schema::DataType::Utf8, false),
+            arrow_schema::Field::new("count", arrow_schema::DataType::Int64, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(arrow_array::StringArray::from(vec!["a"])),
+                Arc::new(arrow_array::Int64Array::from(vec![5])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0])),
+            ],
+        )
+        .unwrap();
+
+        let mut serializer = ArrowSerializer::new(Format::Protobuf(format.clone()));
+        let message = serializer.serialize(&batch).next().unwrap();
+
+        // magic byte, schema id, and message indexes [0, 0] as zigzag varints
+        assert_eq!(&message[..8], &[0, 0, 0, 0, 7, 4, 0, 0]);
+
+        let arroyo_schema = ArroyoSchema::from_schema_unkeyed(schema.clone()).unwrap();
+        let mut builders = arroyo_schema.builders();
+        let mut deserializer = ArrowDeserializer::new(
+            Format::Protobuf(format),
+            arroyo_schema,
+            None,
+            BadData::Fail {},
+        );
+
+        let errors = deserializer
+            .deserialize_slice(&mut builders, &message, SystemTime::now(), None)
+            .await;
+        assert!(errors.is_empty());
+
+        let errors = deserializer
+            .deserialize_slice(&mut builders, &message[5..], SystemTime::now(), None)
+            .await;
+        assert_eq!(errors.len(), 1);
+
+        let result = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(result.num_rows(), 1);
+        assert_eq!(result.column(0).as_string::<i32>().value(0), "a");
+        assert_eq!(result.column(1).as_primitive::<Int64Type>().value(0), 5);
+    }
 }
@@ -1,9 +1,11 @@
 use crate::operator::OperatorNode;
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use arrow::datatypes::{DataType, Schema};
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
 use arroyo_rpc::OperatorConfig;
+use async_trait::async_trait;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::value::Value;
@@ -22,6 +24,26 @@ pub struct Connection {
     pub description: String,
 }
 
+/// A piece of metadata that a connector's sources can provide for each message, which tables
+/// can select into a field with `GENERATED ALWAYS AS (metadata('<name>')) STORED`; binary metadata
+/// may also be selected into a TEXT field
+#[derive(Debug, Clone)]
+pub struct MetadataDef {
+    pub name: &'static str,
+    pub data_type: DataType,
+}
+
+/// A table in an external key-value store that can be queried by key, which is used to enrich
+/// a stream in a lookup join
+#[async_trait]
+pub trait LookupConnector: Send {
+    fn name(&self) -> String;
+
+    /// Fetches the values for a batch of keys, returning the (still serialized) value for each
+    /// key in the same order as the keys, or None if there is no value for that key
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>>;
+}
+
 #[allow(clippy::wrong_self_convention)]
 pub trait Connector: Send {
     type ProfileT: DeserializeOwned + Serialize;
@@ -44,6 +66,16 @@ pub trait Connector: Send {
 
     fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector;
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        &[]
+    }
+
+    /// Whether sources of this connector use the filters that the planner pushes down into them
+    /// (see [OperatorConfig::filters]) to skip reading data
+    fn supports_filter_pushdown(&self) -> bool {
+        false
+    }
+
     fn table_type(&self, config: Self::ProfileT, table: Self::TableT) -> ConnectionType;
 
     #[allow(unused)]
@@ -100,6 +132,18 @@ pub trait Connector: Send {
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection>;
 
+    /// Checks that a sink can write rows with the given schema, which may have been inferred
+    /// from the query that writes to it
+    #[allow(unused)]
+    fn validate_sink_schema(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        schema: &Schema,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     #[allow(unused)]
     fn make_operator(
         &self,
@@ -107,6 +151,16 @@ pub trait Connector: Send {
         table: Self::TableT,
         config: OperatorConfig,
     ) -> anyhow::Result<OperatorNode>;
+
+    #[allow(unused)]
+    fn make_lookup(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<Box<dyn LookupConnector>> {
+        bail!("{} does not support lookup tables", self.name())
+    }
 }
 #[allow(clippy::type_complexity)]
 #[allow(clippy::wrong_self_convention)]
@@ -115,6 +169,10 @@ pub trait ErasedConnector: Send {
 
     fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector;
 
+    fn metadata_defs(&self) -> &'static [MetadataDef];
+
+    fn supports_filter_pushdown(&self) -> bool;
+
     fn validate_config(&self, s: &serde_json::Value) -> Result<(), serde_json::Error>;
 
     fn validate_table(&self, s: &serde_json::Value) -> Result<(), serde_json::Error>;
@@ -173,7 +231,11 @@ pub trait ErasedConnector: Send {
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection>;
 
+    fn validate_sink_schema(&self, config: &OperatorConfig, schema: &Schema) -> anyhow::Result<()>;
+
     fn make_operator(&self, config: OperatorConfig) -> anyhow::Result<OperatorNode>;
+
+    fn make_lookup(&self, config: OperatorConfig) -> anyhow::Result<Box<dyn LookupConnector>>;
 }
 
 impl<C: Connector> ErasedConnector for C {
@@ -185,6 +247,14 @@ impl<C: Connector> ErasedConnector for C {
         self.metadata()
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        self.metadata_defs()
+    }
+
+    fn supports_filter_pushdown(&self) -> bool {
+        self.supports_filter_pushdown()
+    }
+
     fn config_description(&self, s: &serde_json::Value) -> Result<String, serde_json::Error> {
         Ok(self.config_description(self.parse_config(s)?))
     }
@@ -277,6 +347,16 @@ impl<C: Connector> ErasedConnector for C {
         )
     }
 
+    fn validate_sink_schema(&self, config: &OperatorConfig, schema: &Schema) -> anyhow::Result<()> {
+        self.validate_sink_schema(
+            self.parse_config(&config.connection)
+                .map_err(|e| anyhow!("invalid profile config for sink {}: {:?}", self.name(), e))?,
+            self.parse_table(&config.table)
+                .map_err(|e| anyhow!("invalid table config for sink {}: {:?}", self.name(), e))?,
+            schema,
+        )
+    }
+
     fn make_operator(&self, config: OperatorConfig) -> anyhow::Result<OperatorNode> {
         self.make_operator(
             self.parse_config(&config.connection).map_err(|e| {
@@ -292,4 +372,15 @@ impl<C: Connector> ErasedConnector for C {
             config,
         )
     }
+
+    fn make_lookup(&self, config: OperatorConfig) -> anyhow::Result<Box<dyn LookupConnector>> {
+        self.make_lookup(
+            self.parse_config(&config.connection).map_err(|e| {
+                anyhow!("invalid profile config for lookup {}: {:?}", self.name(), e)
+            })?,
+            self.parse_table(&config.table)
+                .map_err(|e| anyhow!("invalid table config for lookup {}: {:?}", self.name(), e))?,
+            config,
+        )
+    }
 }
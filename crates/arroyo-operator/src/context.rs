@@ -1,19 +1,22 @@
 use crate::{server_for_hash_array, RateLimiter};
-use arrow::array::{make_builder, Array, ArrayBuilder, PrimitiveArray, RecordBatch};
+use arrow::array::{
+    make_builder, Array, ArrayBuilder, ArrayRef, BinaryArray, PrimitiveArray, RecordBatch,
+    StringArray, TimestampNanosecondArray,
+};
 use arrow::compute::{partition, sort_to_indices, take};
 use arrow::datatypes::{SchemaRef, UInt64Type};
 use arroyo_formats::de::ArrowDeserializer;
 use arroyo_metrics::{register_queue_gauge, QueueGauges, TaskCounters};
-use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::df::{dead_letter_schema, ArroyoSchema};
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::{CheckpointMetadata, TableConfig, TaskCheckpointEventType};
 use arroyo_rpc::schema_resolver::SchemaResolver;
-use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp, MetadataField};
 use arroyo_state::tables::table_manager::TableManager;
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{
-    from_micros, should_flush, ArrowMessage, CheckpointBarrier, SourceError, TaskInfo, UserError,
-    Watermark,
+    from_micros, to_nanos, ArrowMessage, BatchingConfig, CheckpointBarrier, MetadataValue,
+    SourceError, TaskInfo, UserError, Watermark,
 };
 use datafusion::common::hash_utils;
 use rand::Rng;
@@ -228,8 +231,8 @@ impl ContextBuffer {
         self.buffer[0].len()
     }
 
-    pub fn should_flush(&self) -> bool {
-        should_flush(self.size(), self.created)
+    pub fn should_flush(&self, batching: &BatchingConfig) -> bool {
+        batching.should_flush(self.size(), self.created)
     }
 
     pub fn finish(self) -> RecordBatch {
@@ -251,6 +254,7 @@ pub struct ArrowContext {
     pub out_schema: Option<ArroyoSchema>,
     pub collector: ArrowCollector,
     buffer: Option<ContextBuffer>,
+    batching: BatchingConfig,
     buffered_error: Option<UserError>,
     error_rate_limiter: RateLimiter,
     deserializer: Option<ArrowDeserializer>,
@@ -283,6 +287,8 @@ pub struct ArrowCollector {
     out_schema: Option<ArroyoSchema>,
     projection: Option<Vec<usize>>,
     out_qs: Vec<Vec<BatchSender>>,
+    // indices into out_qs of the edges that carry dead letters rather than data
+    dead_letter_outputs: Vec<usize>,
     tx_queue_rem_gauges: QueueGauges,
     tx_queue_size_gauges: QueueGauges,
     tx_queue_bytes_gauges: QueueGauges,
@@ -374,27 +380,45 @@ impl ArrowCollector {
                 );
             });
 
-        for (i, out_q) in self.out_qs.iter_mut().enumerate() {
-            let partitions = repartition(&record, &out_schema.key_indices, out_q.len());
+        let keys = out_schema.key_indices.clone();
+        for i in 0..self.out_qs.len() {
+            if !self.dead_letter_outputs.contains(&i) {
+                self.send(i, &record, &keys).await;
+            }
+        }
+    }
+
+    /// Sends a batch of dead letters (with the schema from `dead_letter_schema`) to the
+    /// dead-letter outputs of this operator
+    pub async fn collect_dead_letters(&mut self, record: RecordBatch) {
+        for i in 0..self.out_qs.len() {
+            if self.dead_letter_outputs.contains(&i) {
+                self.send(i, &record, &None).await;
+            }
+        }
+    }
 
-            for (partition, batch) in partitions {
-                out_q[partition]
-                    .send(ArrowMessage::Data(batch))
-                    .await
-                    .unwrap();
+    async fn send(&mut self, i: usize, record: &RecordBatch, keys: &Option<Vec<usize>>) {
+        let out_q = &mut self.out_qs[i];
+        let partitions = repartition(record, keys, out_q.len());
 
-                self.tx_queue_rem_gauges[i][partition]
-                    .iter()
-                    .for_each(|g| g.set(out_q[partition].capacity() as i64));
+        for (partition, batch) in partitions {
+            out_q[partition]
+                .send(ArrowMessage::Data(batch))
+                .await
+                .unwrap();
 
-                self.tx_queue_size_gauges[i][partition]
-                    .iter()
-                    .for_each(|g| g.set(out_q[partition].size() as i64));
+            self.tx_queue_rem_gauges[i][partition]
+                .iter()
+                .for_each(|g| g.set(out_q[partition].capacity() as i64));
 
-                self.tx_queue_bytes_gauges[i][partition]
-                    .iter()
-                    .for_each(|g| g.set(out_q[partition].queued_bytes() as i64));
-            }
+            self.tx_queue_size_gauges[i][partition]
+                .iter()
+                .for_each(|g| g.set(out_q[partition].size() as i64));
+
+            self.tx_queue_bytes_gauges[i][partition]
+                .iter()
+                .for_each(|g| g.set(out_q[partition].queued_bytes() as i64));
         }
     }
 
@@ -493,6 +517,7 @@ impl ArrowContext {
             collector: ArrowCollector {
                 task_info: task_info.clone(),
                 out_qs,
+                dead_letter_outputs: vec![],
                 tx_queue_rem_gauges,
                 tx_queue_size_gauges,
                 tx_queue_bytes_gauges,
@@ -504,6 +529,7 @@ impl ArrowContext {
                 task_info,
             },
             buffer: out_schema.map(|t| ContextBuffer::new(t.schema)),
+            batching: BatchingConfig::default(),
             error_rate_limiter: RateLimiter::new(),
             deserializer: None,
             buffered_error: None,
@@ -540,6 +566,17 @@ impl ArrowContext {
         // (ctx, data_rx)
     }
 
+    /// Marks which of the outputs of this operator lead to a dead-letter sink; these don't
+    /// receive data, only records that the source failed to deserialize or that the sink failed
+    /// to deliver
+    pub fn set_dead_letter_outputs(&mut self, outputs: Vec<usize>) {
+        self.collector.dead_letter_outputs = outputs;
+    }
+
+    pub fn set_batching(&mut self, batching: BatchingConfig) {
+        self.batching = batching;
+    }
+
     pub fn watermark(&self) -> Option<Watermark> {
         self.watermarks.watermark()
     }
@@ -564,6 +601,7 @@ impl ArrowContext {
 
         if let Some(deserializer) = self.deserializer.as_mut() {
             if let Some(buffer) = deserializer.flush_buffer() {
+                let rejected = deserializer.take_rejected();
                 match buffer {
                     Ok(batch) => {
                         self.collector.collect(batch).await;
@@ -572,6 +610,7 @@ impl ArrowContext {
                         self.collect_source_errors(vec![e]).await?;
                     }
                 }
+                self.collect_source_errors(rejected).await?;
             }
         }
 
@@ -589,12 +628,12 @@ impl ArrowContext {
     pub fn should_flush(&self) -> bool {
         self.buffer
             .as_ref()
-            .map(|b| b.should_flush())
+            .map(|b| b.should_flush(&self.batching))
             .unwrap_or(false)
             || self
                 .deserializer
                 .as_ref()
-                .map(|d| d.should_flush())
+                .map(|d| d.should_flush(&self.batching))
                 .unwrap_or(false)
     }
 
@@ -671,12 +710,14 @@ impl ArrowContext {
         format: Format,
         framing: Option<Framing>,
         bad_data: Option<BadData>,
+        metadata_fields: &[MetadataField],
         schema_resolver: Arc<dyn SchemaResolver + Sync>,
     ) {
         self.deserializer = Some(ArrowDeserializer::with_schema_resolver(
             format,
             framing,
             self.out_schema.as_ref().expect("no out schema").clone(),
+            metadata_fields,
             bad_data.unwrap_or_default(),
             schema_resolver,
         ));
@@ -686,6 +727,7 @@ impl ArrowContext {
         &mut self,
         msg: &[u8],
         time: SystemTime,
+        metadata: Option<&HashMap<&str, MetadataValue<'_>>>,
     ) -> Result<(), UserError> {
         let deserializer = self
             .deserializer
@@ -696,6 +738,7 @@ impl ArrowContext {
                 &mut self.buffer.as_mut().expect("no out schema").buffer,
                 msg,
                 time,
+                metadata,
             )
             .await;
         self.collect_source_errors(errors).await?;
@@ -703,33 +746,67 @@ impl ArrowContext {
         Ok(())
     }
 
+    /// Sets the header row for CSV input, which is used to map columns to fields if the
+    /// format is configured to do so
+    pub async fn set_csv_header(&mut self, header: &[u8]) -> Result<(), UserError> {
+        let deserializer = self
+            .deserializer
+            .as_mut()
+            .expect("deserializer not initialized!");
+
+        if let Err(e) = deserializer.set_csv_header(header) {
+            self.collect_source_errors(vec![e]).await?;
+        }
+
+        Ok(())
+    }
+
     /// Handling errors and rate limiting error reporting.
-    /// Considers the `bad_data` option to determine whether to drop or fail on bad data.
+    /// Considers the `bad_data` option to determine whether to drop or fail on bad data, or to
+    /// send it to the dead-letter sink.
     async fn collect_source_errors(&mut self, errors: Vec<SourceError>) -> Result<(), UserError> {
         let bad_data = self
             .deserializer
             .as_ref()
             .expect("deserializer not initialized")
-            .bad_data();
+            .bad_data()
+            .clone();
+
+        let mut dead_letters = vec![];
+
         for error in errors {
             match error {
-                SourceError::BadData { details } => match bad_data {
-                    BadData::Drop {} => {
+                SourceError::BadData {
+                    details,
+                    raw_data,
+                    timestamp,
+                } => match &bad_data {
+                    BadData::Drop {} | BadData::DeadLetter { .. } => {
+                        let message = if matches!(bad_data, BadData::Drop {}) {
+                            "Dropping invalid data"
+                        } else {
+                            "Sending invalid data to dead-letter sink"
+                        };
+
                         self.error_rate_limiter
                             .rate_limit(|| async {
-                                warn!("Dropping invalid data: {}", details.clone());
+                                warn!("{}: {}", message, details.clone());
                                 self.control_tx
                                     .send(ControlResp::Error {
                                         operator_id: self.task_info.operator_id.clone(),
                                         task_index: self.task_info.task_index,
-                                        message: "Dropping invalid data".to_string(),
-                                        details,
+                                        message: message.to_string(),
+                                        details: details.clone(),
                                     })
                                     .await
                                     .unwrap();
                             })
                             .await;
-                        TaskCounters::DeserializationErrors.for_task(&self.task_info, |c| c.inc())
+                        TaskCounters::DeserializationErrors.for_task(&self.task_info, |c| c.inc());
+
+                        if matches!(bad_data, BadData::DeadLetter { .. }) {
+                            dead_letters.push((details, raw_data, timestamp));
+                        }
                     }
                     BadData::Fail {} => {
                         return Err(UserError::new("Deserialization error", details));
@@ -741,8 +818,45 @@ impl ArrowContext {
             }
         }
 
+        if let BadData::DeadLetter { source, .. } = &bad_data {
+            if !dead_letters.is_empty() {
+                let batch = Self::dead_letter_batch(source, dead_letters);
+                self.collector.collect_dead_letters(batch).await;
+            }
+        }
+
         Ok(())
     }
+
+    /// Builds a batch of dead letters from the given table; records that failed before they were
+    /// given a timestamp (like CSV headers) are ingested at the current time
+    pub fn dead_letter_batch(
+        source: &str,
+        dead_letters: Vec<(String, Option<Vec<u8>>, Option<SystemTime>)>,
+    ) -> RecordBatch {
+        let schema = dead_letter_schema();
+        let now = SystemTime::now();
+
+        let ingest_times = Arc::new(TimestampNanosecondArray::from_iter_values(
+            dead_letters
+                .iter()
+                .map(|(_, _, t)| to_nanos(t.unwrap_or(now)) as i64),
+        ));
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![source; dead_letters.len()])),
+            Arc::new(StringArray::from_iter_values(
+                dead_letters.iter().map(|(details, _, _)| details),
+            )),
+            Arc::new(BinaryArray::from_iter(
+                dead_letters.iter().map(|(_, raw, _)| raw.as_deref()),
+            )),
+            ingest_times.clone(),
+        ];
+        columns.insert(schema.timestamp_index, ingest_times);
+
+        RecordBatch::try_new(schema.schema, columns).expect("dead letters should match schema")
+    }
 }
 
 #[cfg(test)]
@@ -847,6 +961,7 @@ mod tests {
             out_schema: Some(ArroyoSchema::new_keyed(schema, 1, vec![0])),
             projection: None,
             out_qs,
+            dead_letter_outputs: vec![],
             tx_queue_rem_gauges,
             tx_queue_size_gauges,
             tx_queue_bytes_gauges,
@@ -1,5 +1,5 @@
 use crate::formats::{BadData, Format, Framing};
-use crate::primitive_to_sql;
+use crate::{primitive_to_sql, MetadataField};
 use anyhow::bail;
 use arrow_schema::{DataType, Field, Fields, TimeUnit};
 use serde::{Deserialize, Serialize};
@@ -51,6 +51,7 @@ pub struct ConnectionProfilePost {
 pub enum ConnectionType {
     Source,
     Sink,
+    Lookup,
 }
 
 impl Display for ConnectionType {
@@ -58,6 +59,7 @@ impl Display for ConnectionType {
         match self {
             ConnectionType::Source => write!(f, "SOURCE"),
             ConnectionType::Sink => write!(f, "SINK"),
+            ConnectionType::Lookup => write!(f, "LOOKUP"),
         }
     }
 }
@@ -69,6 +71,7 @@ impl TryFrom<String> for ConnectionType {
         match value.to_lowercase().as_str() {
             "source" => Ok(ConnectionType::Source),
             "sink" => Ok(ConnectionType::Sink),
+            "lookup" => Ok(ConnectionType::Lookup),
             _ => Err(format!("Invalid connection type: {}", value)),
         }
     }
@@ -120,6 +123,10 @@ pub struct SourceField {
     pub field_name: String,
     pub field_type: SourceFieldType,
     pub nullable: bool,
+    /// If set, this field is filled from the named metadata of the source (like a Kafka
+    /// message key) rather than from the deserialized payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_key: Option<String>,
 }
 
 impl From<SourceField> for Field {
@@ -220,6 +227,7 @@ impl TryFrom<Field> for SourceField {
                 sql_name,
             },
             nullable: f.is_nullable(),
+            metadata_key: None,
         })
     }
 }
@@ -271,10 +279,15 @@ impl ConnectionSchema {
     pub fn validate(self) -> anyhow::Result<Self> {
         match &self.format {
             Some(Format::RawString(_)) => {
-                if self.fields.len() != 1
-                    || self.fields.first().unwrap().field_type.r#type
-                        != FieldType::Primitive(PrimitiveType::String)
-                    || self.fields.first().unwrap().field_name != "value"
+                let fields: Vec<_> = self
+                    .fields
+                    .iter()
+                    .filter(|f| f.metadata_key.is_none())
+                    .collect();
+
+                if fields.len() != 1
+                    || fields[0].field_type.r#type != FieldType::Primitive(PrimitiveType::String)
+                    || fields[0].field_name != "value"
                 {
                     bail!("raw_string format requires a schema with a single field called `value` of type TEXT");
                 }
@@ -286,6 +299,19 @@ impl ConnectionSchema {
 
         Ok(self)
     }
+
+    pub fn metadata_fields(&self) -> Vec<MetadataField> {
+        self.fields
+            .iter()
+            .filter_map(|f| {
+                Some(MetadataField {
+                    field_name: f.field_name.clone(),
+                    key: f.metadata_key.clone()?,
+                })
+            })
+            .collect()
+    }
+
     pub fn arroyo_schema(&self) -> ArroyoSchemaRef {
         let fields: Vec<Field> = self.fields.iter().map(|f| f.clone().into()).collect();
         Arc::new(ArroyoSchema::from_fields(fields))
@@ -113,6 +113,21 @@ impl TryFrom<ArroyoSchema> for api::ArroyoSchema {
     }
 }
 
+/// The schema of the records that sources configured with the dead-letter `bad_data` mode send
+/// to their dead-letter sink
+pub fn dead_letter_schema() -> ArroyoSchema {
+    ArroyoSchema::from_fields(vec![
+        Field::new("source", DataType::Utf8, false),
+        Field::new("error", DataType::Utf8, false),
+        Field::new("raw_data", DataType::Binary, true),
+        Field::new(
+            "ingest_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ),
+    ])
+}
+
 impl ArroyoSchema {
     pub fn new(
         schema: Arc<Schema>,
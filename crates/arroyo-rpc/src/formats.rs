@@ -1,3 +1,4 @@
+use chrono::format::StrftimeItems;
 use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
@@ -15,7 +16,14 @@ pub enum TimestampFormat {
     #[default]
     #[serde(rename = "rfc3339")]
     RFC3339,
+    UnixSeconds,
     UnixMillis,
+    UnixMicros,
+    UnixNanos,
+    /// Seconds since the epoch, with sub-second precision as a fractional part
+    UnixSecondsFractional,
+    /// A strftime-style pattern, like `%Y-%m-%d %H:%M:%S`
+    Pattern(String),
 }
 
 impl TryFrom<&str> for TimestampFormat {
@@ -24,12 +32,31 @@ impl TryFrom<&str> for TimestampFormat {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "RFC3339" => Ok(TimestampFormat::RFC3339),
+            "UnixSeconds" | "unix_seconds" => Ok(TimestampFormat::UnixSeconds),
             "UnixMillis" | "unix_millis" => Ok(TimestampFormat::UnixMillis),
+            "UnixMicros" | "unix_micros" => Ok(TimestampFormat::UnixMicros),
+            "UnixNanos" | "unix_nanos" => Ok(TimestampFormat::UnixNanos),
+            "UnixSecondsFractional" | "unix_seconds_fractional" => {
+                Ok(TimestampFormat::UnixSecondsFractional)
+            }
             _ => Err(()),
         }
     }
 }
 
+impl TimestampFormat {
+    /// Creates a pattern format, checking that the pattern is a valid strftime pattern
+    pub fn pattern(pattern: &str) -> Result<Self, String> {
+        if pattern.is_empty()
+            || StrftimeItems::new(pattern).any(|item| matches!(item, chrono::format::Item::Error))
+        {
+            return Err(format!("invalid timestamp pattern '{}'", pattern));
+        }
+
+        Ok(TimestampFormat::Pattern(pattern.to_string()))
+    }
+}
+
 #[derive(
     Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default, Hash, PartialOrd, ToSchema,
 )]
@@ -52,6 +79,10 @@ pub struct JsonFormat {
 
     #[serde(default)]
     pub timestamp_format: TimestampFormat,
+
+    /// The timezone used for timestamp patterns that don't include an offset; defaults to UTC
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 impl JsonFormat {
@@ -75,18 +106,37 @@ impl JsonFormat {
             .filter(|t| t == "true")
             .is_some();
 
-        let timestamp_format: TimestampFormat = opts
-            .remove("json.timestamp_format")
-            .map(|t| t.as_str().try_into())
-            .transpose()
-            .map_err(|_| "json.timestamp_format".to_string())?
-            .unwrap_or_else(|| {
+        let timestamp_format: TimestampFormat = match (
+            opts.remove("json.timestamp_format"),
+            opts.remove("json.timestamp_pattern"),
+        ) {
+            (None, Some(pattern)) => TimestampFormat::pattern(&pattern)?,
+            (Some(format), Some(pattern)) if format == "pattern" => {
+                TimestampFormat::pattern(&pattern)?
+            }
+            (Some(format), Some(_)) => {
+                return Err(format!(
+                    "json.timestamp_pattern can't be used with json.timestamp_format '{}'",
+                    format
+                ));
+            }
+            (Some(format), None) => format
+                .as_str()
+                .try_into()
+                .map_err(|_| "json.timestamp_format".to_string())?,
+            (None, None) => {
                 if debezium {
                     TimestampFormat::UnixMillis
                 } else {
                     TimestampFormat::default()
                 }
-            });
+            }
+        };
+
+        let timezone = opts.remove("json.timezone");
+        if let Some(tz) = &timezone {
+            chrono_tz::Tz::from_str(tz).map_err(|_| format!("invalid json.timezone '{}'", tz))?;
+        }
 
         Ok(Self {
             confluent_schema_registry,
@@ -95,6 +145,7 @@ impl JsonFormat {
             debezium,
             unstructured,
             timestamp_format,
+            timezone,
         })
     }
 }
@@ -176,6 +227,9 @@ pub struct AvroFormat {
     #[serde(default)]
     pub into_unstructured_json: bool,
 
+    #[serde(default)]
+    pub debezium: bool,
+
     #[serde(default)]
     #[schema(read_only, value_type = String)]
     pub reader_schema: Option<SerializableAvroSchema>,
@@ -195,13 +249,14 @@ impl AvroFormat {
             confluent_schema_registry,
             raw_datums,
             into_unstructured_json,
+            debezium: false,
             reader_schema: None,
             schema_id: None,
         }
     }
 
-    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
-        Ok(Self::new(
+    pub fn from_opts(debezium: bool, opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let mut format = Self::new(
             opts.remove("avro.confluent_schema_registry")
                 .filter(|t| t == "true")
                 .is_some(),
@@ -211,7 +266,14 @@ impl AvroFormat {
             opts.remove("avro.into_unstructured_json")
                 .filter(|t| t == "true")
                 .is_some(),
-        ))
+        );
+
+        if debezium && format.into_unstructured_json {
+            return Err("can't use avro.into_unstructured_json with debezium".to_string());
+        }
+
+        format.debezium = debezium;
+        Ok(format)
     }
 
     pub fn add_reader_schema(&mut self, schema: apache_avro::Schema) {
@@ -230,11 +292,152 @@ impl AvroFormat {
 #[serde(rename_all = "camelCase")]
 pub struct ParquetFormat {}
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtobufFormat {
+    #[serde(default)]
+    pub confluent_schema_registry: bool,
+
+    #[serde(default)]
+    pub into_unstructured_json: bool,
+
+    #[serde(default)]
+    pub message_name: Option<String>,
+
+    /// The compiled `FileDescriptorSet` for the schema, populated when the schema is expanded
+    #[serde(default)]
+    #[schema(read_only, value_type = Vec<u8>)]
+    pub compiled_schema: Option<Vec<u8>>,
+
+    /// The text of the schema the table was created with, used to look up the id a
+    /// user-provided sink schema is registered under
+    #[serde(default)]
+    #[schema(read_only)]
+    pub definition: Option<String>,
+
+    #[serde(default)]
+    #[schema(read_only)]
+    pub schema_id: Option<u32>,
+}
+
+impl ProtobufFormat {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        Ok(Self {
+            confluent_schema_registry: opts
+                .remove("protobuf.confluent_schema_registry")
+                .filter(|t| t == "true")
+                .is_some(),
+            into_unstructured_json: opts
+                .remove("protobuf.into_unstructured_json")
+                .filter(|t| t == "true")
+                .is_some(),
+            message_name: opts.remove("protobuf.message_name"),
+            compiled_schema: None,
+            definition: None,
+            schema_id: None,
+        })
+    }
+}
+
+fn default_csv_delimiter() -> char {
+    ','
+}
+
+fn default_csv_quote() -> char {
+    '"'
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvFormat {
+    #[serde(default = "default_csv_delimiter")]
+    pub delimiter: char,
+
+    #[serde(default = "default_csv_quote")]
+    pub quote: char,
+
+    /// The character used to escape quotes inside of quoted fields; if not set, quotes
+    /// are escaped by doubling them
+    #[serde(default)]
+    pub escape: Option<char>,
+
+    /// Whether the first record of each file is a header row, which is skipped; only
+    /// supported for filesystem sources
+    #[serde(default)]
+    pub has_header: bool,
+
+    /// Whether columns should be matched to fields by the names in the header row, rather
+    /// than by position; only supported for filesystem sources
+    #[serde(default)]
+    pub map_by_header: bool,
+
+    /// A string that is read and written as SQL NULL
+    #[serde(default)]
+    pub null_value: Option<String>,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: default_csv_delimiter(),
+            quote: default_csv_quote(),
+            escape: None,
+            has_header: false,
+            map_by_header: false,
+            null_value: None,
+        }
+    }
+}
+
+impl CsvFormat {
+    pub fn from_opts(delimiter: char, opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        fn char_opt(opts: &mut HashMap<String, String>, key: &str) -> Result<Option<char>, String> {
+            let Some(value) = opts.remove(key) else {
+                return Ok(None);
+            };
+
+            match value.as_str() {
+                "\\t" => Ok(Some('\t')),
+                v if v.len() == 1 && v.is_ascii() => Ok(v.chars().next()),
+                _ => Err(format!(
+                    "invalid value for {}: '{}'; must be a single ASCII character",
+                    key, value
+                )),
+            }
+        }
+
+        let has_header = opts
+            .remove("csv.has_header")
+            .filter(|t| t == "true")
+            .is_some();
+
+        let map_by_header = opts
+            .remove("csv.map_by_header")
+            .filter(|t| t == "true")
+            .is_some();
+
+        if map_by_header && !has_header {
+            return Err("csv.map_by_header requires csv.has_header to be set".to_string());
+        }
+
+        Ok(Self {
+            delimiter: char_opt(opts, "csv.delimiter")?.unwrap_or(delimiter),
+            quote: char_opt(opts, "csv.quote")?.unwrap_or_else(default_csv_quote),
+            escape: char_opt(opts, "csv.escape")?,
+            has_header,
+            map_by_header,
+            null_value: opts.remove("csv.null_value"),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Format {
     Json(JsonFormat),
     Avro(AvroFormat),
+    Protobuf(ProtobufFormat),
+    Csv(CsvFormat),
     Parquet(ParquetFormat),
     RawString(RawStringFormat),
     RawBytes(RawBytesFormat),
@@ -249,8 +452,11 @@ impl Format {
         Ok(Some(match name.as_str() {
             "json" => Format::Json(JsonFormat::from_opts(false, opts)?),
             "debezium_json" => Format::Json(JsonFormat::from_opts(true, opts)?),
-            "protobuf" => return Err("protobuf is not yet supported".to_string()),
-            "avro" => Format::Avro(AvroFormat::from_opts(opts)?),
+            "protobuf" => Format::Protobuf(ProtobufFormat::from_opts(opts)?),
+            "avro" => Format::Avro(AvroFormat::from_opts(false, opts)?),
+            "debezium_avro" => Format::Avro(AvroFormat::from_opts(true, opts)?),
+            "csv" => Format::Csv(CsvFormat::from_opts(',', opts)?),
+            "tsv" => Format::Csv(CsvFormat::from_opts('\t', opts)?),
             "raw_string" => Format::RawString(RawStringFormat {}),
             "raw_bytes" => Format::RawBytes(RawBytesFormat {}),
             "parquet" => Format::Parquet(ParquetFormat {}),
@@ -260,9 +466,10 @@ impl Format {
 
     pub fn is_updating(&self) -> bool {
         match self {
-            Format::Json(JsonFormat { debezium: true, .. }) => true,
+            Format::Json(JsonFormat { debezium: true, .. })
+            | Format::Avro(AvroFormat { debezium: true, .. }) => true,
             Format::Json(_) | Format::Avro(_) | Format::Parquet(_) | Format::RawString(_) => false,
-            Format::RawBytes(_) => false,
+            Format::Protobuf(_) | Format::Csv(_) | Format::RawBytes(_) => false,
         }
     }
 }
@@ -272,6 +479,14 @@ impl Format {
 pub enum BadData {
     Fail {},
     Drop {},
+    /// Sends records that can't be deserialized to the named sink table
+    DeadLetter {
+        table: String,
+        /// The name of the source table, which is set by the planner and written to the
+        /// `source` column of each dead letter
+        #[serde(default)]
+        source: String,
+    },
 }
 
 impl Default for BadData {
@@ -289,6 +504,12 @@ impl BadData {
         let method = match method.as_str() {
             "drop" => BadData::Drop {},
             "fail" => BadData::Fail {},
+            "dead_letter" => BadData::DeadLetter {
+                table: opts.remove("bad_data.dead_letter_table").ok_or_else(|| {
+                    "'bad_data.dead_letter_table' must be set for dead_letter".to_string()
+                })?,
+                source: String::new(),
+            },
             f => return Err(format!("Unknown invalid data behavior '{}'", f)),
         };
 
@@ -310,6 +531,10 @@ impl Framing {
 
         let method = match method.as_str() {
             "newline" => FramingMethod::Newline(NewlineDelimitedFraming::from_opts(opts)?),
+            "length_prefixed" => {
+                FramingMethod::LengthPrefixed(LengthPrefixedFraming::from_opts(opts)?)
+            }
+            "delimited" => FramingMethod::Delimited(DelimitedFraming::from_opts(opts)?),
             f => return Err(format!("Unknown framing method '{}'", f)),
         };
 
@@ -338,8 +563,132 @@ impl NewlineDelimitedFraming {
     }
 }
 
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default, Hash, PartialOrd, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthPrefix {
+    #[default]
+    U32Be,
+    U32Le,
+    U64Be,
+    U64Le,
+    /// An unsigned LEB128 varint, as used for length-delimited protobuf messages
+    Varint,
+}
+
+impl TryFrom<&str> for LengthPrefix {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "u32_be" => Ok(LengthPrefix::U32Be),
+            "u32_le" => Ok(LengthPrefix::U32Le),
+            "u64_be" => Ok(LengthPrefix::U64Be),
+            "u64_le" => Ok(LengthPrefix::U64Le),
+            "varint" => Ok(LengthPrefix::Varint),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LengthPrefixedFraming {
+    #[serde(default)]
+    pub prefix: LengthPrefix,
+    pub max_length: Option<u64>,
+}
+
+impl LengthPrefixedFraming {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let prefix = opts
+            .remove("framing.length_prefixed.prefix")
+            .map(|t| t.as_str().try_into())
+            .transpose()
+            .map_err(|_| {
+                "invalid value for framing.length_prefixed.prefix; must be one of \
+                u32_be, u32_le, u64_be, u64_le, or varint"
+                    .to_string()
+            })?
+            .unwrap_or_default();
+
+        let max_length = opts
+            .remove("framing.length_prefixed.max_length")
+            .map(|t| u64::from_str(&t))
+            .transpose()
+            .map_err(|_| {
+                "invalid value for framing.length_prefixed.max_length; must be an unsigned integer"
+                    .to_string()
+            })?;
+
+        Ok(LengthPrefixedFraming { prefix, max_length })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DelimitedFraming {
+    pub delimiter: Vec<u8>,
+}
+
+impl DelimitedFraming {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let delimiter = opts
+            .remove("framing.delimited.delimiter")
+            .ok_or_else(|| "framing.delimited.delimiter must be set".to_string())?;
+
+        let delimiter = unescape_bytes(&delimiter).ok_or_else(|| {
+            format!(
+                "invalid value for framing.delimited.delimiter: '{}'",
+                delimiter
+            )
+        })?;
+
+        if delimiter.is_empty() {
+            return Err("framing.delimited.delimiter must not be empty".to_string());
+        }
+
+        Ok(DelimitedFraming { delimiter })
+    }
+}
+
+/// Parses a string containing backslash escapes (\n, \r, \t, \0, \\, and \xNN) into bytes
+fn unescape_bytes(s: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            '0' => out.push(0),
+            '\\' => out.push(b'\\'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                out.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(out)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum FramingMethod {
     Newline(NewlineDelimitedFraming),
+    LengthPrefixed(LengthPrefixedFraming),
+    Delimited(DelimitedFraming),
 }
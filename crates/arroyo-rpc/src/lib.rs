@@ -173,6 +173,21 @@ pub struct OperatorConfig {
     pub bad_data: Option<BadData>,
     pub framing: Option<Framing>,
     pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub metadata_fields: Vec<MetadataField>,
+    /// Predicates over the columns of a source table that were pushed down by the planner, as
+    /// serialized physical expressions. Sources may use these to skip reading data that can't
+    /// match, but the predicates are still applied to everything they emit.
+    #[serde(default)]
+    pub filters: Vec<Vec<u8>>,
+}
+
+/// A field of a source table that is filled from the metadata of each message with the given
+/// key, rather than from its payload
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct MetadataField {
+    pub field_name: String,
+    pub key: String,
 }
 
 impl Default for OperatorConfig {
@@ -184,6 +199,8 @@ impl Default for OperatorConfig {
             bad_data: None,
             framing: None,
             rate_limit: None,
+            metadata_fields: vec![],
+            filters: vec![],
         }
     }
 }
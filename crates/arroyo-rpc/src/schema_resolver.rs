@@ -15,6 +15,18 @@ use tracing::warn;
 #[async_trait]
 pub trait SchemaResolver: Send {
     async fn resolve_schema(&self, id: u32) -> Result<Option<String>, String>;
+
+    /// Resolves a schema along with the schemas it references, including transitively, as
+    /// (name, schema) pairs; protobuf schemas need these in order to be compiled
+    async fn resolve_schema_with_references(
+        &self,
+        id: u32,
+    ) -> Result<Option<(String, Vec<(String, String)>)>, String> {
+        Ok(self
+            .resolve_schema(id)
+            .await?
+            .map(|schema| (schema, vec![])))
+    }
 }
 
 /// A schema resolver that return errors when schemas are requested; this is intended
@@ -78,6 +90,16 @@ pub enum ConfluentSchemaType {
     Protobuf,
 }
 
+/// A reference from a schema to another schema in the registry; for protobuf, `name` is the
+/// path the referenced file is imported as
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfluentSchemaReference {
+    pub name: String,
+    pub subject: String,
+    pub version: u32,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfluentSchemaSubjectResponse {
@@ -87,6 +109,8 @@ pub struct ConfluentSchemaSubjectResponse {
     pub schema_type: ConfluentSchemaType,
     pub subject: String,
     pub version: u32,
+    #[serde(default)]
+    pub references: Vec<ConfluentSchemaReference>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -95,6 +119,8 @@ pub struct ConfluentSchemaIdResponse {
     pub schema: String,
     #[serde(default)]
     pub schema_type: ConfluentSchemaType,
+    #[serde(default)]
+    pub references: Vec<ConfluentSchemaReference>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -207,6 +233,64 @@ impl ConfluentSchemaRegistryClient {
         })
     }
 
+    /// Looks up the version of the subject at `url` that the registry considers the same as
+    /// `schema`, returning None if it's not registered
+    async fn lookup_schema(
+        &self,
+        url: Url,
+        schema: impl Into<String>,
+        schema_type: ConfluentSchemaType,
+    ) -> anyhow::Result<Option<ConfluentSchemaSubjectResponse>> {
+        let req = PostSchemaRequest {
+            schema: schema.into(),
+            schema_type,
+        };
+
+        let resp = self
+            .client
+            .post(url.clone())
+            .json(&req)
+            .send()
+            .await
+            .map_err(|e| {
+                warn!("Got error response from schema registry: {:?}", e);
+                anyhow!(
+                    "could not connect to Schema Registry at {}: unknown error",
+                    self.endpoint
+                )
+            })?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let bytes = resp
+                .bytes()
+                .await
+                .map(|b| b.to_vec())
+                .unwrap_or_else(|_| "<failed to read body>".to_string().into_bytes());
+            let json = serde_json::from_slice::<RegistryErrorResponse>(&bytes);
+            // 40401 means the subject doesn't exist, 40403 that the schema isn't registered for it
+            if status == StatusCode::NOT_FOUND
+                && matches!(&json, Ok(e) if e.error_code == 40401 || e.error_code == 40403)
+            {
+                return Ok(None);
+            }
+            bail!(
+                "received an error status code from the schema endpoint while looking up {}: {} {}",
+                url,
+                status.as_u16(),
+                String::from_utf8_lossy(&bytes)
+            );
+        }
+
+        resp.json().await.map_err(|e| {
+            warn!(
+                "invalid json from schema registry: {:?} for request {:?}",
+                e, url
+            );
+            anyhow!("schema registry response could not be deserialized: {}", e)
+        })
+    }
+
     async fn write_schema(
         &self,
         url: Url,
@@ -327,9 +411,12 @@ impl ConfluentSchemaRegistry {
     }
 
     fn subject_endpoint(&self) -> Url {
-        self.client
-            .endpoint
-            .join(&format!("subjects/{}/versions/", self.subject))
+        Self::versions_endpoint(&self.client.endpoint, &self.subject)
+    }
+
+    fn versions_endpoint(endpoint: &Url, subject: &str) -> Url {
+        endpoint
+            .join(&format!("subjects/{}/versions/", subject))
             .unwrap()
     }
 
@@ -344,6 +431,24 @@ impl ConfluentSchemaRegistry {
             .context(format!("subject '{}'", self.subject))
     }
 
+    /// Finds the registered version of the subject that matches `schema`, if there is one
+    pub async fn lookup_schema(
+        &self,
+        schema: impl Into<String>,
+        schema_type: ConfluentSchemaType,
+    ) -> anyhow::Result<Option<ConfluentSchemaSubjectResponse>> {
+        let url = self
+            .client
+            .endpoint
+            .join(&format!("subjects/{}", self.subject))
+            .unwrap();
+
+        self.client
+            .lookup_schema(url, schema, schema_type)
+            .await
+            .context(format!("subject '{}'", self.subject))
+    }
+
     pub async fn get_schema_for_id(
         &self,
         id: u32,
@@ -363,18 +468,62 @@ impl ConfluentSchemaRegistry {
     pub async fn get_schema_for_version(
         &self,
         version: Option<u32>,
+    ) -> anyhow::Result<Option<ConfluentSchemaSubjectResponse>> {
+        self.get_schema_for_subject_version(&self.subject, version)
+            .await
+    }
+
+    async fn get_schema_for_subject_version(
+        &self,
+        subject: &str,
+        version: Option<u32>,
     ) -> anyhow::Result<Option<ConfluentSchemaSubjectResponse>> {
         let version = version
             .map(|v| format!("{}", v))
             .unwrap_or_else(|| "latest".to_string());
 
-        let url = self.subject_endpoint().join(&version).unwrap();
+        let url = Self::versions_endpoint(&self.client.endpoint, subject)
+            .join(&version)
+            .unwrap();
 
         self.client.get_schema_for_url(url).await.context(format!(
             "failed to fetch schema for subject '{}' with version {}",
-            self.subject, version
+            subject, version
         ))
     }
+
+    /// Fetches the schemas referenced by a schema, including those referenced transitively,
+    /// returning them as (name, schema) pairs
+    pub async fn resolve_references(
+        &self,
+        references: &[ConfluentSchemaReference],
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let mut resolved: Vec<(String, String)> = vec![];
+        let mut queue = references.to_vec();
+
+        while let Some(reference) = queue.pop() {
+            if resolved.iter().any(|(name, _)| *name == reference.name) {
+                continue;
+            }
+
+            let response = self
+                .get_schema_for_subject_version(&reference.subject, Some(reference.version))
+                .await?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "referenced schema '{}' (subject '{}', version {}) was not found",
+                        reference.name,
+                        reference.subject,
+                        reference.version
+                    )
+                })?;
+
+            queue.extend(response.references);
+            resolved.push((reference.name, response.schema));
+        }
+
+        Ok(resolved)
+    }
 }
 
 #[async_trait]
@@ -385,4 +534,24 @@ impl SchemaResolver for ConfluentSchemaRegistry {
             .map(|s| s.map(|r| r.schema))
             .map_err(|e| e.to_string())
     }
+
+    async fn resolve_schema_with_references(
+        &self,
+        id: u32,
+    ) -> Result<Option<(String, Vec<(String, String)>)>, String> {
+        let Some(response) = self
+            .get_schema_for_id(id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(None);
+        };
+
+        let references = self
+            .resolve_references(&response.references)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Some((response.schema, references)))
+    }
 }
@@ -567,6 +567,7 @@ async fn get_graph(query_string: String, udfs: &[LocalUdf]) -> Result<LogicalPro
         schema_provider,
         SqlConfig {
             default_parallelism: 1,
+            ..Default::default()
         },
     )
     .await?
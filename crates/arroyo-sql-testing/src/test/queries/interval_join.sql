@@ -0,0 +1,28 @@
+CREATE TABLE impulse (
+      timestamp TIMESTAMP,
+      counter bigint unsigned not null,
+      subtask_index bigint unsigned not null
+    ) WITH (
+      connector = 'single_file',
+      path = '$input_dir/impulse.json',
+      format = 'json',
+      type = 'source',
+      event_time_field = 'timestamp'
+    );
+
+    CREATE TABLE output (
+      left_counter bigint,
+      right_counter bigint
+    ) WITH (
+      connector = 'single_file',
+      path = '$output_path',
+      format = 'json',
+      type = 'sink'
+    );
+
+    INSERT INTO output
+    SELECT CAST(a.counter AS BIGINT) as left_counter, CAST(b.counter AS BIGINT) as right_counter
+    FROM (SELECT timestamp, counter, subtask_index FROM impulse WHERE counter < 10) a
+    JOIN (SELECT timestamp, counter, subtask_index FROM impulse WHERE counter < 20) b
+    ON a.subtask_index = b.subtask_index
+      AND b.timestamp BETWEEN a.timestamp AND a.timestamp + INTERVAL '400 milliseconds';
@@ -0,0 +1,31 @@
+CREATE TABLE impulse (
+      timestamp TIMESTAMP,
+      counter bigint unsigned not null,
+      subtask_index bigint unsigned not null
+    ) WITH (
+      connector = 'single_file',
+      path = '$input_dir/impulse.json',
+      format = 'json',
+      type = 'source',
+      event_time_field = 'timestamp'
+    );
+
+    CREATE TABLE output (
+      left_counter bigint,
+      right_counter bigint
+    ) WITH (
+      connector = 'single_file',
+      path = '$output_path',
+      format = 'debezium_json',
+      type = 'sink'
+    );
+
+
+    INSERT INTO output
+    SELECT CAST(a.counter AS BIGINT) as left_counter, CAST(b.counter AS BIGINT) as right_counter
+    FROM (SELECT timestamp, counter FROM impulse WHERE counter < 20) a
+    LEFT JOIN (SELECT timestamp, counter FROM impulse WHERE counter % 2 = 0 AND counter < 20) b
+    -- right rows are kept for 200ms and left rows for 2s, so the right side of each match
+    -- expires first and the left row stays matched
+    ON a.counter = b.counter
+      AND b.timestamp BETWEEN a.timestamp - INTERVAL '200 milliseconds' AND a.timestamp + INTERVAL '2 seconds';
@@ -0,0 +1,35 @@
+CREATE TABLE impulse (
+      timestamp TIMESTAMP,
+      counter bigint unsigned not null,
+      subtask_index bigint unsigned not null
+    ) WITH (
+      connector = 'single_file',
+      path = '$input_dir/impulse.json',
+      format = 'json',
+      type = 'source',
+      event_time_field = 'timestamp'
+    );
+
+    CREATE TABLE output (
+      c bigint,
+      groups bigint,
+      min_k bigint,
+      max_k bigint,
+      sum_k bigint
+    ) WITH (
+      connector = 'single_file',
+      path = '$output_path',
+      format = 'debezium_json',
+      type = 'sink'
+    );
+
+    INSERT INTO output
+    -- every change to a count retracts the group from its prior count in the outer aggregate
+    SELECT c, count(*) as groups, min(k) as min_k, max(k) as max_k, sum(k) as sum_k
+    FROM (
+      SELECT CAST(counter % 3 AS BIGINT) as k, count(*) as c
+      FROM impulse
+      WHERE counter < 10
+      GROUP BY 1
+    )
+    GROUP BY c;
@@ -0,0 +1,27 @@
+CREATE TABLE impulse (
+      timestamp TIMESTAMP,
+      counter bigint unsigned not null,
+      subtask_index bigint unsigned not null
+    ) WITH (
+      connector = 'single_file',
+      path = '$input_dir/impulse.json',
+      format = 'json',
+      type = 'source',
+      event_time_field = 'timestamp'
+    );
+
+    CREATE TABLE output (
+      left_counter bigint,
+      right_counter bigint
+    ) WITH (
+      connector = 'single_file',
+      path = '$output_path',
+      format = 'debezium_json',
+      type = 'sink'
+    );
+
+    INSERT INTO output
+    SELECT CAST(a.counter AS BIGINT) as left_counter, CAST(b.counter AS BIGINT) as right_counter
+    FROM (SELECT counter FROM impulse WHERE counter < 10) a
+    LEFT JOIN (SELECT counter FROM impulse WHERE counter % 2 = 0 AND counter < 10) b
+    ON a.counter = b.counter;
@@ -0,0 +1,31 @@
+CREATE TABLE impulse (
+      timestamp TIMESTAMP,
+      counter bigint unsigned not null,
+      subtask_index bigint unsigned not null
+    ) WITH (
+      connector = 'single_file',
+      path = '$input_dir/impulse.json',
+      format = 'json',
+      type = 'source',
+      event_time_field = 'timestamp'
+    );
+
+    CREATE TABLE output (
+      left_counter bigint,
+      right_counter bigint
+    ) WITH (
+      connector = 'single_file',
+      path = '$output_path',
+      format = 'debezium_json',
+      type = 'sink'
+    );
+
+    INSERT INTO output
+    SELECT a.counter as left_counter, b.counter as right_counter
+    FROM (SELECT CAST(counter AS BIGINT) as counter FROM impulse WHERE counter < 10) a
+    LEFT JOIN (
+      -- each match arrives five events after the row it matches, which by then has been
+      -- emitted without a match
+      SELECT CAST(counter AS BIGINT) - 5 as counter FROM impulse WHERE counter % 2 = 0 AND counter < 15
+    ) b
+    ON a.counter = b.counter;
@@ -1,4 +1,3 @@
---fail=This feature is not implemented: can't handle non-inner joins without windows
 CREATE TABLE impulse (
       timestamp TIMESTAMP,
       counter bigint unsigned not null,
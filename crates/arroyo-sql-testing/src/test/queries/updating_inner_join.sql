@@ -1,4 +1,3 @@
---fail=Error during planning: can't handle updating right side of join
 CREATE TABLE impulse (
       timestamp TIMESTAMP,
       counter bigint unsigned not null,
@@ -6,12 +6,12 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Ok, Result};
-use arrow::compute::{concat_batches, filter_record_batch, kernels::aggregate, take};
-use arrow::row::OwnedRow;
+use arrow::compute::{concat_batches, filter_record_batch, kernels::aggregate, not, take};
+use arrow::row::{OwnedRow, Row, Rows, SortField};
 use arrow_array::{
     cast::AsArray,
     types::{TimestampNanosecondType, UInt64Type},
-    BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
+    ArrayRef, BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
 };
 use arrow_ord::{partition::partition, sort::sort_to_indices};
 use arroyo_rpc::{
@@ -20,7 +20,7 @@ use arroyo_rpc::{
         ExpiringKeyedTimeSubtaskCheckpointMetadata, ExpiringKeyedTimeTableCheckpointMetadata,
         ExpiringKeyedTimeTableConfig, OperatorMetadata, ParquetTimeFile, TableEnum,
     },
-    Converter,
+    Converter, IS_RETRACT_FIELD, TIMESTAMP_FIELD,
 };
 use arroyo_storage::StorageProviderRef;
 use arroyo_types::{
@@ -895,10 +895,16 @@ pub struct KeyTimeView {
     key_converter: Converter,
     parent: ExpiringTimeKeyTable,
     keyed_data: HashMap<Vec<u8>, BatchData>,
+    // keys by the earliest timestamp of the rows inserted for them, used to find expired rows
+    expirations: BTreeMap<SystemTime, HashSet<Vec<u8>>>,
     schema: ArroyoSchemaRef,
     value_schema: ArroyoSchemaRef,
-    // indices of schema that aren't keys, used for projection
+    // indices of schema that aren't keys or the retraction flag, used for projection
     value_indices: Vec<usize>,
+    // for updating inputs, retracted rows remove the stored rows with the same values
+    retract_index: Option<usize>,
+    // converts values other than the timestamp, to find the rows that a retraction removes
+    value_converter: Converter,
     state_tx: Sender<StateMessage>,
 }
 
@@ -912,15 +918,37 @@ impl KeyTimeView {
     fn new(parent: ExpiringTimeKeyTable, state_tx: Sender<StateMessage>) -> Result<Self> {
         let schema = parent.schema.memory_schema();
         let key_converter = schema.converter(false)?;
-        let value_schema = Arc::new(schema.schema_without_keys()?);
-        let value_indices = schema.value_indices(true);
+        let retract_index = schema.schema.index_of(IS_RETRACT_FIELD).ok();
+        let value_indices: Vec<_> = schema
+            .value_indices(true)
+            .into_iter()
+            .filter(|index| Some(*index) != retract_index)
+            .collect();
+        let value_arrow_schema = schema.schema.project(&value_indices)?;
+        let timestamp_index = value_arrow_schema.index_of(TIMESTAMP_FIELD)?;
+        let value_converter = Converter::new(
+            value_arrow_schema
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != timestamp_index)
+                .map(|(_, field)| SortField::new(field.data_type().clone()))
+                .collect(),
+        )?;
+        let value_schema = Arc::new(ArroyoSchema::new_unkeyed(
+            Arc::new(value_arrow_schema),
+            timestamp_index,
+        ));
         Ok(Self {
             key_converter,
             parent,
             keyed_data: HashMap::new(),
+            expirations: BTreeMap::new(),
             schema,
             value_indices,
             value_schema,
+            retract_index,
+            value_converter,
             state_tx,
         })
     }
@@ -942,6 +970,138 @@ impl KeyTimeView {
         Ok(Some(single_batch))
     }
 
+    /// Returns the rows stored for a key in the table's full schema, restoring the key columns
+    /// and leaving the retraction flag, if there is one, unset
+    pub fn get_rows(&mut self, key: &[u8]) -> Result<Option<RecordBatch>> {
+        let Some(value_batch) = self.get_batch(key)?.cloned() else {
+            return Ok(None);
+        };
+        Ok(Some(self.with_key_columns(key, value_batch)?))
+    }
+
+    fn with_key_columns(&self, key: &[u8], value_batch: RecordBatch) -> Result<RecordBatch> {
+        let num_rows = value_batch.num_rows();
+        let key_indices = self.schema.key_indices.clone().unwrap_or_default();
+        let key_columns = self.key_converter.convert_raw_rows(vec![key; num_rows])?;
+        let mut value_columns = value_batch.columns().iter().cloned();
+
+        let columns = (0..self.schema.schema.fields().len())
+            .map(|index| {
+                if Some(index) == self.retract_index {
+                    Arc::new(BooleanArray::from(vec![false; num_rows])) as ArrayRef
+                } else if let Some(position) = key_indices.iter().position(|key| *key == index) {
+                    key_columns[position].clone()
+                } else {
+                    value_columns
+                        .next()
+                        .expect("should have a column for each value")
+                }
+            })
+            .collect();
+
+        Ok(RecordBatch::try_new(self.schema.schema.clone(), columns)?)
+    }
+
+    pub fn contains_key(&self, row: &[u8]) -> bool {
+        self.keyed_data.contains_key(row)
+    }
+
+    /// Returns the distinct keys of a batch, in the same order as `insert`, without inserting it
+    pub fn keys(&self, batch: &RecordBatch) -> Result<Vec<OwnedRow>> {
+        let sorted_batch = self.schema.sort(batch.clone(), false)?;
+        self.schema
+            .partition(&sorted_batch, false)?
+            .into_iter()
+            .map(|range| {
+                let key_columns = match &self.schema.key_indices {
+                    None => vec![],
+                    Some(key_indices) => sorted_batch
+                        .slice(range.start, 1)
+                        .project(key_indices)?
+                        .columns()
+                        .to_vec(),
+                };
+                Ok(self.key_converter.convert_columns(&key_columns)?)
+            })
+            .collect()
+    }
+
+    /// Splits a batch into the rows for each of its distinct keys, in the same order as `insert`
+    pub fn partition_by_key(&self, batch: &RecordBatch) -> Result<Vec<(OwnedRow, RecordBatch)>> {
+        let sorted_batch = self.schema.sort(batch.clone(), false)?;
+        self.schema
+            .partition(&sorted_batch, false)?
+            .into_iter()
+            .map(|range| {
+                let rows = sorted_batch.slice(range.start, range.end - range.start);
+                let key_columns = match &self.schema.key_indices {
+                    None => vec![],
+                    Some(key_indices) => rows.slice(0, 1).project(key_indices)?.columns().to_vec(),
+                };
+                Ok((self.key_converter.convert_columns(&key_columns)?, rows))
+            })
+            .collect()
+    }
+
+    fn min_timestamp(&self, value_batch: &RecordBatch) -> Result<SystemTime> {
+        let timestamp_array: &PrimitiveArray<TimestampNanosecondType> = value_batch
+            .column(self.value_schema.timestamp_index)
+            .as_primitive_opt()
+            .ok_or_else(|| anyhow!("failed to find timestamp column"))?;
+        Ok(from_nanos(
+            aggregate::min(timestamp_array).ok_or_else(|| anyhow!("should have min timestamp"))?
+                as u128,
+        ))
+    }
+
+    /// Drops the rows that are older than the table's retention as of the watermark, returning
+    /// the expired rows of each key in the table's full schema
+    pub fn expire(&mut self, watermark: Option<SystemTime>) -> Result<Vec<(Vec<u8>, RecordBatch)>> {
+        let Some(watermark) = watermark else {
+            return Ok(vec![]);
+        };
+        let cutoff = watermark - self.parent.retention;
+        let mut to_check = self.expirations.split_off(&cutoff);
+        mem::swap(&mut self.expirations, &mut to_check);
+
+        let cutoff_nanos = to_nanos(cutoff) as i64;
+        let keys: HashSet<Vec<u8>> = to_check.into_values().flatten().collect();
+        let mut expired_rows = vec![];
+        for key in keys {
+            let Some(batch) = self.get_batch(&key)?.cloned() else {
+                continue;
+            };
+            let timestamp_array: &PrimitiveArray<TimestampNanosecondType> = batch
+                .column(self.value_schema.timestamp_index)
+                .as_primitive_opt()
+                .ok_or_else(|| anyhow!("failed to find timestamp column"))?;
+            let unexpired = BooleanArray::from(
+                timestamp_array
+                    .values()
+                    .iter()
+                    .map(|timestamp| *timestamp >= cutoff_nanos)
+                    .collect::<Vec<_>>(),
+            );
+            let expired = filter_record_batch(&batch, &not(&unexpired)?)?;
+            if expired.num_rows() > 0 {
+                expired_rows.push((key.clone(), self.with_key_columns(&key, expired)?));
+            }
+            let remaining = filter_record_batch(&batch, &unexpired)?;
+            if remaining.num_rows() == 0 {
+                self.keyed_data.remove(&key);
+            } else {
+                let min_timestamp = self.min_timestamp(&remaining)?;
+                self.expirations
+                    .entry(min_timestamp)
+                    .or_default()
+                    .insert(key.clone());
+                self.keyed_data
+                    .insert(key, BatchData::SingleBatch(remaining));
+            }
+        }
+        Ok(expired_rows)
+    }
+
     pub async fn write_batch_to_state(&mut self, batch: RecordBatch) -> Result<()> {
         self.state_tx
             .send(StateMessage::TableData {
@@ -953,6 +1113,16 @@ impl KeyTimeView {
     }
 
     pub async fn insert(&mut self, batch: RecordBatch) -> Result<Vec<OwnedRow>> {
+        Ok(self.insert_retracting(batch).await?.0)
+    }
+
+    /// Inserts a batch like `insert`, also returning the stored rows that its retractions removed
+    /// for each key, in the table's full schema. Retractions of rows that aren't stored, like
+    /// ones that have already expired, don't remove anything.
+    pub async fn insert_retracting(
+        &mut self,
+        batch: RecordBatch,
+    ) -> Result<(Vec<OwnedRow>, Vec<(Vec<u8>, RecordBatch)>)> {
         self.state_tx
             .send(StateMessage::TableData {
                 table: self.parent.table_name.to_string(),
@@ -962,10 +1132,17 @@ impl KeyTimeView {
         Ok(self.insert_internal(batch)?)
     }
 
-    fn insert_internal(&mut self, batch: RecordBatch) -> Result<Vec<OwnedRow>> {
+    fn insert_internal(
+        &mut self,
+        batch: RecordBatch,
+    ) -> Result<(Vec<OwnedRow>, Vec<(Vec<u8>, RecordBatch)>)> {
         let sorted_batch = self.schema.sort(batch, false)?;
         let value_batch = sorted_batch.project(&self.value_indices)?;
+        let is_retract = self
+            .retract_index
+            .map(|index| sorted_batch.column(index).as_boolean().values().clone());
         let mut rows = vec![];
+        let mut retracted = vec![];
         for range in self.schema.partition(&sorted_batch, false)? {
             let value_batch = value_batch.slice(range.start, range.end - range.start);
             let key_columns = if self.schema.key_indices.is_none() {
@@ -978,28 +1155,102 @@ impl KeyTimeView {
                     .to_vec()
             };
             let key_row = self.key_converter.convert_columns(&key_columns)?;
-            let contents = self.keyed_data.get_mut(key_row.as_ref());
             rows.push(key_row.clone());
-            let batch = match contents {
-                Some(BatchData::BatchVec(vec)) => {
-                    vec.push(value_batch);
-                    continue;
-                }
-                None => {
-                    self.keyed_data.insert(
-                        key_row.as_ref().to_vec(),
-                        BatchData::SingleBatch(value_batch),
-                    );
-                    continue;
-                }
-                Some(BatchData::SingleBatch(single_batch)) => single_batch.clone(),
+
+            let Some(is_retract) = &is_retract else {
+                self.append(key_row.as_ref(), value_batch)?;
+                continue;
             };
-            self.keyed_data.insert(
-                key_row.as_ref().to_vec(),
-                BatchData::BatchVec(vec![batch, value_batch]),
-            );
+            let is_retract =
+                BooleanArray::new(is_retract.slice(range.start, range.end - range.start), None);
+            let appends = filter_record_batch(&value_batch, &not(&is_retract)?)?;
+            let retracts = filter_record_batch(&value_batch, &is_retract)?;
+            // appends go first, so that a retraction can cancel a row from the same batch
+            if appends.num_rows() > 0 {
+                self.append(key_row.as_ref(), appends)?;
+            }
+            if retracts.num_rows() > 0 {
+                let removed = self.retract(key_row.as_ref(), &retracts)?;
+                if removed.num_rows() > 0 {
+                    let removed = self.with_key_columns(key_row.as_ref(), removed)?;
+                    retracted.push((key_row.as_ref().to_vec(), removed));
+                }
+            }
         }
-        Ok(rows)
+        Ok((rows, retracted))
+    }
+
+    fn append(&mut self, key: &[u8], value_batch: RecordBatch) -> Result<()> {
+        let min_timestamp = self.min_timestamp(&value_batch)?;
+        self.expirations
+            .entry(min_timestamp)
+            .or_default()
+            .insert(key.to_vec());
+        let batch = match self.keyed_data.get_mut(key) {
+            Some(BatchData::BatchVec(vec)) => {
+                vec.push(value_batch);
+                return Ok(());
+            }
+            None => {
+                self.keyed_data
+                    .insert(key.to_vec(), BatchData::SingleBatch(value_batch));
+                return Ok(());
+            }
+            Some(BatchData::SingleBatch(single_batch)) => single_batch.clone(),
+        };
+        self.keyed_data
+            .insert(key.to_vec(), BatchData::BatchVec(vec![batch, value_batch]));
+        Ok(())
+    }
+
+    /// Removes a stored row for each retracted row, matching on every value but the timestamp,
+    /// and returns the removed rows
+    fn retract(&mut self, key: &[u8], retracts: &RecordBatch) -> Result<RecordBatch> {
+        let Some(batch) = self.get_batch(key)?.cloned() else {
+            return Ok(retracts.slice(0, 0));
+        };
+        let stored_rows = self.convert_values(&batch)?;
+        let retracted_rows = self.convert_values(retracts)?;
+
+        // the indices of the stored rows with each value, in order, so each retraction removes
+        // the first one that hasn't been removed yet
+        let mut stored_indices: HashMap<Row, Vec<usize>> = HashMap::new();
+        for (index, row) in stored_rows.iter().enumerate().rev() {
+            stored_indices.entry(row).or_default().push(index);
+        }
+
+        let mut keep = vec![true; batch.num_rows()];
+        for retracted in retracted_rows.iter() {
+            if let Some(index) = stored_indices
+                .get_mut(&retracted)
+                .and_then(|indices| indices.pop())
+            {
+                keep[index] = false;
+            }
+        }
+
+        let keep = BooleanArray::from(keep);
+        let removed = filter_record_batch(&batch, &not(&keep)?)?;
+        let remaining = filter_record_batch(&batch, &keep)?;
+        if remaining.num_rows() == 0 {
+            self.keyed_data.remove(key);
+        } else {
+            self.keyed_data
+                .insert(key.to_vec(), BatchData::SingleBatch(remaining));
+        }
+        Ok(removed)
+    }
+
+    fn convert_values(&self, value_batch: &RecordBatch) -> Result<Rows> {
+        let columns: Vec<_> = value_batch
+            .columns()
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != self.value_schema.timestamp_index)
+            .map(|(_, column)| column.clone())
+            .collect();
+        self.value_converter
+            .convert_all_columns(&columns, value_batch.num_rows())
     }
 }
 
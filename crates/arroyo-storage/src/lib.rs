@@ -10,11 +10,13 @@ use arroyo_types::{S3_ENDPOINT_ENV, S3_REGION_ENV};
 use aws::ArroyoCredentialProvider;
 use bytes::Bytes;
 use futures::{Stream, StreamExt};
-use object_store::aws::{AmazonS3ConfigKey, AwsCredential};
+use object_store::aws::{AmazonS3ConfigKey, AwsCredential, S3ConditionalPut};
 use object_store::gcp::GoogleCloudStorageBuilder;
 use object_store::multipart::PartId;
 use object_store::path::Path;
-use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectStore};
+use object_store::{
+    aws::AmazonS3Builder, local::LocalFileSystem, ListResult, ObjectMeta, ObjectStore,
+};
 use object_store::{CredentialProvider, MultipartId};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
@@ -431,6 +433,14 @@ impl StorageProvider {
             );
         }
 
+        // conditional puts let writers (like the Iceberg sink) atomically create files, which S3
+        // supports with If-None-Match; this can be overridden for stores that don't
+        if !s3_options.contains_key(&AmazonS3ConfigKey::ConditionalPut)
+            && std::env::var("AWS_CONDITIONAL_PUT").is_err()
+        {
+            builder = builder.with_conditional_put(S3ConditionalPut::ETagMatch);
+        }
+
         let mut canonical_url = match (&config.region, &config.endpoint) {
             (_, Some(endpoint)) => {
                 format!("s3::{}/{}", endpoint, config.bucket)
@@ -550,6 +560,19 @@ impl StorageProvider {
         &self,
         include_subdirectories: bool,
     ) -> Result<impl Stream<Item = Result<Path, object_store::Error>> + '_, StorageError> {
+        Ok(self
+            .list_with_metadata(include_subdirectories)
+            .await?
+            .map(|meta| meta.map(|meta| meta.location)))
+    }
+
+    /// Lists the objects under the key of this provider, along with their metadata (like size and
+    /// last-modified time)
+    pub async fn list_with_metadata(
+        &self,
+        include_subdirectories: bool,
+    ) -> Result<impl Stream<Item = Result<ObjectMeta, object_store::Error>> + '_, StorageError>
+    {
         let key_path: Option<Path> = self.config.key().map(|key| key.to_string().into());
         let key_part_count = key_path
             .as_ref()
@@ -562,12 +585,12 @@ impl StorageProvider {
                 let result = {
                     match meta {
                         Ok(metadata) => {
-                            let path = metadata.location;
-                            if !include_subdirectories && path.parts().count() != key_part_count + 1
+                            if !include_subdirectories
+                                && metadata.location.parts().count() != key_part_count + 1
                             {
                                 None
                             } else {
-                                Some(Ok(path))
+                                Some(Ok(metadata))
                             }
                         }
                         Err(err) => Some(Err(err)),
@@ -579,6 +602,16 @@ impl StorageProvider {
         Ok(list)
     }
 
+    /// Lists the objects and subdirectories directly under `prefix`, or under the key of this
+    /// provider if `prefix` is None
+    pub async fn list_directory(&self, prefix: Option<&Path>) -> Result<ListResult, StorageError> {
+        let key_path: Option<Path> = self.config.key().map(|key| key.to_string().into());
+        self.object_store
+            .list_with_delimiter(prefix.or(key_path.as_ref()))
+            .await
+            .map_err(Into::into)
+    }
+
     pub async fn get<P: Into<String>>(&self, path: P) -> Result<Bytes, StorageError> {
         let path: String = path.into();
         let bytes = self
@@ -655,7 +688,8 @@ impl StorageProvider {
         Ok(format!("{}/{}", self.canonical_url, path))
     }
 
-    fn qualify_path(&self, path: &Path) -> Path {
+    /// Prefixes `path` with the key of this provider, producing a path in the backing store
+    pub fn qualify_path(&self, path: &Path) -> Path {
         match self.config.key() {
             Some(prefix) => {
                 let prefix_path: Path = prefix.to_string().into();
@@ -408,16 +408,61 @@ impl UserError {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SourceError {
-    BadData { details: String },
-    Other { name: String, details: String },
+    BadData {
+        details: String,
+        raw_data: Option<Vec<u8>>,
+        /// The timestamp of the record that failed to deserialize
+        timestamp: Option<SystemTime>,
+    },
+    Other {
+        name: String,
+        details: String,
+    },
 }
 
 impl SourceError {
     pub fn bad_data(details: impl Into<String>) -> SourceError {
         SourceError::BadData {
             details: details.into(),
+            raw_data: None,
+            timestamp: None,
         }
     }
+
+    /// Attaches the raw bytes of the record that failed to deserialize, which are sent to
+    /// the dead-letter sink if one is configured
+    pub fn with_raw_data(self, data: &[u8]) -> SourceError {
+        match self {
+            SourceError::BadData {
+                details,
+                raw_data: None,
+                timestamp,
+            } => SourceError::BadData {
+                details,
+                raw_data: Some(data.to_vec()),
+                timestamp,
+            },
+            e => e,
+        }
+    }
+
+    /// Attaches the timestamp of the record that failed to deserialize, which is used as the
+    /// ingest time of its dead letter
+    pub fn with_timestamp(self, time: SystemTime) -> SourceError {
+        match self {
+            SourceError::BadData {
+                details,
+                raw_data,
+                timestamp: None,
+            } => SourceError::BadData {
+                details,
+                raw_data,
+                timestamp: Some(time),
+            },
+            e => e,
+        }
+    }
+
     pub fn other(name: impl Into<String>, details: impl Into<String>) -> SourceError {
         SourceError::Other {
             name: name.into(),
@@ -427,11 +472,22 @@ impl SourceError {
 
     pub fn details(&self) -> &String {
         match self {
-            SourceError::BadData { details } | SourceError::Other { details, .. } => details,
+            SourceError::BadData { details, .. } | SourceError::Other { details, .. } => details,
         }
     }
 }
 
+/// A value that a source attaches to a message alongside its payload (like a Kafka key or
+/// offset), which can be exposed to SQL as a metadata column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetadataValue<'a> {
+    Int32(i32),
+    Int64(i64),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Timestamp(SystemTime),
+}
+
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Serialize, Deserialize)]
 pub enum UpdatingData<T: Data> {
     Retract(T),
@@ -782,16 +838,32 @@ pub fn range_for_server(i: usize, n: usize) -> RangeInclusive<u64> {
     start..=end
 }
 
-pub fn should_flush(size: usize, time: Instant) -> bool {
-    static FLUSH_SIZE: OnceLock<usize> = OnceLock::new();
-    let flush_size =
-        FLUSH_SIZE.get_or_init(|| u32_config(BATCH_SIZE_ENV, DEFAULT_BATCH_SIZE as u32) as usize);
+/// How many records are buffered into a batch before it's emitted, and how long to wait for a
+/// batch to fill
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchingConfig {
+    pub size: usize,
+    pub linger: Duration,
+}
 
-    static FLUSH_LINGER: OnceLock<Duration> = OnceLock::new();
-    let flush_linger =
-        FLUSH_LINGER.get_or_init(|| duration_millis_config(BATCH_LINGER_MS_ENV, DEFAULT_LINGER));
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        static DEFAULT: OnceLock<BatchingConfig> = OnceLock::new();
+        *DEFAULT.get_or_init(|| BatchingConfig {
+            size: u32_config(BATCH_SIZE_ENV, DEFAULT_BATCH_SIZE as u32) as usize,
+            linger: duration_millis_config(BATCH_LINGER_MS_ENV, DEFAULT_LINGER),
+        })
+    }
+}
 
-    size > 0 && (size >= *flush_size || time.elapsed() >= *flush_linger)
+impl BatchingConfig {
+    pub fn should_flush(&self, size: usize, time: Instant) -> bool {
+        size > 0 && (size >= self.size || time.elapsed() >= self.linger)
+    }
+}
+
+pub fn should_flush(size: usize, time: Instant) -> bool {
+    BatchingConfig::default().should_flush(size, time)
 }
 
 #[cfg(test)]
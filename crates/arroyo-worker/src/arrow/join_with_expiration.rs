@@ -5,16 +5,21 @@ use std::{
 };
 
 use anyhow::Result;
-use arrow::compute::concat_batches;
-use arrow_array::RecordBatch;
+use arrow::buffer::BooleanBuffer;
+use arrow::compute::{concat_batches, filter_record_batch, not};
+use arrow::row::{Row, RowConverter, SortField};
+use arrow_array::{cast::AsArray, BooleanArray, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
 use arroyo_df::physical::{ArroyoPhysicalExtensionCodec, DecodingContext};
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::{ArrowOperator, OperatorConstructor, OperatorNode, Registry};
 use arroyo_rpc::{
     df::ArroyoSchema,
     grpc::{api, TableConfig},
+    IS_RETRACT_FIELD, TIMESTAMP_FIELD,
 };
 use arroyo_state::timestamp_table_config;
+use arroyo_types::Watermark;
 use datafusion::execution::context::SessionContext;
 use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion::physical_plan::ExecutionPlan;
@@ -22,6 +27,38 @@ use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNod
 use futures::StreamExt;
 use prost::Message;
 
+// how long rows are kept for joins that don't bound the time between matching rows
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoinSide {
+    Left,
+    Right,
+}
+
+impl JoinSide {
+    fn table_name(&self) -> &'static str {
+        match self {
+            JoinSide::Left => "left",
+            JoinSide::Right => "right",
+        }
+    }
+
+    fn unmatched_table_name(&self) -> &'static str {
+        match self {
+            JoinSide::Left => "left_unmatched",
+            JoinSide::Right => "right_unmatched",
+        }
+    }
+
+    fn other(&self) -> Self {
+        match self {
+            JoinSide::Left => JoinSide::Right,
+            JoinSide::Right => JoinSide::Left,
+        }
+    }
+}
+
 pub struct JoinWithExpiration {
     left_expiration: Duration,
     right_expiration: Duration,
@@ -29,94 +66,496 @@ pub struct JoinWithExpiration {
     right_input_schema: ArroyoSchema,
     left_schema: ArroyoSchema,
     right_schema: ArroyoSchema,
+    // the keys of each side whose rows have been emitted without a match, for outer joins
+    left_unmatched_schema: ArroyoSchema,
+    right_unmatched_schema: ArroyoSchema,
+    // the columns of each input that are passed to the join, excluding keys and retractions
+    left_value_indices: Vec<usize>,
+    right_value_indices: Vec<usize>,
+    output_schema: SchemaRef,
+    // whether the output has a retraction column, for outer joins and joins over updating inputs
+    is_updating: bool,
+    join_type: api::JoinType,
     left_passer: Arc<RwLock<Option<RecordBatch>>>,
     right_passer: Arc<RwLock<Option<RecordBatch>>>,
     join_execution_plan: Arc<dyn ExecutionPlan>,
 }
 
+/// Splits a batch into the rows with non-null keys and the rows with a null key, which can't
+/// match any rows on the other side
+fn split_null_keys(
+    schema: &ArroyoSchema,
+    batch: RecordBatch,
+) -> Result<(RecordBatch, Option<RecordBatch>)> {
+    let Some(key_indices) = &schema.key_indices else {
+        return Ok((batch, None));
+    };
+
+    let mut valid = BooleanBuffer::new_set(batch.num_rows());
+    for index in key_indices {
+        if let Some(nulls) = batch.column(*index).logical_nulls() {
+            valid = &valid & nulls.inner();
+        }
+    }
+
+    if valid.count_set_bits() == batch.num_rows() {
+        return Ok((batch, None));
+    }
+
+    let valid = BooleanArray::new(valid, None);
+    Ok((
+        filter_record_batch(&batch, &valid)?,
+        Some(filter_record_batch(&batch, &not(&valid)?)?),
+    ))
+}
+
+/// Splits a batch from an updating input into its appended and retracted rows. Rows that are
+/// both appended and retracted within the batch cancel out and are dropped, so that the remaining
+/// retractions can be processed before the appends.
+fn split_retractions(
+    schema: &ArroyoSchema,
+    batch: RecordBatch,
+) -> Result<(RecordBatch, Option<RecordBatch>)> {
+    let Ok(retract_index) = schema.schema.index_of(IS_RETRACT_FIELD) else {
+        return Ok((batch, None));
+    };
+
+    let is_retract = BooleanArray::new(
+        batch.column(retract_index).as_boolean().values().clone(),
+        None,
+    );
+    if is_retract.true_count() == 0 {
+        return Ok((batch, None));
+    }
+
+    let appends = filter_record_batch(&batch, &not(&is_retract)?)?;
+    let retracts = filter_record_batch(&batch, &is_retract)?;
+
+    // rows are matched on every value but the timestamp, like retractions of stored rows
+    let value_indices: Vec<_> = (0..batch.num_columns())
+        .filter(|index| *index != schema.timestamp_index && *index != retract_index)
+        .collect();
+    let converter = RowConverter::new(
+        value_indices
+            .iter()
+            .map(|index| SortField::new(batch.schema().field(*index).data_type().clone()))
+            .collect(),
+    )?;
+    let append_rows = converter.convert_columns(appends.project(&value_indices)?.columns())?;
+    let retract_rows = converter.convert_columns(retracts.project(&value_indices)?.columns())?;
+
+    // the indices of the appended rows with each value, in order, so each retraction cancels the
+    // first one that hasn't been cancelled yet
+    let mut append_indices: HashMap<Row, Vec<usize>> = HashMap::new();
+    for (index, row) in append_rows.iter().enumerate().rev() {
+        append_indices.entry(row).or_default().push(index);
+    }
+
+    let mut keep_appends = vec![true; appends.num_rows()];
+    let mut keep_retracts = vec![true; retracts.num_rows()];
+    for (retracted_index, retracted) in retract_rows.iter().enumerate() {
+        if let Some(append_index) = append_indices
+            .get_mut(&retracted)
+            .and_then(|indices| indices.pop())
+        {
+            keep_appends[append_index] = false;
+            keep_retracts[retracted_index] = false;
+        }
+    }
+
+    Ok((
+        filter_record_batch(&appends, &BooleanArray::from(keep_appends))?,
+        Some(filter_record_batch(
+            &retracts,
+            &BooleanArray::from(keep_retracts),
+        )?),
+    ))
+}
+
+/// The columns of an input that the join plan operates on, which excludes the keys and the
+/// retraction flag, and the schema of those columns
+fn value_columns(input_schema: &ArroyoSchema) -> Result<(Vec<usize>, ArroyoSchema)> {
+    let indices: Vec<_> = input_schema
+        .value_indices(true)
+        .into_iter()
+        .filter(|index| input_schema.schema.field(*index).name() != IS_RETRACT_FIELD)
+        .collect();
+    let schema = input_schema.schema.project(&indices)?;
+    let timestamp_index = schema.index_of(TIMESTAMP_FIELD)?;
+    Ok((
+        indices,
+        ArroyoSchema::new_unkeyed(Arc::new(schema), timestamp_index),
+    ))
+}
+
+/// The schema of the table that records which keys of an input have had rows emitted without a
+/// match: the key columns and the timestamp of each of those rows, so that they expire with them,
+/// and a retraction flag that's used to clear them once a match arrives
+fn unmatched_schema(input_schema: &ArroyoSchema) -> ArroyoSchema {
+    let key_indices = input_schema.key_indices.clone().unwrap_or_default();
+    let mut fields: Vec<_> = key_indices
+        .iter()
+        .map(|index| input_schema.schema.field(*index).clone())
+        .collect();
+    fields.push(
+        input_schema
+            .schema
+            .field(input_schema.timestamp_index)
+            .clone(),
+    );
+    fields.push(Field::new(IS_RETRACT_FIELD, DataType::Boolean, false));
+    ArroyoSchema::new_keyed(
+        Arc::new(Schema::new(fields)),
+        key_indices.len(),
+        (0..key_indices.len()).collect(),
+    )
+}
+
 impl JoinWithExpiration {
-    async fn process_left(
+    fn input_schema(&self, side: JoinSide) -> &ArroyoSchema {
+        match side {
+            JoinSide::Left => &self.left_input_schema,
+            JoinSide::Right => &self.right_input_schema,
+        }
+    }
+
+    fn value_schema(&self, side: JoinSide) -> &ArroyoSchema {
+        match side {
+            JoinSide::Left => &self.left_schema,
+            JoinSide::Right => &self.right_schema,
+        }
+    }
+
+    fn unmatched_schema(&self, side: JoinSide) -> &ArroyoSchema {
+        match side {
+            JoinSide::Left => &self.left_unmatched_schema,
+            JoinSide::Right => &self.right_unmatched_schema,
+        }
+    }
+
+    fn empty_batch(&self, side: JoinSide) -> RecordBatch {
+        RecordBatch::new_empty(self.value_schema(side).schema.clone())
+    }
+
+    fn value_batch(&self, side: JoinSide, batch: &RecordBatch) -> Result<RecordBatch> {
+        Ok(batch.project(match side {
+            JoinSide::Left => &self.left_value_indices,
+            JoinSide::Right => &self.right_value_indices,
+        })?)
+    }
+
+    /// Whether rows on this side are emitted even if they don't match any rows on the other side
+    fn is_outer(&self, side: JoinSide) -> bool {
+        matches!(
+            (self.join_type, side),
+            (api::JoinType::Left, JoinSide::Left)
+                | (api::JoinType::Right, JoinSide::Right)
+                | (api::JoinType::Full, _)
+        )
+    }
+
+    async fn process_side(
         &mut self,
-        record_batch: RecordBatch,
+        side: JoinSide,
+        batch: RecordBatch,
         ctx: &mut ArrowContext,
     ) -> Result<()> {
-        let left_table = ctx
+        let other = side.other();
+        let (batch, null_keyed) = split_null_keys(self.input_schema(side), batch)?;
+
+        // rows with null keys will never find a match, so don't need to be stored
+        if let Some(null_keyed) = null_keyed {
+            if self.is_outer(side) {
+                let (appends, retracts) = split_retractions(self.input_schema(side), null_keyed)?;
+                let empty = self.empty_batch(other);
+                if let Some(retracts) = retracts.filter(|r| r.num_rows() > 0) {
+                    let retracts = self.value_batch(side, &retracts)?;
+                    self.compute_pair(side, retracts, empty.clone(), true, ctx)
+                        .await;
+                }
+                if appends.num_rows() > 0 {
+                    let appends = self.value_batch(side, &appends)?;
+                    self.compute_pair(side, appends, empty, false, ctx).await;
+                }
+            }
+        }
+
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+
+        // retractions go first, so that an update's retraction is emitted before its new value
+        let (appends, retracts) = split_retractions(self.input_schema(side), batch)?;
+        if let Some(retracts) = retracts.filter(|r| r.num_rows() > 0) {
+            self.process_retractions(side, retracts, ctx).await?;
+        }
+        if appends.num_rows() > 0 {
+            self.process_appends(side, appends, ctx).await?;
+        }
+        Ok(())
+    }
+
+    /// Records that these rows (in the side's input schema) were emitted without a match
+    async fn set_unmatched(
+        &self,
+        side: JoinSide,
+        rows: &RecordBatch,
+        ctx: &mut ArrowContext,
+    ) -> Result<()> {
+        let input_schema = self.input_schema(side);
+        let mut indices = input_schema.key_indices.clone().unwrap_or_default();
+        indices.push(input_schema.timestamp_index);
+
+        let mut columns = rows.project(&indices)?.columns().to_vec();
+        columns.push(Arc::new(BooleanArray::from(vec![false; rows.num_rows()])));
+        let batch = RecordBatch::try_new(self.unmatched_schema(side).schema.clone(), columns)?;
+
+        ctx.table_manager
+            .get_key_time_table(side.unmatched_table_name(), ctx.last_present_watermark())
+            .await
+            .expect("should have unmatched table for this side")
+            .insert(batch)
+            .await?;
+        Ok(())
+    }
+
+    /// Clears the record of a key's rows having been emitted without a match, returning whether
+    /// they had been
+    async fn take_unmatched(
+        &self,
+        side: JoinSide,
+        key: &[u8],
+        ctx: &mut ArrowContext,
+    ) -> Result<bool> {
+        let table = ctx
             .table_manager
-            .get_key_time_table("left", ctx.last_present_watermark())
+            .get_key_time_table(side.unmatched_table_name(), ctx.last_present_watermark())
             .await
-            .expect("should have left table");
-        let left_rows = left_table
-            .insert(record_batch.clone())
+            .expect("should have unmatched table for this side");
+
+        let Some(unmatched) = table.get_rows(key)? else {
+            return Ok(false);
+        };
+
+        let mut columns = unmatched.columns().to_vec();
+        let retract_index = columns.len() - 1;
+        columns[retract_index] = Arc::new(BooleanArray::from(vec![true; unmatched.num_rows()]));
+        table
+            .insert(RecordBatch::try_new(unmatched.schema(), columns)?)
+            .await?;
+        Ok(true)
+    }
+
+    /// Emits the rows on the other side that are left without a match once this side has no rows
+    /// for their keys, for outer joins, and records that they've been emitted
+    async fn emit_newly_unmatched(
+        &mut self,
+        side: JoinSide,
+        keys: Vec<Vec<u8>>,
+        ctx: &mut ArrowContext,
+    ) -> Result<()> {
+        let other = side.other();
+        if !self.is_outer(other) || keys.is_empty() {
+            return Ok(());
+        }
+
+        // skip rows that have already been emitted without a match
+        let unmatched_table = ctx
+            .table_manager
+            .get_key_time_table(other.unmatched_table_name(), ctx.last_present_watermark())
             .await
-            .expect("should insert");
-        let right_table = ctx
+            .expect("should have unmatched table for other side");
+        let keys: Vec<_> = keys
+            .into_iter()
+            .filter(|key| !unmatched_table.contains_key(key))
+            .collect();
+
+        let other_table = ctx
             .table_manager
-            .get_key_time_table("right", ctx.last_present_watermark())
+            .get_key_time_table(other.table_name(), ctx.last_present_watermark())
             .await
-            .expect("should have right table");
-        let mut right_batches = vec![];
-        for row in left_rows {
-            if let Some(batch) = right_table
-                .get_batch(row.as_ref())
+            .expect("should have table for other side");
+
+        let mut newly_unmatched = vec![];
+        for key in keys {
+            if let Some(rows) = other_table
+                .get_rows(&key)
+                .expect("shouldn't error getting rows")
+            {
+                newly_unmatched.push(rows);
+            }
+        }
+
+        if newly_unmatched.is_empty() {
+            return Ok(());
+        }
+
+        let unmatched = concat_batches(&self.input_schema(other).schema, newly_unmatched.iter())?;
+        let values = self.value_batch(other, &unmatched)?;
+        self.compute_pair(side, self.empty_batch(side), values, false, ctx)
+            .await;
+        self.set_unmatched(other, &unmatched, ctx).await
+    }
+
+    async fn process_appends(
+        &mut self,
+        side: JoinSide,
+        batch: RecordBatch,
+        ctx: &mut ArrowContext,
+    ) -> Result<()> {
+        let other = side.other();
+        let table = ctx
+            .table_manager
+            .get_key_time_table(side.table_name(), ctx.last_present_watermark())
+            .await
+            .expect("should have table for this side");
+
+        let partitions = table.partition_by_key(&batch)?;
+        table.insert(batch.clone()).await.expect("should insert");
+
+        let other_table = ctx
+            .table_manager
+            .get_key_time_table(other.table_name(), ctx.last_present_watermark())
+            .await
+            .expect("should have table for other side");
+
+        let mut matched = vec![];
+        let mut unmatched = vec![];
+        for (key, rows) in removed {
+            match other_table
+                .get_batch(&key)
                 .expect("shouldn't error getting batch")
             {
-                right_batches.push(batch.clone());
+                Some(other_batch) => matched.push((key, other_batch.clone())),
+                None => unmatched.push(rows),
+            }
+        }
+
+        let other_schema = self.value_schema(other).schema.clone();
+
+        if self.is_outer(other) {
+            // rows on the other side that were emitted without a match are retracted now that
+            // they have one; joining against an empty batch produces the same rows
+            let mut previously_unmatched = vec![];
+            for (key, other_batch) in &matched {
+                if self.take_unmatched(other, key.as_ref(), ctx).await? {
+                    previously_unmatched.push(other_batch.clone());
+                }
+            }
+
+            if !previously_unmatched.is_empty() {
+                let unmatched = concat_batches(&other_schema, previously_unmatched.iter())?;
+                self.compute_pair(side, self.empty_batch(side), unmatched, true, ctx)
+                    .await;
+            }
+        }
+
+        let other_batch = concat_batches(&other_schema, matched.iter().map(|(_, b)| b))?;
+        let values = self.value_batch(side, &batch)?;
+        self.compute_pair(side, values, other_batch, false, ctx)
+            .await;
+
+        if self.is_outer(side) {
+            for rows in unmatched {
+                self.set_unmatched(side, &rows, ctx).await?;
             }
         }
-        let right_batch = concat_batches(&self.right_schema.schema, right_batches.iter()).unwrap();
-        self.compute_pair(
-            self.left_input_schema.unkeyed_batch(&record_batch)?,
-            right_batch,
-            ctx,
-        )
-        .await;
         Ok(())
     }
 
-    async fn process_right(
+    /// Removes retracted rows from this side's state and retracts the results that the removed
+    /// rows produced, which are the same as joining them again
+    async fn process_retractions(
         &mut self,
-        right_batch: RecordBatch,
+        side: JoinSide,
+        batch: RecordBatch,
         ctx: &mut ArrowContext,
     ) -> Result<()> {
-        let right_table = ctx
+        let other = side.other();
+        let table = ctx
             .table_manager
-            .get_key_time_table("right", ctx.last_present_watermark())
+            .get_key_time_table(side.table_name(), ctx.last_present_watermark())
             .await
-            .expect("should have right table");
-        let right_rows = right_table
-            .insert(right_batch.clone())
-            .await
-            .expect("should insert");
-        let left_table = ctx
+            .expect("should have table for this side");
+
+        // retractions of rows that aren't stored, because they've expired, are dropped rather
+        // than retracting results that may never have been emitted
+        let (_, removed) = table.insert_retracting(batch).await.expect("should insert");
+
+        // keys that no longer have any rows on this side
+        let emptied_keys: Vec<_> = removed
+            .iter()
+            .filter(|(key, _)| !table.contains_key(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let other_table = ctx
             .table_manager
-            .get_key_time_table("left", ctx.last_present_watermark())
+            .get_key_time_table(other.table_name(), ctx.last_present_watermark())
             .await
-            .expect("should have left table");
-        let mut left_batches = vec![];
-        for row in right_rows {
-            if let Some(batch) = left_table
-                .get_batch(row.as_ref())
+            .expect("should have table for other side");
+
+        let mut retracted = vec![];
+        let mut other_batches = vec![];
+        let mut without_match = vec![];
+        for (key, rows) in removed {
+            match other_table
+                .get_batch(&key)
                 .expect("shouldn't error getting batch")
             {
-                left_batches.push(batch.clone());
+                Some(other_batch) => {
+                    other_batches.push(other_batch.clone());
+                    retracted.push(rows);
+                }
+                None => without_match.push((key, rows)),
             }
         }
-        let left_batch = concat_batches(&self.left_schema.schema, left_batches.iter()).unwrap();
-        self.compute_pair(
-            left_batch,
-            self.right_input_schema.unkeyed_batch(&right_batch)?,
-            ctx,
-        )
-        .await;
-        Ok(())
+
+        // rows without a match only produced a result if they were emitted without one; if the
+        // rows they matched have since expired, those results can no longer be retracted
+        if self.is_outer(side) {
+            let unmatched_table = ctx
+                .table_manager
+                .get_key_time_table(side.unmatched_table_name(), ctx.last_present_watermark())
+                .await
+                .expect("should have unmatched table for this side");
+            retracted.extend(
+                without_match
+                    .into_iter()
+                    .filter(|(key, _)| unmatched_table.contains_key(key))
+                    .map(|(_, rows)| rows),
+            );
+        }
+
+        let other_schema = self.value_schema(other).schema.clone();
+        let other_batch = concat_batches(&other_schema, other_batches.iter())?;
+        let retracted = concat_batches(&self.input_schema(side).schema, retracted.iter())?;
+        let values = self.value_batch(side, &retracted)?;
+        self.compute_pair(side, values, other_batch, true, ctx)
+            .await;
+
+        // the unmatched rows for these keys have just been retracted
+        if self.is_outer(side) {
+            for key in &emptied_keys {
+                self.take_unmatched(side, key, ctx).await?;
+            }
+        }
+
+        self.emit_newly_unmatched(side, emptied_keys, ctx).await
     }
 
     async fn compute_pair(
         &mut self,
-        left: RecordBatch,
-        right: RecordBatch,
+        side: JoinSide,
+        batch: RecordBatch,
+        other_batch: RecordBatch,
+        is_retract: bool,
         ctx: &mut ArrowContext,
     ) {
+        let (left, right) = match side {
+            JoinSide::Left => (batch, other_batch),
+            JoinSide::Right => (other_batch, batch),
+        };
         {
             self.right_passer.write().unwrap().replace(right);
             self.left_passer.write().unwrap().replace(left);
@@ -128,7 +567,20 @@ impl JoinWithExpiration {
             .expect("successfully computed?");
         while let Some(batch) = records.next().await {
             let batch = batch.expect("should be able to compute batch");
-            ctx.collect(batch).await;
+            if !self.is_updating {
+                ctx.collect(batch).await;
+            } else {
+                let mut columns = batch.columns().to_vec();
+                columns.push(Arc::new(BooleanArray::from(vec![
+                    is_retract;
+                    batch.num_rows()
+                ])));
+                ctx.collect(
+                    RecordBatch::try_new(self.output_schema.clone(), columns)
+                        .expect("join output should match the output schema"),
+                )
+                .await;
+            }
         }
     }
 }
@@ -142,6 +594,37 @@ impl ArrowOperator for JoinWithExpiration {
     async fn process_batch(&mut self, _record_batch: RecordBatch, _ctx: &mut ArrowContext) {
         unreachable!();
     }
+
+    async fn handle_watermark(
+        &mut self,
+        watermark: Watermark,
+        ctx: &mut ArrowContext,
+    ) -> Option<Watermark> {
+        let last_watermark = ctx.last_present_watermark();
+        // rows are expired by their age, even for updating inputs, so a retraction that arrives
+        // after the row it retracts has expired is dropped and the results it was joined in stay;
+        // join_ttl needs to be longer than rows in updating inputs are expected to live
+        //
+        // the results that expired rows were matched in stay as they were, so the rows they
+        // matched on the other side aren't emitted again without a match
+        for side in [JoinSide::Left, JoinSide::Right] {
+            ctx.table_manager
+                .get_key_time_table(side.table_name(), last_watermark)
+                .await
+                .expect("should have table for this side")
+                .expire(last_watermark)
+                .expect("should expire rows");
+
+            ctx.table_manager
+                .get_key_time_table(side.unmatched_table_name(), last_watermark)
+                .await
+                .expect("should have unmatched table for this side")
+                .expire(last_watermark)
+                .expect("should expire unmatched keys");
+        }
+        Some(watermark)
+    }
+
     async fn process_batch_index(
         &mut self,
         index: usize,
@@ -151,11 +634,11 @@ impl ArrowOperator for JoinWithExpiration {
     ) {
         match index / (total_inputs / 2) {
             0 => self
-                .process_left(record_batch, ctx)
+                .process_side(JoinSide::Left, record_batch, ctx)
                 .await
                 .expect("should process left"),
             1 => self
-                .process_right(record_batch, ctx)
+                .process_side(JoinSide::Right, record_batch, ctx)
                 .await
                 .expect("should process right"),
             _ => unreachable!(),
@@ -184,6 +667,26 @@ impl ArrowOperator for JoinWithExpiration {
                 self.right_input_schema.clone(),
             ),
         );
+        tables.insert(
+            "left_unmatched".to_string(),
+            timestamp_table_config(
+                "left_unmatched",
+                "left keys emitted without a match",
+                self.left_expiration,
+                false,
+                self.left_unmatched_schema.clone(),
+            ),
+        );
+        tables.insert(
+            "right_unmatched".to_string(),
+            timestamp_table_config(
+                "right_unmatched",
+                "right keys emitted without a match",
+                self.right_expiration,
+                false,
+                self.right_unmatched_schema.clone(),
+            ),
+        );
         tables
     }
 }
@@ -212,21 +715,135 @@ impl OperatorConstructor for JoinWithExpirationConstructor {
             &codec,
         )?;
 
+        let join_type = config.join_type();
         let left_input_schema: ArroyoSchema = config.left_schema.unwrap().try_into()?;
         let right_input_schema: ArroyoSchema = config.right_schema.unwrap().try_into()?;
-        let left_schema = left_input_schema.schema_without_keys()?;
-        let right_schema = right_input_schema.schema_without_keys()?;
+        let output_schema: ArroyoSchema = config.output_schema.unwrap().try_into()?;
+        let (left_value_indices, left_schema) = value_columns(&left_input_schema)?;
+        let (right_value_indices, right_schema) = value_columns(&right_input_schema)?;
+        let is_updating = output_schema.schema.index_of(IS_RETRACT_FIELD).is_ok();
+        let left_unmatched_schema = unmatched_schema(&left_input_schema);
+        let right_unmatched_schema = unmatched_schema(&right_input_schema);
 
         Ok(OperatorNode::from_operator(Box::new(JoinWithExpiration {
-            left_expiration: Duration::from_secs(3600),
-            right_expiration: Duration::from_secs(3600),
+            left_expiration: config
+                .left_ttl_micros
+                .map(Duration::from_micros)
+                .unwrap_or(DEFAULT_TTL),
+            right_expiration: config
+                .right_ttl_micros
+                .map(Duration::from_micros)
+                .unwrap_or(DEFAULT_TTL),
             left_input_schema,
             right_input_schema,
             left_schema,
             right_schema,
+            left_unmatched_schema,
+            right_unmatched_schema,
+            left_value_indices,
+            right_value_indices,
+            output_schema: output_schema.schema,
+            is_updating,
+            join_type,
             left_passer,
             right_passer,
             join_execution_plan,
         })))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{BooleanArray, Int64Array, RecordBatch, TimestampNanosecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use arroyo_rpc::df::ArroyoSchema;
+
+    use super::{split_null_keys, split_retractions};
+
+    #[test]
+    fn test_split_null_keys() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("_key_0", DataType::Int64, true),
+            Field::new("value", DataType::Int64, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(1), None, Some(3)])),
+                Arc::new(Int64Array::from(vec![10, 20, 30])),
+                Arc::new(TimestampNanosecondArray::from(vec![0, 0, 0])),
+            ],
+        )
+        .unwrap();
+
+        let schema = ArroyoSchema::new_keyed(schema, 2, vec![0]);
+        let (keyed, null_keyed) = split_null_keys(&schema, batch.clone()).unwrap();
+        assert_eq!(keyed.column(1).as_ref(), &Int64Array::from(vec![10, 30]));
+        assert_eq!(
+            null_keyed.unwrap().column(1).as_ref(),
+            &Int64Array::from(vec![20])
+        );
+
+        let (keyed, null_keyed) = split_null_keys(&schema, batch.slice(0, 1)).unwrap();
+        assert_eq!(keyed.num_rows(), 1);
+        assert!(null_keyed.is_none());
+    }
+
+    #[test]
+    fn test_split_retractions() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("_key_0", DataType::Int64, true),
+            Field::new("value", DataType::Int64, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+            Field::new("_is_retract", DataType::Boolean, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 1, 2])),
+                Arc::new(Int64Array::from(vec![10, 20, 30])),
+                Arc::new(TimestampNanosecondArray::from(vec![0, 0, 0])),
+                Arc::new(BooleanArray::from(vec![false, true, false])),
+            ],
+        )
+        .unwrap();
+
+        let schema = ArroyoSchema::new_keyed(schema, 2, vec![0]);
+        let (appends, retracts) = split_retractions(&schema, batch.clone()).unwrap();
+        assert_eq!(appends.column(1).as_ref(), &Int64Array::from(vec![10, 30]));
+        assert_eq!(
+            retracts.unwrap().column(1).as_ref(),
+            &Int64Array::from(vec![20])
+        );
+
+        let (appends, retracts) = split_retractions(&schema, batch.slice(0, 1)).unwrap();
+        assert_eq!(appends.num_rows(), 1);
+        assert!(retracts.is_none());
+
+        // a row that's appended and retracted in the same batch cancels out
+        let batch = RecordBatch::try_new(
+            schema.schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 1, 1])),
+                Arc::new(Int64Array::from(vec![10, 20, 10])),
+                Arc::new(TimestampNanosecondArray::from(vec![0, 0, 1])),
+                Arc::new(BooleanArray::from(vec![false, false, true])),
+            ],
+        )
+        .unwrap();
+        let (appends, retracts) = split_retractions(&schema, batch).unwrap();
+        assert_eq!(appends.column(1).as_ref(), &Int64Array::from(vec![20]));
+        assert_eq!(retracts.unwrap().num_rows(), 0);
+    }
+}
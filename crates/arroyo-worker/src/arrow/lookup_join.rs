@@ -0,0 +1,317 @@
+use anyhow::anyhow;
+use arrow::compute::{cast, filter, interleave};
+use arrow_array::builder::make_builder;
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{DataType, Field};
+use arroyo_connectors::connectors;
+use arroyo_formats::de::ArrowDeserializer;
+use arroyo_operator::connector::LookupConnector;
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::{ArrowOperator, OperatorConstructor, OperatorNode, Registry};
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::api;
+use arroyo_rpc::schema_resolver::FailingSchemaResolver;
+use arroyo_rpc::{MetadataField, OperatorConfig};
+use arroyo_types::MetadataValue;
+use async_trait::async_trait;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion_proto::physical_plan::from_proto::parse_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+use datafusion_proto::protobuf::PhysicalExprNode;
+use lru::LruCache;
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::warn;
+
+const DEFAULT_MAX_CACHE_ENTRIES: usize = 10_000;
+const LOOKUP_BATCH_SIZE: usize = 1024;
+
+/// Enriches each input row with the row from an external lookup table whose key matches the
+/// join key. Lookups are made in batches for all of the keys in an input batch that aren't
+/// already cached, and the results (including misses) are kept in an LRU cache.
+pub struct LookupJoin {
+    name: String,
+    connector: Box<dyn LookupConnector>,
+    key_expr: Arc<dyn PhysicalExpr>,
+    join_type: api::JoinType,
+    deserializer: ArrowDeserializer,
+    lookup_schema: ArroyoSchema,
+    // the lookup columns for a row with no match in the lookup table
+    null_row: Vec<ArrayRef>,
+    cache: LruCache<String, (Instant, Option<Vec<ArrayRef>>)>,
+    ttl: Option<Duration>,
+}
+
+pub struct LookupJoinConstructor;
+
+impl OperatorConstructor for LookupJoinConstructor {
+    type ConfigT = api::LookupJoinOperator;
+
+    fn with_config(
+        &self,
+        config: Self::ConfigT,
+        registry: Arc<Registry>,
+    ) -> anyhow::Result<OperatorNode> {
+        let join_type = config.join_type();
+
+        let input_schema: ArroyoSchema = config
+            .input_schema
+            .ok_or_else(|| anyhow!("missing input schema"))?
+            .try_into()?;
+        let lookup_schema: ArroyoSchema = config
+            .lookup_schema
+            .ok_or_else(|| anyhow!("missing lookup schema"))?
+            .try_into()?;
+
+        let key_expr = parse_physical_expr(
+            &PhysicalExprNode::decode(&mut config.key_expr.as_slice())?,
+            &*registry,
+            &input_schema.schema,
+            &DefaultPhysicalExtensionCodec {},
+        )?;
+
+        let op = config
+            .connector
+            .ok_or_else(|| anyhow!("missing connector for lookup join"))?;
+        let operator_config: OperatorConfig = serde_json::from_str(&op.config)
+            .map_err(|e| anyhow!("invalid operator config for lookup join: {:?}", e))?;
+
+        let format: Format = operator_config
+            .format
+            .clone()
+            .ok_or_else(|| anyhow!("lookup table must have a format"))?;
+        let framing: Option<Framing> = operator_config.framing.clone();
+
+        // only the metadata fields that were selected in the query are part of the schema
+        let metadata_fields: Vec<MetadataField> = operator_config
+            .metadata_fields
+            .iter()
+            .filter(|f| lookup_schema.schema.index_of(&f.field_name).is_ok())
+            .cloned()
+            .collect();
+
+        let connector = connectors()
+            .get(op.connector.as_str())
+            .ok_or_else(|| anyhow!("no connector with name '{}'", op.connector))?
+            .make_lookup(operator_config)?;
+
+        let deserializer = ArrowDeserializer::with_schema_resolver(
+            format,
+            framing,
+            lookup_schema.clone(),
+            &metadata_fields,
+            BadData::Fail {},
+            Arc::new(FailingSchemaResolver::new()),
+        );
+
+        let null_row = lookup_fields(&lookup_schema)
+            .map(|(_, f)| arrow_array::new_null_array(f.data_type(), 1))
+            .collect();
+
+        let max_entries = config
+            .max_cache_entries
+            .map(|n| n as usize)
+            .unwrap_or(DEFAULT_MAX_CACHE_ENTRIES);
+
+        Ok(OperatorNode::from_operator(Box::new(LookupJoin {
+            name: config.name,
+            connector,
+            key_expr,
+            join_type,
+            deserializer,
+            lookup_schema,
+            null_row,
+            cache: LruCache::new(NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN)),
+            ttl: config.cache_ttl_micros.map(Duration::from_micros),
+        })))
+    }
+}
+
+/// The fields of the lookup schema that appear in the output, which is everything but the timestamp
+fn lookup_fields(schema: &ArroyoSchema) -> impl Iterator<Item = (usize, &Arc<Field>)> {
+    schema
+        .schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(move |(i, _)| *i != schema.timestamp_index)
+}
+
+impl LookupJoin {
+    fn cached(&mut self, key: &str) -> Option<&Option<Vec<ArrayRef>>> {
+        let ttl = self.ttl;
+        let (inserted, _) = self.cache.peek(key)?;
+        if ttl.is_some_and(|ttl| inserted.elapsed() > ttl) {
+            self.cache.pop(key);
+            return None;
+        }
+        self.cache.get(key).map(|(_, row)| row)
+    }
+
+    /// Deserializes a single value from the lookup table into the (single-row) lookup columns,
+    /// returning None if it couldn't be deserialized
+    async fn deserialize(&mut self, key: &str, value: &[u8]) -> Option<Vec<ArrayRef>> {
+        let mut builders: Vec<_> = self
+            .lookup_schema
+            .schema
+            .fields()
+            .iter()
+            .map(|f| make_builder(f.data_type(), 1))
+            .collect();
+
+        let metadata = HashMap::from([("key", MetadataValue::String(key))]);
+
+        let errors = self
+            .deserializer
+            .deserialize_slice(&mut builders, value, SystemTime::now(), Some(&metadata))
+            .await;
+
+        if let Some(error) = errors.first() {
+            warn!(
+                "failed to deserialize value for key '{}' in lookup table: {:?}",
+                key, error
+            );
+            return None;
+        }
+
+        let batch = match self.deserializer.flush_buffer() {
+            Some(Ok(batch)) => batch,
+            Some(Err(e)) => {
+                warn!(
+                    "failed to deserialize value for key '{}' in lookup table: {:?}",
+                    key, e
+                );
+                return None;
+            }
+            None => RecordBatch::try_new(
+                self.lookup_schema.schema.clone(),
+                builders.into_iter().map(|mut b| b.finish()).collect(),
+            )
+            .ok()?,
+        };
+
+        if batch.num_rows() == 0 {
+            return None;
+        }
+
+        Some(
+            lookup_fields(&self.lookup_schema)
+                .map(|(i, _)| batch.column(i).slice(0, 1))
+                .collect(),
+        )
+    }
+
+    /// Fetches the rows for keys that aren't in the cache from the lookup table, returning them
+    /// in the same order as the keys and adding them to the cache
+    async fn fetch(&mut self, keys: &[&str], ctx: &mut ArrowContext) -> Vec<Option<Vec<ArrayRef>>> {
+        let mut rows = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(LOOKUP_BATCH_SIZE) {
+            let chunk: Vec<String> = chunk.iter().map(|k| k.to_string()).collect();
+            let values = match self.connector.lookup(&chunk).await {
+                Ok(values) => values,
+                Err(e) => {
+                    ctx.report_error("failed to read from lookup table", format!("{:?}", e))
+                        .await;
+                    panic!("failed to read from lookup table: {:?}", e);
+                }
+            };
+
+            assert_eq!(
+                values.len(),
+                chunk.len(),
+                "lookup table returned the wrong number of values"
+            );
+
+            for (key, value) in chunk.into_iter().zip(values) {
+                let row = match value {
+                    Some(value) => self.deserialize(&key, &value).await,
+                    None => None,
+                };
+                self.cache.put(key, (Instant::now(), row.clone()));
+                rows.push(row);
+            }
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for LookupJoin {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let keys = self
+            .key_expr
+            .evaluate(&batch)
+            .and_then(|v| v.into_array(batch.num_rows()))
+            .expect("failed to evaluate lookup join key");
+        let keys = cast(&keys, &DataType::Utf8).expect("failed to cast lookup join key to string");
+        let keys = keys.as_string::<i32>();
+
+        // the rows for each distinct key in the batch, from the cache or the lookup table
+        let mut found: HashMap<&str, Option<Vec<ArrayRef>>> = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut missing = vec![];
+        for key in keys.iter().flatten() {
+            if !seen.insert(key) {
+                continue;
+            }
+            match self.cached(key) {
+                Some(row) => {
+                    found.insert(key, row.clone());
+                }
+                None => missing.push(key),
+            }
+        }
+
+        if !missing.is_empty() {
+            let rows = self.fetch(&missing, ctx).await;
+            found.extend(missing.into_iter().zip(rows));
+        }
+
+        // the lookup columns are assembled by interleaving the rows that were found, with the
+        // null row at index 0 for keys that have no match
+        let mut rows: Vec<&[ArrayRef]> = vec![self.null_row.as_slice()];
+        let mut row_indices: HashMap<&str, usize> = HashMap::new();
+        let mut indices = Vec::with_capacity(batch.num_rows());
+        for key in keys.iter() {
+            let index = match key.and_then(|k| Some((k, found.get(k)?.as_ref()?))) {
+                Some((key, row)) => *row_indices.entry(key).or_insert_with(|| {
+                    rows.push(row);
+                    rows.len() - 1
+                }),
+                None => 0,
+            };
+            indices.push((index, 0));
+        }
+
+        let mut columns = batch.columns().to_vec();
+        for i in 0..self.null_row.len() {
+            let arrays: Vec<&dyn Array> = rows.iter().map(|r| r[i].as_ref()).collect();
+            columns
+                .push(interleave(&arrays, &indices).expect("failed to build lookup join columns"));
+        }
+
+        if self.join_type == api::JoinType::Inner {
+            let matched: BooleanArray = indices.iter().map(|(i, _)| Some(*i != 0)).collect();
+            columns = columns
+                .iter()
+                .map(|c| filter(c, &matched).expect("failed to filter lookup join output"))
+                .collect();
+        }
+
+        let result = RecordBatch::try_new(ctx.out_schema.as_ref().unwrap().schema.clone(), columns)
+            .expect("lookup join output should match schema");
+
+        if result.num_rows() > 0 {
+            ctx.collect(result).await;
+        }
+    }
+}
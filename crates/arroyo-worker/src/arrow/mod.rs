@@ -24,6 +24,7 @@ use std::sync::RwLock;
 pub mod async_udf;
 pub mod instant_join;
 pub mod join_with_expiration;
+pub mod lookup_join;
 pub mod session_aggregating_window;
 pub mod sliding_aggregating_window;
 pub(crate) mod sync;
@@ -1,32 +1,41 @@
 use std::{
     any::Any,
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{btree_map::Entry, BTreeMap, HashMap},
     pin::Pin,
     sync::{Arc, RwLock},
 };
 
-use anyhow::{anyhow, Result};
-use arrow::compute::concat_batches;
-use arrow_array::RecordBatch;
+use anyhow::{anyhow, bail, Result};
+use arrow::compute::{cast, concat_batches, filter_record_batch, not};
+use arrow::row::OwnedRow;
+use arrow_array::{cast::AsArray, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
 
 use arroyo_operator::{
     context::ArrowContext,
     operator::{ArrowOperator, OperatorConstructor, OperatorNode},
 };
 use arroyo_rpc::grpc::{api::UpdatingAggregateOperator, TableConfig};
+use arroyo_rpc::IS_RETRACT_FIELD;
 use arroyo_state::timestamp_table_config;
 use arroyo_types::{CheckpointBarrier, SignalMessage, Watermark};
 use datafusion::{execution::context::SessionContext, physical_plan::ExecutionPlan};
 
 use arroyo_df::physical::{ArroyoPhysicalExtensionCodec, DecodingContext};
 use arroyo_operator::operator::Registry;
-use arroyo_rpc::df::ArroyoSchemaRef;
+use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
 use datafusion::common::ScalarValue;
 use datafusion::execution::{
     runtime_env::{RuntimeConfig, RuntimeEnv},
     SendableRecordBatchStream,
 };
-use datafusion::logical_expr::ColumnarValue;
+use datafusion::logical_expr::{Accumulator, ColumnarValue};
+use datafusion::physical_expr::{
+    expressions::{Avg, Count, Max, Min, Sum},
+    AggregateExpr, PhysicalExpr,
+};
+use datafusion::physical_plan::aggregates::AggregateExec;
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
 use futures::{lock::Mutex, Future};
 use prost::Message;
@@ -34,12 +43,16 @@ use std::time::Duration;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio_stream::StreamExt;
 
+// how long the state for a group is kept, for plans that don't configure it
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
 pub struct UpdatingAggregatingFunc {
     partial_aggregation_plan: Arc<dyn ExecutionPlan>,
     partial_schema: ArroyoSchemaRef,
     state_partial_schema: ArroyoSchemaRef,
     state_final_schema: ArroyoSchemaRef,
     flush_interval: Duration,
+    ttl: Duration,
     combine_plan: Arc<dyn ExecutionPlan>,
     finish_execution_plan: Arc<dyn ExecutionPlan>,
     receiver: Arc<RwLock<Option<UnboundedReceiver<RecordBatch>>>>,
@@ -48,10 +61,239 @@ pub struct UpdatingAggregatingFunc {
     // In particular, if it is a global aggregate it will emit a record batch with 1 row initialized with the empty aggregate state,
     // while if it does have group by keys it will emit a record batch with 0 rows.
     exec: Arc<Mutex<Option<SendableRecordBatchStream>>>,
+    // set when the input is updating. The input rows are stored so that retractions can be
+    // matched, and the last result of each group is kept to be retracted when the group changes.
+    updating_input_schema: Option<ArroyoSchemaRef>,
+    updating_final_schema: Option<ArroyoSchemaRef>,
+    // None if the input isn't updating or an aggregate can't be retracted, in which case the
+    // groups touched by each flush are recomputed from their stored rows.
+    retractable_aggregates: Option<RetractableAggregates>,
+    pending: Vec<RecordBatch>,
+}
+
+/// Wraps a value so that min and max can keep their values sorted
+#[derive(PartialEq, Eq)]
+struct OrderedValue(ScalarValue);
+
+impl PartialOrd for OrderedValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The state of one aggregate for one group, which rows can be added to and retracted from
+enum RetractableAccumulator {
+    /// accumulators whose retractions don't depend on the order the rows were added in
+    Sliding(Box<dyn Accumulator>),
+    /// DataFusion's sliding min and max can only retract the oldest row, so these keep a count
+    /// of each value instead
+    MinMax {
+        counts: BTreeMap<OrderedValue, usize>,
+        is_max: bool,
+        data_type: DataType,
+    },
+}
+
+impl RetractableAccumulator {
+    fn update(&mut self, values: &[ArrayRef], is_retract: bool) -> Result<()> {
+        match self {
+            Self::Sliding(accumulator) if is_retract => accumulator.retract_batch(values)?,
+            Self::Sliding(accumulator) => accumulator.update_batch(values)?,
+            Self::MinMax { counts, .. } => {
+                let array = &values[0];
+                for index in 0..array.len() {
+                    if array.is_null(index) {
+                        continue;
+                    }
+                    let value = OrderedValue(ScalarValue::try_from_array(array, index)?);
+                    if !is_retract {
+                        *counts.entry(value).or_default() += 1;
+                    } else if let Entry::Occupied(mut entry) = counts.entry(value) {
+                        *entry.get_mut() -= 1;
+                        if *entry.get() == 0 {
+                            entry.remove();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        Ok(match self {
+            Self::Sliding(accumulator) => accumulator.evaluate()?,
+            Self::MinMax {
+                counts,
+                is_max,
+                data_type,
+            } => {
+                let value = if *is_max {
+                    counts.last_key_value()
+                } else {
+                    counts.first_key_value()
+                };
+                match value {
+                    Some((value, _)) => value.0.clone(),
+                    None => ScalarValue::try_from(&*data_type)?,
+                }
+            }
+        })
+    }
+}
+
+/// The aggregates of each group of an updating input, which are updated as rows are appended
+/// and retracted instead of being recomputed from all of the group's rows
+struct RetractableAggregates {
+    group_exprs: Vec<Arc<dyn PhysicalExpr>>,
+    aggr_exprs: Vec<Arc<dyn AggregateExpr>>,
+    filters: Vec<Option<Arc<dyn PhysicalExpr>>>,
+    accumulators: HashMap<Vec<u8>, Vec<RetractableAccumulator>>,
+}
+
+impl RetractableAggregates {
+    /// Returns None unless the plan is an aggregate over the input whose aggregates can all be
+    /// retracted
+    fn try_new(plan: &Arc<dyn ExecutionPlan>, input_schema: &SchemaRef) -> Option<Self> {
+        let aggregate = plan.as_any().downcast_ref::<AggregateExec>()?;
+        if !aggregate.group_expr().is_single()
+            || aggregate.input_schema().fields().len() != input_schema.fields().len()
+        {
+            return None;
+        }
+        let aggregates = Self {
+            group_exprs: aggregate
+                .group_expr()
+                .expr()
+                .iter()
+                .map(|(expr, _)| expr.clone())
+                .collect(),
+            aggr_exprs: aggregate.aggr_expr().to_vec(),
+            filters: aggregate.filter_expr().to_vec(),
+            accumulators: HashMap::new(),
+        };
+        aggregates.new_accumulators().ok()?;
+        Some(aggregates)
+    }
+
+    fn new_accumulators(&self) -> Result<Vec<RetractableAccumulator>> {
+        self.aggr_exprs
+            .iter()
+            .map(|expr| {
+                let expr_any = expr.as_any();
+                if expr_any.is::<Max>() || expr_any.is::<Min>() {
+                    Ok(RetractableAccumulator::MinMax {
+                        counts: BTreeMap::new(),
+                        is_max: expr_any.is::<Max>(),
+                        data_type: expr.field()?.data_type().clone(),
+                    })
+                } else if expr_any.is::<Sum>() || expr_any.is::<Count>() || expr_any.is::<Avg>() {
+                    Ok(RetractableAccumulator::Sliding(
+                        expr.create_sliding_accumulator()?,
+                    ))
+                } else {
+                    bail!("{} can't be retracted", expr.name())
+                }
+            })
+            .collect()
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.accumulators.contains_key(key)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.accumulators.remove(key);
+    }
+
+    /// Adds or retracts rows of the input from a group's aggregates
+    fn update(&mut self, key: &[u8], rows: &RecordBatch, is_retract: bool) -> Result<()> {
+        if !self.accumulators.contains_key(key) {
+            let accumulators = self.new_accumulators()?;
+            self.accumulators.insert(key.to_vec(), accumulators);
+        }
+        if rows.num_rows() == 0 {
+            return Ok(());
+        }
+        let accumulators = self.accumulators.get_mut(key).unwrap();
+        for ((accumulator, expr), filter) in accumulators
+            .iter_mut()
+            .zip(&self.aggr_exprs)
+            .zip(&self.filters)
+        {
+            let rows = match filter {
+                Some(filter) => {
+                    let mask = filter.evaluate(rows)?.into_array(rows.num_rows())?;
+                    filter_record_batch(rows, mask.as_boolean())?
+                }
+                None => rows.clone(),
+            };
+            let values = expr
+                .expressions()
+                .iter()
+                .map(|value| {
+                    value
+                        .evaluate(&rows)
+                        .and_then(|value| value.into_array(rows.num_rows()))
+                })
+                .collect::<datafusion::common::Result<Vec<_>>>()?;
+            accumulator.update(&values, is_retract)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the current result of each group, given some of its rows to read the group
+    /// values from
+    fn evaluate(
+        &mut self,
+        groups: &[(OwnedRow, RecordBatch)],
+        schema: &SchemaRef,
+    ) -> Result<RecordBatch> {
+        let mut columns = vec![vec![]; self.group_exprs.len() + self.aggr_exprs.len()];
+        for (key, rows) in groups {
+            let first_row = rows.slice(0, 1);
+            for (column, expr) in columns.iter_mut().zip(&self.group_exprs) {
+                let value = expr.evaluate(&first_row)?.into_array(1)?;
+                column.push(ScalarValue::try_from_array(&value, 0)?);
+            }
+            let accumulators = self
+                .accumulators
+                .get_mut(key.as_ref())
+                .ok_or_else(|| anyhow!("no aggregates for group"))?;
+            for (column, accumulator) in columns[self.group_exprs.len()..]
+                .iter_mut()
+                .zip(accumulators.iter_mut())
+            {
+                column.push(accumulator.evaluate()?);
+            }
+        }
+        let columns = columns
+            .into_iter()
+            .zip(schema.fields())
+            .map(|(values, field)| {
+                let array = ScalarValue::iter_to_array(values)?;
+                if array.data_type() == field.data_type() {
+                    Ok(array)
+                } else {
+                    Ok(cast(&array, field.data_type())?)
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
 }
 
 impl UpdatingAggregatingFunc {
     async fn flush(&mut self, ctx: &mut ArrowContext) -> Result<()> {
+        if let Some(input_schema) = self.updating_input_schema.clone() {
+            return self.flush_updating(&input_schema.schema, ctx).await;
+        }
         if self.sender.is_none() {
             return Ok(());
         }
@@ -147,6 +389,145 @@ impl UpdatingAggregatingFunc {
         Ok(())
     }
 
+    async fn flush_updating(
+        &mut self,
+        input_schema: &SchemaRef,
+        ctx: &mut ArrowContext,
+    ) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = concat_batches(input_schema, &self.pending)?;
+        self.pending.clear();
+        let retract_index = input_schema.index_of(IS_RETRACT_FIELD)?;
+
+        let input_table = ctx
+            .table_manager
+            .get_key_time_table("i", ctx.last_present_watermark())
+            .await?;
+        let groups = input_table.partition_by_key(&batch)?;
+        if let Some(aggregates) = &mut self.retractable_aggregates {
+            for (key, rows) in &groups {
+                // groups that aren't in memory, like after a restore, start from their stored rows
+                if !aggregates.contains(key.as_ref()) {
+                    if let Some(stored_rows) = input_table.get_rows(key.as_ref())? {
+                        aggregates.update(key.as_ref(), &stored_rows, false)?;
+                    }
+                }
+                let is_retract = rows.column(retract_index).as_boolean();
+                let appends = filter_record_batch(rows, &not(is_retract)?)?;
+                aggregates.update(key.as_ref(), &appends, false)?;
+            }
+        }
+        // only the stored rows that retractions removed are retracted from the aggregates, as
+        // the rows that other retractions were for have already expired
+        let (_, retracted) = input_table.insert_retracting(batch).await?;
+        if let Some(aggregates) = &mut self.retractable_aggregates {
+            for (key, rows) in retracted {
+                aggregates.update(&key, &rows, true)?;
+            }
+        }
+
+        // groups whose rows were all retracted only have their last result retracted
+        let (current_groups, emptied_groups): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(key, _)| input_table.contains_key(key.as_ref()));
+        let updates = if let Some(aggregates) = &mut self.retractable_aggregates {
+            for (key, _) in &emptied_groups {
+                aggregates.remove(key.as_ref());
+            }
+            if current_groups.is_empty() {
+                vec![]
+            } else {
+                vec![aggregates.evaluate(&current_groups, &self.state_final_schema.schema)?]
+            }
+        } else {
+            let mut current_rows = vec![];
+            for (key, _) in &current_groups {
+                current_rows.extend(input_table.get_rows(key.as_ref())?);
+            }
+            self.aggregate(input_schema, current_rows).await?
+        };
+
+        let out_schema = ctx.out_schema.as_ref().unwrap().schema.clone();
+        let final_schema = self.updating_final_schema.as_ref().unwrap().schema.clone();
+        let final_table = ctx
+            .table_manager
+            .get_key_time_table("f", ctx.last_present_watermark())
+            .await?;
+        let mut batches_to_write = vec![];
+        for (key, _) in current_groups.iter().chain(&emptied_groups) {
+            let Some(prior_results) = final_table.get_rows(key.as_ref())? else {
+                continue;
+            };
+            let mut columns = prior_results.columns().to_vec();
+            let retract_flag = columns.len() - 1;
+            columns[retract_flag] = ColumnarValue::Scalar(ScalarValue::Boolean(Some(true)))
+                .into_array(prior_results.num_rows())?;
+            let retraction = RecordBatch::try_new(final_schema.clone(), columns)?;
+            // retracting the stored result removes it from the table
+            final_table.insert(retraction.clone()).await?;
+            batches_to_write.push(RecordBatch::try_new(
+                out_schema.clone(),
+                retraction.columns().to_vec(),
+            )?);
+        }
+        for results in updates {
+            let mut columns = results.columns().to_vec();
+            columns.push(
+                ColumnarValue::Scalar(ScalarValue::Boolean(Some(false)))
+                    .into_array(results.num_rows())?,
+            );
+            final_table
+                .insert(RecordBatch::try_new(final_schema.clone(), columns.clone())?)
+                .await?;
+            batches_to_write.push(RecordBatch::try_new(out_schema.clone(), columns)?);
+        }
+        for batch in batches_to_write.into_iter() {
+            ctx.collect(batch).await;
+        }
+        Ok(())
+    }
+
+    /// Computes the final aggregates over a set of input rows, for aggregates that can't be
+    /// retracted
+    async fn aggregate(
+        &self,
+        input_schema: &SchemaRef,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Vec<RecordBatch>> {
+        let input_batch = concat_batches(input_schema, &batches)?;
+        // a global aggregate over no rows would still produce a row
+        if input_batch.num_rows() == 0 {
+            return Ok(vec![]);
+        }
+        let mut partial_exec = {
+            let (sender, receiver) = unbounded_channel();
+            sender.send(input_batch)?;
+            self.receiver.write().unwrap().replace(receiver);
+            self.partial_aggregation_plan
+                .execute(0, SessionContext::new().task_ctx())?
+        };
+        let mut partial_batches = vec![];
+        while let Some(batch) = partial_exec.next().await {
+            partial_batches.push(batch?);
+        }
+        let partial_batch = concat_batches(&self.partial_schema.schema, &partial_batches)?;
+
+        let mut final_exec = {
+            let (sender, receiver) = unbounded_channel();
+            sender.send(partial_batch)?;
+            self.receiver.write().unwrap().replace(receiver);
+            self.finish_execution_plan
+                .execute(0, SessionContext::new().task_ctx())?
+        };
+        let mut results = vec![];
+        while let Some(batch) = final_exec.next().await {
+            results.push(batch?);
+        }
+        Ok(results)
+    }
+
     fn init_exec(&mut self) {
         let (sender, receiver) = unbounded_channel();
         {
@@ -169,6 +550,10 @@ impl ArrowOperator for UpdatingAggregatingFunc {
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, _ctx: &mut ArrowContext) {
+        if self.updating_input_schema.is_some() {
+            self.pending.push(batch);
+            return;
+        }
         if self.sender.is_none() {
             self.init_exec();
         }
@@ -180,30 +565,51 @@ impl ArrowOperator for UpdatingAggregatingFunc {
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
-        vec![
-            (
-                "f".to_string(),
-                timestamp_table_config(
-                    "f",
-                    "final_table",
-                    Duration::from_secs(60 * 60 * 24),
-                    true,
-                    self.state_final_schema.as_ref().clone(),
-                ),
+        // for updating inputs the last results are retracted from the final table when they change
+        let final_table = match &self.updating_final_schema {
+            Some(final_schema) => timestamp_table_config(
+                "f",
+                "final_table",
+                self.ttl,
+                false,
+                final_schema.as_ref().clone(),
+            ),
+            None => timestamp_table_config(
+                "f",
+                "final_table",
+                self.ttl,
+                true,
+                self.state_final_schema.as_ref().clone(),
             ),
+        };
+        let mut tables: HashMap<_, _> = vec![
+            ("f".to_string(), final_table),
             (
                 "p".to_string(),
                 timestamp_table_config(
                     "p",
                     "partial_table",
-                    Duration::from_secs(60 * 60 * 24),
+                    self.ttl,
                     true,
                     self.state_partial_schema.as_ref().clone(),
                 ),
             ),
         ]
         .into_iter()
-        .collect()
+        .collect();
+        if let Some(input_schema) = &self.updating_input_schema {
+            tables.insert(
+                "i".to_string(),
+                timestamp_table_config(
+                    "i",
+                    "input_table",
+                    self.ttl,
+                    false,
+                    input_schema.as_ref().clone(),
+                ),
+            );
+        }
+        tables
     }
     fn tick_interval(&self) -> Option<Duration> {
         Some(self.flush_interval)
@@ -235,14 +641,42 @@ impl ArrowOperator for UpdatingAggregatingFunc {
         partial_table
             .expire(last_watermark)
             .expect("should expire partial table");
-        let final_table = ctx
-            .table_manager
-            .get_last_key_value_table("f", last_watermark)
+        if self.updating_input_schema.is_none() {
+            ctx.table_manager
+                .get_last_key_value_table("f", last_watermark)
+                .await
+                .expect("should have final table")
+                .expire(last_watermark)
+                .expect("should expire final table");
+            return Some(watermark);
+        }
+        ctx.table_manager
+            .get_key_time_table("f", last_watermark)
             .await
-            .expect("should have final table");
-        final_table
+            .expect("should have final table")
             .expire(last_watermark)
             .expect("should expire final table");
+        let input_table = ctx
+            .table_manager
+            .get_key_time_table("i", last_watermark)
+            .await
+            .expect("should have input table");
+        let expired = input_table
+            .expire(last_watermark)
+            .expect("should expire input table");
+        // expired rows are retracted from the aggregates, so that they match what would be
+        // rebuilt from the stored rows after a restore
+        if let Some(aggregates) = &mut self.retractable_aggregates {
+            for (key, rows) in expired {
+                if !input_table.contains_key(&key) {
+                    aggregates.remove(&key);
+                } else if aggregates.contains(&key) {
+                    aggregates
+                        .update(&key, &rows, true)
+                        .expect("should retract expired rows");
+                }
+            }
+        }
         Some(watermark)
     }
 
@@ -269,14 +703,25 @@ impl ArrowOperator for UpdatingAggregatingFunc {
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
         // fetch the tables so they are ready to be queried.
-        ctx.table_manager
-            .get_last_key_value_table("f", ctx.last_present_watermark())
-            .await
-            .unwrap();
         ctx.table_manager
             .get_last_key_value_table("p", ctx.last_present_watermark())
             .await
             .unwrap();
+        if self.updating_input_schema.is_some() {
+            ctx.table_manager
+                .get_key_time_table("f", ctx.last_present_watermark())
+                .await
+                .unwrap();
+            ctx.table_manager
+                .get_key_time_table("i", ctx.last_present_watermark())
+                .await
+                .unwrap();
+        } else {
+            ctx.table_manager
+                .get_last_key_value_table("f", ctx.last_present_watermark())
+                .await
+                .unwrap();
+        }
     }
 }
 
@@ -326,6 +771,41 @@ impl OperatorConstructor for UpdatingAggregatingConstructor {
             &codec,
         )?;
 
+        let updating_input_schema = config
+            .updating_input_schema
+            .map(ArroyoSchema::try_from)
+            .transpose()?
+            .map(Arc::new);
+
+        let state_final_schema: ArroyoSchema = config
+            .state_final_schema
+            .ok_or_else(|| anyhow!("requires final schema"))?
+            .try_into()?;
+
+        // the final results of an updating aggregate are stored with the retraction flag, so that
+        // retracting them removes them from state
+        let updating_final_schema = updating_input_schema.as_ref().map(|_| {
+            let mut fields = state_final_schema.schema.fields().to_vec();
+            fields.push(Arc::new(Field::new(
+                IS_RETRACT_FIELD,
+                DataType::Boolean,
+                false,
+            )));
+            let schema = Arc::new(Schema::new_with_metadata(
+                fields,
+                state_final_schema.schema.metadata().clone(),
+            ));
+            let key_indices = state_final_schema.key_indices.clone().unwrap_or_default();
+            Arc::new(if key_indices.is_empty() {
+                ArroyoSchema::new_unkeyed(schema, state_final_schema.timestamp_index)
+            } else {
+                ArroyoSchema::new_keyed(schema, state_final_schema.timestamp_index, key_indices)
+            })
+        });
+        let retractable_aggregates = updating_input_schema.as_ref().and_then(|input_schema| {
+            RetractableAggregates::try_new(&partial_aggregation_plan, &input_schema.schema)
+        });
+
         Ok(OperatorNode::from_operator(Box::new(
             UpdatingAggregatingFunc {
                 partial_aggregation_plan,
@@ -337,17 +817,20 @@ impl OperatorConstructor for UpdatingAggregatingConstructor {
                         .ok_or_else(|| anyhow!("requires partial schema"))?
                         .try_into()?,
                 ),
-                state_final_schema: Arc::new(
-                    config
-                        .state_final_schema
-                        .ok_or_else(|| anyhow!("requires final schema"))?
-                        .try_into()?,
-                ),
+                state_final_schema: Arc::new(state_final_schema),
                 flush_interval: Duration::from_micros(config.flush_interval_micros),
+                ttl: config
+                    .ttl_micros
+                    .map(Duration::from_micros)
+                    .unwrap_or(DEFAULT_TTL),
                 finish_execution_plan,
                 receiver,
                 sender: None,
                 exec: Arc::new(Mutex::new(None)),
+                updating_input_schema,
+                updating_final_schema,
+                retractable_aggregates,
+                pending: vec![],
             },
         )))
     }
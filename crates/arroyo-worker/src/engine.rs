@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::mem;
 use std::sync::{Arc, RwLock};
@@ -13,8 +13,9 @@ use futures::StreamExt;
 use tracing::{info, warn};
 
 use crate::arrow::async_udf::AsyncUdfConstructor;
-use crate::arrow::instant_join::InstantJoinConstructor;
 use crate::arrow::join_with_expiration::JoinWithExpirationConstructor;
+use crate::arrow::lookup_join::LookupJoinConstructor;
+use crate::arrow::lookup_join::LookupJoinConstructor;
 use crate::arrow::session_aggregating_window::SessionAggregatingWindowConstructor;
 use crate::arrow::sliding_aggregating_window::SlidingAggregatingWindowConstructor;
 use crate::arrow::tumbling_aggregating_window::TumblingAggregateWindowConstructor;
@@ -24,7 +25,7 @@ use crate::arrow::window_fn::WindowFunctionConstructor;
 use crate::arrow::{KeyExecutionConstructor, ValueExecutionConstructor};
 use crate::network_manager::{NetworkManager, Quad, Senders};
 use arroyo_datastream::logical::{
-    LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName,
+    LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName, ProgramConfig,
 };
 use arroyo_df::physical::new_registry;
 use arroyo_operator::context::{batch_bounded, ArrowContext, BatchReceiver, BatchSender};
@@ -35,7 +36,8 @@ use arroyo_rpc::grpc::{api, CheckpointMetadata, TaskAssignment};
 use arroyo_rpc::{ControlMessage, ControlResp};
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{
-    range_for_server, u32_config, Key, TaskInfo, WorkerId, DEFAULT_QUEUE_SIZE, QUEUE_SIZE_ENV,
+    range_for_server, u32_config, BatchingConfig, Key, TaskInfo, WorkerId, DEFAULT_QUEUE_SIZE,
+    QUEUE_SIZE_ENV,
 };
 use arroyo_udf_host::LocalUdf;
 use petgraph::graph::{DiGraph, NodeIndex};
@@ -168,6 +170,7 @@ impl SubtaskOrQueueNode {
 pub struct Program {
     pub name: String,
     pub graph: Arc<RwLock<DiGraph<SubtaskOrQueueNode, PhysicalGraphEdge>>>,
+    pub batching: BatchingConfig,
 }
 
 impl Program {
@@ -196,12 +199,19 @@ impl Program {
         for udf in udfs {
             registry.add_local_udf(udf);
         }
-        Self::from_logical(name, logical, &assignments, registry)
+        Self::from_logical(
+            name,
+            logical,
+            &ProgramConfig::default(),
+            &assignments,
+            registry,
+        )
     }
 
     pub fn from_logical(
         name: String,
         logical: &LogicalGraph,
+        config: &ProgramConfig,
         assignments: &Vec<TaskAssignment>,
         registry: Registry,
     ) -> Program {
@@ -293,7 +303,8 @@ impl Program {
                 }
                 LogicalEdgeType::Shuffle
                 | LogicalEdgeType::LeftJoin
-                | LogicalEdgeType::RightJoin => {
+                | LogicalEdgeType::RightJoin
+                | LogicalEdgeType::DeadLetter => {
                     for f in &from_nodes {
                         for (idx, t) in to_nodes.iter().enumerate() {
                             let (tx, rx) = batch_bounded(queue_size);
@@ -313,9 +324,18 @@ impl Program {
             }
         }
 
+        let mut batching = BatchingConfig::default();
+        if let Some(size) = config.batch_size {
+            batching.size = size;
+        }
+        if let Some(linger) = config.batch_linger {
+            batching.linger = linger;
+        }
+
         Program {
             name,
             graph: Arc::new(RwLock::new(physical)),
+            batching,
         }
     }
 
@@ -673,6 +693,7 @@ impl Engine {
 
         let mut in_qs_map: BTreeMap<(LogicalEdgeType, usize), Vec<BatchReceiver>> = BTreeMap::new();
         let mut out_qs_map: BTreeMap<usize, BTreeMap<usize, BatchSender>> = BTreeMap::new();
+        let mut dead_letter_outputs = HashSet::new();
         let task_info = {
             let mut graph = self.program.graph.write().unwrap();
             for edge in graph.edge_indices() {
@@ -696,6 +717,10 @@ impl Engine {
                         == self.worker_id.0
                 };
 
+                if edge.weight().edge == LogicalEdgeType::DeadLetter {
+                    dead_letter_outputs.insert(edge.weight().out_logical_idx);
+                }
+
                 let tx = edge.weight().tx.as_ref().unwrap().clone();
                 out_qs_map
                     .entry(edge.weight().out_logical_idx)
@@ -712,7 +737,15 @@ impl Engine {
         let tables = node.node.tables();
         let in_qs: Vec<_> = in_qs_map.into_values().flatten().collect();
 
-        let ctx = ArrowContext::new(
+        // the positions of the dead-letter edges within the outputs passed to the context
+        let dead_letter_outputs: Vec<_> = out_qs_map
+            .keys()
+            .enumerate()
+            .filter(|(_, idx)| dead_letter_outputs.contains(*idx))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut ctx = ArrowContext::new(
             task_info,
             checkpoint_metadata.clone(),
             control_rx,
@@ -728,6 +761,8 @@ impl Engine {
             tables,
         )
         .await;
+        ctx.set_dead_letter_outputs(dead_letter_outputs);
+        ctx.set_batching(self.program.batching);
 
         let operator = Box::new(node.node);
         let join_task = tokio::spawn(async move {
@@ -774,6 +809,7 @@ pub fn construct_operator(
         OperatorName::ExpressionWatermark => Box::new(WatermarkGeneratorConstructor),
         OperatorName::Join => Box::new(JoinWithExpirationConstructor),
         OperatorName::InstantJoin => Box::new(InstantJoinConstructor),
+        OperatorName::LookupJoin => Box::new(LookupJoinConstructor),
         OperatorName::WindowFunction => Box::new(WindowFunctionConstructor),
         OperatorName::ConnectorSource | OperatorName::ConnectorSink => {
             let op: api::ConnectorOp = prost::Message::decode(&mut config.as_slice()).unwrap();
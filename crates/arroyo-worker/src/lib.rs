@@ -467,6 +467,7 @@ impl WorkerGrpc for WorkerServer {
             let program = Program::from_logical(
                 self.name.to_string(),
                 &self.logical_graph,
+                &self.program_config,
                 &req.tasks,
                 registry,
             );
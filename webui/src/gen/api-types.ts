@@ -129,6 +129,7 @@ export interface components {
   schemas: {
     AvroFormat: {
       confluentSchemaRegistry?: boolean;
+      debezium?: boolean;
       intoUnstructuredJson?: boolean;
       rawDatums?: boolean;
       readerSchema?: string;
@@ -139,6 +140,11 @@ export interface components {
       fail: Record<string, never>;
     }, {
       drop: Record<string, never>;
+    }, {
+      dead_letter: {
+        source?: string;
+        table: string;
+      };
     }]>;
     Checkpoint: {
       backend: string;
@@ -216,7 +222,7 @@ export interface components {
       schema?: components["schemas"]["ConnectionSchema"] | null;
     };
     /** @enum {string} */
-    ConnectionType: "source" | "sink";
+    ConnectionType: "source" | "sink" | "lookup";
     Connector: {
       connectionConfig?: string | null;
       customSchemas: boolean;
@@ -234,6 +240,23 @@ export interface components {
     ConnectorCollection: {
       data: (components["schemas"]["Connector"])[];
     };
+    CsvFormat: {
+      delimiter?: string;
+      /** @description The character used to escape quotes inside of quoted fields; if not set, quotes
+       * are escaped by doubling them */
+      escape?: string | null;
+      /** @description Whether the first line of each file is a header row, which is skipped */
+      hasHeader?: boolean;
+      /** @description Whether columns should be matched to fields by the names in the header row, rather
+       * than by position */
+      mapByHeader?: boolean;
+      /** @description A string that is read and written as SQL NULL */
+      nullValue?: string | null;
+      quote?: string;
+    };
+    DelimitedFraming: {
+      delimiter: (number)[];
+    };
     ErrorResp: {
       error: string;
     };
@@ -248,6 +271,10 @@ export interface components {
       json: components["schemas"]["JsonFormat"];
     }, {
       avro: components["schemas"]["AvroFormat"];
+    }, {
+      protobuf: components["schemas"]["ProtobufFormat"];
+    }, {
+      csv: components["schemas"]["CsvFormat"];
     }, {
       parquet: components["schemas"]["ParquetFormat"];
     }, {
@@ -258,9 +285,13 @@ export interface components {
     Framing: {
       method: components["schemas"]["FramingMethod"];
     };
-    FramingMethod: {
+    FramingMethod: OneOf<[{
       newline: components["schemas"]["NewlineDelimitedFraming"];
-    };
+    }, {
+      lengthPrefixed: components["schemas"]["LengthPrefixedFraming"];
+    }, {
+      delimited: components["schemas"]["DelimitedFraming"];
+    }]>;
     GlobalUdf: {
       /** Format: int64 */
       createdAt: number;
@@ -319,8 +350,17 @@ export interface components {
       /** Format: int32 */
       schemaId?: number | null;
       timestampFormat?: components["schemas"]["TimestampFormat"];
+      /** @description The timezone used for timestamp patterns that don't include an offset; defaults to UTC */
+      timezone?: string | null;
       unstructured?: boolean;
     };
+    /** @enum {string} */
+    LengthPrefix: "u32_be" | "u32_le" | "u64_be" | "u64_le" | "varint";
+    LengthPrefixedFraming: {
+      /** Format: int64 */
+      maxLength?: number | null;
+      prefix?: components["schemas"]["LengthPrefix"];
+    };
     Metric: {
       /** Format: int64 */
       time: number;
@@ -425,6 +465,18 @@ export interface components {
     };
     /** @enum {string} */
     PrimitiveType: "Int32" | "Int64" | "UInt32" | "UInt64" | "F32" | "F64" | "Bool" | "String" | "Bytes" | "UnixMillis" | "UnixMicros" | "UnixNanos" | "DateTime" | "Json";
+    ProtobufFormat: {
+      /** @description The compiled `FileDescriptorSet` for the schema, populated when the schema is expanded */
+      compiledSchema?: (number)[] | null;
+      confluentSchemaRegistry?: boolean;
+      /** @description The text of the schema the table was created with, used to look up the id a
+       * user-provided sink schema is registered under */
+      definition?: string | null;
+      intoUnstructuredJson?: boolean;
+      messageName?: string | null;
+      /** Format: int32 */
+      schemaId?: number | null;
+    };
     QueryValidationResult: {
       errors: (string)[];
       graph?: components["schemas"]["PipelineGraph"] | null;
@@ -443,6 +495,9 @@ export interface components {
     SourceField: {
       fieldName: string;
       fieldType: components["schemas"]["SourceFieldType"];
+      /** @description If set, this field is filled from the named metadata of the source (like a Kafka
+       * message key) rather than from the deserialized payload */
+      metadataKey?: string | null;
       nullable: boolean;
     };
     SourceFieldType: {
@@ -473,7 +528,9 @@ export interface components {
       message: string;
     };
     /** @enum {string} */
-    TimestampFormat: "rfc3339" | "unix_millis";
+    TimestampFormat: OneOf<["rfc3339", "unix_seconds", "unix_millis", "unix_micros", "unix_nanos", "unix_seconds_fractional", {
+      pattern: string;
+    }]>;
     Udf: {
       definition: string;
     };
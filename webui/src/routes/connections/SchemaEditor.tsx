@@ -14,7 +14,7 @@ export function SchemaEditor({
   state: CreateConnectionState;
   setState: Dispatch<CreateConnectionState>;
   next: () => void;
-  format: 'avro' | 'json';
+  format: 'avro' | 'json' | 'protobuf';
 }) {
   const [editor, setEditor] = useState<monaco.editor.IStandaloneCodeEditor | null>(null);
   const monacoEl = useRef(null);
@@ -71,7 +71,7 @@ export function SchemaEditor({
   useEffect(() => {
     if (monacoEl && !editor && !created.current) {
       let e = monaco.editor.create(monacoEl.current!, {
-        language: 'json',
+        language: format == 'protobuf' ? 'proto' : 'json',
         theme: 'vs-dark',
         minimap: {
           enabled: false,